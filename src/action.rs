@@ -0,0 +1,194 @@
+use crate::error::MintError;
+use crate::meta::{Event, EventSource, ReadinessPager, Listener};
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub type ActionID = usize;
+
+/// How many worker threads there are, i.e. how many slow actions can be running at once.
+const WORKERS: usize = 4;
+
+/// How long an action gets if nobody says otherwise.
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(10);
+
+// What goes to the workers: the job, its ID, and its time limit.
+type JobRequest = (ActionID, Job, Duration);
+
+/// Something slow enough that it shouldn't happen on the main thread.
+pub enum Job {
+    /// Run a command with `sh -c` and collect what it prints.
+    Shell(String),
+    /// Anything else.  If this goes over its time limit there's no way to stop it, so its result
+    /// is thrown away instead when it finally turns up.
+    Func(Box<dyn FnOnce() -> Result<String, String> + Send>),
+}
+
+/// EventSource that runs Jobs on a pool of worker threads.  Submit one and you get back an ID;
+/// when it's done, an Event::ActionDone with that ID and the result comes out of the
+/// EventManager.
+pub struct ActionPool {
+    last_id: ActionID,
+
+    job_tx: mpsc::Sender<JobRequest>,
+    // Shared between the workers; whichever is free takes the next job.  In an Option because
+    // get_listeners() hands it out, like Timers does with its receiver.
+    job_rx: Option<Arc<Mutex<mpsc::Receiver<JobRequest>>>>,
+
+    done_tx: mpsc::Sender<(ActionID, Result<String, String>)>,
+    done_rx: mpsc::Receiver<(ActionID, Result<String, String>)>,
+}
+
+impl ActionPool {
+    pub fn new() -> ActionPool {
+        let (job_tx, job_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+
+        ActionPool {
+            last_id: 0,
+            job_tx,
+            job_rx: Some(Arc::new(Mutex::new(job_rx))),
+            done_tx,
+            done_rx,
+        }
+    }
+
+    /// Run `job` in the background, giving up on it after `limit`.
+    pub fn submit(&mut self, job: Job, limit: Duration) -> ActionID {
+        self.last_id += 1;
+        self.job_tx.send((self.last_id, job, limit))
+            .expect("ActionPool internal error: Couldn't send() job to workers");
+        self.last_id
+    }
+}
+
+impl Default for ActionPool {
+    fn default() -> ActionPool {
+        ActionPool::new()
+    }
+}
+
+impl EventSource for ActionPool {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
+        match self.job_rx.take() {
+            Some(rx) => (0..WORKERS).map(|n| Box::new(ActionWorker {
+                n,
+                rx: rx.clone(),
+                done_tx: self.done_tx.clone(),
+            }) as Box<dyn Listener>).collect(),
+            None => panic!("Cannot call get_listeners() on ActionPool more than once."),
+        }
+    }
+
+    fn process(&mut self) -> Vec<Event> {
+        self.done_rx.try_iter().map(|(id, result)| Event::ActionDone { id, result }).collect()
+    }
+}
+
+/// One of the worker threads.  No heartbeat: a worker is supposed to be busy for a long time
+/// sometimes, and the time limits are what keep that in check.
+struct ActionWorker {
+    n: usize,
+    rx: Arc<Mutex<mpsc::Receiver<JobRequest>>>,
+    done_tx: mpsc::Sender<(ActionID, Result<String, String>)>,
+}
+
+impl Listener for ActionWorker {
+    fn name(&self) -> String {
+        format!("action-{}", self.n)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        loop {
+            // Hold the lock only long enough to take a job, so the others can take the next ones.
+            let next = self.rx.lock()
+                .map_err(|_| MintError::Internal("the job queue's lock was poisoned".to_string()))?
+                .recv();
+            let (id, job, limit) = match next {
+                Ok(job) => job,
+                Err(_) => return Ok(()),
+            };
+
+            let result = run_job(job, limit);
+            self.done_tx.send((id, result))?;
+            flag.ok();
+        }
+    }
+}
+
+/// Run a job to completion or until `limit` runs out, whichever comes first.
+pub fn run_job(job: Job, limit: Duration) -> Result<String, String> {
+    match job {
+        Job::Shell(command) => run_shell(&command, limit),
+        Job::Func(f) => {
+            // Run it on a thread of its own so we can stop waiting for it.
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || { let _ = tx.send(f()); });
+            match rx.recv_timeout(limit) {
+                Ok(result) => result,
+                Err(_) => Err(format!("Gave up after {}s", limit.as_secs_f32())),
+            }
+        },
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut from: R) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut text = String::new();
+        let _ = from.read_to_string(&mut text);
+        text
+    })
+}
+
+fn run_shell(command: &str, limit: Duration) -> Result<String, String> {
+    let mut child = Command::new("sh").arg("-c").arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Couldn't run {}: {}", command, e))?;
+
+    // Read the output on other threads, so a chatty child can't fill up the pipe and block
+    // forever while we're waiting for it to exit.
+    let stdout = read_in_background(child.stdout.take().expect("child stdout"));
+    let stderr = read_in_background(child.stderr.take().expect("child stderr"));
+
+    let deadline = Instant::now() + limit;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Killed {} after {}s", command, limit.as_secs_f32()));
+            },
+            Ok(None) => thread::sleep(Duration::from_millis(10)),
+            Err(e) => return Err(format!("Couldn't wait for {}: {}", command, e)),
+        }
+    };
+
+    let out = stdout.join().unwrap_or_default();
+    let err = stderr.join().unwrap_or_default();
+    if status.success() {
+        Ok(out)
+    } else {
+        Err(format!("{} failed ({}): {}", command, status, err.trim()))
+    }
+}
+
+#[test]
+fn action_time_limits() {
+    assert_eq!(run_job(Job::Shell("echo hi".to_string()), DEFAULT_TIME_LIMIT), Ok("hi\n".to_string()));
+    assert!(run_job(Job::Shell("exit 3".to_string()), DEFAULT_TIME_LIMIT).is_err());
+
+    let start = Instant::now();
+    assert!(run_job(Job::Shell("sleep 5".to_string()), Duration::from_millis(100)).is_err());
+    assert!(run_job(Job::Func(Box::new(|| {
+        thread::sleep(Duration::from_secs(5));
+        Ok(String::new())
+    })), Duration::from_millis(100)).is_err());
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,135 @@
+use crate::clock::LocalTime;
+use crate::timer::{Timers, TimerID};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A wall-clock alarm, e.g. "21:30 raid time".  Unlike a plain timer, which goes off some amount
+/// of time from now, an alarm goes off at a particular time of day.
+#[derive(Clone, Debug)]
+pub struct Alarm {
+    /// When it goes off, in seconds since the Unix epoch.
+    pub when: u64,
+    pub message: String,
+}
+
+impl Alarm {
+    pub fn local_time(&self) -> LocalTime {
+        LocalTime::from_unix(self.when as libc::time_t)
+    }
+}
+
+/// The set of alarms that haven't gone off yet.  They're saved to a file whenever they change,
+/// so that restarting the client doesn't lose them.
+pub struct Alarms {
+    pending: Vec<(TimerID, Alarm)>,
+    path: Option<PathBuf>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Parse a time of day like "21:30" into (hour, minute).
+pub fn parse_time_of_day(text: &str) -> Option<(u32, u32)> {
+    let mut parts = text.splitn(2, ':');
+    let hour: u32 = parts.next()?.parse().ok()?;
+    let minute: u32 = parts.next()?.parse().ok()?;
+    if hour < 24 && minute < 60 {
+        Some((hour, minute))
+    } else {
+        None
+    }
+}
+
+impl Alarms {
+    /// Load saved alarms from `path` (if there is one) and schedule any that haven't gone off
+    /// yet.  Ones whose time passed while we weren't running are dropped.
+    pub fn load(path: Option<PathBuf>, timers: &mut Timers) -> Alarms {
+        let mut alarms = Alarms {
+            pending: vec![],
+            path,
+        };
+
+        let contents = match alarms.path {
+            Some(ref p) => fs::read_to_string(p).unwrap_or_default(),
+            None => String::new(),
+        };
+
+        let now = unix_now();
+        for line in contents.lines() {
+            let mut parts = line.splitn(2, ' ');
+            let when = parts.next().and_then(|w| w.parse::<u64>().ok());
+            if let (Some(when), Some(message)) = (when, parts.next()) {
+                if when > now {
+                    alarms.schedule(timers, Alarm { when, message: message.to_string() });
+                }
+            }
+        }
+
+        alarms
+    }
+
+    fn schedule(&mut self, timers: &mut Timers, alarm: Alarm) {
+        let delay = alarm.when.saturating_sub(unix_now());
+        let id = timers.schedule(Instant::now() + Duration::from_secs(delay));
+        self.pending.push((id, alarm));
+    }
+
+    /// Set an alarm for the next time it's `hour`:`minute` -- today if that's still to come,
+    /// otherwise tomorrow.
+    pub fn add(&mut self, timers: &mut Timers, hour: u32, minute: u32, message: String)
+               -> io::Result<Alarm> {
+        let now = unix_now();
+        let local = LocalTime::from_unix(now as libc::time_t);
+
+        let now_secs = (local.hour * 3600 + local.minute * 60 + local.second) as u64;
+        let target_secs = (hour * 3600 + minute * 60) as u64;
+        let delay = if target_secs > now_secs {
+            target_secs - now_secs
+        } else {
+            target_secs + 24 * 3600 - now_secs
+        };
+
+        let alarm = Alarm { when: now + delay, message };
+        self.schedule(timers, alarm.clone());
+        self.save()?;
+        Ok(alarm)
+    }
+
+    /// If timer `id` belongs to an alarm, that alarm has gone off; forget about it and return it.
+    pub fn fired(&mut self, id: TimerID) -> Option<Alarm> {
+        let idx = self.pending.iter().position(|(i, _)| *i == id)?;
+        let (_, alarm) = self.pending.remove(idx);
+        // If this doesn't work, the worst that happens is that the alarm is dropped on reload
+        // anyway because its time has passed.
+        let _ = self.save();
+        Some(alarm)
+    }
+
+    pub fn list(&self) -> Vec<&Alarm> {
+        let mut all: Vec<&Alarm> = self.pending.iter().map(|(_, a)| a).collect();
+        all.sort_by_key(|a| a.when);
+        all
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(ref path) = self.path {
+            let contents: String = self.pending.iter()
+                .map(|(_, a)| format!("{} {}\n", a.when, a.message))
+                .collect();
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn time_of_day() {
+    assert_eq!(parse_time_of_day("21:30"), Some((21, 30)));
+    assert_eq!(parse_time_of_day("7:05"), Some((7, 5)));
+    assert_eq!(parse_time_of_day("24:00"), None);
+    assert_eq!(parse_time_of_day("2130"), None);
+}
@@ -0,0 +1,88 @@
+//! The idle CPU audit: an opt-in tally of every time something woke up or redrew without anything
+//! coming of it.  None of these are bugs exactly, but each one costs a CPU wakeup, and on a laptop
+//! sitting in an idle session those add up.  Start it, leave the session alone for a while, and
+//! anything it has counted by then is waste.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Tally of pointless wakeups, by what happened.  Does nothing until start() is called.
+pub struct IdleAudit {
+    started: Option<Instant>,
+    // How many times each thing has happened, and when it last did.
+    counts: BTreeMap<String, (usize, Instant)>,
+}
+
+impl IdleAudit {
+    pub fn new() -> IdleAudit {
+        IdleAudit {
+            started: None,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Start counting from zero.
+    pub fn start(&mut self) {
+        self.started = Some(Instant::now());
+        self.counts.clear();
+    }
+
+    /// Stop counting.  What's been counted is kept for report().
+    pub fn stop(&mut self) {
+        self.started = None;
+    }
+
+    pub fn running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Count one occurrence of `what`, if the audit's running.
+    pub fn note(&mut self, what: &str) {
+        if self.running() {
+            let now = Instant::now();
+            let entry = self.counts.entry(what.to_string()).or_insert((0, now));
+            entry.0 += 1;
+            entry.1 = now;
+        }
+    }
+
+    /// One line for each thing that's been counted, like "redraw with nothing to draw: 3 times,
+    /// last 12s ago".
+    pub fn report(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.counts.iter().map(|(what, (count, last))| {
+            format!("{}: {} time{}, last {}s ago", what, count, if *count == 1 { "" } else { "s" },
+                    now.duration_since(*last).as_secs())
+        }).collect()
+    }
+
+    /// How long it's been counting, if it is.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.started.map(|start| start.elapsed())
+    }
+}
+
+impl Default for IdleAudit {
+    fn default() -> IdleAudit {
+        IdleAudit::new()
+    }
+}
+
+#[test]
+fn idle_audit_counts() {
+    let mut audit = IdleAudit::new();
+    audit.note("ignored, since it isn't running");
+    assert!(audit.report().is_empty());
+
+    audit.start();
+    audit.note("spurious wakeup");
+    audit.note("spurious wakeup");
+    audit.note("empty redraw");
+    audit.stop();
+    audit.note("empty redraw");
+
+    assert_eq!(audit.report(), vec![
+        "empty redraw: 1 time, last 0s ago".to_string(),
+        "spurious wakeup: 2 times, last 0s ago".to_string(),
+    ]);
+}
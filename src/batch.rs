@@ -0,0 +1,233 @@
+//! Batch mode, for shell scripts: connect to a world, send some lines and wait for what's
+//! expected back, print everything the server said along the way, and exit with a status that
+//! says how it went.
+//!
+//! ```text
+//! mint --batch --world mud.example.org:4000 --send "who" --expect "\d+ players" --timeout 10
+//! ```
+//!
+//! --send and --expect can be given as many times as needed, and happen in the order they're
+//! given: each --send waits for every --expect before it.  --expect is a regular expression,
+//! matched against lines (and prompts) with the colors taken out.  If the last thing is a --send,
+//! whatever the server says until it closes the connection or the time's up is printed, and either
+//! counts as done.  The timeout's for the whole exchange, and is 30 seconds if it isn't given.
+
+use crate::events::ThreadedManager;
+use crate::meta::{Event, EventManager};
+use crate::net::{ConnectionID, ConnectionInterface};
+use crate::net::tcp::TcpConnectionManager;
+use crate::timer::Timers;
+use crate::ui::ansi;
+
+use regex::Regex;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What mint exits with after a batch run, if it isn't 0 for everything going to plan.
+pub const EXIT_TIMED_OUT: i32 = 1;
+pub const EXIT_CLOSED: i32 = 2;
+pub const EXIT_USAGE: i32 = 3;
+
+pub const USAGE: &str = "Usage: mint --batch --world host:port [--send text | --expect pattern]... \
+                         [--timeout seconds]";
+
+#[derive(Clone, Debug)]
+pub enum Step {
+    Send(String),
+    Expect(Regex),
+}
+
+/// A scripted exchange with a world.
+#[derive(Debug)]
+pub struct Exchange {
+    pub address: String,
+    pub steps: VecDeque<Step>,
+    pub timeout: Duration,
+}
+
+impl Exchange {
+    /// Read the exchange from the command line arguments after --batch.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Exchange, String> {
+        let mut address = None;
+        let mut steps = VecDeque::new();
+        let mut timeout = DEFAULT_TIMEOUT;
+
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{} needs something after it", flag))?;
+            match flag.as_str() {
+                "--world" => address = Some(value),
+                "--send" => steps.push_back(Step::Send(value)),
+                "--expect" => {
+                    let pattern = Regex::new(&value).map_err(|e| format!("Bad pattern {}: {}", value, e))?;
+                    steps.push_back(Step::Expect(pattern));
+                },
+                "--timeout" => {
+                    timeout = match value.parse::<f64>() {
+                        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Duration::from_secs_f64(seconds),
+                        _ => return Err(format!("Not a number of seconds: {}", value)),
+                    };
+                },
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+
+        let address = address.ok_or("Which world? (--world host:port)")?;
+        Ok(Exchange { address, steps, timeout })
+    }
+
+    /// Send everything up to the next --expect, and say whether there was anything.
+    fn send_until_expect(&mut self, tcp: &mut TcpConnectionManager, which: ConnectionID) -> Result<bool, String> {
+        let mut sent = false;
+        while let Some(Step::Send(_)) = self.steps.front() {
+            if let Some(Step::Send(text)) = self.steps.pop_front() {
+                tcp.write_to_connection(which, format!("{}\n", text)).map_err(|e| e.to_string())?;
+                sent = true;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Whether the next step was waiting for `text`, in which case it's done.
+    fn expected(&mut self, text: &str) -> bool {
+        match self.steps.front() {
+            Some(Step::Expect(pattern)) if pattern.is_match(text) => {
+                self.steps.pop_front();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether there's anything left to wait for.
+    fn waiting(&self) -> bool {
+        self.steps.iter().any(|step| matches!(step, Step::Expect(_)))
+    }
+
+    /// Have the exchange, printing what the server says to `out` (without colors), and say what
+    /// to exit with.  What went wrong, if anything, goes to stderr.
+    pub fn run(mut self, out: &mut impl Write) -> i32 {
+        let mut manager = ThreadedManager::new();
+        let tcp = Rc::new(RefCell::new(TcpConnectionManager::new()));
+        manager.start_source(tcp.clone());
+        let timers = Rc::new(RefCell::new(Timers::new()));
+        manager.start_source(timers.clone());
+
+        let deadline = timers.borrow_mut().schedule(Instant::now() + self.timeout);
+        let which = match tcp.borrow_mut().start_connection(self.address.clone()) {
+            Ok(which) => which,
+            Err(e) => {
+                eprintln!("Couldn't connect to {}: {}", self.address, e);
+                return EXIT_CLOSED;
+            },
+        };
+
+        loop {
+            let event = match manager.next_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Stopping: {}", e);
+                    return EXIT_CLOSED;
+                },
+            };
+            let text = match event {
+                Event::ConnectionStart { which: started } if started == which => {
+                    if let Err(e) = self.send_until_expect(&mut tcp.borrow_mut(), which) {
+                        eprintln!("Couldn't send: {}", e);
+                        return EXIT_CLOSED;
+                    }
+                    continue;
+                },
+                Event::ServerText { line, which: from, .. } if from == which => line,
+                Event::ServerPrompt { prompt, which: from } if from == which && !prompt.is_empty() => prompt,
+                Event::ConnectionEnd { which: ended, reason, .. } if ended == which => {
+                    if self.waiting() {
+                        eprintln!("The connection closed before everything expected turned up: {}", reason);
+                        return EXIT_CLOSED;
+                    }
+                    return 0;
+                },
+                Event::TimerFired { id } if id == deadline => {
+                    if self.waiting() {
+                        eprintln!("Timed out after {:.1}s", self.timeout.as_secs_f64());
+                        return EXIT_TIMED_OUT;
+                    }
+                    return 0;
+                },
+                Event::ListenerFailed { error, .. } => {
+                    eprintln!("Stopping: {}", error);
+                    return EXIT_CLOSED;
+                },
+                _ => continue,
+            };
+
+            let text = ansi::parse(&text).text;
+            if writeln!(out, "{}", text).and_then(|_| out.flush()).is_err() {
+                // Whoever was reading has gone, so there's no one to tell.
+                return EXIT_CLOSED;
+            }
+            if self.expected(&text) {
+                // Finishing on an --expect is the end of it; finishing on a --send isn't, until
+                // the server's had its say.
+                match self.send_until_expect(&mut tcp.borrow_mut(), which) {
+                    Ok(false) if self.steps.is_empty() => return 0,
+                    Ok(_) => (),
+                    Err(e) => {
+                        eprintln!("Couldn't send: {}", e);
+                        return EXIT_CLOSED;
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn batch_args() {
+    let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    let exchange = Exchange::from_args(args(&["--world", "localhost:4000", "--send", "who",
+                                              "--expect", "players$", "--timeout", "2.5"])).unwrap();
+    assert_eq!(exchange.address, "localhost:4000");
+    assert_eq!(exchange.timeout, Duration::from_millis(2500));
+    assert!(matches!(exchange.steps[0], Step::Send(ref text) if text == "who"));
+    assert!(matches!(exchange.steps[1], Step::Expect(ref pattern) if pattern.as_str() == "players$"));
+
+    assert!(Exchange::from_args(args(&["--send", "who"])).is_err());
+    assert!(Exchange::from_args(args(&["--world", "localhost:4000", "--timeout"])).is_err());
+    assert!(Exchange::from_args(args(&["--world", "localhost:4000", "--expect", "("])).is_err());
+    assert!(Exchange::from_args(args(&["--world", "localhost:4000", "--timeout", "0"])).is_err());
+}
+
+#[test]
+fn batch_exchange() {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        writer.write_all(b"Welcome!\r\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        if line.trim_end() == "who" {
+            writer.write_all(b"\x1b[1m3\x1b[0m players\r\n").unwrap();
+        }
+        thread::sleep(Duration::from_secs(5));
+    });
+
+    let args = vec!["--world".to_string(), address, "--send".to_string(), "who".to_string(),
+                    "--expect".to_string(), r"^\d+ players$".to_string(), "--timeout".to_string(), "5".to_string()];
+    let mut out = vec![];
+    assert_eq!(Exchange::from_args(args).unwrap().run(&mut out), 0);
+    assert_eq!(String::from_utf8(out).unwrap(), "Welcome!\n3 players\n");
+}
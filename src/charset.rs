@@ -0,0 +1,267 @@
+use std::env;
+use std::io::{self, Read};
+
+/// Character sets we know how to decode into Rust's (UTF-8) strings.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Charset {
+    Utf8,
+    /// ISO-8859-1
+    Latin1,
+    /// ISO-8859-15, which is Latin-1 with a euro sign and a few other changes.
+    Latin9,
+    /// Windows-1252, which is Latin-1 with printable characters where the C1 controls should be.
+    Cp1252,
+    /// The IBM PC's code page 437.
+    Cp437,
+}
+
+// Windows-1252's characters for 0x80-0x9F.  The five holes in it are mapped to the C1 control
+// characters with the same value, same as Windows does.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+// CP437's characters for 0x80-0xFF: the IBM PC's accents, box drawing and so on, which some
+// MUDs still draw their maps with.  (0x00-0x7F is taken as ASCII, not the PC's smiley faces,
+// since the controls are needed for what they are.)
+const CP437_HIGH: [char; 128] = [
+    '\u{00C7}', '\u{00FC}', '\u{00E9}', '\u{00E2}', '\u{00E4}', '\u{00E0}', '\u{00E5}', '\u{00E7}',
+    '\u{00EA}', '\u{00EB}', '\u{00E8}', '\u{00EF}', '\u{00EE}', '\u{00EC}', '\u{00C4}', '\u{00C5}',
+    '\u{00C9}', '\u{00E6}', '\u{00C6}', '\u{00F4}', '\u{00F6}', '\u{00F2}', '\u{00FB}', '\u{00F9}',
+    '\u{00FF}', '\u{00D6}', '\u{00DC}', '\u{00A2}', '\u{00A3}', '\u{00A5}', '\u{20A7}', '\u{0192}',
+    '\u{00E1}', '\u{00ED}', '\u{00F3}', '\u{00FA}', '\u{00F1}', '\u{00D1}', '\u{00AA}', '\u{00BA}',
+    '\u{00BF}', '\u{2310}', '\u{00AC}', '\u{00BD}', '\u{00BC}', '\u{00A1}', '\u{00AB}', '\u{00BB}',
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{2561}', '\u{2562}', '\u{2556}',
+    '\u{2555}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255D}', '\u{255C}', '\u{255B}', '\u{2510}',
+    '\u{2514}', '\u{2534}', '\u{252C}', '\u{251C}', '\u{2500}', '\u{253C}', '\u{255E}', '\u{255F}',
+    '\u{255A}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256C}', '\u{2567}',
+    '\u{2568}', '\u{2564}', '\u{2565}', '\u{2559}', '\u{2558}', '\u{2552}', '\u{2553}', '\u{256B}',
+    '\u{256A}', '\u{2518}', '\u{250C}', '\u{2588}', '\u{2584}', '\u{258C}', '\u{2590}', '\u{2580}',
+    '\u{03B1}', '\u{00DF}', '\u{0393}', '\u{03C0}', '\u{03A3}', '\u{03C3}', '\u{00B5}', '\u{03C4}',
+    '\u{03A6}', '\u{0398}', '\u{03A9}', '\u{03B4}', '\u{221E}', '\u{03C6}', '\u{03B5}', '\u{2229}',
+    '\u{2261}', '\u{00B1}', '\u{2265}', '\u{2264}', '\u{2320}', '\u{2321}', '\u{00F7}', '\u{2248}',
+    '\u{00B0}', '\u{2219}', '\u{00B7}', '\u{221A}', '\u{207F}', '\u{00B2}', '\u{25A0}', '\u{00A0}',
+];
+
+impl Charset {
+    /// Look up a charset by any of its usual names, e.g. "UTF-8", "utf8", "ISO-8859-1", "latin1".
+    pub fn from_name(name: &str) -> Option<Charset> {
+        let name: String = name.chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_lowercase();
+
+        match name.as_str() {
+            "utf8" => Some(Charset::Utf8),
+            "iso88591" | "latin1" | "l1" => Some(Charset::Latin1),
+            "iso885915" | "latin9" | "l9" => Some(Charset::Latin9),
+            "cp1252" | "windows1252" => Some(Charset::Cp1252),
+            "cp437" | "ibm437" | "437" => Some(Charset::Cp437),
+            _ => None,
+        }
+    }
+
+    /// Guess what charset the user's terminal is using from the locale, e.g. "de_DE.ISO-8859-1"
+    /// or "de_DE@euro".  Anything we don't understand (including plain "C") is assumed to be
+    /// UTF-8, since that's what nearly every terminal speaks these days anyway.
+    pub fn from_locale() -> Charset {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+
+        // language_TERRITORY.codeset@modifier -- we want the codeset.
+        let (without_modifier, modifier) = match locale.find('@') {
+            Some(i) => (&locale[..i], &locale[i + 1..]),
+            None => (locale.as_str(), ""),
+        };
+        match without_modifier.find('.') {
+            Some(i) => Charset::from_name(&without_modifier[i + 1..]).unwrap_or(Charset::Utf8),
+            None if modifier == "euro" => Charset::Latin9,
+            None => Charset::Utf8,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Latin1 => "ISO-8859-1",
+            Charset::Latin9 => "ISO-8859-15",
+            Charset::Cp1252 => "CP1252",
+            Charset::Cp437 => "CP437",
+        }
+    }
+
+    /// Decode some text that's all there (like a whole line), so there's nothing to hold on to
+    /// for later, unlike with a Decoder.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            _ => bytes.iter().map(|b| self.decode_byte(*b)).collect(),
+        }
+    }
+
+    /// Encode text for sending somewhere that expects this charset.  Characters it doesn't have
+    /// come out as '?'.
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        if self == Charset::Utf8 {
+            return text.as_bytes().to_vec();
+        }
+        text.chars()
+            .map(|c| match c as u32 {
+                0..=0x7F => c as u8,
+                _ => (0x80..=0xFF).find(|b| self.decode_byte(*b) == c).unwrap_or(b'?'),
+            })
+            .collect()
+    }
+
+    /// Decode one byte of a single-byte charset.  Don't call this for UTF-8.
+    fn decode_byte(self, b: u8) -> char {
+        match self {
+            Charset::Latin1 => b as char,
+            Charset::Latin9 => match b {
+                0xA4 => '\u{20AC}',
+                0xA6 => '\u{0160}',
+                0xA8 => '\u{0161}',
+                0xB4 => '\u{017D}',
+                0xB8 => '\u{017E}',
+                0xBC => '\u{0152}',
+                0xBD => '\u{0153}',
+                0xBE => '\u{0178}',
+                _ => b as char,
+            },
+            Charset::Cp1252 => match b {
+                0x80..=0x9F => CP1252_HIGH[(b - 0x80) as usize],
+                _ => b as char,
+            },
+            Charset::Cp437 => match b {
+                0x80..=0xFF => CP437_HIGH[(b - 0x80) as usize],
+                _ => b as char,
+            },
+            Charset::Utf8 => panic!("decode_byte() called for UTF-8"),
+        }
+    }
+}
+
+/// Decodes a stream of bytes in some charset, a chunk at a time.  Multi-byte characters split
+/// across chunks are held on to until the rest of them arrives; bytes that aren't valid in the
+/// charset come out as U+FFFD.
+pub struct Decoder {
+    charset: Charset,
+    partial: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new(charset: Charset) -> Decoder {
+        Decoder { charset, partial: vec![] }
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        if self.charset != Charset::Utf8 {
+            return self.charset.decode(bytes);
+        }
+
+        self.partial.extend_from_slice(bytes);
+        let mut out = String::new();
+        loop {
+            match std::str::from_utf8(&self.partial) {
+                Ok(valid) => {
+                    out.push_str(valid);
+                    self.partial.clear();
+                    break;
+                },
+                Err(e) => {
+                    let good = e.valid_up_to();
+                    // Can unwrap() since from_utf8 just told us this much is valid.
+                    out.push_str(std::str::from_utf8(&self.partial[..good]).unwrap());
+                    match e.error_len() {
+                        Some(bad) => {
+                            out.push('\u{FFFD}');
+                            self.partial.drain(..good + bad);
+                        },
+                        None => {
+                            // The end of the buffer is the start of a character we don't have
+                            // all of yet.
+                            self.partial.drain(..good);
+                            break;
+                        },
+                    }
+                },
+            }
+        }
+        out
+    }
+}
+
+/// Wraps a reader producing text in some charset into one producing UTF-8, so that things which
+/// only understand UTF-8 (like termion's key parsing) can read it.
+pub struct DecodingReader<R: Read> {
+    inner: R,
+    decoder: Decoder,
+    pending: Vec<u8>,
+}
+
+impl<R: Read> DecodingReader<R> {
+    pub fn new(inner: R, charset: Charset) -> DecodingReader<R> {
+        DecodingReader {
+            inner,
+            decoder: Decoder::new(charset),
+            pending: vec![],
+        }
+    }
+
+    /// Whether there's decoded text waiting that a read() would return without touching the
+    /// inner reader.
+    pub fn has_buffered(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Only go back to the inner reader when we've nothing left to hand out; some callers
+        // (termion, for one) care about whether a read would block.
+        while self.pending.is_empty() {
+            let mut raw = [0u8; 64];
+            let n = self.inner.read(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            self.pending.extend_from_slice(self.decoder.decode(&raw[..n]).as_bytes());
+        }
+
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+#[test]
+fn decode_charsets() {
+    let mut latin1 = Decoder::new(Charset::Latin1);
+    assert_eq!(latin1.decode(b"caf\xe9"), "café");
+
+    let mut cp1252 = Decoder::new(Charset::Cp1252);
+    assert_eq!(cp1252.decode(b"\x93hi\x94 \x80"), "\u{201C}hi\u{201D} €");
+
+    // A two-byte UTF-8 character split across two chunks, then a stray continuation byte.
+    let mut utf8 = Decoder::new(Charset::Utf8);
+    assert_eq!(utf8.decode(b"caf\xc3"), "caf");
+    assert_eq!(utf8.decode(b"\xa9!\x80"), "é!\u{FFFD}");
+
+    assert_eq!(Charset::from_name("ISO-8859-15"), Some(Charset::Latin9));
+}
+
+#[test]
+fn encode_charsets() {
+    assert_eq!(Charset::Cp437.decode(b"\xc9\xcd\xbb caf\x82"), "╔═╗ café");
+    assert_eq!(Charset::Cp437.encode("╚═╝ café"), b"\xc8\xcd\xbc caf\x82");
+    assert_eq!(Charset::Latin1.encode("café €"), b"caf\xe9 ?");
+    assert_eq!(Charset::Latin9.encode("€"), b"\xa4");
+    assert_eq!(Charset::Utf8.decode(b"caf\xc3"), "caf\u{FFFD}");
+    assert_eq!(Charset::from_name("IBM437"), Some(Charset::Cp437));
+}
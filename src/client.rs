@@ -0,0 +1,2974 @@
+//! The client itself: the EventSources, the sessions, and what's done with each event.
+//!
+//! main.rs makes one of these and runs it, but anything else can too, e.g. to test the whole
+//! client against a pretend MUD, or to put a different front on it.  Handlers added with
+//! add_handler get a look at each event before the client does, and can keep it to themselves;
+//! commands added with register_command work like the built in ones.
+
+use crate::meta::*;
+use crate::error::MintError;
+use crate::events::ThreadedManager;
+
+use crate::net::{ConnectionInterface, ConnectionID, ErrorClass, telnet, tcp::TcpConnectionManager};
+use crate::net::process::{self, ProcessConnectionManager};
+use crate::net::mxp::LinkAction;
+use crate::ui::{UserInterface, Lines, DEBUG_WINDOW, SHOWKEY_SEGMENT, style, ansi::Color, term::{self, TermUiManager, layout, picker}};
+use crate::world::{self, World, KeyMacro, IdlePolicy};
+use crate::session::{Session, Sessions};
+use crate::line::Line;
+use crate::log::{self, LogFormat, SessionLog, WindowLogs};
+use crate::timer::{TimerID, Timers};
+use crate::action::{self, ActionID, ActionPool, Job};
+use crate::alarm::{self, Alarms};
+use crate::command::{self, Command, Dispatcher};
+use crate::charset::Charset;
+use crate::config;
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Export, Snapshot, Traffic};
+use crate::demo::{self, Demo, Step};
+use crate::selftest;
+use crate::notes;
+use crate::layouts::{self, SavedLayout};
+use crate::repeat::{self, Repeats};
+use crate::ticker::{Ticker, Tock};
+use crate::clock::{self, LocalTime};
+use crate::script::{self, dice, expr};
+use crate::script::alias::Aliases;
+use crate::script::gag::{self, Gags};
+use crate::script::template::Templates;
+use crate::script::vars::Variables;
+use crate::script::trigger::{self, Trigger, Triggers, Response, Fired};
+use crate::script::lua::{Scripts, ScriptAction};
+
+use regex::Regex;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::{cell::RefCell, rc::Rc};
+
+/// The status bar segment that says how long the world in front has been connected.
+const UPTIME_SEGMENT: &str = "uptime";
+/// The status bar segment counting down to the next tick, if there's a ticker.
+const TICK_SEGMENT: &str = "tick";
+/// The status bar segment listing the worlds whose servers have gone quiet (see IdlePolicy.)
+const IDLE_SEGMENT: &str = "quiet";
+
+fn wrap<T>(x: T) -> Rc<RefCell<T>> {
+    Rc::new(RefCell::new(x))
+}
+
+/// What a handler's done with an event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Let the next handler (and then the client) have it as well.
+    Continue,
+    /// That's the end of it; nothing else sees it.
+    Handled,
+}
+
+/// Something to look at each event before the client does (see Client::add_handler.)
+pub type EventHandler = Box<dyn FnMut(&mut Client, &Event) -> Flow>;
+
+/// Everything that isn't specific to a connection: handles on the EventSources, plus the
+/// sessions.
+pub struct Client {
+    pub manager: ThreadedManager,
+    pub tcp: Rc<RefCell<TcpConnectionManager>>,
+    pub processes: Rc<RefCell<ProcessConnectionManager>>,
+    pub tui: Rc<RefCell<TermUiManager>>,
+    pub timers: Rc<RefCell<Timers>>,
+    actions: Rc<RefCell<ActionPool>>,
+    // The jobs triggers have given the ActionPool, with the connection whose line set each one
+    // off and the window (if any) what it prints goes in.
+    trigger_jobs: HashMap<ActionID, (ConnectionID, Option<String>)>,
+    alarms: Alarms,
+    repeats: Repeats,
+    // The ticker (see ticker.rs), if there is one, and the timer for its next look at the clock.
+    ticker: Option<(Ticker, TimerID)>,
+    pub sessions: Sessions,
+    aliases: Aliases,
+    triggers: Triggers,
+    // Gags, and whether gagged lines are going to the review window as well as being kept.
+    gags: Gags,
+    reviewing_gags: bool,
+    scripts: Scripts,
+    vars: Variables,
+    templates: Templates,
+    commands: Dispatcher<Client>,
+    window_logs: WindowLogs,
+    // Ticks once a minute while anything's connected, to keep the uptime in the status bar right.
+    uptime_timer: Option<TimerID>,
+    // The demo being played (see demo.rs), if there is one, and the timer for the end of the
+    // pause it's in.
+    demo: Option<Demo>,
+    demo_timer: Option<TimerID>,
+    // Where metrics are going (see metrics.rs), and the timer for writing them next.
+    #[cfg(feature = "metrics")]
+    metrics: Option<(Export, TimerID)>,
+    // Whatever's embedding the client gets a look at each event first (see add_handler.)
+    handlers: Vec<EventHandler>,
+    quitting: bool,
+}
+
+impl Client {
+    /// A client with all its EventSources started, and nothing connected.  Only the demo, if
+    /// there is one, has to be decided up front.
+    pub fn new(demo: Option<Demo>) -> Client {
+        Client::with_ui(demo, TermUiManager::new())
+    }
+
+    /// The same, with a UI made already, e.g. a headless one (see TermUiManager::headless.)
+    pub fn with_ui(demo: Option<Demo>, tui: TermUiManager) -> Client {
+        let mut manager = ThreadedManager::new();
+
+        let tcp = wrap(TcpConnectionManager::new());
+        manager.start_source(tcp.clone());
+        let processes = wrap(ProcessConnectionManager::new());
+        manager.start_source(processes.clone());
+
+        let tui = wrap(tui);
+        manager.start_source(tui.clone());
+
+        let timers = wrap(Timers::new());
+        manager.start_source(timers.clone());
+        let actions = wrap(ActionPool::new());
+        manager.start_source(actions.clone());
+
+        let alarms = Alarms::load(config::ensure_config_dir().map(|d| d.join("alarms")),
+                                  &mut timers.borrow_mut());
+
+        let client = Client {
+            manager,
+            sessions: Sessions::new(),
+            aliases: Aliases::new(),
+            triggers: Triggers::new(),
+            gags: Gags::new(),
+            reviewing_gags: false,
+            scripts: Scripts::new().expect("Couldn't start Lua"),
+            vars: Variables::new(),
+            templates: Templates::new(),
+            commands: commands(),
+            window_logs: WindowLogs::new(),
+            tcp,
+            processes,
+            tui,
+            timers,
+            actions,
+            trigger_jobs: HashMap::new(),
+            alarms,
+            repeats: Repeats::new(),
+            ticker: None,
+            uptime_timer: None,
+            demo,
+            demo_timer: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            handlers: vec![],
+            quitting: false,
+        };
+
+        client.tui.borrow_mut().set_command_names(client.commands.names());
+        client
+    }
+
+    /// Load the scripts and key bindings from the config directory.  Scripts go before anything's
+    /// connected, so that their triggers are there for the first lines from the server.
+    pub fn load_config(&mut self) {
+        if let Some(dir) = config::config_dir() {
+            let errors = self.scripts.load_dir(&dir.join("scripts"));
+            self.echo_all(errors);
+            self.script_actions(None);
+            self.load_key_bindings(&dir.join("keys"));
+        }
+    }
+
+    /// Have `handler` look at every event from now on, before the client (and after any handlers
+    /// added before it.)
+    pub fn add_handler(&mut self, handler: EventHandler) {
+        self.handlers.push(handler);
+    }
+
+    /// Add a command, or replace one of the built in ones.
+    pub fn register_command(&mut self, name: &str, usage: &'static str, help: &'static str,
+                            handler: fn(&mut Client, &str) -> Result<(), String>) {
+        self.commands.register(name, usage, help, handler);
+        self.tui.borrow_mut().set_command_names(self.commands.names());
+    }
+
+    /// Whether a QuitRequest has come through.
+    pub fn quitting(&self) -> bool {
+        self.quitting
+    }
+
+    /// Wait for the next event and deal with it.
+    pub fn step(&mut self) -> Result<(), MintError> {
+        let event = self.manager.next_event()?;
+        // Scripts hearing about activity might well write to windows out of sight themselves, and
+        // that mustn't be more activity for them to hear about, round and round.
+        let about_activity = matches!(event, Event::WindowActivity { .. });
+        self.handle(event);
+        let activity = self.tui.borrow_mut().take_activity();
+        if !about_activity {
+            for (window, unread) in activity {
+                self.manager.post(Event::WindowActivity { window, unread });
+            }
+        }
+        Ok(())
+    }
+
+    /// Deal with events until there's a QuitRequest, or until there's no getting any more.
+    pub fn run(&mut self) -> Result<(), MintError> {
+        while !self.quitting {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Deal with one event: the handlers first, then the client, unless a handler's kept it.
+    pub fn handle(&mut self, event: Event) {
+        // The handlers are taken out while they run, so they can have the client to themselves.
+        let mut handlers = std::mem::take(&mut self.handlers);
+        let handled = handlers.iter_mut().any(|handler| handler(self, &event) == Flow::Handled);
+        handlers.append(&mut self.handlers);
+        self.handlers = handlers;
+        if handled {
+            return;
+        }
+
+        match event {
+            Event::ServerText { line: l, which, links } => {
+                self.heard_from(which);
+                let mut line = Line::server(&l, which);
+                line.links = links;
+                for fired in self.triggers.check(&mut line, &self.vars) {
+                    match fired {
+                        Fired::Command(text) => self.command(&text),
+                        Fired::Send(text) => self.send_line(which, text),
+                        Fired::Event { name, captures } => {
+                            self.manager.post(Event::TriggerFired { name, which, captures });
+                        },
+                        Fired::Copy(text) => self.tui.borrow_mut().copy(text),
+                        Fired::Clear(window) => self.tui.borrow_mut().clear_window(&window),
+                        Fired::Echo { window, text } => {
+                            self.used_window(Some(which), &window);
+                            self.show(window, Line::client(&text));
+                        },
+                        Fired::Rename { window, to } => {
+                            if let Err(e) = self.rename_window(window, to) {
+                                self.echo(e);
+                            }
+                        },
+                        Fired::Complete { list, word } => {
+                            if let Some(session) = self.sessions.by_connection(which) {
+                                self.tui.borrow_mut().add_completion(&session.window, &list, &word);
+                            }
+                        },
+                        Fired::Shell { command, limit, window } => {
+                            let id = self.actions.borrow_mut().submit(Job::Shell(command), limit);
+                            self.trigger_jobs.insert(id, (which, window));
+                        },
+                    }
+                }
+                let errors = self.scripts.line(line.text());
+                self.echo_all(errors);
+                self.script_actions(Some(which));
+                let (window, next) = match self.sessions.by_connection(which) {
+                    Some(session) => {
+                        if let Some(ref mut log) = session.log {
+                            if let Err(e) = log.server_line(&line) {
+                                self.tui.borrow_mut().push_to_window(session.window.clone(),
+                                        Line::client(&format!("Couldn't write to log: {}", e))).unwrap();
+                            }
+                        }
+                        (session.window.clone(), session.stack.server_line(line.text()))
+                    },
+                    None => ("default".to_string(), None),
+                };
+                if let Some(next) = next {
+                    self.send_line(which, next);
+                }
+                if let Some(summary) = self.gags.check(&window, &mut line) {
+                    self.show(window.clone(), summary);
+                }
+                if line.gag {
+                    let mut shown = line.clone();
+                    shown.gag = false;
+                    if self.reviewing_gags {
+                        self.show(gag::REVIEW_WINDOW.to_string(), shown.clone());
+                    }
+                    self.gags.keep(shown);
+                }
+                self.show(window, line);
+            },
+            Event::ConnectionStart { which } => {
+                if let Some(session) = self.sessions.by_connection(which) {
+                    let mut tui = self.tui.borrow_mut();
+                    tui.set_window_status(session.window.clone(), format!("{}: connected", session.world.name));
+                    tui.push_to_window(session.window.clone(),
+                            Line::client(&format!("Connected to {}.", session.world.address))).unwrap();
+                    session.heard(Instant::now());
+                }
+                self.watch_idle(which);
+                self.show_quiet();
+                self.show_uptime();
+            },
+            Event::Reconnecting { which, reason, attempt, delay, .. } => {
+                // The session stays, since it'll be the same connection again if this works.
+                if let Some(session) = self.sessions.by_connection(which) {
+                    let mut tui = self.tui.borrow_mut();
+                    session.server_echo = false;
+                    session.idle = false;
+                    if let Some(timer) = session.idle_timer.take() {
+                        self.timers.borrow_mut().cancel(timer);
+                    }
+                    tui.set_input_masked(session.window.clone(), false);
+                    tui.set_window_status(session.window.clone(),
+                            format!("{}: reconnecting in {}s", session.world.name, delay.as_secs()));
+                    tui.push_to_window(session.window.clone(), Line::client(&format!(
+                            "Lost {}: {}.  Reconnecting in {}s (try {})...",
+                            session.world.address, reason, delay.as_secs(), attempt))).unwrap();
+                }
+                self.show_quiet();
+                self.show_uptime();
+            },
+            Event::ConnectionEnd { which, reason, error } => {
+                // Keep the window around, with what was said in it.
+                if let Some(session) = self.sessions.remove(which) {
+                    self.save_layout(&session);
+                    let mut tui = self.tui.borrow_mut();
+                    if let Some(timer) = session.idle_timer {
+                        self.timers.borrow_mut().cancel(timer);
+                    }
+                    tui.set_input_masked(session.window.clone(), false);
+                    tui.set_window_status(session.window.clone(), format!("{}: disconnected", session.world.name));
+                    let mut message = format!("Disconnected from {}: {}", session.world.address, reason);
+                    let policy = &session.world.reconnect;
+                    if let (true, Some(error)) = (policy.enabled, error) {
+                        if !policy.after.contains(&error) {
+                            message.push_str(&format!(" (not reconnecting after {} errors.)", error));
+                        }
+                    }
+                    tui.push_to_window(session.window, Line::client(&message)).unwrap();
+                }
+                self.show_quiet();
+                self.show_uptime();
+            },
+            Event::QuitRequest => {
+                for session in self.sessions.iter() {
+                    self.save_layout(session);
+                }
+                self.quitting = true;
+            },
+            Event::UserInput { line, which } => {
+                self.user_line(which, line);
+            }
+            Event::UserCommand { cmd } => self.run_command(cmd),
+            Event::KeyPress { key, which } => {
+                let bound = self.session_for(which)
+                    .and_then(|s| s.world.keys.get(&key).cloned().map(|m| (s.cid, m)));
+                match bound {
+                    Some((_, KeyMacro::Command(line))) => self.user_line(which, line),
+                    Some((cid, KeyMacro::Send(text))) => self.send_line(cid, text),
+                    None => { },
+                }
+            }
+            Event::LinkFollowed { action: LinkAction::Send(command), which } => {
+                // Straight to the server: commands and aliases are for what the user types, not
+                // for what the server would like typed.
+                match self.session_for(which).map(|session| session.cid) {
+                    Some(cid) => self.send_line(cid, command),
+                    None => self.echo("Not connected to anything.".to_string()),
+                }
+            },
+            Event::LinkFollowed { action: LinkAction::Url(url), .. } => {
+                self.tui.borrow_mut().copy(url.clone());
+                self.echo(format!("Copied {} to the clipboard.", url));
+            },
+            Event::KeyShown { key, name, builtin, which } => {
+                let bound = match (self.session_for(which), name.as_ref()) {
+                    (Some(session), Some(n)) => session.world.keys.get(n).cloned(),
+                    _ => None,
+                };
+                let action = match (builtin, bound) {
+                    (Some(builtin), _) => format!("built in: {}", builtin),
+                    (None, Some(KeyMacro::Command(line))) => format!("runs {}", line),
+                    (None, Some(KeyMacro::Send(text))) => format!("sends {}", text),
+                    (None, None) if name.is_some() => "not bound".to_string(),
+                    (None, None) => "can't be bound".to_string(),
+                };
+                let text = match name {
+                    Some(name) => format!("{} is {} ({})", key, name, action),
+                    None => format!("{} ({})", key, action),
+                };
+                self.tui.borrow_mut().set_status(SHOWKEY_SEGMENT.to_string(), text);
+            }
+            Event::TriggerFired { name, which, captures } => {
+                let errors = self.scripts.event(&name, &captures);
+                self.echo_all(errors);
+                self.script_actions(Some(which));
+            }
+            Event::Compression { which, ours, enabled } => {
+                if let Some(session) = self.sessions.by_connection(which) {
+                    let text = match (ours, enabled) {
+                        (false, true) => "The server's compressing what it sends.",
+                        (false, false) => "The server's stopped compressing what it sends.",
+                        (true, true) => "Compressing what we send.",
+                        (true, false) => "Stopped compressing what we send.",
+                    };
+                    self.tui.borrow_mut().push_to_window(session.window.clone(), Line::client(text)).unwrap();
+                }
+            },
+            Event::Charset { which, charset } => {
+                if let Some(session) = self.sessions.by_connection(which) {
+                    let text = format!("The server's text is in {}.", charset.name());
+                    self.tui.borrow_mut().push_to_window(session.window.clone(), Line::client(&text)).unwrap();
+                }
+            },
+            Event::TelnetOption { which, option: telnet::opt::MSDP, ours: false, enabled: true } => {
+                if let Some(session) = self.sessions.by_connection(which) {
+                    if let Err(e) = self.tcp.borrow_mut().msdp_report(which, &session.world.msdp) {
+                        self.tui.borrow_mut().push_to_window(session.window.clone(), Line::client(&e)).unwrap();
+                    }
+                }
+            },
+            Event::MsdpUpdate { which, variable, value: _ } => {
+                let shown = self.sessions.by_connection(which)
+                    .is_some_and(|session| session.world.msdp.contains(&variable));
+                if shown {
+                    self.show_msdp(which);
+                }
+            },
+            Event::ServerPrompt { prompt, which } => {
+                self.heard_from(which);
+                // The prompt goes on a line of its own right away, rather than waiting for
+                // whatever the server says next to finish it off.
+                let (window, next) = match self.sessions.by_connection(which) {
+                    Some(session) => {
+                        if let (Some(ref mut log), false) = (&mut session.log, prompt.is_empty()) {
+                            if let Err(e) = log.server_line(&Line::server(&prompt, which)) {
+                                self.tui.borrow_mut().push_to_window(session.window.clone(),
+                                        Line::client(&format!("Couldn't write to log: {}", e))).unwrap();
+                            }
+                        }
+                        (session.window.clone(), session.stack.prompt_marked())
+                    },
+                    None => ("default".to_string(), None),
+                };
+                if let Some(next) = next {
+                    self.send_line(which, next);
+                }
+                if !prompt.is_empty() {
+                    self.show(window, Line::server(&prompt, which));
+                }
+            },
+            Event::TelnetOption { which, option: telnet::opt::SGA, ours: false, enabled: true } => {
+                // No more GAs, so prompts have to be spotted by what they look like again.
+                if let Some(session) = self.sessions.by_connection(which) {
+                    session.stack.prompts_unmarked();
+                }
+            },
+            Event::ServerEcho { which, enabled } => {
+                if let Some(session) = self.sessions.by_connection(which) {
+                    session.server_echo = enabled;
+                    self.tui.borrow_mut().set_input_masked(session.window.clone(), enabled);
+                }
+            },
+            Event::TelnetOption { .. } | Event::TelnetSubnegotiation { .. } => {
+                // The telnet layer has already answered the server; nothing else needs to know
+                // about these yet.
+            }
+            Event::TimerFired { id } if self.demo_timer == Some(id) => {
+                self.demo_timer = None;
+                self.play_demo();
+            },
+            #[cfg(feature = "metrics")]
+            Event::TimerFired { id } if self.metrics.as_ref().map(|m| m.1) == Some(id) => self.write_metrics(),
+            Event::TimerFired { id } if self.ticker.as_ref().map(|t| t.1) == Some(id) => self.check_ticker(),
+            Event::TimerFired { id } if self.sessions.iter().any(|s| s.idle_timer == Some(id)) => self.check_idle(id),
+            Event::ServerIdle { which, quiet } => {
+                if let Some(session) = self.sessions.by_connection(which) {
+                    let name = session.world.name.clone();
+                    let since = LocalTime::at(SystemTime::now() - quiet);
+                    let mut tui = self.tui.borrow_mut();
+                    tui.set_window_status(session.window.clone(), format!("{}: quiet since {}", name, since.hm()));
+                    tui.push_to_window(session.window.clone(), Line::client(&format!(
+                            "Nothing from {} for {}.", session.world.address, clock::short_duration(quiet)))).unwrap();
+                    if session.world.idle.notify {
+                        tui.notify(format!("{} has gone quiet", name));
+                    }
+                    drop(tui);
+                    self.show_quiet();
+                    let errors = self.scripts.event("server-idle", &[name, quiet.as_secs().to_string()]);
+                    self.echo_all(errors);
+                    self.script_actions(Some(which));
+                }
+            },
+            Event::WindowActivity { window, unread } => {
+                let errors = self.scripts.event("window-activity", &[window, unread.to_string()]);
+                self.echo_all(errors);
+                self.script_actions(None);
+            },
+            Event::TimerFired { id } if self.uptime_timer == Some(id) => {
+                self.uptime_timer = None;
+                self.show_uptime();
+            },
+            Event::TimerFired { id } => {
+                let go = self.repeats.fired(&mut self.timers.borrow_mut(), id);
+                if let Some((which, line)) = go {
+                    self.user_line(which, line);
+                }
+                if let Some(alarm) = self.alarms.fired(id) {
+                    self.echo(format!("Alarm ({}): {}", alarm.local_time().hm(), alarm.message));
+                    self.tui.borrow_mut().notify(alarm.message);
+                }
+            }
+            Event::ActionDone { id, result } => {
+                let output = match result {
+                    Ok(output) => output,
+                    Err(e) => {
+                        self.trigger_jobs.remove(&id);
+                        return self.echo(e);
+                    },
+                };
+                // A trigger's goes where the trigger said, and is only ever sent as text, never run
+                // as commands; /sh's is just shown.
+                for line in output.lines() {
+                    match self.trigger_jobs.get(&id).cloned() {
+                        Some((_, Some(window))) => {
+                            self.used_window(None, &window);
+                            self.show(window, Line::client(line));
+                        },
+                        Some((which, None)) => self.send_line(which, line.to_string()),
+                        None => self.echo(line.to_string()),
+                    }
+                }
+                self.trigger_jobs.remove(&id);
+            }
+            Event::SourceStalled { source, listener } => {
+                let name = self.manager.threads().get(listener)
+                    .map_or("?".to_string(), |t| t.name.clone());
+                self.echo(format!("Warning: thread {} (of event source {}) has stopped \
+                                     responding; some things may not work until it's restarted \
+                                     with /threads restart {}.", name, source, listener));
+            }
+            Event::ListenerFailed { source, listener, error } => {
+                let thread = self.manager.threads().get(listener).cloned();
+                let name = thread.as_ref().map_or("?".to_string(), |t| t.name.clone());
+                match thread.map(|t| t.status) {
+                    Some(ThreadStatus::Running) => {
+                        self.echo(format!("Error: thread {} (of event source {}) stopped: {}  It's been \
+                                             started again.", name, source, error));
+                    },
+                    _ => self.echo(format!("Error: thread {} (of event source {}) has stopped: {}  Some \
+                                              things won't work until you restart.", name, source, error)),
+                }
+            }
+            Event::SourceDied { id, reason } => {
+                self.echo(format!("Error: event source {} has stopped for good ({}), and nothing more \
+                                     will come from it until you restart.", id, reason));
+            }
+        }
+    }
+
+    /// Show the user a message from the client itself, in whatever window they're looking at.
+    pub fn echo(&self, text: String) {
+        let mut tui = self.tui.borrow_mut();
+        let window = tui.active_window();
+        tui.push_to_window(window, Line::client(&text)).unwrap();
+    }
+
+    /// Put a line in a window, logging it first if the window's being logged.
+    pub fn show(&mut self, window: String, line: Line) {
+        if let Err(e) = self.window_logs.line(&window, &line) {
+            self.window_logs.stop(&window);
+            self.echo(format!("Couldn't write to the log for window {}, so stopped logging it: {}", window, e));
+        }
+        self.tui.borrow_mut().push_to_window(window, line).unwrap();
+    }
+
+    /// Give a window a new name, taking its session (if it has one) and its log along with it.
+    fn rename_window(&mut self, from: String, to: String) -> Result<(), String> {
+        self.tui.borrow_mut().rename_window(from.clone(), to.clone())?;
+        for session in self.sessions.iter_mut() {
+            if session.window == from {
+                session.window = to.clone();
+            }
+            if session.windows.remove(&from) {
+                session.windows.insert(to.clone());
+            }
+        }
+        self.window_logs.rename(&from, to);
+        Ok(())
+    }
+
+    /// Which ConnectionInterface a connection belongs to.
+    fn connections(&self, cid: ConnectionID) -> Rc<RefCell<dyn ConnectionInterface>> {
+        if ProcessConnectionManager::owns(cid) {
+            self.processes.clone()
+        } else {
+            self.tcp.clone()
+        }
+    }
+
+    /// Connect to a world, with a new session whose text goes in `window`.  Worlds whose
+    /// addresses start with "exec:" are programs to run instead (see net/process.rs.)
+    pub fn connect(&mut self, world: World, window: String) {
+        let started = match process::command(&world.address) {
+            Some(_) => self.processes.borrow_mut().start_connection(world.address.clone()),
+            None => self.tcp.borrow_mut().start_connection(world.address.clone()),
+        };
+        let cid = match started {
+            Ok(cid) => cid,
+            Err(e) => return self.echo(format!("Couldn't connect to {}: {}", world.address, e)),
+        };
+        if !ProcessConnectionManager::owns(cid) {
+            self.tcp.borrow_mut().set_compression(cid, world.compress);
+            if let Some(charset) = world.charset {
+                self.tcp.borrow_mut().set_charset(cid, charset);
+            }
+            self.tcp.borrow_mut().set_reconnect(cid, world.reconnect.clone());
+        }
+
+        let windows = self.restore_layout(&world, &window);
+        let mut tui = self.tui.borrow_mut();
+        tui.set_bell_policy(window.clone(), world.bell);
+        tui.set_window_status(window.clone(), format!("{}: connecting", world.name));
+        let mut session = Session::new(world, cid, window);
+        session.windows.extend(windows);
+        if let Err(e) = session.open_log() {
+            tui.push_to_window(session.window.clone(), Line::client(&e)).unwrap();
+        }
+        self.sessions.add(session);
+    }
+
+    /// Make the windows kept from the last time `world` was connected to, and split the screen
+    /// up the way it was, unless it's split up already (by the user, or for another world.)
+    /// Returns the windows, which are the world's to keep again.
+    fn restore_layout(&mut self, world: &World, window: &str) -> Vec<String> {
+        let path = match layouts::path(&world.name) {
+            Some(path) if world.layout => path,
+            _ => return vec![],
+        };
+        let saved = match layouts::load(&path) {
+            Ok(saved) => saved.unwrap_or_default(),
+            Err(e) => {
+                self.echo(format!("Couldn't put {}'s windows back: {}", world.name, e));
+                return vec![];
+            },
+        };
+        let mut tui = self.tui.borrow_mut();
+        for name in &saved.windows {
+            tui.create_window(name.clone());
+        }
+        if *tui.layout() == layout::Layout::default() {
+            let mut panes = saved.layout;
+            // The world's window might not have the same name as last time.
+            panes.rename(&world.name, window);
+            tui.set_layout(panes);
+        }
+        saved.windows
+    }
+
+    /// Keep the windows and panes `session` has been using, for next time (see layouts.)
+    fn save_layout(&self, session: &Session) {
+        let path = match layouts::path(&session.world.name) {
+            Some(path) if session.world.layout => path,
+            _ => return,
+        };
+        let layout = self.tui.borrow().layout().clone();
+        let mut windows: Vec<String> = session.windows.iter().cloned().collect();
+        for pane in layout.windows() {
+            if pane != session.window && !windows.iter().any(|window| window == pane) {
+                windows.push(pane.to_string());
+            }
+        }
+        let mut saved = SavedLayout { windows, layout };
+        saved.layout.rename(&session.window, &session.world.name);
+        if let Err(e) = layouts::save(&path, &session.world.name, &saved) {
+            self.echo(format!("Couldn't keep {}'s windows for next time: {}", session.world.name, e));
+        }
+    }
+
+    /// Note that something for connection `cid` (a trigger, say) put things in `window`, so
+    /// it's one of the windows to remember for its world.
+    fn used_window(&mut self, cid: Option<ConnectionID>, window: &str) {
+        if window == DEBUG_WINDOW || self.sessions.by_window(window).is_some() {
+            return;
+        }
+        if let Some(session) = cid.and_then(|cid| self.sessions.by_connection(cid)) {
+            session.windows.insert(window.to_string());
+        }
+    }
+
+    /// Send a line of text to a connection, complaining in the UI if that doesn't work.
+    pub fn send_line(&mut self, cid: ConnectionID, mut line: String) {
+        let connections = self.connections(cid);
+        let session = match self.sessions.by_connection(cid) {
+            Some(session) => session,
+            None => return,
+        };
+
+        // Obviously needs more error handling too, like everything else in this program.
+        line.push('\n');
+        if let (Some(ref mut log), false) = (&mut session.log, session.server_echo) {
+            if let Err(e) = log.sent_line(&line) {
+                self.tui.borrow_mut().push_to_window(session.window.clone(),
+                        Line::client(&format!("Couldn't write to log: {}", e))).unwrap();
+            }
+        }
+        let written = connections.borrow_mut().write_to_connection(cid, line);
+        if let Err(e) = written {
+            self.tui.borrow_mut().push_to_window(session.window.clone(),
+                    Line::client(&format!("Couldn't write to connection: {}", e))).unwrap();
+        }
+    }
+
+    /// Show some error messages, e.g. from scripts.
+    pub fn echo_all(&self, errors: Vec<String>) {
+        for e in errors {
+            self.echo(e);
+        }
+    }
+
+    /// Do whatever scripts have asked for.  Lines they send go to connection `cid`, if they were
+    /// handling something from one, or else to the session in front.
+    fn script_actions(&mut self, cid: Option<ConnectionID>) {
+        for action in self.scripts.take_actions() {
+            match action {
+                ScriptAction::Send(text) => {
+                    let active = self.tui.borrow().active_window();
+                    let target = cid.or_else(|| self.sessions.for_window(&active).map(|s| s.cid));
+                    match target {
+                        Some(cid) => self.send_line(cid, text),
+                        None => self.echo("Script tried to send, but not connected to anything.".to_string()),
+                    }
+                },
+                ScriptAction::Echo { text, window: Some(window) } => {
+                    self.used_window(cid, &window);
+                    self.show(window, Line::client(&text));
+                },
+                ScriptAction::Echo { text, window: None } => self.echo(text),
+                ScriptAction::Alias { name, body } => {
+                    if let Err(e) = self.aliases.set(&name, &body) {
+                        self.echo(e);
+                    }
+                },
+                ScriptAction::Trigger(trigger) => self.triggers.add(trigger),
+                ScriptAction::Window(window) => {
+                    self.used_window(cid, &window);
+                    self.tui.borrow_mut().create_window(window);
+                },
+                ScriptAction::Clear(window) => self.tui.borrow_mut().clear_window(&window),
+                ScriptAction::Rename { window, to } => {
+                    if let Err(e) = self.rename_window(window, to) {
+                        self.echo(e);
+                    }
+                },
+                ScriptAction::Complete { list, word } => {
+                    let window = cid.and_then(|cid| self.sessions.by_connection(cid)).map(|s| s.window.clone())
+                        .unwrap_or_else(|| self.tui.borrow().active_window());
+                    self.tui.borrow_mut().add_completion(&window, &list, &word);
+                },
+                ScriptAction::Status { segment, text: Some(text) } => self.tui.borrow_mut().set_status(segment, text),
+                ScriptAction::Status { segment, text: None } => self.tui.borrow_mut().remove_status(segment),
+                ScriptAction::Repeat { every, times, line } => {
+                    let window = cid.and_then(|cid| self.sessions.by_connection(cid)).map(|s| s.window.clone())
+                        .unwrap_or_else(|| self.tui.borrow().active_window());
+                    let which = self.tui.borrow_mut().window_id(&window);
+                    self.repeats.add(&mut self.timers.borrow_mut(), which, every, times, line);
+                },
+            }
+        }
+    }
+
+    /// The session that what's typed in window `which` should go to (see Sessions::for_window.)
+    fn session_for(&mut self, which: WindowID) -> Option<&mut Session> {
+        let window = self.tui.borrow().window_name(which)
+            .unwrap_or_else(|| self.tui.borrow().active_window());
+        self.sessions.for_window(&window)
+    }
+
+    /// Deal with a line the user typed in window `which` (or something standing in for one):
+    /// either it's a client command, or it goes to the server, held back first if the world
+    /// wants commands stacked.  Aliases are expanded first.
+    ///
+    /// A line starting with "//" goes to the server with just one "/", as an escape for servers
+    /// with commands of their own that start with "/".  Worlds with client commands switched off
+    /// get every line just as it was typed, with no commands or aliases; worlds in raw send mode
+    /// still have commands, but no aliases.
+    pub fn user_line(&mut self, which: WindowID, line: String) {
+        let window = self.tui.borrow().window_name(which)
+            .unwrap_or_else(|| self.tui.borrow().active_window());
+        let (commands, raw) = match self.sessions.by_window(&window) {
+            Some(session) => (session.world.client_commands, session.world.raw_send),
+            None => (true, false),
+        };
+        if !commands {
+            return self.server_line(which, line);
+        }
+
+        if let Some(escaped) = line.strip_prefix('/').filter(|rest| rest.starts_with('/')) {
+            return self.server_line(which, escaped.to_string());
+        }
+        if line.starts_with('/') {
+            return self.command(&line);
+        }
+        if raw {
+            return self.server_line(which, line);
+        }
+        match self.aliases.expand(&line) {
+            Some(expanded) if expanded.starts_with('/') => self.command(&expanded),
+            Some(expanded) => self.server_line(which, expanded),
+            None => self.server_line(which, line),
+        }
+    }
+
+    /// Send a line the user typed in window `which` to its session's server.
+    fn server_line(&mut self, which: WindowID, line: String) {
+        let (cid, line) = match self.session_for(which) {
+            Some(session) if session.world.stack_commands => (session.cid, session.stack.push(line)),
+            Some(session) => (session.cid, Some(line)),
+            None => return self.echo("Not connected to anything (/connect host:port.)".to_string()),
+        };
+        if let Some(line) = line {
+            self.send_line(cid, line);
+        }
+    }
+
+    /// Run a client command, showing the user what went wrong if it didn't work.
+    pub fn command(&mut self, line: &str) {
+        match Command::parse(line) {
+            Some(command) => self.run_command(command),
+            None => self.echo(format!("Not a command: {}", line)),
+        }
+    }
+
+    /// Run a client command, unless it throws things away and hasn't been said yes to, in which
+    /// case the user's asked first (see Dispatcher::destructive.)
+    pub fn run_command(&mut self, mut command: Command) {
+        if let Some(question) = self.commands.ask(&mut command) {
+            command.args = format!("{} {}", command.args, command::YES).trim_start().to_string();
+            return self.tui.borrow_mut().confirm(question, command);
+        }
+        // The handler needs all of the Client, dispatcher included, so take it out first.
+        let result = self.commands.handler(&command.name)
+            .and_then(|handler| handler(self, &command.args));
+        if let Err(e) = result {
+            self.echo(e);
+        }
+    }
+
+    /// Play the demo up to its next pause, and set a timer for the end of that.
+    pub fn play_demo(&mut self) {
+        while let Some(step) = self.demo.as_mut().and_then(Demo::next_step) {
+            match step {
+                Step::Wait(pause) => {
+                    self.demo_timer = Some(self.timers.borrow_mut().schedule(Instant::now() + pause));
+                    return;
+                },
+                Step::Text { window, text } => {
+                    let window = window.unwrap_or_else(|| self.tui.borrow().active_window());
+                    self.show(window, Line::server(&text, demo::SOURCE));
+                },
+                Step::Command(line) => self.command(&line),
+            }
+        }
+        self.demo = None;
+        self.demo_timer = None;
+    }
+
+    /// Which windows a -w option means: "*" for all of them, otherwise a comma-separated list.
+    fn target_windows(&self, spec: &str) -> Vec<String> {
+        if spec == "*" {
+            self.tui.borrow().window_names()
+        } else {
+            spec.split(',').map(|w| w.trim()).filter(|w| !w.is_empty()).map(String::from).collect()
+        }
+    }
+
+    /// Put `text` in `style` into each of `windows`, complaining if the style makes no sense.
+    fn write_styled(&mut self, windows: Vec<String>, text: &str, style: &str) -> Result<(), String> {
+        let line = Line::client(&style::styled(text, style)?);
+        for window in windows {
+            self.show(window, line.clone());
+        }
+        Ok(())
+    }
+
+    /// /help -- list the commands.
+    fn cmd_help(&mut self, _args: &str) -> Result<(), String> {
+        for (usage, help) in self.commands.list() {
+            self.echo(format!("{} -- {}", usage, help));
+        }
+        Ok(())
+    }
+
+    /// /quit -- leave.
+    fn cmd_quit(&mut self, _args: &str) -> Result<(), String> {
+        self.manager.post(Event::QuitRequest);
+        Ok(())
+    }
+
+    /// /window name -- show a window.
+    fn cmd_window(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            return Err("Usage: /window name".to_string());
+        }
+        self.tui.borrow_mut().show_window(args.to_string());
+        self.show_uptime();
+        Ok(())
+    }
+
+    /// /clear-all -- empty out every window (which asks first.)
+    fn cmd_clear_all(&mut self, _args: &str) -> Result<(), String> {
+        let mut tui = self.tui.borrow_mut();
+        for window in tui.window_names() {
+            tui.clear_window(&window);
+        }
+        Ok(())
+    }
+
+    /// /mark -- put a divider with the time in the current window, for /jump to come back to.
+    fn cmd_mark(&mut self, _args: &str) -> Result<(), String> {
+        let mut tui = self.tui.borrow_mut();
+        let window = tui.active_window();
+        tui.mark(window);
+        Ok(())
+    }
+
+    /// /selftest -- check the client's parts are working (the terminal, the screen code, regexes,
+    /// the clock, the config directory) and say how it went in the selftest window.
+    fn cmd_selftest(&mut self, _args: &str) -> Result<(), String> {
+        let mut checks = self.tui.borrow().self_test();
+        checks.extend(selftest::run());
+
+        self.tui.borrow_mut().clear_window("selftest");
+        let failed = checks.iter().filter(|check| check.outcome.is_err()).count();
+        for check in checks {
+            let (verdict, detail) = match check.outcome {
+                Ok(detail) => (style::styled("ok", "bright-green")?, detail),
+                Err(detail) => (style::styled("FAIL", "bold,bright-red")?, detail),
+            };
+            let text = format!("{:<8} {} {}", check.name, verdict, detail);
+            self.show("selftest".to_string(), Line::client(&text));
+        }
+        let summary = match failed {
+            0 => "Everything's working.".to_string(),
+            n => format!("{} check(s) failed.", n),
+        };
+        self.show("selftest".to_string(), Line::client(&summary));
+        self.tui.borrow_mut().show_window("selftest".to_string());
+        Ok(())
+    }
+
+    /// /clipboard [osc52|command] -- copy by piping text into a shell command (like "xclip
+    /// -selection clipboard" or "pbcopy"), or with osc52, by asking the terminal.  With nothing,
+    /// say which.
+    fn cmd_clipboard(&mut self, args: &str) -> Result<(), String> {
+        match args.trim() {
+            "" => { },
+            "osc52" => self.tui.borrow_mut().set_copy_command(None),
+            command => self.tui.borrow_mut().set_copy_command(Some(command.to_string())),
+        }
+        let how = match self.tui.borrow().copy_command() {
+            Some(command) => format!("Copying with: {}", command),
+            None => "Copying with the terminal (OSC 52.)".to_string(),
+        };
+        self.echo(how);
+        Ok(())
+    }
+
+    /// /mouse [on|off] -- switch the mouse (scrolling, selecting, clicking on panes) on or off, or
+    /// say whether it's on.  Off leaves the mouse to the terminal.
+    fn cmd_mouse(&mut self, args: &str) -> Result<(), String> {
+        match args {
+            "on" => self.tui.borrow_mut().set_mouse(true),
+            "off" => self.tui.borrow_mut().set_mouse(false),
+            "" => { },
+            _ => return Err("Usage: /mouse [on|off]".to_string()),
+        }
+        let on = self.tui.borrow().mouse();
+        self.echo(format!("The mouse is {}.", if on { "on" } else { "off" }));
+        Ok(())
+    }
+
+    /// /esctimeout [milliseconds] -- how long an ESC waits for another key before it's taken as
+    /// the Esc key by itself, rather than Alt held down with that key.  Slow connections (e.g.
+    /// over ssh) might need longer.  With nothing, say how long it is.
+    fn cmd_esctimeout(&mut self, args: &str) -> Result<(), String> {
+        match args.parse::<u64>() {
+            Ok(ms) if ms > 0 && ms <= 2000 => self.tui.borrow_mut().set_escape_timeout(Duration::from_millis(ms)),
+            _ if args.is_empty() => { },
+            _ => return Err("Usage: /esctimeout [milliseconds] (1 to 2000)".to_string()),
+        }
+        let timeout = self.tui.borrow().escape_timeout();
+        self.echo(format!("ESC waits {}ms for another key.", timeout.as_millis()));
+        Ok(())
+    }
+
+    /// /showkey -- say what the next key is called and what it does, instead of doing it.
+    fn cmd_showkey(&mut self, _args: &str) -> Result<(), String> {
+        self.tui.borrow_mut().show_next_key();
+        Ok(())
+    }
+
+    /// /background [-w<window>] color|off -- give the window in front (or the one after -w) a
+    /// background color, like blue or bright-black, or put it back to the terminal's.
+    fn cmd_background(&mut self, args: &str) -> Result<(), String> {
+        let (opts, color) = options(args);
+        let window = opts.get(&'w').cloned().unwrap_or_else(|| self.tui.borrow().active_window());
+        let color = match color {
+            "off" => None,
+            "" => return Err("Usage: /background [-w<window>] color|off".to_string()),
+            color => Some(style::style(&format!("bg-{}", color)).map_err(|_| format!("Unknown color '{}'", color))?.bg),
+        };
+        self.tui.borrow_mut().set_background(window, color);
+        Ok(())
+    }
+
+    /// /colors -- show the colors there are for styles (see /highlight), by name, in the colors
+    /// window.
+    fn cmd_colors(&mut self, _args: &str) -> Result<(), String> {
+        let window = "colors".to_string();
+        let swatch = |n: u8| format!("\x1b[48;5;{}m  \x1b[0m", n);
+        let mut lines = vec!["The colors styles can use, by name (with bg- in front for backgrounds):".to_string()];
+        for row in 0..4 {
+            let names: Vec<String> = (row * 4..row * 4 + 4)
+                .map(|n| format!("{} {:<15}", swatch(n), style::color_name(n)))
+                .collect();
+            lines.push(names.join(" ").trim_end().to_string());
+        }
+        lines.push("color16 to color231, six levels each of red, green and blue:".to_string());
+        for start in (16..232).step_by(36) {
+            let row: String = (start..start + 36).map(|n| swatch(n as u8)).collect();
+            lines.push(format!("{:>3} {}", start, row));
+        }
+        lines.push("color232 to color255, grays:".to_string());
+        lines.push(format!("232 {}", (232..=255).map(swatch).collect::<String>()));
+        lines.push("#rrggbb works too, on terminals that can show any color.  /color-pick picks one.".to_string());
+
+        self.tui.borrow_mut().clear_window(&window);
+        for line in lines {
+            self.show(window.clone(), Line::client(&line));
+        }
+        self.tui.borrow_mut().show_window(window);
+        Ok(())
+    }
+
+    /// /color-pick highlight pattern | background [window] | timestamps -- pick a color from a
+    /// chart of them, seeing how it looks as you go, for a highlight (keeping the rest of its
+    /// style), a window's background or the timestamps.  Enter sets it, as /highlight,
+    /// /background or /timestamps would.
+    fn cmd_color_pick(&mut self, args: &str) -> Result<(), String> {
+        let usage = || "Usage: /color-pick highlight pattern | background [window] | timestamps".to_string();
+        let (what, rest) = args.split_once(' ').map_or((args, ""), |(what, rest)| (what, rest.trim()));
+        let mut tui = self.tui.borrow_mut();
+        let (command, sample, background, current) = match what {
+            "highlight" if !rest.is_empty() => {
+                let (spec, flash) = tui.highlights().into_iter().find(|(pattern, _, _)| pattern == rest)
+                    .map_or((String::new(), false), |(_, spec, flash)| (spec, flash));
+                let kept = style::without_foreground(&spec);
+                let color = if kept.is_empty() { picker::COLOR.to_string() } else { format!("{},{}", kept, picker::COLOR) };
+                let current = style::style(&spec).ok().map(|style| style.fg);
+                let flash = if flash { "-v " } else { "" };
+                (format!("/highlight {}{} = {}", flash, rest, color), rest.to_string(), false, current)
+            },
+            "background" => {
+                let window = if rest.is_empty() { tui.active_window() } else { rest.to_string() };
+                (format!("/background -w{} {}", window, picker::COLOR), window, true, None)
+            },
+            "timestamps" if rest.is_empty() => {
+                let current = tui.display().timestamps.map(|style| style.fg);
+                (format!("/timestamps {}", picker::COLOR), LocalTime::now().hm(), false, current)
+            },
+            _ => return Err(usage()),
+        };
+        let selected = match current {
+            Some(Color::Ansi(n)) | Some(Color::Indexed(n)) => n,
+            _ => 7,
+        };
+        tui.pick_color(command, sample, background, selected);
+        Ok(())
+    }
+
+    /// /bind [key [action|-]] -- make a key (named the way /showkey names it) do one of the UI's
+    /// own actions, like delete-word, or with "-", nothing by itself.  With just a key, say what
+    /// it does; with nothing, list every binding.
+    fn cmd_bind(&mut self, args: &str) -> Result<(), String> {
+        let mut parts = args.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (None, _) => {
+                let list = self.tui.borrow().key_bindings();
+                for (key, action, what) in list {
+                    self.echo(format!("/bind {} {} ({})", key, action, what));
+                }
+            },
+            (Some(key), None) => {
+                let bound = self.tui.borrow().key_bindings().into_iter().find(|(k, _, _)| k == key);
+                let shown = match bound {
+                    Some((key, action, what)) => format!("/bind {} {} ({})", key, action, what),
+                    None => format!("{} isn't bound to anything.", key),
+                };
+                self.echo(shown);
+            },
+            (Some(key), Some("-")) => self.tui.borrow_mut().bind_key(key, None)?,
+            (Some(key), Some(action)) => self.tui.borrow_mut().bind_key(key, Some(action))?,
+        }
+        Ok(())
+    }
+
+    /// /unbind key -- the same as /bind key -.
+    fn cmd_unbind(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            return Err("Usage: /unbind key".to_string());
+        }
+        self.tui.borrow_mut().bind_key(args, None)
+    }
+
+    /// Apply the key bindings in `path`, one /bind's worth of arguments per line ("C-w
+    /// delete-word", or "C-b -" to unbind one.)  Blank lines and lines starting with # don't
+    /// count.  It's fine for the file not to be there.
+    fn load_key_bindings(&mut self, path: &Path) {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return,
+        };
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = self.cmd_bind(line) {
+                self.echo(format!("{}, line {}: {}", path.display(), n + 1, e));
+            }
+        }
+    }
+
+    /// /wall [-s<style>] [-w<windows>] text -- write a line to some windows, or all of them if
+    /// there's no -w.
+    fn cmd_wall(&mut self, args: &str) -> Result<(), String> {
+        let (opts, text) = options(args);
+        let windows = self.target_windows(opts.get(&'w').map_or("*", |w| w.as_str()));
+        let style = opts.get(&'s').cloned().unwrap_or_default();
+        self.write_styled(windows, text, &style)
+    }
+
+    /// /metrics [-i<seconds>] file, /metrics off -- write metrics (see metrics.rs) to `file` every
+    /// so often (every 15 seconds, unless -i says otherwise), or stop.  With no arguments, say
+    /// where they're going.
+    #[cfg(feature = "metrics")]
+    fn cmd_metrics(&mut self, args: &str) -> Result<(), String> {
+        let (opts, path) = options(args);
+        match path {
+            "" => {
+                self.echo(match self.metrics {
+                    Some((ref export, _)) => format!("Writing metrics to {} every {}s.",
+                                                     export.path.display(), export.interval.as_secs_f64()),
+                    None => "Not writing metrics.".to_string(),
+                });
+                return Ok(());
+            },
+            "off" => {
+                if let Some((_, timer)) = self.metrics.take() {
+                    self.timers.borrow_mut().cancel(timer);
+                }
+                return Ok(());
+            },
+            _ => (),
+        }
+
+        let interval = match opts.get(&'i').map(|i| i.parse::<f64>()) {
+            Some(Ok(seconds)) if seconds.is_finite() && seconds >= 1.0 => Duration::from_secs_f64(seconds),
+            Some(_) => return Err("The interval (-i) has to be a number of seconds, at least 1".to_string()),
+            None => metrics::DEFAULT_INTERVAL,
+        };
+        if let Some((_, timer)) = self.metrics.take() {
+            self.timers.borrow_mut().cancel(timer);
+        }
+        let export = Export { path: PathBuf::from(path), interval };
+        // Write the first lot straight away, so a path that won't work says so now.
+        export.write(&self.metrics_snapshot()).map_err(|e| format!("Couldn't write {}: {}", path, e))?;
+        let timer = self.timers.borrow_mut().schedule(Instant::now() + interval);
+        self.metrics = Some((export, timer));
+        Ok(())
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics_snapshot(&self) -> Snapshot {
+        let connections = self.tcp.borrow().all_stats().into_iter()
+            .map(|(which, stats)| Traffic { which, address: stats.address, bytes_in: stats.bytes_in, bytes_out: stats.bytes_out })
+            .collect();
+        let (redraws, redraw_time) = self.tui.borrow().redraws();
+        Snapshot {
+            events: self.manager.event_counts(),
+            queued: self.manager.queued(),
+            connections,
+            redraws,
+            redraw_time,
+        }
+    }
+
+    /// Time to write the metrics out again.  If that doesn't work, it stops.
+    #[cfg(feature = "metrics")]
+    fn write_metrics(&mut self) {
+        let (export, _) = match self.metrics.take() {
+            Some(metrics) => metrics,
+            None => return,
+        };
+        if let Err(e) = export.write(&self.metrics_snapshot()) {
+            return self.echo(format!("Couldn't write metrics to {}, so stopped: {}", export.path.display(), e));
+        }
+        let timer = self.timers.borrow_mut().schedule(Instant::now() + export.interval);
+        self.metrics = Some((export, timer));
+    }
+
+    /// /repeat [-n<times>] interval line -- run `line` every `interval` (e.g. 90, 30s or 5m), as
+    /// if it had been typed in the window in front, `times` times or until it's stopped (see
+    /// /unrepeat.)  With no arguments, list the repeats.
+    fn cmd_repeat(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            let list: Vec<String> = self.repeats.list().iter().map(|r| {
+                let window = self.tui.borrow().window_name(r.window).unwrap_or_default();
+                let left = r.left.map_or(String::new(), |n| format!(", {} more time(s)", n));
+                format!("#{}: every {}s in {}{}: {}", r.number, r.every.as_secs_f64(), window, left, r.line)
+            }).collect();
+            if list.is_empty() {
+                self.echo("No repeats.".to_string());
+            }
+            for line in list {
+                self.echo(line);
+            }
+            return Ok(());
+        }
+
+        let (opts, rest) = options(args);
+        let times = match opts.get(&'n').map(|n| n.parse::<u32>()) {
+            Some(Ok(times)) if times > 0 => Some(times),
+            Some(_) => return Err("The number of times (-n) has to be a whole number, at least 1".to_string()),
+            None => None,
+        };
+        self.schedule_repeat(rest, times, "Usage: /repeat [-n<times>] interval line")
+    }
+
+    /// /after interval line -- run `line` once, after `interval`, as if it had been typed in the
+    /// window in front.
+    fn cmd_after(&mut self, args: &str) -> Result<(), String> {
+        self.schedule_repeat(args, Some(1), "Usage: /after interval line")
+    }
+
+    fn schedule_repeat(&mut self, args: &str, times: Option<u32>, usage: &str) -> Result<(), String> {
+        let (interval, line) = args.split_once(' ').ok_or_else(|| usage.to_string())?;
+        let every = repeat::parse_interval(interval).ok_or_else(|| usage.to_string())?;
+        if every < repeat::MIN_INTERVAL {
+            return Err(format!("That's too often; the most is every {}s.", repeat::MIN_INTERVAL.as_secs_f64()));
+        }
+        let mut tui = self.tui.borrow_mut();
+        let window = tui.active_window();
+        let which = tui.window_id(&window);
+        drop(tui);
+        let number = self.repeats.add(&mut self.timers.borrow_mut(), which, every, times, line.trim().to_string());
+        self.echo(format!("Repeat #{} set.", number));
+        Ok(())
+    }
+
+    /// /unrepeat number|all -- stop a repeat (or /after), or all of them.
+    fn cmd_unrepeat(&mut self, args: &str) -> Result<(), String> {
+        if args == "all" {
+            self.repeats.clear(&mut self.timers.borrow_mut());
+            return Ok(());
+        }
+        let number = args.trim_start_matches('#').parse::<usize>()
+            .map_err(|_| "Usage: /unrepeat number|all".to_string())?;
+        match self.repeats.remove(&mut self.timers.borrow_mut(), number) {
+            true => Ok(()),
+            false => Err(format!("No repeat #{}", number)),
+        }
+    }
+
+    /// /tick [-w<seconds>] [length|sync|off] -- keep track of the MUD's ticks: start a ticker
+    /// with ticks `length` apart (e.g. 60 or 75s), line it up with a tick that's just happened
+    /// ("sync", usually from a trigger), or stop it.  -w gives a warning that many seconds before
+    /// each tick (-w0 stops them.)  With no arguments, say how long there is to go.
+    fn cmd_tick(&mut self, args: &str) -> Result<(), String> {
+        let usage = "Usage: /tick [-w<seconds>] [length|sync|off]";
+        let (opts, rest) = options(args);
+        let warning = match opts.get(&'w').map(|w| repeat::parse_interval(w)) {
+            Some(Some(w)) if w == Duration::from_secs(0) => Some(None),
+            Some(Some(w)) => Some(Some(w)),
+            Some(None) => return Err(usage.to_string()),
+            None => None,
+        };
+        let now = Instant::now();
+
+        match rest.trim() {
+            "off" => {
+                if let Some((_, timer)) = self.ticker.take() {
+                    self.timers.borrow_mut().cancel(timer);
+                }
+                self.tui.borrow_mut().remove_status(TICK_SEGMENT.to_string());
+                return Ok(());
+            },
+            "sync" => match &mut self.ticker {
+                Some((ticker, _)) => ticker.sync(now),
+                None => return Err("There's no ticker to sync (/tick length starts one.)".to_string()),
+            },
+            "" if warning.is_none() => {
+                let text = match &self.ticker {
+                    Some((ticker, _)) => format!("Next tick in {}s.", ticker.remaining(now).as_secs()),
+                    None => "No ticker.".to_string(),
+                };
+                self.echo(text);
+                return Ok(());
+            },
+            "" => (),
+            length => {
+                let length = repeat::parse_interval(length).ok_or_else(|| usage.to_string())?;
+                if length < Duration::from_secs(1) {
+                    return Err("Ticks have to be at least a second apart.".to_string());
+                }
+                let warning = self.ticker.take().and_then(|(ticker, timer)| {
+                    self.timers.borrow_mut().cancel(timer);
+                    ticker.warning
+                });
+                let mut ticker = Ticker::new(length, now);
+                ticker.warning = warning;
+                let timer = self.timers.borrow_mut().schedule(now);
+                self.ticker = Some((ticker, timer));
+            },
+        }
+
+        match (&mut self.ticker, warning) {
+            (Some((ticker, _)), Some(warning)) => ticker.warning = warning,
+            (None, Some(_)) => return Err("There's no ticker to warn about (/tick length starts one.)".to_string()),
+            _ => (),
+        }
+        self.check_ticker();
+        Ok(())
+    }
+
+    /// Bring the countdown in the status bar up to date, and say so if a tick's coming up.  Ticks
+    /// and warnings are raised as events for scripts too ("tick" and "tick-warning".)
+    fn check_ticker(&mut self) {
+        let now = Instant::now();
+        let (ticker, timer) = match &mut self.ticker {
+            Some(ticker) => ticker,
+            None => return,
+        };
+        let tocks = ticker.poll(now);
+        let countdown = ticker.describe(now);
+        let mut timers = self.timers.borrow_mut();
+        timers.cancel(*timer);
+        *timer = timers.schedule(now + ticker.next_wake(now));
+        drop(timers);
+        self.tui.borrow_mut().set_status(TICK_SEGMENT.to_string(), countdown);
+
+        for tock in tocks {
+            let errors = match tock {
+                Tock::Tick => self.scripts.event("tick", &[]),
+                Tock::Warning(left) => {
+                    let secs = (left.as_secs_f64().ceil() as u64).to_string();
+                    self.echo(format!("Tick in {}s.", secs));
+                    self.scripts.event("tick-warning", &[secs])
+                },
+            };
+            self.echo_all(errors);
+        }
+        self.script_actions(None);
+    }
+
+    /// /gag [-c] pattern, /gag -d pattern, /gag -r -- stop lines from the server that match the
+    /// regex `pattern` being shown, or with -c, only show the first of each run of them.  -d
+    /// takes a gag away.  -r switches reviewing on or off: the lines gagged lately are put in the
+    /// review window, and while it's on, so is each one gagged after that.  With no arguments,
+    /// list the gags.
+    fn cmd_gag(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            let list: Vec<String> = self.gags.list().iter()
+                .map(|g| format!("{}{}", g.pattern, if g.collapse { " (collapsed)" } else { "" }))
+                .collect();
+            if list.is_empty() {
+                self.echo("No gags.".to_string());
+            }
+            for line in list {
+                self.echo(line);
+            }
+            return Ok(());
+        }
+
+        let (opts, pattern) = options(args);
+        if opts.contains_key(&'r') {
+            self.reviewing_gags = !self.reviewing_gags;
+            if !self.reviewing_gags {
+                self.echo(format!("Gagged lines aren't going to the {} window any more.", gag::REVIEW_WINDOW));
+                return Ok(());
+            }
+            let window = gag::REVIEW_WINDOW.to_string();
+            let lines: Vec<Line> = self.gags.recent().cloned().collect();
+            self.tui.borrow_mut().clear_window(&window);
+            self.show(window.clone(), Line::client(&format!("{} gagged line(s) lately; /gag -r to stop reviewing.", lines.len())));
+            for line in lines {
+                self.show(window.clone(), line);
+            }
+            self.tui.borrow_mut().show_window(window);
+            return Ok(());
+        }
+        if pattern.is_empty() {
+            return Err("Usage: /gag [-c] pattern, /gag -d pattern or /gag -r".to_string());
+        }
+        if opts.contains_key(&'d') {
+            return match self.gags.remove(pattern) {
+                true => Ok(()),
+                false => Err(format!("No gag for {}", pattern)),
+            };
+        }
+        self.gags.add(pattern, opts.contains_key(&'c'))
+    }
+
+    /// /divider [-s<style>] [-w<windows>] [label] -- draw a rule across some windows, or the
+    /// current one if there's no -w, with an optional label in the middle.
+    fn cmd_divider(&mut self, args: &str) -> Result<(), String> {
+        let (opts, label) = options(args);
+        let windows = match opts.get(&'w') {
+            Some(spec) => self.target_windows(spec),
+            None => vec![self.tui.borrow().active_window()],
+        };
+        let style = opts.get(&'s').cloned().unwrap_or_default();
+
+        let width = self.tui.borrow().text_width();
+        let rule = if label.is_empty() {
+            "-".repeat(width)
+        } else {
+            let label = format!(" {} ", label);
+            let left = width.saturating_sub(label.chars().count()) / 2;
+            let right = width.saturating_sub(left + label.chars().count());
+            format!("{}{}{}", "-".repeat(left), label, "-".repeat(right))
+        };
+        self.write_styled(windows, &rule, &style)
+    }
+
+    /// /jump -- scroll back to the last /mark in the current window; /jump end scrolls back down.
+    fn cmd_jump(&mut self, args: &str) -> Result<(), String> {
+        let window = self.tui.borrow().active_window();
+        if args == "end" {
+            self.tui.borrow_mut().scroll_to_end(window);
+        } else if !self.tui.borrow_mut().jump_to_mark(window) {
+            return Err("No marks in this window.".to_string());
+        }
+        Ok(())
+    }
+
+    /// /snapshot [-w<window>] name [N] -- copy the last N lines of a window (the current one,
+    /// unless there's a -w), or everything since its last /mark if there's no N, into a new
+    /// window called `name`.
+    fn cmd_snapshot(&mut self, args: &str) -> Result<(), String> {
+        let (opts, rest) = options(args);
+        let from = opts.get(&'w').cloned().unwrap_or_else(|| self.tui.borrow().active_window());
+
+        let mut parts = rest.split_whitespace();
+        let to = match parts.next() {
+            Some(to) => to.to_string(),
+            None => return Err("Usage: /snapshot [-w<window>] name [lines]".to_string()),
+        };
+        let lines = match parts.next().map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) => Lines::Last(n),
+            Some(Err(_)) => return Err("The number of lines should be a number.".to_string()),
+            None => Lines::SinceMark,
+        };
+
+        let n = self.tui.borrow_mut().snapshot(from.clone(), to.clone(), lines)?;
+        self.echo(format!("Copied {} lines from {} into {}.", n, from, to));
+        Ok(())
+    }
+
+    /// /sh [-t<seconds>] command -- run a shell command in the background and show what it
+    /// prints when it's done.  It's killed if it takes longer than the time limit.
+    fn cmd_sh(&mut self, args: &str) -> Result<(), String> {
+        let (opts, command) = options(args);
+        if command.is_empty() {
+            return Err("Usage: /sh [-t<seconds>] command".to_string());
+        }
+        let limit = match opts.get(&'t').map(|t| t.parse::<u64>()) {
+            Some(Ok(secs)) => Duration::from_secs(secs),
+            Some(Err(_)) => return Err("The time limit should be a number of seconds.".to_string()),
+            None => action::DEFAULT_TIME_LIMIT,
+        };
+        self.actions.borrow_mut().submit(Job::Shell(command.to_string()), limit);
+        Ok(())
+    }
+
+    /// /connect [-w<window>] [-r] [-c<charset>] world|address -- open another connection, with its
+    /// text in its own window (named after the world or address, unless there's a -w.)  A world
+    /// in the worlds file comes with all its settings; anything else is taken as an address.
+    /// With -r, lines typed in that window all go to the server, even ones starting with "/".  -c
+    /// says what charset the server's text is in (see /charset.)  An address like "exec:command"
+    /// runs the command and talks to that instead.
+    fn cmd_connect(&mut self, args: &str) -> Result<(), String> {
+        let (opts, address) = options(args);
+        if address.is_empty() {
+            return Err("Usage: /connect [-w<window>] [-r] [-c<charset>] world|host:port|exec:command".to_string());
+        }
+        let charset = opts.get(&'c')
+            .map(|name| Charset::from_name(name).ok_or_else(|| format!("Don't know the charset {}", name)))
+            .transpose()?;
+        let window = self.sessions.free_window_name(opts.get(&'w').map_or(address, |w| w.as_str()));
+        let mut world = world::worlds_path().and_then(|path| world::find_world(&path, address))
+            .unwrap_or_else(|| World::new(address.to_string(), address.to_string()));
+        if opts.contains_key(&'r') {
+            world.client_commands = false;
+        }
+        if charset.is_some() {
+            world.charset = charset;
+        }
+        self.connect(world, window.clone());
+        self.tui.borrow_mut().show_window(window);
+        Ok(())
+    }
+
+    /// /dim on|off -- draw the panes besides the main one, where typing goes, dimmer than it.
+    fn cmd_dim(&mut self, args: &str) -> Result<(), String> {
+        let dim = match args {
+            "on" => true,
+            "off" => false,
+            _ => return Err("Usage: /dim on|off".to_string()),
+        };
+        self.tui.borrow_mut().set_dim_unfocused(dim);
+        Ok(())
+    }
+
+    /// /disconnect [window|all] -- close the connection whose text goes in `window`, or the one
+    /// in front, or all of them (which asks first.)  The window stays, with what was said in it.
+    fn cmd_disconnect(&mut self, args: &str) -> Result<(), String> {
+        if args == "all" {
+            let cids: Vec<ConnectionID> = self.sessions.iter().map(|s| s.cid).collect();
+            if cids.is_empty() {
+                return Err("Not connected to anything.".to_string());
+            }
+            for cid in cids {
+                self.connections(cid).borrow_mut().stop_connection(cid)
+                    .map_err(|e| format!("Couldn't close a connection: {}", e))?;
+            }
+            return Ok(());
+        }
+        let window = if args.is_empty() { self.tui.borrow().active_window() } else { args.to_string() };
+        let cid = match self.sessions.for_window(&window) {
+            Some(session) => session.cid,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        // The session goes when the ConnectionEnd comes in, same as if the server had hung up.
+        self.connections(cid).borrow_mut().stop_connection(cid)
+            .map_err(|e| format!("Couldn't close the connection: {}", e))
+    }
+
+    /// /isize lines -- how many lines to keep for the input line, used or not.  (The name's from
+    /// tinyfugue.)
+    fn cmd_isize(&mut self, args: &str) -> Result<(), String> {
+        match args.parse::<usize>() {
+            Ok(lines) if lines > 0 => self.tui.borrow_mut().set_input_height(lines),
+            _ => return Err("Usage: /isize lines (at least 1)".to_string()),
+        }
+        Ok(())
+    }
+
+    /// /histsize lines -- how many lines of scrollback each window keeps.  (Also tinyfugue's.)
+    fn cmd_histsize(&mut self, args: &str) -> Result<(), String> {
+        match args.parse::<usize>() {
+            Ok(lines) if lines > 0 => self.tui.borrow_mut().set_max_history(lines),
+            _ => return Err("Usage: /histsize lines (at least 1)".to_string()),
+        }
+        Ok(())
+    }
+
+    /// /wrapsize [columns|off] -- wrap lines at `columns` even if the window's wider.
+    fn cmd_wrapsize(&mut self, args: &str) -> Result<(), String> {
+        let wrap = match args {
+            "off" => None,
+            _ => match args.parse::<usize>() {
+                Ok(columns) if columns > 0 => Some(columns),
+                _ => return Err("Usage: /wrapsize [columns|off]".to_string()),
+            },
+        };
+        let mut tui = self.tui.borrow_mut();
+        let display = tui.display();
+        tui.set_display(term::Display { wrap, ..display });
+        Ok(())
+    }
+
+    /// /wrapspace columns -- indent wrapped lines by `columns`, or the first line instead if
+    /// it's negative.
+    fn cmd_wrapspace(&mut self, args: &str) -> Result<(), String> {
+        let indent = args.parse::<isize>()
+            .map_err(|_| "Usage: /wrapspace columns".to_string())?;
+        let mut tui = self.tui.borrow_mut();
+        let display = tui.display();
+        tui.set_display(term::Display { indent, ..display });
+        Ok(())
+    }
+
+    /// /highlight [-d] [-v] [pattern [= style]] -- show whatever matches the regex `pattern` in
+    /// lines from the server in `style` (bold by default), flashing the screen too with -v, or
+    /// with -d, stop.  With no arguments, list the highlights.
+    fn cmd_highlight(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            let list = self.tui.borrow().highlights();
+            if list.is_empty() {
+                self.echo("No highlights.".to_string());
+            }
+            for (pattern, spec, flash) in list {
+                self.echo(format!("{}{} = {}", if flash { "-v " } else { "" }, pattern, spec));
+            }
+            return Ok(());
+        }
+
+        let (opts, rest) = options(args);
+        if opts.contains_key(&'d') {
+            return match self.tui.borrow_mut().remove_highlight(rest) {
+                true => Ok(()),
+                false => Err(format!("No highlight for {}", rest)),
+            };
+        }
+        let (pattern, spec) = match rest.rfind(" = ") {
+            Some(i) => (&rest[..i], rest[i + 3..].trim()),
+            None => (rest, "bold"),
+        };
+        self.tui.borrow_mut().add_highlight(pattern, spec, opts.contains_key(&'v'))
+    }
+
+    /// /raise [-w<window>] pattern, /raise -d pattern, /raise -i<interval> -- bring a window
+    /// (or with no -w, whichever one the line's going to) into view when a line matching the
+    /// regex `pattern` goes to it while it's out of sight; or with -d, stop.  Raises are kept at
+    /// least `interval` apart (like 10s, as for /repeat), so spam can't keep flipping the screen
+    /// about.  With no arguments, list the rules.
+    fn cmd_raise(&mut self, args: &str) -> Result<(), String> {
+        let (opts, rest) = options(args);
+        if let Some(interval) = opts.get(&'i') {
+            let interval = repeat::parse_interval(interval).ok_or("-i takes a length of time, like 10s")?;
+            self.tui.borrow_mut().set_raise_interval(interval);
+            return Ok(());
+        }
+        if rest.is_empty() {
+            let (list, interval) = {
+                let tui = self.tui.borrow();
+                (tui.raise_rules(), tui.raise_interval())
+            };
+            if list.is_empty() {
+                self.echo("No raise rules.".to_string());
+            }
+            for (window, pattern) in list {
+                self.echo(format!("{}{}", window.map_or(String::new(), |w| format!("-w{} ", w)), pattern));
+            }
+            self.echo(format!("At most one raise every {}.", clock::short_duration(interval)));
+            return Ok(());
+        }
+
+        if opts.contains_key(&'d') {
+            return match self.tui.borrow_mut().remove_raise_rule(rest) {
+                0 => Err(format!("No raise rule for {}", rest)),
+                _ => Ok(()),
+            };
+        }
+        self.tui.borrow_mut().add_raise_rule(opts.get(&'w').cloned(), rest)
+    }
+
+    /// /hyphenate on|off -- break words too long for a line somewhere sensible, with a hyphen if
+    /// need be, or just wherever the line runs out.
+    fn cmd_hyphenate(&mut self, args: &str) -> Result<(), String> {
+        let hyphenate = match args {
+            "on" => true,
+            "off" => false,
+            _ => return Err("Usage: /hyphenate on|off".to_string()),
+        };
+        let mut tui = self.tui.borrow_mut();
+        let display = tui.display();
+        tui.set_display(term::Display { hyphenate, ..display });
+        Ok(())
+    }
+
+    /// /timestamps on|off|style -- show the time each line arrived in front of it, in `style` if
+    /// one's given.
+    fn cmd_timestamps(&mut self, args: &str) -> Result<(), String> {
+        let timestamps = match args {
+            "off" => None,
+            "on" => Some(style::style("dim")?),
+            "" => return Err("Usage: /timestamps on|off|style".to_string()),
+            spec => Some(style::style(spec)?),
+        };
+        let mut tui = self.tui.borrow_mut();
+        let display = tui.display();
+        tui.set_display(term::Display { timestamps, ..display });
+        Ok(())
+    }
+
+    /// /split [lines|off] -- split the window so the newest lines stay in sight at the bottom
+    /// (`lines` of them, or a few if that's left out) while the rest scrolls back.
+    fn cmd_split(&mut self, args: &str) -> Result<(), String> {
+        let lines = match args {
+            "off" => None,
+            "" => Some(term::DEFAULT_SPLIT_LINES),
+            _ => match args.parse::<usize>() {
+                Ok(lines) if lines > 0 => Some(lines),
+                _ => return Err("Usage: /split [lines|off]".to_string()),
+            },
+        };
+        self.tui.borrow_mut().set_split(lines);
+        Ok(())
+    }
+
+    /// /pane [-v] name, /pane -r name, /pane off -- give a window a pane of its own, above the
+    /// main one (or with -v, to the left of it), or take panes away again.
+    fn cmd_pane(&mut self, args: &str) -> Result<(), String> {
+        let (opts, name) = options(args);
+        let mut tui = self.tui.borrow_mut();
+
+        if name == "off" && opts.is_empty() {
+            tui.remove_pane(None);
+        } else if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err("Usage: /pane [-v] name, /pane -r name or /pane off".to_string());
+        } else if opts.contains_key(&'r') {
+            if !tui.remove_pane(Some(name)) {
+                return Err(format!("{} doesn't have a pane.", name));
+            }
+        } else {
+            let direction = match opts.contains_key(&'v') {
+                true => layout::Direction::SideBySide,
+                false => layout::Direction::Stacked,
+            };
+            tui.add_pane(name.to_string(), direction);
+        }
+        Ok(())
+    }
+
+    /// /raw [on|off] -- switch raw send mode (see World::raw_send) on or off for the session in
+    /// front, or say whether it's on.
+    fn cmd_raw(&mut self, args: &str) -> Result<(), String> {
+        let window = self.tui.borrow().active_window();
+        let session = match self.sessions.for_window(&window) {
+            Some(session) => session,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        match args {
+            "on" => session.world.raw_send = true,
+            "off" => session.world.raw_send = false,
+            "" => { },
+            _ => return Err("Usage: /raw [on|off]".to_string()),
+        }
+        let state = format!("Raw send mode is {} for {}.",
+                            if session.world.raw_send { "on" } else { "off" }, session.world.name);
+        self.echo(state);
+        Ok(())
+    }
+
+    /// /complete [-w<window>] list word..., /complete -d list [word...] -- add words to one of the
+    /// word lists Tab completes from in the window in front (or `window`), or take them out (or
+    /// the whole list, with no words.)  Triggers can fill lists in with -l.  With no list, show
+    /// the window's lists.
+    fn cmd_complete(&mut self, args: &str) -> Result<(), String> {
+        let (opts, rest) = options(args);
+        let window = opts.get(&'w').cloned().unwrap_or_else(|| self.tui.borrow().active_window());
+        let mut words = rest.split_whitespace();
+        let list = match words.next() {
+            Some(list) => list,
+            None if opts.contains_key(&'d') => return Err("Usage: /complete -d list [word...]".to_string()),
+            None => {
+                let lists = self.tui.borrow().completion_lists(&window);
+                if lists.is_empty() {
+                    self.echo(format!("No word lists for {}.", window));
+                }
+                for (name, words) in lists {
+                    self.echo(format!("{} ({}): {}", name, words.len(), words.join(" ")));
+                }
+                return Ok(());
+            },
+        };
+        let words: Vec<&str> = words.collect();
+
+        let mut tui = self.tui.borrow_mut();
+        if opts.contains_key(&'d') {
+            let removed = if words.is_empty() {
+                tui.remove_completion(&window, list, None)
+            } else {
+                words.iter().filter(|word| tui.remove_completion(&window, list, Some(word))).count() > 0
+            };
+            drop(tui);
+            if !removed {
+                return Err(format!("Nothing like that in {}'s word list {}.", window, list));
+            }
+            return Ok(());
+        }
+        if words.is_empty() {
+            return Err("Usage: /complete [-w<window>] list word...".to_string());
+        }
+        for word in words {
+            tui.add_completion(&window, list, word);
+        }
+        Ok(())
+    }
+
+    /// /compress [on|off] -- switch MCCP compression on or off for the session in front, or say
+    /// whether it's on.
+    fn cmd_compress(&mut self, args: &str) -> Result<(), String> {
+        let window = self.tui.borrow().active_window();
+        let session = match self.sessions.for_window(&window) {
+            Some(session) => session,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        if ProcessConnectionManager::owns(session.cid) {
+            return Err("Programs' text is never compressed.".to_string());
+        }
+        match args {
+            "on" => session.world.compress = true,
+            "off" => session.world.compress = false,
+            "" => { },
+            _ => return Err("Usage: /compress [on|off]".to_string()),
+        }
+        if !args.is_empty() {
+            self.tcp.borrow_mut().set_compression(session.cid, session.world.compress);
+        }
+        let state = match (session.world.compress, args) {
+            (true, "on") => format!("Compression is allowed for {} (from the next time the server offers it.)",
+                                    session.world.name),
+            (true, _) => format!("Compression is allowed for {}.", session.world.name),
+            (false, _) => format!("Compression is off for {}.", session.world.name),
+        };
+        self.echo(state);
+        Ok(())
+    }
+
+    /// /charset [name] -- say what charset the text of the world in front is in (UTF-8,
+    /// ISO-8859-1, CP1252, CP437 and so on), for servers that don't send UTF-8 and don't say so.
+    /// It's used for what's sent, too.  With nothing, say which it's using.
+    fn cmd_charset(&mut self, args: &str) -> Result<(), String> {
+        let window = self.tui.borrow().active_window();
+        let session = match self.sessions.for_window(&window) {
+            Some(session) => session,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        if ProcessConnectionManager::owns(session.cid) {
+            return Err("Programs' text is always UTF-8.".to_string());
+        }
+        if !args.is_empty() {
+            let charset = Charset::from_name(args).ok_or_else(|| format!("Don't know the charset {}", args))?;
+            session.world.charset = Some(charset);
+            self.tcp.borrow_mut().set_charset(session.cid, charset);
+        }
+        let state = match self.tcp.borrow().charset(session.cid) {
+            (charset, true) => format!("{}'s text is in {}, as the server said.", session.world.name, charset.name()),
+            (charset, false) => format!("{}'s text is in {}.", session.world.name, charset.name()),
+        };
+        self.echo(state);
+        Ok(())
+    }
+
+    /// /reconnect [-d<seconds>] [-m<seconds>] [-n<tries>] [-c<errors>] [on|off] -- whether the
+    /// session in front reconnects if the connection drops: first after -d seconds, then waiting
+    /// twice as long each time up to -m seconds, giving up after -n tries (0 for never.)  -c
+    /// says after which sorts of error, e.g. "reset,timeout,eof" (or "all"; see ErrorClass for
+    /// the names.)  With nothing, say how it's set.
+    fn cmd_reconnect(&mut self, args: &str) -> Result<(), String> {
+        let (opts, rest) = options(args);
+        let window = self.tui.borrow().active_window();
+        let session = match self.sessions.for_window(&window) {
+            Some(session) => session,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        if ProcessConnectionManager::owns(session.cid) {
+            return Err("Programs aren't reconnected to; /connect to run one again.".to_string());
+        }
+
+        let mut policy = session.world.reconnect.clone();
+        let seconds = |opt: Option<&String>| -> Result<Option<u64>, String> {
+            opt.map(|n| n.parse::<u64>().map_err(|_| format!("Expected a number, not {}", n))).transpose()
+        };
+        if let Some(secs) = seconds(opts.get(&'d'))? {
+            policy.initial_delay = Duration::from_secs(secs.max(1));
+        }
+        if let Some(secs) = seconds(opts.get(&'m'))? {
+            policy.max_delay = Duration::from_secs(secs.max(1));
+        }
+        if let Some(tries) = seconds(opts.get(&'n'))? {
+            policy.max_attempts = if tries == 0 { None } else { Some(tries as u32) };
+        }
+        if let Some(names) = opts.get(&'c') {
+            policy.after = match names.as_str() {
+                "all" => ErrorClass::ALL.to_vec(),
+                _ => names.split(',').map(|name| ErrorClass::from_name(name.trim()).ok_or_else(|| {
+                    let all: Vec<&str> = ErrorClass::ALL.iter().map(|class| class.name()).collect();
+                    format!("Unknown sort of error {} (try {} or all.)", name, all.join(", "))
+                })).collect::<Result<_, _>>()?,
+            };
+        }
+        match rest {
+            "on" => policy.enabled = true,
+            "off" => policy.enabled = false,
+            "" => { },
+            _ => return Err("Usage: /reconnect [-d<seconds>] [-m<seconds>] [-n<tries>] [-c<errors>] [on|off]".to_string()),
+        }
+        session.world.reconnect = policy.clone();
+        self.tcp.borrow_mut().set_reconnect(session.cid, policy.clone());
+
+        let state = if policy.enabled {
+            let tries = policy.max_attempts.map_or("forever".to_string(), |n| format!("up to {} times", n));
+            let after: Vec<&str> = policy.after.iter().map(|class| class.name()).collect();
+            format!("{} reconnects if it drops ({}): after {}s at first, at most {}s apart, {}.", session.world.name,
+                    after.join(", "), policy.initial_delay.as_secs(), policy.max_delay.as_secs(), tries)
+        } else {
+            format!("{} doesn't reconnect if it drops.", session.world.name)
+        };
+        self.echo(state);
+        Ok(())
+    }
+
+    /// /msdp [variables] -- pick which MSDP variables the server should keep us up to date on for
+    /// the session in front, and show in its window's status.  With nothing, list what the server
+    /// has sent.
+    fn cmd_msdp(&mut self, args: &str) -> Result<(), String> {
+        let window = self.tui.borrow().active_window();
+        let session = match self.sessions.for_window(&window) {
+            Some(session) => session,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        let cid = session.cid;
+
+        if args.is_empty() {
+            let list = self.tcp.borrow().msdp_variables(cid);
+            if list.is_empty() {
+                self.echo("The server hasn't sent any MSDP variables.".to_string());
+            }
+            for (variable, value) in list {
+                self.echo(format!("{} = {}", variable, value));
+            }
+            return Ok(());
+        }
+
+        session.world.msdp = args.split_whitespace().map(|v| v.to_uppercase()).collect();
+        self.tcp.borrow_mut().msdp_report(cid, &session.world.msdp)?;
+        self.show_msdp(cid);
+        Ok(())
+    }
+
+    /// /status segment [text] -- set a segment of the status bar to `text`, making it if it isn't
+    /// there, or with no text, take it away.  Meant for triggers, so a little HUD like
+    /// `/trigger ^You attack (\w+) \((\d+)%\) = /status target Target: %1 (%2%%)` stays up to
+    /// date in place.
+    fn cmd_status(&mut self, args: &str) -> Result<(), String> {
+        let (segment, text) = args.split_once(' ').unwrap_or((args, ""));
+        if segment.is_empty() {
+            return Err("Usage: /status segment [text]".to_string());
+        }
+        match text.trim() {
+            "" => self.tui.borrow_mut().remove_status(segment.to_string()),
+            text => self.tui.borrow_mut().set_status(segment.to_string(), text.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Put a session's MSDP variables (the ones its world asks for) in its window's status.
+    fn show_msdp(&mut self, cid: ConnectionID) {
+        let session = match self.sessions.by_connection(cid) {
+            Some(session) => session,
+            None => return,
+        };
+        let tcp = self.tcp.borrow();
+        let values: Vec<String> = session.world.msdp.iter()
+            .filter_map(|variable| tcp.msdp_value(cid, variable).map(|value| format!("{} {}", variable, value)))
+            .collect();
+        let status = if values.is_empty() {
+            format!("{}: connected", session.world.name)
+        } else {
+            format!("{}: {}", session.world.name, values.join("  "))
+        };
+        self.tui.borrow_mut().set_window_status(session.window.clone(), status);
+    }
+
+    /// The name of the world in front, or the one given with -w, for /note and /notes.
+    fn notes_world(&mut self, given: Option<&str>) -> Result<String, String> {
+        if let Some(world) = given.filter(|world| !world.is_empty()) {
+            return Ok(world.to_string());
+        }
+        let window = self.tui.borrow().active_window();
+        match self.sessions.for_window(&window) {
+            Some(session) => Ok(session.world.name.clone()),
+            None => Err("Not connected to anything (say which world with -w.)".to_string()),
+        }
+    }
+
+    /// /note [-w<world>] text -- add a note, with the time, to the notes for the world in front
+    /// (or the one named.)
+    fn cmd_note(&mut self, args: &str) -> Result<(), String> {
+        let (opts, text) = options(args);
+        if text.is_empty() {
+            return Err("Usage: /note [-w<world>] text".to_string());
+        }
+        let world = self.notes_world(opts.get(&'w').map(|w| w.as_str()))?;
+        let path = notes::path(&world).ok_or("Couldn't find somewhere to keep notes.")?;
+        notes::append(&path, LocalTime::now(), text)
+            .map_err(|e| format!("Couldn't write to {}: {}", path.display(), e))?;
+        self.echo(format!("Noted for {}.", world));
+        Ok(())
+    }
+
+    /// /notes [world] -- show the notes for the world in front (or the one named) in the notes
+    /// window.
+    fn cmd_notes(&mut self, args: &str) -> Result<(), String> {
+        let world = self.notes_world(Some(args))?;
+        let path = notes::path(&world).ok_or("Couldn't find somewhere to keep notes.")?;
+        let list = notes::read(&path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+
+        let mut tui = self.tui.borrow_mut();
+        tui.clear_window("notes");
+        tui.push_to_window("notes".to_string(), Line::client(&format!("Notes for {}:", world))).unwrap();
+        if list.is_empty() {
+            tui.push_to_window("notes".to_string(), Line::client("None yet (/note adds one.)")).unwrap();
+        }
+        for note in list {
+            tui.push_to_window("notes".to_string(), Line::client(&note)).unwrap();
+        }
+        tui.show_window("notes".to_string());
+        Ok(())
+    }
+
+    /// /log [-w[window]] [start [-f<format>] [-s] [file] | stop] -- start logging the session in
+    /// front to a file (by default one named after the world and the time, in the logs
+    /// directory), or stop.  -s logs what's sent as well.  With no arguments, say where it's
+    /// logging to.  With -w, it's a window that's logged instead (the one in front if none's
+    /// named): whatever goes in it, and nothing else.
+    fn cmd_log(&mut self, args: &str) -> Result<(), String> {
+        let (opts, args) = options(args);
+        let (verb, rest) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+        if !["", "start", "stop"].contains(&verb) {
+            return Err("Usage: /log [-w[window]] [start [-f<format>] [-s] [file] | stop]".to_string());
+        }
+        let window = self.tui.borrow().active_window();
+
+        if let Some(name) = opts.get(&'w') {
+            let window = if name.is_empty() { window } else { name.clone() };
+            let message = match verb {
+                "" => match self.window_logs.get(&window) {
+                    Some(log) => format!("Logging window {} to {}.", window, describe_log(log)),
+                    None => format!("Not logging window {}.", window),
+                },
+                "stop" => match self.window_logs.stop(&window) {
+                    Some(log) => format!("Stopped logging window {} to {}.", window, log.path.display()),
+                    None => return Err(format!("Not logging window {}.", window)),
+                },
+                _ => {
+                    let log = open_log(&window, rest)?;
+                    let message = format!("Logging window {} to {}.", window, log.path.display());
+                    self.window_logs.start(window, log);
+                    message
+                },
+            };
+            self.echo(message);
+            return Ok(());
+        }
+
+        let session = match self.sessions.for_window(&window) {
+            Some(session) => session,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        let message = match verb {
+            "" => match session.log {
+                Some(ref log) => format!("Logging to {}.", describe_log(log)),
+                None => "Not logging.".to_string(),
+            },
+            "stop" => match session.log.take() {
+                Some(log) => format!("Stopped logging to {}.", log.path.display()),
+                None => return Err("Not logging.".to_string()),
+            },
+            _ => {
+                let mut log = open_log(&session.world.name, rest)?;
+                log.log_sent |= session.world.log_sent;
+                let message = format!("Logging to {}.", log.path.display());
+                // Whatever log there was before is closed off when it's replaced.
+                session.log = Some(log);
+                message
+            },
+        };
+        self.echo(message);
+        Ok(())
+    }
+
+    /// /windows -- list the windows, with their numbers, what they're connected to, and how many
+    /// lines they have that haven't been seen.
+    fn cmd_windows(&mut self, _args: &str) -> Result<(), String> {
+        let mut list: Vec<(WindowID, String)> = {
+            let mut tui = self.tui.borrow_mut();
+            tui.window_names().into_iter().map(|name| (tui.window_id(&name), name)).collect()
+        };
+        list.sort();
+
+        for (id, name) in list {
+            let connection = match self.sessions.by_window(&name) {
+                Some(session) => format!(" (connection {} to {})", session.cid, session.world.address),
+                None => String::new(),
+            };
+            let unread = match self.tui.borrow().unread(&name) {
+                0 => String::new(),
+                n => format!(", {} unread", n),
+            };
+            self.echo(format!("{:>3} {}{}{}", id, name, connection, unread));
+        }
+        Ok(())
+    }
+
+    /// /threads [restart n] -- list the listening threads and how they're doing, or start
+    /// another in place of thread `n`, which has stalled.
+    fn cmd_threads(&mut self, args: &str) -> Result<(), String> {
+        if let Some(n) = args.strip_prefix("restart") {
+            let n = n.trim().parse::<usize>().map_err(|_| "Usage: /threads [restart n]".to_string())?;
+            let new = self.manager.restart_stalled(n)?;
+            self.echo(format!("Started thread {} in place of thread {}.", new, n));
+            return Ok(());
+        }
+        if !args.is_empty() {
+            return Err("Usage: /threads [restart n]".to_string());
+        }
+        for (n, t) in self.manager.threads().into_iter().enumerate() {
+            let heartbeat = match t.heartbeat_interval {
+                Some(interval) => format!("every {}s", interval.as_secs()),
+                None => "none".to_string(),
+            };
+            let restarts = match t.restarts {
+                0 => String::new(),
+                n => format!(", restarted {} times", n),
+            };
+            self.echo(format!("{:<3} {:<16} source {:<3} {:<8} last active {}s ago (heartbeat: {}{})",
+                              n, t.name, t.source, format!("{:?}", t.status), t.idle.as_secs(),
+                              heartbeat, restarts));
+        }
+        Ok(())
+    }
+
+    /// /stats [window] -- how the connection in a window (or the one in front) is doing.
+    fn cmd_stats(&mut self, args: &str) -> Result<(), String> {
+        let window = if args.is_empty() { self.tui.borrow().active_window() } else { args.to_string() };
+        let cid = match self.sessions.for_window(&window) {
+            Some(session) => session.cid,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        let stats = match self.tcp.borrow().stats(cid) {
+            Some(stats) => stats,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+
+        let up = match stats.connected_for {
+            Some(up) => format!("up {}", clock::short_duration(up)),
+            None => "connecting".to_string(),
+        };
+        self.echo(format!("{}: {}, reconnected {} times", stats.address, up, stats.reconnects));
+        if !stats.layers.is_empty() {
+            self.echo(format!("Read through (from the socket up): {}", stats.layers.join(", ")));
+        }
+        if stats.tls {
+            self.echo(match stats.handshake {
+                Some(handshake) => format!("TLS handshake took {}ms ({})", handshake.took.as_millis(),
+                                           if handshake.resumed { "resumed session" } else { "full" }),
+                None => "TLS handshake not finished yet".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// /netstat -- list the connections, with when each came up and last dropped, and the last
+    /// few that are over.
+    fn cmd_netstat(&mut self, _args: &str) -> Result<(), String> {
+        let all = self.tcp.borrow().all_stats();
+        if all.is_empty() {
+            return Err("No connections yet.".to_string());
+        }
+        let at = |when: SystemTime| LocalTime::at(when).hms();
+        for (cid, stats) in all {
+            let mut line = format!("#{} {}: ", cid, stats.address);
+            line.push_str(&match (stats.connected_for, stats.connected_at) {
+                (Some(up), Some(since)) => format!("up {} since {}", clock::short_duration(up), at(since)),
+                (None, _) if self.sessions.by_connection(cid).is_some() => "connecting".to_string(),
+                (None, Some(since)) => format!("was up from {}", at(since)),
+                _ => "never connected".to_string(),
+            });
+            if stats.reconnects > 0 {
+                line.push_str(&format!(", reconnected {} times", stats.reconnects));
+            }
+            if let Some((when, why)) = stats.dropped {
+                line.push_str(&format!(", dropped at {} ({})", at(when), why));
+            }
+            self.echo(line);
+        }
+        Ok(())
+    }
+
+    /// Show how long the world in front has been up in the status bar, and keep showing it once
+    /// a minute for as long as anything's connected.
+    fn show_uptime(&mut self) {
+        let window = self.tui.borrow().active_window();
+        let cid = self.sessions.for_window(&window).map(|session| session.cid);
+        let up = cid.and_then(|cid| self.tcp.borrow().stats(cid)).and_then(|stats| stats.connected_for);
+        // It's only updated once a minute, so seconds would just look stuck.
+        match up {
+            Some(up) if up.as_secs() < 60 => self.tui.borrow_mut().set_status(UPTIME_SEGMENT.to_string(), "up <1m".to_string()),
+            Some(up) => self.tui.borrow_mut().set_status(UPTIME_SEGMENT.to_string(),
+                                                         format!("up {}", clock::short_duration(up))),
+            None => self.tui.borrow_mut().remove_status(UPTIME_SEGMENT.to_string()),
+        }
+
+        let any_up = self.tcp.borrow().all_stats().iter().any(|(_, stats)| stats.connected_for.is_some());
+        if any_up && self.uptime_timer.is_none() {
+            self.uptime_timer = Some(self.timers.borrow_mut().schedule(Instant::now() + Duration::from_secs(60)));
+        }
+    }
+
+    /// Note that connection `which`'s server has said something, and if it had gone quiet, that
+    /// it's back.
+    fn heard_from(&mut self, which: ConnectionID) {
+        let session = match self.sessions.by_connection(which) {
+            Some(session) => session,
+            None => return,
+        };
+        if session.heard(Instant::now()) {
+            self.tui.borrow_mut().set_window_status(session.window.clone(), format!("{}: connected", session.world.name));
+            self.watch_idle(which);
+            self.show_quiet();
+        }
+    }
+
+    /// Set the timer for when connection `which`'s server will have been quiet for too long, if
+    /// its world says there's such a thing, in place of any set before.
+    fn watch_idle(&mut self, which: ConnectionID) {
+        if let Some(session) = self.sessions.by_connection(which) {
+            let mut timers = self.timers.borrow_mut();
+            if let Some(timer) = session.idle_timer.take() {
+                timers.cancel(timer);
+            }
+            session.idle_timer = session.idle_due().map(|due| timers.schedule(due));
+        }
+    }
+
+    /// An idle timer's gone off.  The timer isn't moved every time the server says something, so
+    /// it might have spoken since it was set, in which case there's a new one.
+    fn check_idle(&mut self, id: TimerID) {
+        let now = Instant::now();
+        let session = match self.sessions.iter_mut().find(|s| s.idle_timer == Some(id)) {
+            Some(session) => session,
+            None => return,
+        };
+        session.idle_timer = None;
+        let which = session.cid;
+        match session.idle_due() {
+            Some(due) if due <= now => {
+                session.idle = true;
+                let quiet = now.duration_since(session.last_heard);
+                self.manager.post(Event::ServerIdle { which, quiet });
+            },
+            Some(_) => self.watch_idle(which),
+            None => { },
+        }
+    }
+
+    /// Show which worlds have gone quiet in the status bar, if any have.
+    fn show_quiet(&mut self) {
+        let quiet: Vec<&str> = self.sessions.iter().filter(|s| s.idle).map(|s| s.world.name.as_str()).collect();
+        let mut tui = self.tui.borrow_mut();
+        match quiet.is_empty() {
+            true => tui.remove_status(IDLE_SEGMENT.to_string()),
+            false => tui.set_status(IDLE_SEGMENT.to_string(), format!("quiet: {}", quiet.join(" "))),
+        }
+    }
+
+    /// /idle [-n] [interval|off] -- say when the server in front has sent nothing for `interval`
+    /// (like 10m, as for /repeat), by marking its window and the status bar, and with -n, with a
+    /// desktop notification too.  Scripts hear about it as the event "server-idle", with the
+    /// world's name and the seconds it's been quiet.  With nothing, say how it's set.
+    fn cmd_idle(&mut self, args: &str) -> Result<(), String> {
+        let usage = || "Usage: /idle [-n] [interval|off]".to_string();
+        let (opts, rest) = options(args);
+        let window = self.tui.borrow().active_window();
+        let session = match self.sessions.for_window(&window) {
+            Some(session) => session,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        match rest {
+            "" if opts.contains_key(&'n') && session.world.idle.after.is_some() => session.world.idle.notify = true,
+            "" if opts.is_empty() => { },
+            "off" => session.world.idle = IdlePolicy::default(),
+            interval => {
+                let after = repeat::parse_interval(interval).filter(|after| after.as_secs() > 0).ok_or_else(usage)?;
+                session.world.idle = IdlePolicy { after: Some(after), notify: opts.contains_key(&'n') };
+            },
+        }
+        if session.world.idle.after.is_none() {
+            session.idle = false;
+        }
+
+        let state = match session.world.idle {
+            IdlePolicy { after: Some(after), notify } => format!("{} counts as quiet after {} without a word{}.",
+                    session.world.name, clock::short_duration(after), if notify { ", with a notification" } else { "" }),
+            IdlePolicy { after: None, .. } => format!("{} never counts as quiet.", session.world.name),
+        };
+        let which = session.cid;
+        self.watch_idle(which);
+        self.show_quiet();
+        self.echo(state);
+        Ok(())
+    }
+
+    /// /audit [on|off] -- start or stop the idle CPU audit (see audit.rs), or with no arguments,
+    /// say what it's found so far.  Stopping it says what it found, too.
+    fn cmd_audit(&mut self, args: &str) -> Result<(), String> {
+        match args {
+            "on" => {
+                self.manager.audit().start();
+                self.tui.borrow_mut().audit().start();
+                self.echo("Idle audit started.  Leave the session alone for a while, then /audit.".to_string());
+            },
+            "off" | "" => {
+                let elapsed = match self.manager.audit().elapsed() {
+                    Some(elapsed) => elapsed,
+                    None => return Err("The idle audit isn't running; /audit on starts it.".to_string()),
+                };
+                let mut found = self.manager.audit().report();
+                found.extend(self.tui.borrow_mut().audit().report());
+                if args == "off" {
+                    self.manager.audit().stop();
+                    self.tui.borrow_mut().audit().stop();
+                }
+
+                self.echo(format!("Idle audit, {}s so far:", elapsed.as_secs()));
+                if found.is_empty() {
+                    self.echo("  Nothing woke up for no reason.".to_string());
+                }
+                for line in found {
+                    self.echo(format!("  {}", line));
+                }
+            },
+            _ => return Err("Usage: /audit [on|off]".to_string()),
+        }
+        Ok(())
+    }
+
+    /// /alias name body -- make typing `name` (as the first word of a line) do `body` instead,
+    /// with %1, %2... and %* filled in from the rest of the line.  With just a name, show that
+    /// alias; with nothing, list them all.
+    fn cmd_alias(&mut self, args: &str) -> Result<(), String> {
+        let mut parts = args.splitn(2, ' ');
+        let name = parts.next().unwrap_or("");
+        let body = parts.next().unwrap_or("").trim();
+
+        if name.is_empty() {
+            let list: Vec<String> = self.aliases.list().iter()
+                .map(|(name, body)| format!("/alias {} {}", name, body))
+                .collect();
+            if list.is_empty() {
+                self.echo("No aliases defined.".to_string());
+            }
+            for line in list {
+                self.echo(line);
+            }
+        } else if body.is_empty() {
+            let shown = match self.aliases.get(name) {
+                Some(body) => format!("/alias {} {}", name, body),
+                None => return Err(format!("No alias {}", name)),
+            };
+            self.echo(shown);
+        } else {
+            self.aliases.set(name, body)?;
+        }
+        Ok(())
+    }
+
+    /// /set [name [value]] -- set a variable, or show one, or list them all.
+    fn cmd_set(&mut self, args: &str) -> Result<(), String> {
+        let mut parts = args.splitn(2, ' ');
+        let name = parts.next().unwrap_or("");
+        let value = parts.next().unwrap_or("").trim();
+
+        if name.is_empty() {
+            let list: Vec<String> = self.vars.list().iter()
+                .map(|(name, value)| format!("/set {} {}", name, value))
+                .collect();
+            if list.is_empty() {
+                self.echo("No variables set.".to_string());
+            }
+            for line in list {
+                self.echo(line);
+            }
+        } else if value.is_empty() {
+            let shown = match self.vars.get(name) {
+                Some(value) => format!("/set {} {}", name, value),
+                None => return Err(format!("No variable {}", name)),
+            };
+            self.echo(shown);
+        } else {
+            self.vars.set(name.to_string(), value.to_string());
+        }
+        Ok(())
+    }
+
+    /// /unset name -- forget a variable.
+    fn cmd_unset(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            return Err("Usage: /unset name".to_string());
+        }
+        match self.vars.unset(args) {
+            Some(_) => Ok(()),
+            None => Err(format!("No variable {}", args)),
+        }
+    }
+
+    /// /roll [-s[template]] expression -- work out an expression with dice in it, like 2d6+3 or
+    /// d20+str, and show what came up.  The total's kept in the variable `roll`.  With -s, it's
+    /// sent to the world in front instead, through the template (or the variable `roll_format`,
+    /// or "say I rolled %1: %2"), where %1 is the expression, %2 the total and %3 the dice.
+    fn cmd_roll(&mut self, args: &str) -> Result<(), String> {
+        let (opts, text) = options(args);
+        if text.is_empty() {
+            return Err("Usage: /roll [-s[template]] expression".to_string());
+        }
+
+        let expression = expr::parse(text)?;
+        dice::take_rolls();
+        let total = expression.eval(&self.vars);
+        let rolls = dice::take_rolls();
+        let total = total?.to_string();
+        let shown: Vec<String> = rolls.iter()
+            .map(|roll| {
+                let results: Vec<String> = roll.results.iter().map(|n| n.to_string()).collect();
+                format!("{}d{}: {}", roll.count, roll.sides, results.join(" "))
+            })
+            .collect();
+        let shown = shown.join(", ");
+        self.vars.set("roll".to_string(), total.clone());
+
+        let template = match opts.get(&'s') {
+            None => {
+                let dice = if shown.is_empty() { String::new() } else { format!(" ({})", shown) };
+                self.echo(format!("{} = {}{}", text, total, dice));
+                return Ok(());
+            },
+            Some(template) if !template.is_empty() => template.clone(),
+            Some(_) => self.vars.get("roll_format").unwrap_or("say I rolled %1: %2").to_string(),
+        };
+        let window = self.tui.borrow().active_window();
+        let cid = match self.sessions.for_window(&window) {
+            Some(session) => session.cid,
+            None => return Err("Not connected to anything.".to_string()),
+        };
+        self.send_line(cid, script::substitute(&template, &[text, &total, &shown]));
+        Ok(())
+    }
+
+    /// /template add name body, /template remove name, /template [list] -- define, forget or
+    /// list templates.  /template name [args] sends one, a line at a time, to the world in front
+    /// (or runs the lines that are commands.)  Quotes around a body are taken off, so
+    /// `/template add greet "bow %1"` works.
+    fn cmd_template(&mut self, args: &str) -> Result<(), String> {
+        let mut parts = args.splitn(2, ' ');
+        let first = parts.next().unwrap_or("");
+        let rest = parts.next().unwrap_or("").trim();
+
+        match first {
+            "" | "list" => {
+                let list: Vec<String> = self.templates.list().iter()
+                    .map(|(name, body)| format!("/template add {} {}", name, body))
+                    .collect();
+                if list.is_empty() {
+                    self.echo("No templates defined.".to_string());
+                }
+                for line in list {
+                    self.echo(line);
+                }
+            },
+            "add" => {
+                let mut parts = rest.splitn(2, ' ');
+                let name = parts.next().unwrap_or("");
+                let body = parts.next().unwrap_or("").trim();
+                let body = body.strip_prefix('"').and_then(|b| b.strip_suffix('"')).unwrap_or(body);
+                if body.is_empty() {
+                    return Err("Usage: /template add name body".to_string());
+                }
+                self.templates.set(name, body)?;
+            },
+            "remove" => {
+                if self.templates.remove(rest).is_none() {
+                    return Err(format!("No template {}", rest));
+                }
+            },
+            name => {
+                let args: Vec<&str> = rest.split_whitespace().collect();
+                let lines = self.templates.expand(name, &args, &self.vars)
+                    .ok_or_else(|| format!("No template {}", name))?;
+                let which = {
+                    let mut tui = self.tui.borrow_mut();
+                    let window = tui.active_window();
+                    tui.window_id(&window)
+                };
+                for line in lines {
+                    if line.starts_with('/') {
+                        self.command(&line);
+                    } else {
+                        self.server_line(which, line);
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// /unalias name -- forget an alias.
+    fn cmd_unalias(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            return Err("Usage: /unalias name".to_string());
+        }
+        match self.aliases.remove(args) {
+            Some(_) => Ok(()),
+            None => Err(format!("No alias {}", args)),
+        }
+    }
+
+    /// /trigger [-n<name>] [-p<priority>] [-f] [-i<condition>] [-g] [-h<style>] [-v] [-e<event>]
+    /// [-c] [-x<window>] [-o<window> [-s<style>]] [-r<window>:<name>] [-l<list>[:<word>]]
+    /// [-t[seconds]] pattern [= text] -- do something whenever a line from the server matches the
+    /// regex `pattern` (and the condition, an expression with no spaces in like
+    /// `autoheal=1&&%1<50`, is true, if there is one): gag it (-g), highlight it (-h), flash the
+    /// screen (-v), raise an event (-e), copy it to the clipboard (-c), clear a window (-x), rename
+    /// one (-r), add a word (the first group, if it isn't given) to a word list for completing
+    /// (-l), and/or send `text` back, or with -o, show it in a window instead (in a style, with
+    /// -s.)  With -t, `text` is a shell command instead, run in the background and killed after
+    /// `seconds` (10 by default), and what it prints is what's sent back (as text, even lines
+    /// starting with "/") or shown.  %1, %2... in `text`, new names and words are filled in from
+    /// the pattern's groups.  Triggers with higher priorities go first, and the first to match
+    /// stops the rest unless it falls through (-f).  With no arguments, list the triggers.
+    /// /trigger purge removes all of them (which asks first.)
+    fn cmd_trigger(&mut self, args: &str) -> Result<(), String> {
+        if args == "purge" {
+            let removed = self.triggers.clear();
+            self.echo(format!("Removed {} trigger(s).", removed));
+            return Ok(());
+        }
+        if args.is_empty() {
+            let list: Vec<String> = self.triggers.list().iter()
+                .map(|t| format!("{} (priority {}{}{}): {}", t.name, t.priority,
+                                 if t.fall_through { ", falls through" } else { "" },
+                                 t.condition.as_ref().map_or(String::new(), |c| format!(", if {}", c)),
+                                 t.pattern))
+                .collect();
+            if list.is_empty() {
+                self.echo("No triggers defined.".to_string());
+            }
+            for line in list {
+                self.echo(line);
+            }
+            return Ok(());
+        }
+
+        let (opts, rest) = options(args);
+        let (pattern, text) = match rest.find(" = ") {
+            Some(i) => (&rest[..i], Some(rest[i + 3..].trim())),
+            None => (rest, None),
+        };
+        let pattern = Regex::new(pattern).map_err(|e| format!("Bad pattern: {}", e))?;
+        let priority = match opts.get(&'p').map(|p| p.parse::<i32>()) {
+            Some(Ok(priority)) => priority,
+            Some(Err(_)) => return Err("The priority (-p) has to be a number".to_string()),
+            None => 0,
+        };
+        if let Some(condition) = opts.get(&'i') {
+            trigger::check_condition(condition)?;
+        }
+
+        let mut responses = vec![];
+        if opts.contains_key(&'g') {
+            responses.push(Response::Gag);
+        }
+        if let Some(spec) = opts.get(&'h') {
+            responses.push(Response::Highlight(style::style(spec)?));
+        }
+        if opts.contains_key(&'v') {
+            responses.push(Response::Flash);
+        }
+        if let Some(name) = opts.get(&'e') {
+            responses.push(Response::Event(name.clone()));
+        }
+        if opts.contains_key(&'c') {
+            responses.push(Response::Copy);
+        }
+        // Clearing goes before anything's put in the window.
+        if let Some(window) = opts.get(&'x') {
+            responses.push(Response::Clear(window.clone()));
+        }
+        let limit = match opts.get(&'t').map(|t| (t, t.parse::<u64>())) {
+            Some((t, _)) if t.is_empty() => Some(action::DEFAULT_TIME_LIMIT),
+            Some((_, Ok(secs))) if secs > 0 => Some(Duration::from_secs(secs)),
+            Some((t, _)) => return Err(format!("Bad time limit: {} (-t wants a number of seconds, more than 0.)", t)),
+            None => None,
+        };
+        match (text, opts.get(&'o'), limit) {
+            (Some(command), window, Some(limit)) => {
+                responses.push(Response::Shell { command: command.to_string(), limit, window: window.cloned() });
+            },
+            (None, _, Some(_)) => return Err("No command to run with -t (it needs = text)".to_string()),
+            (Some(text), Some(window), None) => {
+                let text = match opts.get(&'s') {
+                    Some(spec) => style::styled(text, spec)?,
+                    None => text.to_string(),
+                };
+                responses.push(Response::Echo { window: window.clone(), text });
+            },
+            (Some(text), None, None) => responses.push(Response::Send(text.to_string())),
+            (None, Some(_), None) => return Err("Nothing to show with -o (it needs = text)".to_string()),
+            (None, None, None) => { },
+        }
+        if let Some(rename) = opts.get(&'r') {
+            match rename.split_once(':') {
+                Some((window, to)) if !window.is_empty() && !to.is_empty() => {
+                    responses.push(Response::Rename { window: window.to_string(), to: to.to_string() });
+                },
+                _ => return Err("-r takes the window and its new name, like -rmap:map-%1".to_string()),
+            }
+        }
+        if let Some(complete) = opts.get(&'l') {
+            let (list, word) = complete.split_once(':').unwrap_or((complete, "%1"));
+            if list.is_empty() || word.is_empty() {
+                return Err("-l takes a word list, and what to add to it if not the first group, like -lplayers:%2".to_string());
+            }
+            responses.push(Response::Complete { list: list.to_string(), word: word.to_string() });
+        }
+        if responses.is_empty() {
+            return Err("A trigger has to do something (-g, -h, -v, -e, -c, -x, -r, -l, or = text)".to_string());
+        }
+
+        self.triggers.add(Trigger {
+            name: opts.get(&'n').cloned().unwrap_or_else(|| pattern.as_str().to_string()),
+            pattern,
+            priority,
+            fall_through: opts.contains_key(&'f'),
+            condition: opts.get(&'i').cloned(),
+            responses,
+        });
+        Ok(())
+    }
+
+    /// /untrigger name -- remove a trigger.  Triggers made without -n are named after their
+    /// pattern.
+    fn cmd_untrigger(&mut self, args: &str) -> Result<(), String> {
+        if !self.triggers.remove(args) {
+            return Err(format!("No trigger {}", args));
+        }
+        Ok(())
+    }
+
+    /// /lua code -- run some Lua, with the same `mint` table scripts get.
+    fn cmd_lua(&mut self, args: &str) -> Result<(), String> {
+        let result = self.scripts.run(args, "/lua");
+        // Whatever it asked for before it went wrong still happens.
+        self.script_actions(None);
+        result
+    }
+
+    /// /alarm HH:MM message -- or with no arguments, list the alarms that are set.
+    fn cmd_alarm(&mut self, args: &str) -> Result<(), String> {
+        if args.is_empty() {
+            let list: Vec<String> = self.alarms.list().iter()
+                .map(|a| format!("{} {}", a.local_time().hm(), a.message))
+                .collect();
+            if list.is_empty() {
+                self.echo("No alarms set.".to_string());
+            }
+            for line in list {
+                self.echo(line);
+            }
+            return Ok(());
+        }
+
+        let mut parts = args.splitn(2, ' ');
+        let time = parts.next().unwrap_or("");
+        let message = parts.next().unwrap_or("").trim().trim_matches('"').to_string();
+
+        let (hour, minute) = alarm::parse_time_of_day(time)
+            .ok_or_else(|| format!("Couldn't understand the time '{}' (expected HH:MM)", time))?;
+        match self.alarms.add(&mut self.timers.borrow_mut(), hour, minute, message) {
+            Ok(a) => self.echo(format!("Alarm set for {}.", a.local_time().hm())),
+            Err(e) => return Err(format!("Alarm set, but couldn't be saved: {}", e)),
+        }
+        Ok(())
+    }
+}
+
+/// All the client commands.
+fn commands() -> Dispatcher<Client> {
+    let mut commands = Dispatcher::new();
+    commands.register("after", "/after interval line", "Run a line once, a while from now.", Client::cmd_after);
+    commands.register("alarm", "/alarm [HH:MM message]", "Set an alarm, or list them.", Client::cmd_alarm);
+    commands.register("alias", "/alias [name [body]]", "Define an alias, or show them.", Client::cmd_alias);
+    commands.register("audit", "/audit [on|off]", "Count wakeups that didn't do anything.", Client::cmd_audit);
+    commands.register("background", "/background [-w<window>] color|off", "Give a window a background color.", Client::cmd_background);
+    commands.register("bind", "/bind [key [action|-]]", "Change what a key does, or list the keys.", Client::cmd_bind);
+    commands.register("charset", "/charset [name]", "Say what charset the world in front's text is in.", Client::cmd_charset);
+    commands.register("clear-all", "/clear-all", "Empty out every window.", Client::cmd_clear_all);
+    commands.register("clipboard", "/clipboard [osc52|command]", "Say how copying gets text to the clipboard.", Client::cmd_clipboard);
+    commands.register("color-pick", "/color-pick highlight pattern | background [window] | timestamps", "Pick a color from a chart, and use it.", Client::cmd_color_pick);
+    commands.register("colors", "/colors", "Show the colors styles can use.", Client::cmd_colors);
+    commands.register("complete", "/complete [-w<window>] [-d] [list [word...]]", "Add words to a list for Tab to complete from.", Client::cmd_complete);
+    commands.register("compress", "/compress [on|off]", "Switch compression for the world in front.", Client::cmd_compress);
+    commands.register("connect", "/connect [-w<window>] [-r] [-c<charset>] world|[socks5://|http://proxy:port/]host:port|exec:command", "Connect to a world, or run a program like one.", Client::cmd_connect);
+    commands.register("dim", "/dim on|off", "Dim the panes that typing doesn't go to.", Client::cmd_dim);
+    commands.register("disconnect", "/disconnect [window|all]", "Close a connection, or all of them.", Client::cmd_disconnect);
+    commands.register("divider", "/divider [-s<style>] [-w<windows>] [label]", "Draw a rule across windows.", Client::cmd_divider);
+    commands.register("esctimeout", "/esctimeout [milliseconds]", "Set how long ESC waits to see if it's Alt with another key.", Client::cmd_esctimeout);
+    commands.register("gag", "/gag [-c|-d] [pattern] | -r", "Hide lines that match a pattern, or review the ones hidden.", Client::cmd_gag);
+    commands.register("help", "/help", "List the commands.", Client::cmd_help);
+    commands.register("highlight", "/highlight [-d] [-v] [pattern [= style]]", "Show what matches a pattern in a style of its own.", Client::cmd_highlight);
+    commands.register("histsize", "/histsize lines", "Set how much scrollback windows keep.", Client::cmd_histsize);
+    commands.register("hyphenate", "/hyphenate on|off", "Hyphenate words too long for a line.", Client::cmd_hyphenate);
+    commands.register("idle", "/idle [-n] [interval|off]", "Say when the world in front's server has gone quiet.", Client::cmd_idle);
+    commands.register("isize", "/isize lines", "Set the input line's height.", Client::cmd_isize);
+    commands.register("jump", "/jump [end]", "Scroll back to the last mark, or to the end.", Client::cmd_jump);
+    commands.register("log", "/log [-w[window]] [start [-f<format>] [-s] [file] | stop]", "Log the world (or a window) in front to a file.", Client::cmd_log);
+    commands.register("lua", "/lua code", "Run some Lua.", Client::cmd_lua);
+    commands.register("mark", "/mark", "Put a divider with the time in the window.", Client::cmd_mark);
+    commands.register("msdp", "/msdp [variables]", "Show MSDP variables in the status bar, or list them.", Client::cmd_msdp);
+    #[cfg(feature = "metrics")]
+    commands.register("metrics", "/metrics [-i<seconds>] [file|off]", "Write metrics for Prometheus to a file.", Client::cmd_metrics);
+    commands.register("mouse", "/mouse [on|off]", "Use the mouse to scroll, select and pick panes, or leave it to the terminal.", Client::cmd_mouse);
+    commands.register("netstat", "/netstat", "List the connections.", Client::cmd_netstat);
+    commands.register("note", "/note [-w<world>] text", "Write down a note for a world.", Client::cmd_note);
+    commands.register("notes", "/notes [world]", "Show a world's notes.", Client::cmd_notes);
+    commands.register("pane", "/pane [-v|-r] name|off", "Give a window a pane of its own, or take it away.", Client::cmd_pane);
+    commands.register("quit", "/quit", "Leave.", Client::cmd_quit);
+    commands.register("raise", "/raise [-w<window>] [-d] pattern | -i<interval>", "Bring a window into view when a line matches.", Client::cmd_raise);
+    commands.register("raw", "/raw [on|off]", "Switch raw send mode for the world in front.", Client::cmd_raw);
+    commands.register("reconnect", "/reconnect [-d<seconds>] [-m<seconds>] [-n<tries>] [-c<errors>] [on|off]", "Reconnect the world in front if it drops.", Client::cmd_reconnect);
+    commands.register("repeat", "/repeat [-n<times>] [interval line]", "Run a line every so often, or list the repeats.", Client::cmd_repeat);
+    commands.register("roll", "/roll [-s[template]] expression", "Roll dice, like 2d6+3.", Client::cmd_roll);
+    commands.register("set", "/set [name [value]]", "Set a variable, or show them.", Client::cmd_set);
+    commands.register("sh", "/sh [-t<seconds>] command", "Run a shell command.", Client::cmd_sh);
+    commands.register("selftest", "/selftest", "Check the client's parts are working, for bug reports.", Client::cmd_selftest);
+    commands.register("showkey", "/showkey", "Describe the next key pressed.", Client::cmd_showkey);
+    commands.register("snapshot", "/snapshot [-w<window>] name [lines]", "Copy lines into a new window.", Client::cmd_snapshot);
+    commands.register("split", "/split [lines|off]", "Keep the newest lines in sight while scrolling back.", Client::cmd_split);
+    commands.register("status", "/status segment [text]", "Set a segment of the status bar, or take it away.", Client::cmd_status);
+    commands.register("stats", "/stats [window]", "Show how a connection is doing.", Client::cmd_stats);
+    commands.register("template", "/template [add name body | remove name | name [args]]", "Send a template, or define them.", Client::cmd_template);
+    commands.register("threads", "/threads [restart n]", "List the listening threads, or restart one that's stalled.", Client::cmd_threads);
+    commands.register("tick", "/tick [-w<seconds>] [length|sync|off]", "Count down to the MUD's next tick.", Client::cmd_tick);
+    commands.register("timestamps", "/timestamps on|off|style", "Show when each line arrived.", Client::cmd_timestamps);
+    commands.register("trigger", "/trigger [options] pattern [= text] | purge", "Define a trigger, list them, or remove them all.", Client::cmd_trigger);
+    commands.register("unalias", "/unalias name", "Forget an alias.", Client::cmd_unalias);
+    commands.register("unbind", "/unbind key", "Make a key do nothing by itself.", Client::cmd_unbind);
+    commands.register("unrepeat", "/unrepeat number|all", "Stop a repeat.", Client::cmd_unrepeat);
+    commands.register("unset", "/unset name", "Forget a variable.", Client::cmd_unset);
+    commands.register("untrigger", "/untrigger name", "Remove a trigger.", Client::cmd_untrigger);
+    commands.register("wall", "/wall [-s<style>] [-w<windows>] text", "Write a line to windows.", Client::cmd_wall);
+    commands.register("window", "/window name", "Show a window.", Client::cmd_window);
+    commands.register("windows", "/windows", "List the windows.", Client::cmd_windows);
+    commands.register("wrapsize", "/wrapsize [columns|off]", "Wrap lines narrower than the window.", Client::cmd_wrapsize);
+    commands.register("wrapspace", "/wrapspace columns", "Set the indent for wrapped lines.", Client::cmd_wrapspace);
+
+    // These throw things away that'd be a pain to get back, so the user has to say yes first (or
+    // put --yes after them.)
+    commands.destructive("clear-all", |_| Some("Empty out every window?  What's in them now will be gone.".to_string()));
+    commands.destructive("disconnect", |args| match args {
+        "all" => Some("Close every connection?".to_string()),
+        _ => None,
+    });
+    commands.destructive("trigger", |args| match args {
+        "purge" => Some("Remove every trigger?".to_string()),
+        _ => None,
+    });
+    commands
+}
+
+/// Open a log as /log start asks, with `args` being what comes after "start": [-f<format>] [-s]
+/// [file].  With no file, it's named after `name` and the time, in the logs directory.
+fn open_log(name: &str, args: &str) -> Result<SessionLog, String> {
+    let (opts, file) = options(args);
+    let format = match (opts.get(&'f'), file) {
+        (Some(format), _) => LogFormat::from_name(format)
+            .ok_or_else(|| format!("Unknown log format {} (try ansi, plain or html.)", format))?,
+        (None, "") => LogFormat::Ansi,
+        (None, file) => LogFormat::for_path(file.as_ref()),
+    };
+    let path = match file {
+        "" => log::default_path(name, LocalTime::now(), format)
+            .ok_or("Couldn't find somewhere to keep logs.")?,
+        file => PathBuf::from(file),
+    };
+    let mut log = SessionLog::open(&path, format)
+        .map_err(|e| format!("Couldn't open log file {}: {}", path.display(), e))?;
+    log.log_sent = opts.contains_key(&'s');
+    Ok(log)
+}
+
+/// Where a log's going and in what format, e.g. "mud.log (ansi)".
+fn describe_log(log: &SessionLog) -> String {
+    format!("{} ({})", log.path.display(), format!("{:?}", log.format).to_lowercase())
+}
+
+/// Split leading options like "-sbold" off of a command's arguments, tinyfugue style.  Returns the
+/// options by letter, and whatever's left after them.  A "-" with a digit or "(" after it is a
+/// minus sign rather than an option, so /roll -1+d6 works.
+fn options(args: &str) -> (HashMap<char, String>, &str) {
+    let mut opts = HashMap::new();
+    let mut rest = args.trim_start();
+
+    while rest.starts_with('-') {
+        if rest[1..].starts_with(|c: char| c.is_ascii_digit() || c == '(') {
+            break;
+        }
+        let end = rest.find(' ').unwrap_or(rest.len());
+        let mut opt = rest[1..end].chars();
+        match opt.next() {
+            Some(letter) => { opts.insert(letter, opt.collect()); },
+            // A lone "-" ends the options, so text can start with one.
+            None => { rest = rest[end..].trim_start(); break; },
+        }
+        rest = rest[end..].trim_start();
+    }
+
+    (opts, rest)
+}
+
+#[test]
+fn status_from_triggers() {
+    let (tui, _headless) = TermUiManager::headless();
+    let mut client = Client::with_ui(None, tui);
+    client.command("/trigger ^You attack (\\w+) \\((\\d+)%\\) = /status target Target: %1 (%2%%)");
+
+    let which = ConnectionID::new(1, 0);
+    let line = |text: &str| Event::ServerText { line: text.to_string(), which, links: vec![] };
+    client.handle(line("You attack rat (40%)"));
+    assert_eq!(client.tui.borrow().status_segment("target").as_deref(), Some("Target: rat (40%)"));
+    client.handle(line("You attack rat (15%)"));
+    assert_eq!(client.tui.borrow().status_segment("target").as_deref(), Some("Target: rat (15%)"));
+
+    client.command("/status target");
+    assert_eq!(client.tui.borrow().status_segment("target"), None);
+}
+
+#[test]
+fn raise_rules() {
+    let (tui, _headless) = TermUiManager::headless();
+    let mut client = Client::with_ui(None, tui);
+    client.command("/raise -wchat tells you");
+    client.command("/raise -i1m");
+    assert_eq!(client.tui.borrow().raise_interval(), Duration::from_secs(60));
+
+    client.show("chat".to_string(), Line::client("Bob says hi"));
+    assert_eq!(client.tui.borrow().active_window(), "default");
+    client.show("chat".to_string(), Line::client("Bob tells you hi"));
+    assert_eq!(client.tui.borrow().active_window(), "chat");
+
+    // Too soon after the last one.
+    client.tui.borrow_mut().show_window("default".to_string());
+    client.show("chat".to_string(), Line::client("Bob tells you again"));
+    assert_eq!(client.tui.borrow().active_window(), "default");
+
+    client.command("/raise -d tells you");
+    assert!(client.tui.borrow().raise_rules().is_empty());
+}
+
+#[test]
+fn escape_timeout() {
+    let (tui, _headless) = TermUiManager::headless();
+    let mut client = Client::with_ui(None, tui);
+    client.command("/esctimeout 200");
+    assert_eq!(client.tui.borrow().escape_timeout(), Duration::from_millis(200));
+    client.command("/esctimeout 0");
+    assert_eq!(client.tui.borrow().escape_timeout(), Duration::from_millis(200));
+}
+
+#[test]
+fn options_and_minus_signs() {
+    let (opts, rest) = options("-s -n3 -1+d6");
+    assert_eq!(opts.get(&'s').map(String::as_str), Some(""));
+    assert_eq!(opts.get(&'n').map(String::as_str), Some("3"));
+    assert_eq!(rest, "-1+d6");
+    assert_eq!(options("-(2+3)*d4").1, "-(2+3)*d4");
+    assert_eq!(options("- -x").1, "-x");
+}
+
+#[test]
+fn trigger_shell_commands() {
+    let (tui, headless) = TermUiManager::headless();
+    let mut client = Client::with_ui(None, tui);
+    assert!(client.cmd_trigger("-t0 ^(\\w+) waves = true").unwrap_err().starts_with("Bad time limit"));
+    assert!(client.cmd_trigger("-tsoon ^(\\w+) waves = true").unwrap_err().starts_with("Bad time limit"));
+    assert!(client.triggers.list().is_empty());
+    client.command("/trigger -t5 -oshell ^(\\w+) waves = echo $((2 + 2)) waves back at %1");
+
+    let which = ConnectionID::new(1, 0);
+    client.handle(Event::ServerText { line: "Bob waves".to_string(), which, links: vec![] });
+    // The command's running in the background, so nothing's back yet.
+    assert!(headless.text("shell").is_empty());
+    loop {
+        match client.manager.next_event() {
+            Ok(event @ Event::ActionDone { .. }) => break client.handle(event),
+            Ok(_) => { },
+            Err(e) => panic!("The manager failed: {}", e),
+        }
+    }
+    assert_eq!(headless.text("shell"), vec!["4 waves back at Bob".to_string()]);
+    assert!(client.trigger_jobs.is_empty());
+}
+
+#[test]
+fn trigger_shell_output_is_sent_as_text() {
+    use crate::net::mock::{MockMud, Step as MudStep};
+
+    let mud = MockMud::start(vec![
+        MudStep::Line("Bob says /sh x"),
+        MudStep::Expect(b"/sh x\r\n".to_vec()),
+        MudStep::Close,
+    ]);
+    let (tui, _headless) = TermUiManager::headless();
+    let mut client = Client::with_ui(None, tui);
+    client.command("/trigger -t5 ^\\w+ says (.*)$ = echo %1");
+    client.command(&format!("/connect -wmud {}", mud.address));
+
+    // The output's "/sh x", which goes to the MUD, rather than running /sh.
+    loop {
+        match client.manager.next_event() {
+            Ok(event @ Event::ActionDone { .. }) => break client.handle(event),
+            Ok(event) => client.handle(event),
+            Err(e) => panic!("The manager failed: {}", e),
+        }
+    }
+    assert!(client.trigger_jobs.is_empty());
+    assert_eq!(mud.finish(), b"/sh x\r\n".to_vec());
+}
+
+#[test]
+fn trigger_sends_captures_as_text() {
+    use crate::net::mock::{MockMud, Step as MudStep};
+
+    let mud = MockMud::start(vec![
+        MudStep::Line("Bob says /sh x"),
+        MudStep::Expect(b"/sh x\r\n".to_vec()),
+        MudStep::Close,
+    ]);
+    let (tui, _headless) = TermUiManager::headless();
+    let mut client = Client::with_ui(None, tui);
+    client.command("/trigger ^\\w+ says (.*)$ = %1");
+    client.command(&format!("/connect -wmud {}", mud.address));
+
+    loop {
+        match client.manager.next_event() {
+            Ok(event @ Event::ConnectionEnd { .. }) => break client.handle(event),
+            Ok(event) => client.handle(event),
+            Err(e) => panic!("The manager failed: {}", e),
+        }
+    }
+    assert!(client.trigger_jobs.is_empty());
+    assert_eq!(mud.finish(), b"/sh x\r\n".to_vec());
+}
+
+#[test]
+fn programs_refuse_network_settings() {
+    let (tui, _headless) = TermUiManager::headless();
+    let mut client = Client::with_ui(None, tui);
+    client.command("/connect -wcat exec:cat");
+
+    assert_eq!(client.cmd_charset("cp437"), Err("Programs' text is always UTF-8.".to_string()));
+    assert_eq!(client.cmd_compress("on"), Err("Programs' text is never compressed.".to_string()));
+    assert!(client.cmd_reconnect("on").unwrap_err().starts_with("Programs aren't reconnected"));
+    client.cmd_disconnect("").unwrap();
+}
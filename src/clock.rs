@@ -0,0 +1,79 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The local wall-clock time, broken down.  We get this from the C library rather than pulling in
+/// a whole date/time crate, since all we really need is to show the user times they'll recognize.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1-12
+    pub month: u32,
+    /// 1-31
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl LocalTime {
+    pub fn now() -> LocalTime {
+        LocalTime::at(SystemTime::now())
+    }
+
+    /// The local time at some moment, e.g. when a line arrived.
+    pub fn at(time: SystemTime) -> LocalTime {
+        let secs = time.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        LocalTime::from_unix(secs as libc::time_t)
+    }
+
+    pub fn from_unix(secs: libc::time_t) -> LocalTime {
+        // localtime_r() is the thread-safe version; it fills in the struct we give it instead of
+        // a static one.  All-zeroes is a valid `tm`, so mem::zeroed() is fine here.
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::localtime_r(&secs, &mut tm);
+        }
+
+        LocalTime {
+            year: tm.tm_year + 1900,
+            month: (tm.tm_mon + 1) as u32,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+        }
+    }
+
+    /// e.g. "2024-03-09"
+    pub fn ymd(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    /// e.g. "22:14"
+    pub fn hm(&self) -> String {
+        format!("{:02}:{:02}", self.hour, self.minute)
+    }
+
+    /// e.g. "22:14:05"
+    pub fn hms(&self) -> String {
+        format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+/// A length of time the way you'd say it roughly, e.g. "2h05m", "7m" or "40s".
+pub fn short_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[test]
+fn short_durations() {
+    assert_eq!(short_duration(Duration::from_secs(40)), "40s");
+    assert_eq!(short_duration(Duration::from_secs(7 * 60 + 59)), "7m");
+    assert_eq!(short_duration(Duration::from_secs(2 * 3600 + 5 * 60)), "2h05m");
+}
@@ -0,0 +1,194 @@
+//! Client commands: lines like "/connect example.com:4000", parsed into a name and arguments and
+//! handed to whatever's registered for that name.
+
+use std::collections::BTreeMap;
+
+/// A client command, as typed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Command {
+    /// Without the "/", and in lower case.
+    pub name: String,
+    /// Everything after the name, with the spaces around it trimmed off.
+    pub args: String,
+}
+
+impl Command {
+    /// Split a line into a command and its arguments.  None if the line doesn't start with "/",
+    /// or is just "/".
+    pub fn parse(line: &str) -> Option<Command> {
+        let rest = line.strip_prefix('/')?;
+        let mut parts = rest.splitn(2, char::is_whitespace);
+        let name = parts.next().filter(|name| !name.is_empty())?;
+        Some(Command {
+            name: name.to_lowercase(),
+            args: parts.next().unwrap_or("").trim().to_string(),
+        })
+    }
+}
+
+/// What runs a command: given whatever `T` the commands work on, and the arguments.  If it
+/// returns an error, it's shown to the user.
+pub type Handler<T> = fn(&mut T, &str) -> Result<(), String>;
+
+/// For commands that can throw things away: given the arguments, what to ask the user before
+/// going ahead, or None if it's nothing to ask about.
+pub type Question = fn(&str) -> Option<String>;
+
+/// What goes in a destructive command's arguments to have it go ahead without asking, e.g. from
+/// a script, or once the user's said yes.
+pub const YES: &str = "--yes";
+
+struct Registered<T> {
+    handler: Handler<T>,
+    usage: &'static str,
+    help: &'static str,
+    question: Option<Question>,
+}
+
+/// The commands there are and what handles them.  `T` is whatever the handlers work on (for the
+/// client, the Client itself.)
+pub struct Dispatcher<T> {
+    commands: BTreeMap<String, Registered<T>>,
+}
+
+impl<T> Dispatcher<T> {
+    pub fn new() -> Dispatcher<T> {
+        Dispatcher { commands: BTreeMap::new() }
+    }
+
+    /// Register a command.  `usage` is how to use it, like "/connect [-w<window>] host:port", and
+    /// `help` is a sentence about what it does.  Registering a name again replaces the handler.
+    pub fn register(&mut self, name: &str, usage: &'static str, help: &'static str, handler: Handler<T>) {
+        self.commands.insert(name.to_lowercase(), Registered { handler, usage, help, question: None });
+    }
+
+    /// Mark a registered command as one that can throw things away, which the user has to say
+    /// yes to first (see ask().)  `question` says which arguments that goes for, and what to ask.
+    pub fn destructive(&mut self, name: &str, question: Question) {
+        if let Some(registered) = self.commands.get_mut(name) {
+            registered.question = Some(question);
+        }
+    }
+
+    /// What to ask the user before running `command`, if anything.  If it's destructive and YES
+    /// is one of its arguments, there's nothing to ask, and YES is taken out of them so the
+    /// handler doesn't see it.
+    pub fn ask(&self, command: &mut Command) -> Option<String> {
+        let question = self.commands.get(&command.name)?.question?;
+        if let Some(args) = without_yes(&command.args) {
+            command.args = args;
+            return None;
+        }
+        question(&command.args)
+    }
+
+    /// Run a command.  Errors are for the user: unknown commands, or whatever the handler said
+    /// was wrong.
+    pub fn dispatch(&self, target: &mut T, command: &Command) -> Result<(), String> {
+        (self.handler(&command.name)?)(target, &command.args)
+    }
+
+    /// The handler for a command, or an error saying there isn't one.  For when dispatch() can't
+    /// be used because the target owns the Dispatcher: take the handler out, then call it.
+    pub fn handler(&self, name: &str) -> Result<Handler<T>, String> {
+        match self.commands.get(name) {
+            Some(registered) => Ok(registered.handler),
+            None => Err(format!("Unknown command /{} (/help lists them)", name)),
+        }
+    }
+
+    pub fn usage(&self, name: &str) -> Option<&'static str> {
+        self.commands.get(name).map(|r| r.usage)
+    }
+
+    /// Every command's name, in order.
+    pub fn names(&self) -> Vec<String> {
+        self.commands.keys().cloned().collect()
+    }
+
+    /// (usage, help) for every command, in order by name.
+    pub fn list(&self) -> Vec<(&'static str, &'static str)> {
+        self.commands.values().map(|r| (r.usage, r.help)).collect()
+    }
+}
+
+/// `args` with YES taken out (along with a space next to it), and the rest left just as it was,
+/// or None if YES isn't one of them.
+fn without_yes(args: &str) -> Option<String> {
+    let mut from = 0;
+    while let Some(i) = args[from..].find(YES) {
+        let (start, end) = (from + i, from + i + YES.len());
+        let (before, after) = (&args[..start], &args[end..]);
+        // It has to be a word of its own, not part of one.
+        let alone = (before.is_empty() || before.ends_with(char::is_whitespace))
+            && (after.is_empty() || after.starts_with(char::is_whitespace));
+        if alone {
+            let after = after.strip_prefix(char::is_whitespace).unwrap_or(after);
+            let before = if after.is_empty() { before.trim_end() } else { before };
+            return Some(format!("{}{}", before, after));
+        }
+        from = end;
+    }
+    None
+}
+
+impl<T> Default for Dispatcher<T> {
+    fn default() -> Dispatcher<T> {
+        Dispatcher::new()
+    }
+}
+
+#[test]
+fn dispatch_commands() {
+    assert_eq!(Command::parse("/Connect  example.com:4000 "), Some(Command {
+        name: "connect".to_string(),
+        args: "example.com:4000".to_string(),
+    }));
+    assert_eq!(Command::parse("/quit").map(|c| c.args), Some(String::new()));
+    assert_eq!(Command::parse("/"), None);
+    assert_eq!(Command::parse("say /quit"), None);
+
+    let mut commands: Dispatcher<Vec<String>> = Dispatcher::new();
+    commands.register("echo", "/echo text", "Say something.", |said, args| {
+        if args.is_empty() {
+            return Err("Usage: /echo text".to_string());
+        }
+        said.push(args.to_string());
+        Ok(())
+    });
+
+    let mut said = vec![];
+    assert!(commands.dispatch(&mut said, &Command::parse("/ECHO hi").unwrap()).is_ok());
+    assert_eq!(commands.dispatch(&mut said, &Command::parse("/echo").unwrap()),
+               Err("Usage: /echo text".to_string()));
+    assert!(commands.dispatch(&mut said, &Command::parse("/nope").unwrap()).is_err());
+    assert_eq!(said, vec!["hi".to_string()]);
+}
+
+#[test]
+fn destructive_commands() {
+    let mut commands: Dispatcher<Vec<String>> = Dispatcher::new();
+    commands.register("forget", "/forget all|thing", "Forget things.", |_, _| Ok(()));
+    commands.destructive("forget", |args| match args {
+        "all" => Some("Forget everything?".to_string()),
+        _ => None,
+    });
+
+    let mut command = Command::parse("/forget all").unwrap();
+    assert_eq!(commands.ask(&mut command), Some("Forget everything?".to_string()));
+    assert_eq!(commands.ask(&mut Command::parse("/forget keys").unwrap()), None);
+    let mut command = Command::parse("/forget --yes all").unwrap();
+    assert_eq!(commands.ask(&mut command), None);
+    assert_eq!(command.args, "all");
+    let mut command = Command::parse("/forget  a  b --yes").unwrap();
+    assert_eq!(commands.ask(&mut command), None);
+    assert_eq!(command.args, "a  b");
+    let mut command = Command::parse("/forget --yes-please  x --yes   y").unwrap();
+    assert_eq!(commands.ask(&mut command), None);
+    assert_eq!(command.args, "--yes-please  x   y");
+    // Only destructive commands have --yes taken out.
+    commands.register("echo", "/echo text", "Say something.", |_, _| Ok(()));
+    let mut command = Command::parse("/echo --yes").unwrap();
+    assert_eq!(commands.ask(&mut command), None);
+    assert_eq!(command.args, "--yes");
+}
@@ -0,0 +1,31 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Where mint keeps its files: $XDG_CONFIG_HOME/mint, or ~/.config/mint if that isn't set.  None
+/// if we can't figure out where the user's home directory is.
+///
+/// This doesn't create the directory; use ensure_config_dir() for that.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("mint"))
+}
+
+/// Like config_dir(), but creates the directory if it doesn't exist yet.
+pub fn ensure_config_dir() -> Option<PathBuf> {
+    let dir = config_dir()?;
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+/// Something that can go in a file name, made from a world's name (or anything else the user
+/// made up.)  Names can have anything in them, but a file name can't have slashes, and it's best
+/// off without dots at the start or control characters anywhere.
+pub fn file_name(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+    name.trim_start_matches('.').to_string()
+}
@@ -0,0 +1,130 @@
+//! Demos: a script of text to show, commands to run and pauses in between, played instead of
+//! connecting anywhere, so screenshots and recordings come out the same every time.
+//!
+//! A script has a step on each line.  Blank lines, and ones starting with #, don't count.
+//!
+//! ```text
+//! # Pretend to connect.
+//! text \e[1;36mWelcome to Example MUD!\e[0m
+//! wait 1.5
+//! text -wchat \e[35mAnnie says, 'hello!'\e[0m
+//! /pane chat
+//! ```
+//!
+//! `wait` pauses for that many seconds.  `text` shows a line as if a server had sent it, in the
+//! window after -w (or the one in front), with \e for ESC, \a for a bell and \\ for a backslash.
+//! Anything starting with / is run as a command, the same as if it had been typed.
+
+use crate::net::ConnectionID;
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// What demo text says it's from.  Slot 0 never belongs to a real connection.
+pub const SOURCE: ConnectionID = ConnectionID::new(0, 0);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Wait(Duration),
+    Text { window: Option<String>, text: String },
+    Command(String),
+}
+
+/// A demo being played: the steps that haven't been yet.
+pub struct Demo {
+    steps: VecDeque<Step>,
+}
+
+impl Demo {
+    /// Read a script, saying which line's wrong if one is.
+    pub fn parse(script: &str) -> Result<Demo, String> {
+        let mut steps = VecDeque::new();
+        for (n, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = step(line).map_err(|e| format!("Line {}: {}", n + 1, e))?;
+            steps.push_back(step);
+        }
+        Ok(Demo { steps })
+    }
+
+    pub fn load(path: &Path) -> Result<Demo, String> {
+        let script = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+        Demo::parse(&script)
+    }
+
+    /// The next step, or None once it's over.
+    pub fn next_step(&mut self) -> Option<Step> {
+        self.steps.pop_front()
+    }
+}
+
+fn step(line: &str) -> Result<Step, String> {
+    if line.starts_with('/') {
+        return Ok(Step::Command(line.to_string()));
+    }
+    let (verb, rest) = match line.find(' ') {
+        Some(space) => (&line[..space], line[space + 1..].trim_start()),
+        None => (line, ""),
+    };
+    match verb {
+        "wait" => {
+            let seconds: f64 = rest.parse().map_err(|_| format!("Not a number of seconds: {}", rest))?;
+            if !seconds.is_finite() || seconds < 0.0 {
+                return Err(format!("Not a number of seconds: {}", rest));
+            }
+            Ok(Step::Wait(Duration::from_secs_f64(seconds)))
+        },
+        "text" => {
+            let (window, text) = match rest.strip_prefix("-w") {
+                Some(rest) => match rest.find(' ') {
+                    Some(space) => (Some(rest[..space].to_string()), &rest[space + 1..]),
+                    None => (Some(rest.to_string()), ""),
+                },
+                None => (None, rest),
+            };
+            Ok(Step::Text { window, text: unescape(text) })
+        },
+        _ => Err(format!("Don't know how to {} (try wait, text or a /command.)", verb)),
+    }
+}
+
+/// Turn \e, \a and \\ into what they stand for.  Any other backslash is left alone.
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('e')) => '\x1b',
+            ('\\', Some('a')) => '\x07',
+            ('\\', Some('\\')) => '\\',
+            _ => {
+                out.push(c);
+                continue;
+            },
+        };
+        chars.next();
+        out.push(escaped);
+    }
+    out
+}
+
+#[test]
+fn demo_scripts() {
+    let mut demo = Demo::parse("# A demo\n\ntext \\e[1mHello\\e[0m \\\\o/\nwait 0.5\n\
+                                text -wchat Annie waves.\ntext -wchat\n/window chat\n").unwrap();
+    assert_eq!(demo.next_step(), Some(Step::Text { window: None, text: "\x1b[1mHello\x1b[0m \\o/".to_string() }));
+    assert_eq!(demo.next_step(), Some(Step::Wait(Duration::from_millis(500))));
+    assert_eq!(demo.next_step(), Some(Step::Text { window: Some("chat".to_string()), text: "Annie waves.".to_string() }));
+    assert_eq!(demo.next_step(), Some(Step::Text { window: Some("chat".to_string()), text: String::new() }));
+    assert_eq!(demo.next_step(), Some(Step::Command("/window chat".to_string())));
+    assert_eq!(demo.next_step(), None);
+
+    assert_eq!(Demo::parse("wait\n").err(), Some("Line 1: Not a number of seconds: ".to_string()));
+    assert!(Demo::parse("text hi\nwait -1").err().unwrap().starts_with("Line 2:"));
+    assert!(Demo::parse("dance").is_err());
+}
@@ -0,0 +1,69 @@
+//! The one error type the parts of the client hand each other: connections, the user interface,
+//! and the threads that listen for things on their behalf.  Commands still complain in plain
+//! Strings, since all anyone does with those is show them to the user; a MintError turns into
+//! one of those on the way out.
+
+use crate::net::ConnectionID;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::mpsc;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MintError {
+    /// Something went wrong talking to a server, or trying to (including looking it up.)
+    Net(String),
+    /// There's no connection with that ID, or not any more.
+    NoConnection(ConnectionID),
+    /// The user interface couldn't do what it was asked, e.g. there's no such window.
+    Ui(String),
+    /// A channel to another thread broke, which means whatever was on the other end is gone.
+    Channel(String),
+    /// Something that shouldn't happen did, like a listening thread dying.
+    Internal(String),
+}
+
+impl fmt::Display for MintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MintError::Net(ref why) => write!(f, "{}", why),
+            MintError::NoConnection(cid) => write!(f, "There's no connection {}.", cid),
+            MintError::Ui(ref why) => write!(f, "{}", why),
+            MintError::Channel(ref why) => write!(f, "Lost touch with another thread: {}", why),
+            MintError::Internal(ref why) => write!(f, "Internal error: {}", why),
+        }
+    }
+}
+
+impl Error for MintError {}
+
+impl From<io::Error> for MintError {
+    fn from(e: io::Error) -> MintError {
+        MintError::Net(e.to_string())
+    }
+}
+
+impl<T> From<mpsc::SendError<T>> for MintError {
+    fn from(_: mpsc::SendError<T>) -> MintError {
+        MintError::Channel("nobody's listening at the other end".to_string())
+    }
+}
+
+impl From<MintError> for String {
+    fn from(e: MintError) -> String {
+        e.to_string()
+    }
+}
+
+#[test]
+fn error_conversions() {
+    let (tx, rx) = mpsc::channel::<u8>();
+    drop(rx);
+    let e: MintError = tx.send(1).unwrap_err().into();
+    assert!(matches!(e, MintError::Channel(_)));
+
+    let e: MintError = io::Error::new(io::ErrorKind::ConnectionRefused, "refused").into();
+    assert_eq!(e, MintError::Net("refused".to_string()));
+    assert_eq!(String::from(MintError::NoConnection(ConnectionID::new(3, 0))), "There's no connection 3.");
+}
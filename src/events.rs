@@ -1,16 +1,35 @@
 
-use crate::meta::{Event, EventSource, EventManager, ReadinessPager};
+use crate::audit::IdleAudit;
+use crate::error::MintError;
+use crate::meta::{Event, EventSource, EventManager, Origin, ReadinessPager, Stamped, ThreadInfo, ThreadStatus};
 
 use std::thread;
 use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::VecDeque;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::time::{Duration, Instant};
 
-/// A notice sent by a child thread, either 'data is ready' or 'fatal error.'
+/// How long we wait for a notice from any thread before checking on the ones that promised to
+/// send heartbeats anyway.
+const WATCHDOG_PERIOD: Duration = Duration::from_secs(1);
+
+/// How many heartbeats in a row a listening thread can miss before we decide it's stalled.
+const MISSED_HEARTBEATS: u32 = 3;
+
+/// A listener that stops is started over (if its source can), unless it's stopped this many times
+/// within RESTART_WINDOW already; then it's taken to be broken for good, rather than restarted
+/// over and over.
+const MAX_RESTARTS: usize = 3;
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// A notice sent by a child thread: 'data is ready,' 'fatal error,' or 'still alive.'
 enum StateNotice {
     Ready,
-    Error(String),
+    Error(MintError),
+    Heartbeat,
 }
 
 /// Listener for readiness/error notices sent out by child threads.  The type parameter `I`
@@ -30,15 +49,28 @@ impl<I> Listener<I>
         }
     }
 
-    fn clone_tx(&self, tag: I) -> Pager<I> {
+    fn clone_tx(&self, tag: I, flagged: Arc<AtomicBool>) -> Pager<I> {
         Pager {
             tx: self.tx.clone(),
             tag: tag,
+            flagged,
         }
     }
 
-    fn recv(&self) -> (I, StateNotice) {
-        self.rx.recv().expect("Couldn't receive any more readiness signals")
+    /// Everything that's arrived and not been received yet, without waiting for more.
+    fn drain(&self) -> Vec<(I, StateNotice)> {
+        self.rx.try_iter().collect()
+    }
+
+    /// Wait for a notice, but give up and return None after `timeout`.
+    fn recv_timeout(&self, timeout: Duration) -> Option<(I, StateNotice)> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(notice) => Some(notice),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                panic!("Couldn't receive any more readiness signals")
+            },
+        }
     }
 }
 
@@ -47,31 +79,68 @@ struct Pager<I>
     where I: Sync + Send + Copy {
     tx: mpsc::Sender<(I, StateNotice)>,
     tag: I,
+    // Set when we've said we're ready and the manager hasn't called process() since.  Saying it
+    // again before then would only wake the manager up for nothing, since process() takes
+    // everything that's waiting anyway.
+    flagged: Arc<AtomicBool>,
 }
 
+// If a send fails, the ThreadedManager is gone, which only happens when the program's on its way
+// out.  There's nobody left to tell, so there's no sense in panicking about it.
 impl<I> ReadinessPager for Pager<I>
     where I: Sync + Send + Copy {
     fn ok(&mut self) {
-        self.tx.send((self.tag, StateNotice::Ready)).expect("Error send()ing to notify EventManager of readiness");
+        if self.flagged.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let _ = self.tx.send((self.tag, StateNotice::Ready));
+    }
+
+    fn err(&mut self, why: MintError) {
+        let _ = self.tx.send((self.tag, StateNotice::Error(why)));
     }
 
-    fn err(&mut self, why: String) {
-        self.tx.send((self.tag, StateNotice::Error(why))).expect("Error send()ing to notify EventManager of error");
+    fn heartbeat(&mut self) {
+        let _ = self.tx.send((self.tag, StateNotice::Heartbeat));
     }
 }
 
+/// What the ThreadedManager knows about each listening thread it started.
+struct ListenerInfo {
+    name: String,
+    /// Index into ThreadedManager::sources.
+    source: usize,
+    heartbeat_interval: Option<Duration>,
+    last_heartbeat: Instant,
+    // Also means we only complain once per stall, not every time we check.
+    status: ThreadStatus,
+    // Shared with the thread's Pager; see there.
+    flagged: Arc<AtomicBool>,
+    // When it's been restarted, within RESTART_WINDOW or so (older ones are let go of when it
+    // next stops), and how many times in all.
+    recent_restarts: Vec<Instant>,
+    restarts: u32,
+}
+
 /// System to manage threads listening for data, process the data in an orderly fashion and return
 /// Events to the caller.
 pub struct ThreadedManager {
-    // We're using usize to disambiguate between the EventSources--since we're going to be pushing them
-    // onto a Vec anyway and we don't plan to ever toss out any old entries.
+    // We're using usize to disambiguate between the listening threads--since we're going to be
+    // pushing them onto a Vec anyway and we don't plan to ever toss out any old entries.  Each
+    // listener knows which EventSource it belongs to, and the sources are kept the same way.
     endpoint: Listener<usize>,
     sources: Vec<Rc<RefCell<EventSource>>>,
-    // Has a fatal error occurred?  (If so, we want to refuse to do anything.)
-    poisoned: bool,
+    listeners: Vec<ListenerInfo>,
     // Any time we receive more than one event, we 'cache' the events so that we can return one at
-    // a time to the caller.  Hopefully it's fast about consuming them.
-    events_waiting: VecDeque<Event>,
+    // a time to the caller.  Hopefully it's fast about consuming them.  They're stamped on the
+    // way in (see stamp()), which keeps them in order.
+    events_waiting: VecDeque<Stamped>,
+    // How many Events each source has come up with, indexed like `sources`, which is also the
+    // last sequence number each one's had.  Likewise for the Events we come up with ourselves.
+    event_counts: Vec<u64>,
+    manager_count: u64,
+    // Counts process() calls that didn't come up with anything, when it's switched on.
+    audit: IdleAudit,
 }
 
 impl ThreadedManager {
@@ -79,8 +148,195 @@ impl ThreadedManager {
         ThreadedManager {
             endpoint: Listener::new(),
             sources: vec![],
-            poisoned: false,
+            listeners: vec![],
             events_waiting: VecDeque::new(),
+            event_counts: vec![],
+            manager_count: 0,
+            audit: IdleAudit::new(),
+        }
+    }
+
+    /// The idle audit for wakeups of the main thread (see audit.rs.)  A source whose threads said
+    /// it was ready, but which had no Events when asked, counts.
+    pub fn audit(&mut self) -> &mut IdleAudit {
+        &mut self.audit
+    }
+
+    /// How many Events each source has come up with so far, with the names of its threads (e.g.
+    /// "tcp-listener") to tell them apart.
+    pub fn event_counts(&self) -> Vec<(String, u64)> {
+        self.event_counts.iter().enumerate().map(|(source, count)| {
+            let names: Vec<&str> = self.listeners.iter()
+                .filter(|info| info.source == source)
+                .map(|info| info.name.as_str())
+                .collect();
+            (names.join(","), *count)
+        }).collect()
+    }
+
+    /// How many Events are waiting to be handed out.
+    pub fn queued(&self) -> usize {
+        self.events_waiting.len()
+    }
+
+    /// Queue up an Event that didn't come from any source, e.g. one raised by a trigger.  It goes
+    /// after whatever's already waiting.
+    pub fn post(&mut self, event: Event) {
+        self.stamp(Origin::Manager, event);
+    }
+
+    /// Give an Event its timestamp and the next of its origin's sequence numbers, and queue it.
+    /// Everything goes through here, so the queue's in the order things were stamped.
+    fn stamp(&mut self, origin: Origin, event: Event) {
+        let count = match origin {
+            Origin::Source(source) => &mut self.event_counts[source],
+            Origin::Manager => &mut self.manager_count,
+        };
+        *count += 1;
+        self.events_waiting.push_back(Stamped { event, at: Instant::now(), origin, seq: *count });
+    }
+}
+
+impl ThreadedManager {
+    /// Note that a listener has shown signs of life.  Any notice counts, not just heartbeats.
+    fn beat(&mut self, id: usize) {
+        let info = &mut self.listeners[id];
+        info.last_heartbeat = Instant::now();
+        if info.status == ThreadStatus::Stalled {
+            info.status = ThreadStatus::Running;
+        }
+    }
+
+    /// Run a listener on a thread of its own, as listener `id`, with a second thread to wait for
+    /// it to finish--which it never should--and say why it did.  That's how a listener's errors
+    /// get back to us, and it covers panics as well.
+    fn spawn_listener(&mut self, id: usize, mut listener: Box<dyn crate::meta::Listener>) {
+        let name = self.listeners[id].name.clone();
+        let citizen_pager = self.endpoint.clone_tx(id, self.listeners[id].flagged.clone());
+        let citizen = thread::Builder::new().name(name.clone()).spawn(move || {
+            listener.run(Box::new(citizen_pager))
+        }).expect("Couldn't spawn listener thread");
+
+        let mut police_pager = self.endpoint.clone_tx(id, Arc::new(AtomicBool::new(false)));
+        thread::Builder::new().name(format!("police-{}", id)).spawn(move || {
+            police_pager.err(match citizen.join() {
+                Ok(Ok(())) => MintError::Internal(format!("{} returned, but it should run forever", name)),
+                Ok(Err(e)) => e,
+                Err(panic) => {
+                    let why = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "no reason given".to_string());
+                    MintError::Internal(format!("{} panicked: {}", name, why))
+                },
+            });
+        }).expect("Couldn't spawn police thread");
+    }
+
+    /// A listener's stopped because of `error`.  Start it over if its source can and it hasn't
+    /// stopped too often lately; otherwise it's dead, and if it was its source's last, so is the
+    /// source.  Either way, the rest carry on without it.
+    fn listener_stopped(&mut self, id: usize, error: MintError) {
+        // One that was given up on when it stalled (see restart_stalled) has been replaced already.
+        if self.listeners[id].status == ThreadStatus::Dead {
+            return;
+        }
+        let source = self.listeners[id].source;
+        let now = Instant::now();
+        let info = &mut self.listeners[id];
+        info.recent_restarts.retain(|when| now.duration_since(*when) < RESTART_WINDOW);
+        let replacement = match info.recent_restarts.len() < MAX_RESTARTS {
+            true => self.sources[source].borrow_mut().restart_listener(&info.name),
+            false => None,
+        };
+
+        match replacement {
+            Some(listener) => {
+                info.recent_restarts.push(now);
+                info.restarts += 1;
+                info.status = ThreadStatus::Running;
+                info.last_heartbeat = now;
+                info.heartbeat_interval = listener.heartbeat_interval();
+                info.flagged.store(false, Ordering::Release);
+                self.spawn_listener(id, listener);
+            },
+            None => info.status = ThreadStatus::Dead,
+        }
+
+        let reason = error.to_string();
+        self.stamp(Origin::Manager, Event::ListenerFailed { source, listener: id, error });
+        let alive = self.listeners.iter().any(|info| info.source == source && info.status != ThreadStatus::Dead);
+        if !alive {
+            self.stamp(Origin::Manager, Event::SourceDied { id: source, reason });
+        }
+    }
+
+    /// Start a new listener in place of listener `id`, which has stalled, if its source can make
+    /// one (see EventSource::restart_listener.)  There's no stopping the stalled thread from out
+    /// here, so it's left to itself and counted as dead, and the new one gets a number of its
+    /// own.  Returns that number.
+    pub fn restart_stalled(&mut self, id: usize) -> Result<usize, String> {
+        let info = self.listeners.get(id).ok_or_else(|| format!("There's no thread {}.", id))?;
+        if info.status != ThreadStatus::Stalled {
+            return Err(format!("Thread {} ({}) hasn't stalled.", id, info.name));
+        }
+        let listener = self.sources[info.source].borrow_mut().restart_listener(&info.name)
+            .ok_or_else(|| format!("Thread {} ({}) can't be started again.", id, info.name))?;
+
+        let new_id = self.listeners.len();
+        let replacement = ListenerInfo {
+            name: info.name.clone(),
+            source: info.source,
+            heartbeat_interval: listener.heartbeat_interval(),
+            last_heartbeat: Instant::now(),
+            status: ThreadStatus::Running,
+            flagged: Arc::new(AtomicBool::new(false)),
+            recent_restarts: vec![Instant::now()],
+            restarts: info.restarts + 1,
+        };
+        self.listeners[id].status = ThreadStatus::Dead;
+        self.listeners.push(replacement);
+        self.spawn_listener(new_id, listener);
+        Ok(new_id)
+    }
+
+    /// Call process() on a source and queue up what it returns.
+    fn process_source(&mut self, source: usize) {
+        // Clear the flags first: anything a thread sends after this might be missed by this
+        // process() call, so its next ok() has to get through to us.
+        for info in self.listeners.iter().filter(|info| info.source == source) {
+            info.flagged.store(false, Ordering::Release);
+        }
+        let results = self.sources[source].borrow_mut().process();
+        if results.is_empty() && self.audit.running() {
+            let names: Vec<&str> = self.listeners.iter()
+                .filter(|info| info.source == source)
+                .map(|info| info.name.as_str())
+                .collect();
+            self.audit.note(&format!("source {} ({}) ready with no events", source, names.join(", ")));
+        }
+        for event in results {
+            self.stamp(Origin::Source(source), event);
+        }
+    }
+
+    /// The watchdog: look for listeners that promised heartbeats and have missed too many.
+    fn check_heartbeats(&mut self) {
+        let now = Instant::now();
+        let mut stalled = vec![];
+        for (id, info) in self.listeners.iter_mut().enumerate() {
+            if let Some(interval) = info.heartbeat_interval {
+                if info.status == ThreadStatus::Running
+                    && now.duration_since(info.last_heartbeat) > interval * MISSED_HEARTBEATS {
+                    info.status = ThreadStatus::Stalled;
+                    stalled.push(Event::SourceStalled {
+                        source: info.source,
+                        listener: id,
+                    });
+                }
+            }
+        }
+        for event in stalled {
+            self.stamp(Origin::Manager, event);
         }
     }
 }
@@ -96,49 +352,58 @@ impl EventManager for ThreadedManager {
         let new_id: usize = self.sources.len();
 
         let listeners = src.borrow_mut().get_listeners();
-        for mut listener in listeners {
-            let citizen_pager = self.endpoint.clone_tx(new_id);
-            let citizen = thread::spawn(move || {
-                listener.run(Box::new(citizen_pager));
-            });
-
-            // Check for a badly behaved thread dying in the case that it doesn't actually call err()
-            // on its pager.
-            let mut police_pager = self.endpoint.clone_tx(new_id);
-            thread::spawn(move || {
-                match citizen.join() {
-                    // TODO: This is going to be troublesome if/when threads die because we may not
-                    // know which thread died from this alone.
-                    Ok(_) => { police_pager.err("A thread that should run forever returned!".to_string()); },
-                    Err(_) => { police_pager.err("A thread that should not have died died!".to_string()); }
-                }
+        // The source has to be there before the threads start, in case one needs restarting.
+        self.sources.push(src);
+        self.event_counts.push(0);
+        for listener in listeners {
+            let listener_id = self.listeners.len();
+            self.listeners.push(ListenerInfo {
+                name: listener.name(),
+                source: new_id,
+                heartbeat_interval: listener.heartbeat_interval(),
+                last_heartbeat: Instant::now(),
+                status: ThreadStatus::Running,
+                flagged: Arc::new(AtomicBool::new(false)),
+                recent_restarts: vec![],
+                restarts: 0,
             });
+            self.spawn_listener(listener_id, listener);
         }
-
-        self.sources.push(src);
     }
 
     /// Return the next Event.  This will return any Events that are queued up, but if the queue is empty
     /// it will wait for an Event to arrive.
-    fn next_event(&mut self) -> Result<Event, String> {
+    fn next_stamped(&mut self) -> Result<Stamped, MintError> {
         while self.events_waiting.len() < 1 {
-            if self.sources.len() < 1 {
-                return Err("No threads are running; would block forever".to_string());
-            } else if self.poisoned {
-                return Err("A fatal error has already occurred".to_string());
+            if self.listeners.iter().all(|info| info.status == ThreadStatus::Dead) {
+                return Err(MintError::Internal("No threads are running; would block forever".to_string()));
             } else {
-                match self.endpoint.recv() {
-                    (id, StateNotice::Ready) => {
-                        let results = self.sources[id].borrow_mut().process();
-                        for result in results {
-                            self.events_waiting.push_back(result);
+                if let Some(first) = self.endpoint.recv_timeout(WATCHDOG_PERIOD) {
+                    // Take whatever else has turned up as well, so that several notices for the
+                    // same source only cost one process() call.
+                    let mut notices = vec![first];
+                    notices.extend(self.endpoint.drain());
+
+                    let mut ready: Vec<usize> = vec![];
+                    for (id, notice) in notices {
+                        match notice {
+                            StateNotice::Ready => {
+                                self.beat(id);
+                                let source = self.listeners[id].source;
+                                if !ready.contains(&source) {
+                                    ready.push(source);
+                                }
+                            },
+                            StateNotice::Error(error) => self.listener_stopped(id, error),
+                            StateNotice::Heartbeat => self.beat(id),
                         }
-                    },
-                    (_id, StateNotice::Error(bad_things)) => {
-                        self.poisoned = true;
-                        self.events_waiting.push_back(Event::InternalError { what: bad_things });
-                    },
+                    }
+
+                    for source in ready {
+                        self.process_source(source);
+                    }
                 }
+                self.check_heartbeats();
             }
         }
 
@@ -146,5 +411,289 @@ impl EventManager for ThreadedManager {
         // pop_front() should be guaranteed to function and unwrap() should be safe.
         Ok(self.events_waiting.pop_front().unwrap())
     }
+
+    fn threads(&self) -> Vec<ThreadInfo> {
+        let now = Instant::now();
+        self.listeners.iter().map(|info| ThreadInfo {
+            name: info.name.clone(),
+            source: info.source,
+            status: info.status,
+            idle: now.duration_since(info.last_heartbeat),
+            heartbeat_interval: info.heartbeat_interval,
+            restarts: info.restarts,
+        }).collect()
+    }
+}
+
+#[test]
+fn readiness_is_coalesced() {
+    // A source whose one thread sends a burst of lines, saying it's ready after each one.
+    struct Burst {
+        tx: Option<mpsc::Sender<String>>,
+        rx: mpsc::Receiver<String>,
+        process_calls: usize,
+    }
+    struct BurstListener(mpsc::Sender<String>);
+
+    impl crate::meta::Listener for BurstListener {
+        fn name(&self) -> String { "burst".to_string() }
+        fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+            for i in 0..100 {
+                self.0.send(format!("line {}", i)).unwrap();
+                flag.ok();
+            }
+            // Threads are supposed to run forever.
+            loop { thread::sleep(Duration::from_secs(60)); }
+        }
+    }
+    impl EventSource for Burst {
+        fn get_listeners(&mut self) -> Vec<Box<dyn crate::meta::Listener>> {
+            vec![Box::new(BurstListener(self.tx.take().unwrap()))]
+        }
+        fn process(&mut self) -> Vec<Event> {
+            self.process_calls += 1;
+            self.rx.try_iter().map(|line| Event::ServerText { line, which: crate::net::ConnectionID::new(1, 0), links: vec![] }).collect()
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let burst = Rc::new(RefCell::new(Burst { tx: Some(tx), rx, process_calls: 0 }));
+    let mut manager = ThreadedManager::new();
+    manager.start_source(burst.clone());
+
+    // Let the whole burst arrive before we look.
+    thread::sleep(Duration::from_millis(200));
+    for _ in 0..100 {
+        manager.next_event().unwrap();
+    }
+    assert_eq!(burst.borrow().process_calls, 1);
+}
+
+#[test]
+fn failed_listener_is_reported() {
+    struct Doomed;
+    struct DoomedListener;
+
+    impl crate::meta::Listener for DoomedListener {
+        fn name(&self) -> String { "doomed".to_string() }
+        fn run(&mut self, _flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+            Err(MintError::Net("the network is on fire".to_string()))
+        }
+    }
+    impl EventSource for Doomed {
+        fn get_listeners(&mut self) -> Vec<Box<dyn crate::meta::Listener>> {
+            vec![Box::new(DoomedListener)]
+        }
+        fn process(&mut self) -> Vec<Event> { vec![] }
+    }
+
+    let mut manager = ThreadedManager::new();
+    manager.start_source(Rc::new(RefCell::new(Doomed)));
+    match manager.next_event() {
+        Ok(Event::ListenerFailed { source: 0, listener: 0, error }) => {
+            assert_eq!(error, MintError::Net("the network is on fire".to_string()));
+        },
+        other => panic!("expected ListenerFailed, got {:?}", other),
+    }
+    assert_eq!(manager.threads()[0].status, ThreadStatus::Dead);
+    // It can't be restarted, and it was all there was of its source.
+    match manager.next_event() {
+        Ok(Event::SourceDied { id: 0, reason }) => assert_eq!(reason, "the network is on fire"),
+        other => panic!("expected SourceDied, got {:?}", other),
+    }
+    // With nothing left running, waiting would be forever.
+    assert!(manager.next_event().is_err());
+}
+
+#[test]
+fn events_are_stamped_in_order() {
+    // Two sources, each with a thread that sends numbered lines for a while.
+    struct Counter {
+        tx: Option<mpsc::Sender<String>>,
+        rx: mpsc::Receiver<String>,
+        slot: usize,
+    }
+    struct CounterListener(mpsc::Sender<String>);
+
+    impl crate::meta::Listener for CounterListener {
+        fn name(&self) -> String { "counter".to_string() }
+        fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+            for i in 0..50 {
+                self.0.send(i.to_string()).unwrap();
+                flag.ok();
+                if i % 10 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+            loop { thread::sleep(Duration::from_secs(60)); }
+        }
+    }
+    impl EventSource for Counter {
+        fn get_listeners(&mut self) -> Vec<Box<dyn crate::meta::Listener>> {
+            vec![Box::new(CounterListener(self.tx.take().unwrap()))]
+        }
+        fn process(&mut self) -> Vec<Event> {
+            let which = crate::net::ConnectionID::new(self.slot, 0);
+            self.rx.try_iter().map(|line| Event::ServerText { line, which, links: vec![] }).collect()
+        }
+    }
+
+    let mut manager = ThreadedManager::new();
+    for slot in 1..3 {
+        let (tx, rx) = mpsc::channel();
+        manager.start_source(Rc::new(RefCell::new(Counter { tx: Some(tx), rx, slot })));
+    }
+    manager.post(Event::QuitRequest);
+
+    let mut last_seq = [0u64; 2];
+    let mut last_at = None;
+    let first = manager.next_stamped().unwrap();
+    assert_eq!((first.origin, first.seq), (Origin::Manager, 1));
+    for _ in 0..100 {
+        let stamped = manager.next_stamped().unwrap();
+        assert!(last_at.is_none_or(|at| stamped.at >= at));
+        last_at = Some(stamped.at);
+        let source = match stamped.origin {
+            Origin::Source(source) => source,
+            Origin::Manager => panic!("expected an event from a source"),
+        };
+        // Each source's lines come out in order, numbered without gaps.
+        assert_eq!(stamped.seq, last_seq[source] + 1);
+        last_seq[source] = stamped.seq;
+        match stamped.event {
+            Event::ServerText { line, .. } => assert_eq!(line, (stamped.seq - 1).to_string()),
+            other => panic!("expected ServerText, got {:?}", other),
+        }
+    }
+    assert_eq!(last_seq, [50, 50]);
+}
+
+#[test]
+fn failed_listeners_are_restarted() {
+    // A source whose listener panics the first `failures` times it runs, then works.  Another
+    // thread of the same source keeps going the whole time.
+    struct Flaky {
+        tx: mpsc::Sender<String>,
+        rx: mpsc::Receiver<String>,
+        failures: usize,
+    }
+    struct FlakyListener {
+        tx: mpsc::Sender<String>,
+        fail: bool,
+    }
+    struct Steady;
+
+    impl crate::meta::Listener for FlakyListener {
+        fn name(&self) -> String { "flaky".to_string() }
+        fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+            if self.fail {
+                panic!("not today");
+            }
+            self.tx.send("made it".to_string()).unwrap();
+            flag.ok();
+            loop { thread::sleep(Duration::from_secs(60)); }
+        }
+    }
+    impl crate::meta::Listener for Steady {
+        fn name(&self) -> String { "steady".to_string() }
+        fn run(&mut self, _flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+            loop { thread::sleep(Duration::from_secs(60)); }
+        }
+    }
+    impl Flaky {
+        fn listener(&mut self) -> Box<dyn crate::meta::Listener> {
+            let fail = self.failures > 0;
+            self.failures = self.failures.saturating_sub(1);
+            Box::new(FlakyListener { tx: self.tx.clone(), fail })
+        }
+    }
+    impl EventSource for Flaky {
+        fn get_listeners(&mut self) -> Vec<Box<dyn crate::meta::Listener>> {
+            vec![self.listener(), Box::new(Steady)]
+        }
+        fn restart_listener(&mut self, name: &str) -> Option<Box<dyn crate::meta::Listener>> {
+            assert_eq!(name, "flaky");
+            Some(self.listener())
+        }
+        fn process(&mut self) -> Vec<Event> {
+            self.rx.try_iter().map(|line| Event::ServerText { line, which: crate::net::ConnectionID::new(1, 0), links: vec![] }).collect()
+        }
+    }
+
+    let flaky = |failures| {
+        let (tx, rx) = mpsc::channel();
+        Rc::new(RefCell::new(Flaky { tx, rx, failures }))
+    };
+
+    let mut manager = ThreadedManager::new();
+    manager.start_source(flaky(2));
+    for _ in 0..2 {
+        assert!(matches!(manager.next_event(), Ok(Event::ListenerFailed { source: 0, listener: 0, .. })));
+    }
+    assert!(matches!(manager.next_event(), Ok(Event::ServerText { ref line, .. }) if line == "made it"));
+    assert_eq!(manager.threads()[0].status, ThreadStatus::Running);
+    assert_eq!(manager.threads()[0].restarts, 2);
+
+    // One that never works is given up on, but its source still has another thread going.
+    let mut manager = ThreadedManager::new();
+    manager.start_source(flaky(usize::MAX));
+    for _ in 0..=MAX_RESTARTS {
+        assert!(matches!(manager.next_event(), Ok(Event::ListenerFailed { .. })));
+    }
+    assert_eq!(manager.threads()[0].status, ThreadStatus::Dead);
+    assert_eq!(manager.threads()[0].restarts, MAX_RESTARTS as u32);
+    assert_eq!(manager.threads()[1].status, ThreadStatus::Running);
+    assert_eq!(manager.queued(), 0);
 }
 
+#[test]
+fn stalled_listeners_are_restarted() {
+    // A source whose first listener promises heartbeats and never sends any; the one that takes
+    // over from it just sends a line.
+    struct Stuck {
+        tx: mpsc::Sender<String>,
+        rx: mpsc::Receiver<String>,
+    }
+    struct StuckListener {
+        tx: mpsc::Sender<String>,
+        stall: bool,
+    }
+
+    impl crate::meta::Listener for StuckListener {
+        fn name(&self) -> String { "stuck".to_string() }
+        fn heartbeat_interval(&self) -> Option<Duration> {
+            if self.stall { Some(Duration::from_millis(10)) } else { None }
+        }
+        fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+            if !self.stall {
+                self.tx.send("unstuck".to_string()).unwrap();
+                flag.ok();
+            }
+            loop { thread::sleep(Duration::from_secs(60)); }
+        }
+    }
+    impl EventSource for Stuck {
+        fn get_listeners(&mut self) -> Vec<Box<dyn crate::meta::Listener>> {
+            vec![Box::new(StuckListener { tx: self.tx.clone(), stall: true })]
+        }
+        fn restart_listener(&mut self, _name: &str) -> Option<Box<dyn crate::meta::Listener>> {
+            Some(Box::new(StuckListener { tx: self.tx.clone(), stall: false }))
+        }
+        fn process(&mut self) -> Vec<Event> {
+            self.rx.try_iter().map(|line| Event::ServerText { line, which: crate::net::ConnectionID::new(1, 0), links: vec![] }).collect()
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut manager = ThreadedManager::new();
+    manager.start_source(Rc::new(RefCell::new(Stuck { tx, rx })));
+    assert!(manager.restart_stalled(0).is_err());
+    assert!(matches!(manager.next_event(), Ok(Event::SourceStalled { source: 0, listener: 0 })));
+
+    assert_eq!(manager.restart_stalled(0), Ok(1));
+    assert!(matches!(manager.next_event(), Ok(Event::ServerText { ref line, .. }) if line == "unstuck"));
+    let threads = manager.threads();
+    assert_eq!(threads[0].status, ThreadStatus::Dead);
+    assert_eq!((threads[1].status, threads[1].restarts), (ThreadStatus::Running, 1));
+    assert!(manager.restart_stalled(0).is_err());
+}
@@ -0,0 +1,86 @@
+//! Remembering, for each world, the windows its triggers and scripts put things in and how the
+//! screen was split up between panes, so that connecting to it again puts them all back.  Kept in
+//! a file for each world, next to the worlds file, and written over whenever a connection to the
+//! world ends (or the client quits while it's connected.)
+
+use crate::config;
+use crate::ui::term::layout::Layout;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What's kept for a world.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SavedLayout {
+    /// The windows to make, besides the world's own, in the order they're made.
+    pub windows: Vec<String>,
+    pub layout: Layout,
+}
+
+/// Where the layout for `world` goes: a file in the layouts directory in the config directory,
+/// which is made if it isn't there yet.  None if there's no config directory.
+pub fn path(world: &str) -> Option<PathBuf> {
+    let dir = config::ensure_config_dir()?.join("layouts");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(format!("{}.txt", config::file_name(world))))
+}
+
+/// Write a layout file: a line for each window ("window chat"), then "layout" on a line of its
+/// own, then the layout (see Layout::to_lines.)
+pub fn save(path: &Path, world: &str, saved: &SavedLayout) -> io::Result<()> {
+    let mut text = format!("# Windows and panes for {}, kept by mint for next time.\n", world);
+    for window in &saved.windows {
+        text.push_str(&format!("window {}\n", window));
+    }
+    text.push_str("layout\n");
+    for line in saved.layout.to_lines() {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    fs::write(path, text)
+}
+
+/// Read a layout file back.  No file just means nothing's been kept for the world yet.
+pub fn load(path: &Path) -> Result<Option<SavedLayout>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Couldn't read {}: {}", path.display(), e)),
+    };
+    let lines: Vec<&str> = text.lines().filter(|line| !line.starts_with('#') && !line.trim().is_empty()).collect();
+
+    let mut saved = SavedLayout::default();
+    let mut rest = &lines[..];
+    while let Some((line, after)) = rest.split_first() {
+        rest = after;
+        match line.strip_prefix("window ") {
+            Some(window) => saved.windows.push(window.to_string()),
+            None if *line == "layout" => {
+                saved.layout = Layout::from_lines(rest).map_err(|e| format!("{}: {}", path.display(), e))?;
+                return Ok(Some(saved));
+            },
+            None => return Err(format!("{}: Expected a window or the layout, not {:?}", path.display(), line)),
+        }
+    }
+    Ok(Some(saved))
+}
+
+#[test]
+fn layout_file() {
+    use crate::ui::term::layout::Direction;
+
+    let path = std::env::temp_dir().join(format!("mint-layout-test-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+    assert_eq!(load(&path), Ok(None));
+
+    let mut layout = Layout::default();
+    layout.add("chat".to_string(), Direction::Stacked);
+    let saved = SavedLayout { windows: vec!["chat".to_string(), "loot log".to_string()], layout };
+    save(&path, "tharsis", &saved).unwrap();
+    assert_eq!(load(&path), Ok(Some(saved)));
+
+    fs::write(&path, "window chat\nstacked\n").unwrap();
+    assert!(load(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
@@ -1,13 +1,45 @@
 #![deny(unused_must_use)]
 
 pub mod meta;
+pub mod action;
+pub mod alarm;
+pub mod audit;
+pub mod batch;
+pub mod charset;
+pub mod client;
+pub mod clock;
+pub mod command;
+pub mod config;
+pub mod demo;
+pub mod error;
 pub mod events;
+pub mod layouts;
+pub mod line;
+pub mod log;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod net;
+pub mod notes;
+pub mod repeat;
+pub mod script;
+pub mod selftest;
+pub mod session;
+pub mod ticker;
+pub mod timer;
 pub mod ui;
+pub mod world;
 
 extern crate mio;
 extern crate termion;
 extern crate signal_hook;
 extern crate libc;
 extern crate fnv;
+extern crate regex;
+extern crate unicode_normalization;
+extern crate unicode_width;
+extern crate unicode_segmentation;
+extern crate rustls;
+extern crate webpki_roots;
+extern crate mlua;
+extern crate flate2;
 
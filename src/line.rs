@@ -0,0 +1,109 @@
+//! Lines of text on their way to the screen, from the server or from the client itself, with
+//! everything we know about them.  Whatever wants to look at or change a line on the way (the
+//! log, triggers, the UI) gets one of these, so nothing has to parse the text again or guess
+//! where it came from.
+
+use crate::net::ConnectionID;
+use crate::net::mxp::Link;
+use crate::ui::ansi::{self, Style, StyledLine};
+
+use std::time::SystemTime;
+
+/// A line of text, with its colors parsed out and some facts about it.
+#[derive(Clone, Debug)]
+pub struct Line {
+    /// The text and its styles.
+    pub styled: StyledLine,
+    /// When it arrived (or was made, for the client's own lines.)
+    pub time: SystemTime,
+    /// The connection it came from; None for the client's own messages.
+    pub source: Option<ConnectionID>,
+    /// There was a BEL in it.  The BEL itself is gone; the UI decides what to do about it.
+    pub bell: bool,
+    /// Don't show it.  It still gets logged.
+    pub gag: bool,
+    /// Show it in this style instead of its own.
+    pub highlight: Option<Style>,
+    /// Flash the screen when it's shown.
+    pub flash: bool,
+    /// Pieces of it that do something when they're followed (see net::mxp.)
+    pub links: Vec<Link>,
+}
+
+impl Line {
+    /// A line as it came from the server, escape sequences and all.
+    pub fn server(raw: &str, source: ConnectionID) -> Line {
+        let mut line = Line::client(raw);
+        line.source = Some(source);
+        line
+    }
+
+    /// A line from the client itself.  It can have SGR sequences in it (e.g. from ui::style.)
+    pub fn client(raw: &str) -> Line {
+        let mut raw = raw.to_string();
+        let bell = sanitize(&mut raw);
+        Line {
+            styled: ansi::parse(&raw),
+            time: SystemTime::now(),
+            source: None,
+            bell,
+            gag: false,
+            highlight: None,
+            flash: false,
+            links: vec![],
+        }
+    }
+
+    /// The text without any styles.
+    pub fn text(&self) -> &str {
+        &self.styled.text
+    }
+
+    /// The text with its styles put back as SGR sequences, e.g. for a log file.
+    pub fn to_ansi(&self) -> String {
+        self.styled.slice_with_sgr(0, self.styled.text.len())
+    }
+}
+
+impl From<StyledLine> for Line {
+    fn from(styled: StyledLine) -> Line {
+        Line {
+            styled,
+            time: SystemTime::now(),
+            source: None,
+            bell: false,
+            gag: false,
+            highlight: None,
+            flash: false,
+            links: vec![],
+        }
+    }
+}
+
+/// Sanitation pass for text headed for the screen: strip out control characters that would
+/// confuse the terminal or our idea of where the cursor is.  Returns true if there was a BEL in
+/// the text, so the caller can apply whatever bell policy it likes.
+///
+/// ESC and tab are left alone; they're the renderer's problem.
+pub fn sanitize(text: &mut String) -> bool {
+    let rang = text.contains('\x07');
+    text.retain(|c| c == '\x1b' || c == '\t' || !c.is_control());
+    rang
+}
+
+#[test]
+fn sanitize_strips_bel() {
+    let mut text = "You hear a \x07ding\r".to_string();
+    assert!(sanitize(&mut text));
+    assert_eq!(text, "You hear a ding");
+
+    let mut text = "\x1b[1mNothing\x1b[0m to see".to_string();
+    assert!(!sanitize(&mut text));
+    assert_eq!(text, "\x1b[1mNothing\x1b[0m to see");
+
+    let line = Line::server("\x1b[31mDing!\x07\x1b[0m", ConnectionID::new(3, 0));
+    assert!(line.bell);
+    assert_eq!(line.text(), "Ding!");
+    assert_eq!(line.to_ansi(), "\x1b[0;31mDing!\x1b[0m");
+    assert_eq!(line.source, Some(ConnectionID::new(3, 0)));
+}
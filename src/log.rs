@@ -0,0 +1,265 @@
+use crate::clock::LocalTime;
+use crate::config;
+use crate::line::Line;
+use crate::ui::ansi::Style;
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::{Path, PathBuf};
+
+// The colors an HTML log is shown in where the server didn't say, like a terminal's.
+const HTML_FG: (u8, u8, u8) = (229, 229, 229);
+const HTML_BG: (u8, u8, u8) = (0, 0, 0);
+
+/// How lines are written to a log.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    /// Colors and all, as SGR sequences, so `less -R` (or cat) shows it the way it looked.
+    Ansi,
+    /// Just the text.
+    Plain,
+    /// A web page, colors included.
+    Html,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<LogFormat> {
+        match name.to_lowercase().as_str() {
+            "ansi" => Some(LogFormat::Ansi),
+            "plain" | "text" | "txt" => Some(LogFormat::Plain),
+            "html" | "htm" => Some(LogFormat::Html),
+            _ => None,
+        }
+    }
+
+    /// The format a file name suggests: HTML for .html, plain text for .txt, and otherwise
+    /// colors.
+    pub fn for_path(path: &Path) -> LogFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext != "ansi" => LogFormat::from_name(ext).unwrap_or(LogFormat::Ansi),
+            _ => LogFormat::Ansi,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            LogFormat::Ansi => "log",
+            LogFormat::Plain => "txt",
+            LogFormat::Html => "html",
+        }
+    }
+}
+
+/// Where a log goes when nobody says: a file named after the world and when the log was started,
+/// in the logs directory in the config directory (which is made if it isn't there yet.)  None if
+/// there's no config directory.
+pub fn default_path(world: &str, when: LocalTime, format: LogFormat) -> Option<PathBuf> {
+    let dir = config::ensure_config_dir()?.join("logs");
+    fs::create_dir_all(&dir).ok()?;
+    let stamp = format!("{}-{}", when.ymd(), when.hms().replace(':', ""));
+    Some(dir.join(format!("{}-{}.{}", config::file_name(world), stamp, format.extension())))
+}
+
+/// A transcript of a session, written to disk as it happens.
+///
+/// The logger gets to see both sides of the conversation: server output via server_line(), and
+/// whatever we send via sent_line().  Sent lines only go into the file if `log_sent` is on, in
+/// which case they're timestamped and prefixed so they're easy to tell apart from the server's
+/// text, and the log reads as a complete transcript.
+pub struct SessionLog {
+    // LineWriter, so that the file is never more than a partial line behind if we crash.
+    file: LineWriter<File>,
+    pub path: PathBuf,
+    pub format: LogFormat,
+    pub log_sent: bool,
+    pub sent_prefix: String,
+}
+
+impl SessionLog {
+    /// Start logging to `path`, appending if it already exists.  An HTML log that's appended to
+    /// gets a new block of its own, so each session's text stays in one piece.
+    pub fn open(path: &Path, format: LogFormat) -> io::Result<SessionLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+        let mut log = SessionLog {
+            file: LineWriter::new(file),
+            path: path.to_path_buf(),
+            format,
+            log_sent: false,
+            sent_prefix: "> ".to_string(),
+        };
+        if format == LogFormat::Html {
+            if empty {
+                writeln!(log.file, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title></head>",
+                         escape(&path.file_stem().unwrap_or_default().to_string_lossy()))?;
+                writeln!(log.file, "<body style=\"background: {}; color: {}\">", css_color(HTML_BG), css_color(HTML_FG))?;
+            }
+            writeln!(log.file, "<pre>")?;
+        }
+        Ok(log)
+    }
+
+    /// Log a line from the server.  Gagged lines are logged too; gagging is only about what's on
+    /// the screen.
+    pub fn server_line(&mut self, line: &Line) -> io::Result<()> {
+        match self.format {
+            LogFormat::Ansi => writeln!(self.file, "{}", line.to_ansi()),
+            LogFormat::Plain => writeln!(self.file, "{}", line.text()),
+            LogFormat::Html => writeln!(self.file, "{}", html_line(line)),
+        }
+    }
+
+    pub fn sent_line(&mut self, line: &str) -> io::Result<()> {
+        if self.log_sent {
+            // Lines we send usually still have their newline on the end.
+            let line = line.trim_end_matches(['\r', '\n']);
+            let line = format!("[{}] {}{}", LocalTime::now().hms(), self.sent_prefix, line);
+            match self.format {
+                LogFormat::Html => writeln!(self.file, "<i>{}</i>", escape(&line))?,
+                _ => writeln!(self.file, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SessionLog {
+    fn drop(&mut self) {
+        // Nothing to be done about it if this doesn't work, and browsers don't mind much anyway.
+        if self.format == LogFormat::Html {
+            let _ = writeln!(self.file, "</pre>");
+        }
+    }
+}
+
+/// Logs kept for particular windows rather than connections, so what goes to one window (a chat
+/// window, say) can have a file of its own while the rest of the session doesn't.  Whatever lines
+/// are put in a logged window are written, whichever connection they came from, if any.
+#[derive(Default)]
+pub struct WindowLogs {
+    logs: HashMap<String, SessionLog>,
+}
+
+impl WindowLogs {
+    pub fn new() -> WindowLogs {
+        WindowLogs::default()
+    }
+
+    /// Start logging `window` to `log`, handing back the log it had before, if any.
+    pub fn start(&mut self, window: String, log: SessionLog) -> Option<SessionLog> {
+        self.logs.insert(window, log)
+    }
+
+    pub fn stop(&mut self, window: &str) -> Option<SessionLog> {
+        self.logs.remove(window)
+    }
+
+    pub fn get(&self, window: &str) -> Option<&SessionLog> {
+        self.logs.get(window)
+    }
+
+    /// Keep logging a window that's been renamed.
+    pub fn rename(&mut self, from: &str, to: String) {
+        if let Some(log) = self.logs.remove(from) {
+            self.logs.insert(to, log);
+        }
+    }
+
+    /// Log a line that's going to `window`, if it's being logged.  As with sessions, gagged lines
+    /// are logged too.
+    pub fn line(&mut self, window: &str, line: &Line) -> io::Result<()> {
+        match self.logs.get_mut(window) {
+            Some(log) => log.server_line(line),
+            None => Ok(()),
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn css_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// The CSS that makes text look like `style`, or nothing if it's the default.
+fn css(style: Style) -> String {
+    let (mut fg, mut bg) = (style.fg.rgb(), style.bg.rgb());
+    if style.reverse {
+        let (reversed_fg, reversed_bg) = (bg.unwrap_or(HTML_BG), fg.unwrap_or(HTML_FG));
+        fg = Some(reversed_fg);
+        bg = Some(reversed_bg);
+    }
+
+    let mut css = vec![];
+    if let Some(fg) = fg {
+        css.push(format!("color: {}", css_color(fg)));
+    }
+    if let Some(bg) = bg {
+        css.push(format!("background: {}", css_color(bg)));
+    }
+    let flags = [(style.bold, "font-weight: bold"), (style.dim, "opacity: 0.6"),
+                 (style.italic, "font-style: italic"), (style.underline, "text-decoration: underline")];
+    css.extend(flags.iter().filter(|(on, _)| *on).map(|(_, rule)| rule.to_string()));
+    css.join("; ")
+}
+
+fn html_line(line: &Line) -> String {
+    let mut out = String::new();
+    for (text, style) in line.styled.spans() {
+        let css = css(style);
+        if css.is_empty() {
+            out.push_str(&escape(text));
+        } else {
+            out.push_str(&format!("<span style=\"{}\">{}</span>", css, escape(text)));
+        }
+    }
+    out
+}
+
+#[test]
+fn log_formats() {
+    let dir = std::env::temp_dir();
+    let line = Line::server("\x1b[1;31mDanger!\x1b[0m <Run>", crate::net::ConnectionID::new(1, 0));
+
+    let path = dir.join(format!("mint-log-test-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+    assert_eq!(LogFormat::for_path(&path), LogFormat::Plain);
+    {
+        let mut log = SessionLog::open(&path, LogFormat::Plain).unwrap();
+        log.server_line(&line).unwrap();
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), "Danger! <Run>\n");
+    fs::remove_file(&path).unwrap();
+
+    let path = dir.join(format!("mint-log-test-{}.html", std::process::id()));
+    let _ = fs::remove_file(&path);
+    assert_eq!(LogFormat::for_path(&path), LogFormat::Html);
+    {
+        let mut log = SessionLog::open(&path, LogFormat::Html).unwrap();
+        log.server_line(&line).unwrap();
+    }
+    let html = fs::read_to_string(&path).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.ends_with("<pre>\n<span style=\"color: #cd0000; font-weight: bold\">Danger!</span> &lt;Run&gt;\n</pre>\n"));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn window_logs() {
+    let path = std::env::temp_dir().join(format!("mint-window-log-test-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let mut logs = WindowLogs::new();
+    logs.start("chat".to_string(), SessionLog::open(&path, LogFormat::Plain).unwrap());
+    logs.line("chat", &Line::client("Bob says, 'hi'")).unwrap();
+    logs.line("main", &Line::client("A rat bites you.")).unwrap();
+    assert!(logs.get("main").is_none());
+    assert_eq!(logs.stop("chat").map(|log| log.path.clone()), Some(path.clone()));
+    logs.line("chat", &Line::client("Bob says, 'bye'")).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "Bob says, 'hi'\n");
+    fs::remove_file(&path).unwrap();
+}
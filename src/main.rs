@@ -1,64 +1,118 @@
-
-use mint;
-use mint::meta::*;
-use mint::events::ThreadedManager;
-
-use mint::net::{ConnectionInterface, tcp::TcpConnectionManager};
-use mint::ui::{UserInterface, term::TermUiManager};
+use mint::batch::{self, Exchange};
+use mint::client::Client;
+use mint::demo::Demo;
+use mint::ui::UserInterface;
+use mint::world::{self, World};
 
 use std::env;
-use std::{cell::RefCell, rc::Rc};
+use std::fs;
+use std::path::Path;
+
+/// The worlds called `names` in the worlds file (see world::parse_worlds), in that order, and
+/// anything wrong with the rest of the file.  If there's no file, an example's put there.
+fn load_worlds(names: &[String]) -> Result<(Vec<World>, Vec<String>), String> {
+    let path = world::worlds_path()
+        .ok_or_else(|| "Couldn't find a config directory for the worlds file".to_string())?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(_) => {
+            let _ = fs::write(&path, world::WORLDS_EXAMPLE);
+            return Err(format!("There aren't any worlds yet; they go in {}, which has an example in it now.",
+                               path.display()));
+        },
+    };
 
-fn wrap<T>(x: T) -> Rc<RefCell<T>> {
-    Rc::new(RefCell::new(x))
+    let (mut all, errors) = world::parse_worlds(&text);
+    let errors = errors.into_iter().map(|e| format!("{}, {}", path.display(), e)).collect();
+    let mut worlds = vec![];
+    for name in names {
+        match all.iter().position(|world| world.name == *name) {
+            Some(i) => worlds.push(all.remove(i)),
+            None => return Err(format!("There's no world called {} in {}.", name, path.display())),
+        }
+    }
+    Ok((worlds, errors))
 }
 
 fn main() {
-    let address: String;
-    if let Some(arg1) = env::args().nth(1) {
-        address = arg1;
-    } else {
-        panic!("Expected at least one command line argument (ip:port)");
+    // Batch mode doesn't need anything the rest of this does, and ends here.
+    if env::args().nth(1).as_deref() == Some("--batch") {
+        let status = match Exchange::from_args(env::args().skip(2)) {
+            Ok(exchange) => exchange.run(&mut std::io::stdout()),
+            Err(e) => {
+                eprintln!("{}\n{}", e, batch::USAGE);
+                batch::EXIT_USAGE
+            },
+        };
+        std::process::exit(status);
     }
 
-    let mut manager = ThreadedManager::new();
+    // --world and a world's name, as many times as there are worlds to start out connected to,
+    // each in a window of its own.
+    let mut args = vec![];
+    let mut names = vec![];
+    let mut all = env::args().skip(1);
+    while let Some(arg) = all.next() {
+        if arg != "--world" {
+            args.push(arg);
+            continue;
+        }
+        match all.next() {
+            Some(name) => names.push(name),
+            None => {
+                eprintln!("Expected the name of a world after --world");
+                std::process::exit(1);
+            },
+        }
+    }
+    let (worlds, world_errors) = match names.is_empty() {
+        true => (vec![], vec![]),
+        false => load_worlds(&names).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+    };
 
-    let tcp = wrap(TcpConnectionManager::new());
-    manager.start_source(tcp.clone());
-    let cid = tcp.borrow_mut().start_connection(address.to_string())
-         .unwrap();
+    // Then somewhere to connect to, or --demo and a demo script to play instead.
+    let (address, demo) = match (args.first().cloned(), args.get(1)) {
+        (Some(ref flag), Some(path)) if flag == "--demo" => match Demo::load(Path::new(path)) {
+            Ok(demo) => (None, Some(demo)),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            },
+        },
+        (Some(address), _) => (Some(address), None),
+        (None, _) if !worlds.is_empty() => (None, None),
+        (None, _) => panic!("Expected at least one command line argument (ip:port, tls://host:port, \
+                             --world and a world's name, --demo and a demo script, or --batch and \
+                             what to do)"),
+    };
 
-    let tui = wrap(TermUiManager::new());
-    manager.start_source(tui.clone());
+    let demo_given = demo.is_some();
+    let mut client = Client::new(demo);
+    client.load_config();
+    client.echo_all(world_errors);
 
-    let mut event = manager.next_event();
-    loop {
-        match event.unwrap() {
-            Event::ServerText { line: l, which: _c } => {
-                tui.borrow_mut().push_to_window("default".to_string(), l);
-            },
-            Event::QuitRequest => {
-                break;
-            },
-            Event::UserInput { mut line, which: _ } => {
-                // Since we don't have real window management or multiple connections yet, we do
-                // ...this
-                // Obviously needs more error handling too, like everything else in this program.
-                line.push_str("\n");
-                match tcp.borrow_mut().write_to_connection(cid, line) {
-                    Ok(_) => { },
-                    Err(_) => { tui.borrow_mut().push_to_window("default".to_string(),
-                            format!("Couldn't write to connection")); }
-                }
-            }
-            ref event => {
-                tui.borrow_mut().push_to_window("default".to_string(),
-                        format!("Unhandled event: {:?}", event));
-            },
+    // The connection from the command line gets the window everything starts out in, and the
+    // worlds get windows named after them.  The first of those is in front, unless there's an
+    // address as well.
+    let in_front = address.is_none();
+    match address {
+        Some(address) => client.connect(World::new(address.clone(), address), "default".to_string()),
+        None if demo_given => client.play_demo(),
+        None => { },
+    }
+    for (n, world) in worlds.into_iter().enumerate() {
+        let window = client.sessions.free_window_name(&world.name);
+        client.connect(world, window.clone());
+        if n == 0 && in_front {
+            client.tui.borrow_mut().show_window(window);
         }
-        event = manager.next_event();
     }
 
-    println!("At end of main() due to QuitRequest (probably.)");
+    match client.run() {
+        Ok(()) => println!("At end of main() due to QuitRequest."),
+        Err(e) => println!("Stopping: {}", e),
+    }
 }
-
@@ -1,9 +1,15 @@
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
-use crate::ui::Command;
+use crate::charset::Charset;
+use crate::command::Command;
+use crate::error::MintError;
 
-use crate::net::ConnectionID;
+use crate::net::{ConnectionID, ErrorClass};
+use crate::net::mxp::{Link, LinkAction};
+use crate::timer::TimerID;
+use crate::action::ActionID;
 pub type WindowID = usize;
 
 /// Fragments of data about something that's happened--user input, text sent by a remote server,
@@ -11,21 +17,115 @@ pub type WindowID = usize;
 /// implementing the EventManager trait below.
 #[derive(Debug)]
 pub enum Event {
+    /// A client command to run, e.g. one the user's said yes to (see Dispatcher::destructive.)
     UserCommand { cmd: Command },
     // We will want to be able to discriminate which _window_ in the UI a line of text came from,
     // not which connection it should go to.  (That is, the UI doesn't know anything about the
     // mapping of windows to connections.)
     UserInput { line: String, which: WindowID },
+    /// A key the UI doesn't have any use for itself, by name (e.g. "F5" or "M-x"), so that it can
+    /// be bound to something.
+    KeyPress { key: String, which: WindowID },
+    /// What the UI made of a key pressed after UserInterface::show_next_key(): its internal name
+    /// (`key`), the name it'd have in a KeyPress (if any), and what the UI does with it by itself
+    /// (if anything.)
+    KeyShown { key: String, name: Option<String>, builtin: Option<String>, which: WindowID },
+    /// The user followed a link (see net::mxp) in window `which`.
+    LinkFollowed { action: LinkAction, which: WindowID },
 
-    ServerText { line: String, which: ConnectionID },
+    /// A line from the server, with the links MXP made in it (see net::mxp), if any.
+    ServerText { line: String, which: ConnectionID, links: Vec<Link> },
     ConnectionStart { which: ConnectionID },
-    ConnectionEnd { which: ConnectionID, reason: String },
+    /// A connection's over.  `error` says what sort of thing ended it, unless it was closed on
+    /// purpose.
+    ConnectionEnd { which: ConnectionID, reason: String, error: Option<ErrorClass> },
+    /// A telnet option was switched on or off (see net::telnet.)  `ours` means it's on our end of
+    /// the connection; otherwise it's the server's, like SGA.
+    TelnetOption { which: ConnectionID, option: u8, ours: bool, enabled: bool },
+    /// The server has taken over echoing what's typed (telnet WILL ECHO), or given it back.
+    /// Servers take it over to keep passwords off the screen, so while it's on, what's typed for
+    /// this connection shouldn't be shown.
+    ServerEcho { which: ConnectionID, enabled: bool },
+    TelnetSubnegotiation { which: ConnectionID, option: u8, data: Vec<u8> },
+    /// The server has just shown a prompt, and marked the end of it (with a telnet GA or EOR), so
+    /// it doesn't have to wait for a newline to be seen.  `prompt` is the text since the last
+    /// line, which can be empty.  GAs from servers that have agreed to suppress them (SGA) don't
+    /// count.
+    ServerPrompt { prompt: String, which: ConnectionID },
+    /// MCCP compression started or stopped.  `ours` is for what we send (MCCP3); otherwise it's
+    /// what the server sends (MCCP2.)
+    Compression { which: ConnectionID, ours: bool, enabled: bool },
+    /// The server picked the charset its text is in (telnet CHARSET), and it's been agreed to.
+    Charset { which: ConnectionID, charset: Charset },
+    /// Connection `which` dropped, and it'll be tried again (with the same ConnectionID) after
+    /// `delay`.  If that works there's a ConnectionStart; if it doesn't, another of these, or in
+    /// the end a ConnectionEnd.  `attempt` counts from 1.
+    Reconnecting { which: ConnectionID, reason: String, error: ErrorClass, attempt: u32, delay: Duration },
+    /// The server sent a new value for an MSDP variable (see net::msdp.)
+    MsdpUpdate { which: ConnectionID, variable: String, value: String },
+    /// Connection `which` is still up, but the server hasn't sent anything for `quiet`, which is
+    /// longer than its world allows (see world::IdlePolicy.)  There's one of these each time it
+    /// goes quiet; whatever it sends next means it's back.  This is about the server, not the
+    /// user: nothing we send counts.
+    ServerIdle { which: ConnectionID, quiet: Duration },
 
-    /// A serious internal problem, e.g., a listening thread panicked or died.
-    InternalError { what: String },
+    /// Lines have gone to `window` while it was out of sight, and it has `unread` of them now
+    /// that haven't been seen; or it's come into sight, and `unread` is 0.  There's one of these
+    /// for each window that's changed each time round the event loop, not one for every line.
+    WindowActivity { window: String, unread: usize },
+
+    /// A trigger's pattern matched a line from connection `which`, and the trigger said to raise
+    /// an event called `name`.  `captures` are the pattern's capture groups.
+    TriggerFired { name: String, which: ConnectionID, captures: Vec<String> },
+
+    TimerFired { id: TimerID },
+    /// A job given to the ActionPool has finished (or been given up on.)
+    ActionDone { id: ActionID, result: Result<String, String> },
+
+    /// A listening thread that promised to check in regularly (see Listener::heartbeat_interval)
+    /// hasn't.  It's probably stuck, and whatever it was listening for isn't being heard.
+    SourceStalled { source: usize, listener: usize },
+
+    /// A listening thread has stopped: it gave up because of `error`, or panicked, or returned
+    /// when it should have run forever.  If its source could start another in its place (see
+    /// EventSource::restart_listener), and it hasn't had to too often, that's been done already;
+    /// if not, whatever it was listening for won't be heard.  ThreadInfo::status says which.
+    ListenerFailed { source: usize, listener: usize, error: MintError },
+    /// Every one of source `id`'s listening threads has stopped for good, so nothing more will
+    /// come from it.  `reason` is why the last of them stopped.  The other sources carry on.
+    SourceDied { id: usize, reason: String },
     QuitRequest,
 }
 
+/// Where an Event came from: one of the EventSources (by index, in the order they were started),
+/// or the EventManager itself (Events it raises, like SourceStalled, and ones post()ed to it.)
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Origin {
+    Source(usize),
+    Manager,
+}
+
+/// An Event, with when the EventManager got it and where from.
+///
+/// The ordering an EventManager promises:
+///
+/// - Each origin's Events come out in the order it produced them, and `seq` counts them, from 1
+///   with no gaps, so one that's been lost or reordered shows.  Everything to do with one
+///   connection (ConnectionStart, the ServerText, ConnectionEnd and the rest) comes from one
+///   source, which keeps it in the order it happened, so it comes out first in, first out.
+/// - Nothing is promised about how Events from different origins interleave, except that they
+///   come out in the order they reached the manager, so `at` never goes backwards from one
+///   Event to the next.
+#[derive(Debug)]
+pub struct Stamped {
+    pub event: Event,
+    /// When the manager got it (from a source's process(), or by being posted), which is as
+    /// close to when it happened as the manager can tell.
+    pub at: Instant,
+    pub origin: Origin,
+    pub seq: u64,
+}
+
 /// Objects that generate Events in response to I/O or other similar sources of data, specifically
 /// those that do so by listening on one or more threads, implement this trait.  Communication
 /// between the thread(s) and the EventSource object proper (which is always owned by the main
@@ -38,6 +138,13 @@ pub trait EventSource {
     /// Return a list of objects representing the state and data required for every individual
     /// listening thread this EventSource wants to run.
     fn get_listeners(&mut self) -> Vec<Box<Listener>>;
+
+    /// A new listener to take over from the one called `name` (see Listener::name), which has
+    /// stopped, if the source can make one.  Sources whose listeners take things with them that
+    /// there's no getting back (the other end of a channel, say) can't, and by default don't.
+    fn restart_listener(&mut self, _name: &str) -> Option<Box<dyn Listener>> {
+        None
+    }
 }
 
 /// Object encapsulating the state and functionality for listening for new data, I/O, file writes,
@@ -47,8 +154,21 @@ pub trait Listener: Send {
     /// This method should listen for data, transfer it into the associated EventSource by whatever
     /// synchronization method the implementor chooses, and page the ReadinessPager when either
     /// this has been done and the data needs to be processed (by the EventSource), or an error
-    /// occurs.
-    fn run(&mut self, flag: Box<ReadinessPager>);
+    /// occurs.  It should run forever, unless something goes wrong that it can't carry on from,
+    /// in which case it returns that.
+    fn run(&mut self, flag: Box<dyn ReadinessPager>) -> Result<(), MintError>;
+
+    /// A short name for the thread, e.g. "tcp-listener", used to name the actual OS thread and in
+    /// diagnostics.
+    fn name(&self) -> String;
+
+    /// If the thread can promise to call heartbeat() on its ReadinessPager at least this often,
+    /// return how often.  The EventManager will then complain if it goes quiet for too long.
+    /// Threads that spend their time blocked waiting for something that might never happen
+    /// (like a keypress) can't promise that, and should leave this alone.
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        None
+    }
 }
 
 /// Object allowing its owner to notify the parent thread that either data has been successfully
@@ -57,13 +177,51 @@ pub trait ReadinessPager: Send {
     /// Notify the thread that data needs to be processed.
     fn ok(&mut self);
 
-    /// Notify the thread of an error.  Provide ideally a human-readable error message.
-    fn err(&mut self, why: String);
+    /// Notify the thread of an error the listener can't carry on from.
+    fn err(&mut self, why: MintError);
+
+    /// Let the parent thread know this thread is still alive and not stuck.
+    fn heartbeat(&mut self);
+}
+
+/// How a listening thread seems to be doing, as far as its EventManager can tell.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ThreadStatus {
+    Running,
+    /// It promised heartbeats and stopped sending them.
+    Stalled,
+    /// It returned or panicked.
+    Dead,
+}
+
+/// What an EventManager knows about one of the listening threads it runs, for diagnostics.
+#[derive(Clone, Debug)]
+pub struct ThreadInfo {
+    pub name: String,
+    /// Which EventSource the thread belongs to, in the order they were started.
+    pub source: usize,
+    pub status: ThreadStatus,
+    /// How long it's been since the thread last showed any sign of life.
+    pub idle: Duration,
+    pub heartbeat_interval: Option<Duration>,
+    /// How many times it's been started over after stopping.
+    pub restarts: u32,
 }
 
 /// Trait implemented by an object that manages various sources of Events.
 pub trait EventManager {
     fn start_source(&mut self, src: Rc<RefCell<EventSource>>);
-    fn next_event(&mut self) -> Result<Event, String>;
+
+    /// The next Event, with its timestamp and sequence number (see Stamped for the order they come
+    /// in.)  Waits for one if there aren't any yet.
+    fn next_stamped(&mut self) -> Result<Stamped, MintError>;
+
+    /// The next Event, for when when and where from don't matter.
+    fn next_event(&mut self) -> Result<Event, MintError> {
+        self.next_stamped().map(|stamped| stamped.event)
+    }
+
+    /// List the listening threads, in the order they were started.
+    fn threads(&self) -> Vec<ThreadInfo>;
 }
 
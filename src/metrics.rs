@@ -0,0 +1,106 @@
+//! Metrics, for keeping an eye on a long-running session (a bot, say) from outside: how many
+//! events each source has come up with, how many bytes each connection's moved, how many
+//! redraws there have been and how long they took, and how many events are waiting.  They're
+//! written to a file every so often in Prometheus's text format, which node_exporter's textfile
+//! collector (or anything else that wants to) can pick up.  Everything's a running total, so
+//! rates (events a second and so on) are up to whoever's reading.
+//!
+//! Only built with the "metrics" feature.
+
+use crate::net::ConnectionID;
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How a connection's doing, for the metrics.
+pub struct Traffic {
+    pub which: ConnectionID,
+    pub address: String,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+/// Everything measured, at one moment.
+pub struct Snapshot {
+    /// Events from each source, by the names of its threads.
+    pub events: Vec<(String, u64)>,
+    pub queued: usize,
+    pub connections: Vec<Traffic>,
+    pub redraws: u64,
+    pub redraw_time: Duration,
+}
+
+impl Snapshot {
+    /// The snapshot in Prometheus's text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            let _ = writeln!(out, "# HELP mint_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE mint_{} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "mint_{}{} {}", name, labels, value);
+            }
+        };
+
+        family("events_total", "counter", "Events each source has come up with.",
+               self.events.iter().map(|(source, n)| (format!("{{source=\"{}\"}}", escape(source)), n.to_string())).collect());
+        family("events_queued", "gauge", "Events waiting to be handled.",
+               vec![(String::new(), self.queued.to_string())]);
+
+        let connection = |t: &Traffic| format!("{{connection=\"{}\",address=\"{}\"}}", t.which, escape(&t.address));
+        family("received_bytes_total", "counter", "Bytes received from each connection.",
+               self.connections.iter().map(|t| (connection(t), t.bytes_in.to_string())).collect());
+        family("sent_bytes_total", "counter", "Bytes sent to each connection.",
+               self.connections.iter().map(|t| (connection(t), t.bytes_out.to_string())).collect());
+
+        family("redraws_total", "counter", "Times the screen's been redrawn.",
+               vec![(String::new(), self.redraws.to_string())]);
+        family("redraw_seconds_total", "counter", "Time spent redrawing the screen.",
+               vec![(String::new(), format!("{:.6}", self.redraw_time.as_secs_f64()))]);
+        out
+    }
+}
+
+/// A label value with the characters that mean something in one escaped.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Where metrics are written, and how often.
+pub struct Export {
+    pub path: PathBuf,
+    pub interval: Duration,
+}
+
+impl Export {
+    /// Write a snapshot out.  It goes to a file next to the real one first and is then moved into
+    /// place, so whatever's reading never sees half of one.
+    pub fn write(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        fs::write(&partial, snapshot.render())?;
+        fs::rename(&partial, &self.path)
+    }
+}
+
+#[test]
+fn rendering() {
+    let snapshot = Snapshot {
+        events: vec![("tcp-listener".to_string(), 12)],
+        queued: 1,
+        connections: vec![Traffic { which: ConnectionID::new(1, 0), address: "mud\"x\":23".to_string(),
+                                    bytes_in: 2048, bytes_out: 10 }],
+        redraws: 3,
+        redraw_time: Duration::from_millis(1500),
+    };
+    let text = snapshot.render();
+    assert!(text.contains("# TYPE mint_events_total counter\nmint_events_total{source=\"tcp-listener\"} 12\n"));
+    assert!(text.contains("\nmint_events_queued 1\n"));
+    assert!(text.contains("address=\"mud\\\"x\\\":23\"} 2048\n"));
+    assert!(text.contains("\nmint_redraw_seconds_total 1.500000\n"));
+}
@@ -0,0 +1,178 @@
+//! ConnectionIDs, and handing them out.  An ID is a slot plus a generation: a slot is used again
+//! once the connection in it is over, but with the next generation, so the new connection's ID
+//! never equals the old one's.  Anything still going around about the old connection (an event
+//! the listener thread sent before it heard, say) can then be told apart and thrown away, instead
+//! of being taken for news about the new one.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::ops::Range;
+
+use mio::Token;
+
+// How many of a token's bits are the slot; the rest are the generation.  Token 0 belongs to the
+// listener thread's own alert, which is why there's no slot 0.
+const SLOT_BITS: u32 = 16;
+const SLOT_MASK: usize = (1 << SLOT_BITS) - 1;
+
+/// The slots each sort of connection gets, so that IDs from different ConnectionInterfaces never
+/// clash, and it's plain from an ID alone which one it belongs to.
+pub const TCP_SLOTS: Range<usize> = 1..1000;
+pub const PROCESS_SLOTS: Range<usize> = 1000..2000;
+
+/// Which connection something is about.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ConnectionID(usize);
+
+impl ConnectionID {
+    /// An ID made up rather than handed out by an Allocator, for tests and the like.
+    pub const fn new(slot: usize, generation: usize) -> ConnectionID {
+        ConnectionID((generation << SLOT_BITS) | (slot & SLOT_MASK))
+    }
+
+    pub fn slot(self) -> usize {
+        self.0 & SLOT_MASK
+    }
+
+    pub fn generation(self) -> usize {
+        self.0 >> SLOT_BITS
+    }
+
+    /// The mio Token for this connection's socket.  Since the generation's in it too, readiness
+    /// for a socket that's gone can't be mistaken for readiness on a new one in the same slot.
+    pub fn token(self) -> Token {
+        Token(self.0)
+    }
+
+    pub fn from_token(token: Token) -> ConnectionID {
+        ConnectionID(token.0)
+    }
+}
+
+/// Just the slot, for the first connection in it ("3"); after that, the generation as well
+/// ("3.1").
+impl fmt::Display for ConnectionID {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.generation() {
+            0 => write!(f, "{}", self.slot()),
+            generation => write!(f, "{}.{}", self.slot(), generation),
+        }
+    }
+}
+
+/// By slot, then generation.
+impl Ord for ConnectionID {
+    fn cmp(&self, other: &ConnectionID) -> std::cmp::Ordering {
+        (self.slot(), self.generation()).cmp(&(other.slot(), other.generation()))
+    }
+}
+
+impl PartialOrd for ConnectionID {
+    fn partial_cmp(&self, other: &ConnectionID) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Hands out ConnectionIDs, and knows which are still in use.
+pub struct Allocator {
+    // The generation each slot is on, by slot; slot 0 is never used.
+    generations: Vec<usize>,
+    // Which slots are in use.
+    live: Vec<bool>,
+    // Slots that are free again, the longest free first.  Taking those first leaves each slot
+    // alone for as long as possible before it's used again.
+    free: VecDeque<usize>,
+    // One past the last slot it can hand out.
+    end: usize,
+}
+
+impl Allocator {
+    pub fn new() -> Allocator {
+        Allocator::with_slots(1..SLOT_MASK + 1)
+    }
+
+    /// An Allocator that only hands out IDs in `slots`, e.g. TCP_SLOTS.
+    pub fn with_slots(slots: Range<usize>) -> Allocator {
+        assert!(slots.start > 0, "slot 0 is the listener thread's");
+        // The slots below the range are never handed out, but it's simplest to keep their
+        // places.
+        Allocator {
+            generations: vec![0; slots.start],
+            live: vec![false; slots.start],
+            free: VecDeque::new(),
+            end: slots.end.min(SLOT_MASK + 1),
+        }
+    }
+
+    /// A new ID, not equal to any handed out before.  None if every slot's in use.
+    pub fn allocate(&mut self) -> Option<ConnectionID> {
+        let slot = match self.free.pop_front() {
+            Some(slot) => slot,
+            None if self.generations.len() >= self.end => return None,
+            None => {
+                self.generations.push(0);
+                self.live.push(false);
+                self.generations.len() - 1
+            },
+        };
+        self.live[slot] = true;
+        Some(ConnectionID::new(slot, self.generations[slot]))
+    }
+
+    /// Say a connection's over, freeing its slot for the next generation.  Returns false if the
+    /// ID wasn't in use.
+    pub fn release(&mut self, id: ConnectionID) -> bool {
+        if !self.is_live(id) {
+            return false;
+        }
+        let slot = id.slot();
+        self.live[slot] = false;
+        self.generations[slot] += 1;
+        self.free.push_back(slot);
+        true
+    }
+
+    /// Whether an ID belongs to a connection that isn't over yet.
+    pub fn is_live(&self, id: ConnectionID) -> bool {
+        let slot = id.slot();
+        self.live.get(slot) == Some(&true) && self.generations[slot] == id.generation()
+    }
+}
+
+impl Default for Allocator {
+    fn default() -> Allocator {
+        Allocator::new()
+    }
+}
+
+#[test]
+fn ids_are_not_reused() {
+    let mut ids = Allocator::new();
+    let first = ids.allocate().unwrap();
+    let second = ids.allocate().unwrap();
+    assert_eq!((first.slot(), second.slot()), (1, 2));
+    assert_ne!(first.token(), Token(0));
+
+    assert!(ids.release(first));
+    assert!(!ids.release(first));
+    let third = ids.allocate().unwrap();
+    // Same slot, but not the same connection.
+    assert_eq!(third.slot(), first.slot());
+    assert_ne!(third, first);
+    assert!(!ids.is_live(first));
+    assert!(ids.is_live(third));
+    assert_eq!(ConnectionID::from_token(third.token()), third);
+    assert_eq!((first.to_string(), third.to_string()), ("1".to_string(), "1.1".to_string()));
+    assert!(first < third && third < second);
+}
+
+#[test]
+fn slot_ranges() {
+    let mut ids = Allocator::with_slots(3..5);
+    let first = ids.allocate().unwrap();
+    assert_eq!((first.slot(), ids.allocate().unwrap().slot()), (3, 4));
+    assert_eq!(ids.allocate(), None);
+    assert!(!ids.is_live(ConnectionID::new(1, 0)));
+    ids.release(first);
+    assert_eq!(ids.allocate().unwrap().slot(), 3);
+}
@@ -0,0 +1,140 @@
+//! MCCP, the MUD Client Compression Protocol: zlib streams switched on by telnet negotiation.
+//! Version 2 (option 86) compresses what the server sends us; version 3 (option 87) compresses
+//! what we send the server.
+//!
+//! Either way, everything after IAC SB <option> IAC SE is compressed, and compression stops when
+//! the zlib stream ends, with whatever comes after that back to plain telnet.
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/// How much room to make at a time for output.
+const CHUNK_SIZE: usize = 4096;
+
+/// What came out of inflating some data from the server.
+#[derive(Debug, Default)]
+pub struct Inflated {
+    pub data: Vec<u8>,
+    /// If the compressed stream ended, whatever came after it.  That isn't compressed.
+    pub rest: Option<Vec<u8>>,
+}
+
+/// Decompression for data from the server (MCCP2.)
+pub struct Inflater {
+    z: Decompress,
+}
+
+impl Inflater {
+    pub fn new() -> Inflater {
+        Inflater { z: Decompress::new(true) }
+    }
+
+    /// Inflate some more of the stream.  It can be cut up anywhere; whatever can't be inflated
+    /// yet waits for the next call.  An error means the stream's broken, and there's no getting
+    /// back in step with it.
+    pub fn inflate(&mut self, mut input: &[u8]) -> Result<Inflated, String> {
+        let mut data = Vec::with_capacity(CHUNK_SIZE);
+
+        loop {
+            if data.len() == data.capacity() {
+                data.reserve(CHUNK_SIZE);
+            }
+            let (in_before, out_before) = (self.z.total_in(), data.len());
+            let status = self.z.decompress_vec(input, &mut data, FlushDecompress::None)
+                .map_err(|e| format!("Bad compressed data: {}", e))?;
+            input = &input[(self.z.total_in() - in_before) as usize..];
+
+            if status == Status::StreamEnd {
+                return Ok(Inflated { data, rest: Some(input.to_vec()) });
+            }
+            // If the output didn't fill up, zlib has given us everything it can for now.
+            let stuck = self.z.total_in() == in_before && data.len() == out_before;
+            if (input.is_empty() && data.len() < data.capacity()) || stuck {
+                return Ok(Inflated { data, rest: None });
+            }
+        }
+    }
+}
+
+impl Default for Inflater {
+    fn default() -> Inflater {
+        Inflater::new()
+    }
+}
+
+/// Compression for data we send the server (MCCP3.)
+pub struct Deflater {
+    z: Compress,
+}
+
+impl Deflater {
+    pub fn new() -> Deflater {
+        Deflater { z: Compress::new(Compression::default(), true) }
+    }
+
+    /// Compress some data.  It's flushed, so the server can inflate all of it as soon as it
+    /// arrives; commands can't wait around in a buffer for more to come.
+    pub fn deflate(&mut self, input: &[u8]) -> Vec<u8> {
+        self.run(input, FlushCompress::Sync)
+    }
+
+    /// End the stream, for when compression's switched off.  Anything sent after this goes as
+    /// it is.
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.run(&[], FlushCompress::Finish)
+    }
+
+    fn run(&mut self, mut input: &[u8], flush: FlushCompress) -> Vec<u8> {
+        let mut out = Vec::with_capacity(input.len() / 2 + 64);
+        loop {
+            if out.len() == out.capacity() {
+                out.reserve(CHUNK_SIZE);
+            }
+            let before = self.z.total_in();
+            // Compressing into memory can't fail for any reason but running out of room, and
+            // that's taken care of above.
+            let status = self.z.compress_vec(input, &mut out, flush).expect("compress_vec");
+            input = &input[(self.z.total_in() - before) as usize..];
+
+            // With room left over, everything's gone through and been flushed.
+            if status == Status::StreamEnd || (input.is_empty() && out.len() < out.capacity()) {
+                return out;
+            }
+        }
+    }
+}
+
+impl Default for Deflater {
+    fn default() -> Deflater {
+        Deflater::new()
+    }
+}
+
+#[test]
+fn compression_round_trip() {
+    let text: Vec<u8> = (0..2000).flat_map(|i| format!("line {}\r\n", i).into_bytes()).collect();
+
+    let mut deflater = Deflater::new();
+    let mut compressed = deflater.deflate(&text);
+    compressed.extend(deflater.finish());
+    assert!(compressed.len() < text.len() / 2);
+    compressed.extend_from_slice(b"plain again");
+
+    // However it arrives, the same text comes out, and what's after the end is handed back.
+    let mut inflater = Inflater::new();
+    let mut inflated = vec![];
+    let mut rest = vec![];
+    let mut pieces = compressed.chunks(7);
+    for piece in pieces.by_ref() {
+        let out = inflater.inflate(piece).unwrap();
+        inflated.extend(out.data);
+        if let Some(after) = out.rest {
+            rest = after;
+            break;
+        }
+    }
+    rest.extend(pieces.flatten());
+    assert_eq!(inflated, text);
+    assert_eq!(rest, b"plain again");
+
+    assert!(Inflater::new().inflate(b"not zlib at all").is_err());
+}
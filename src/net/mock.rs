@@ -0,0 +1,224 @@
+//! A pretend MUD for tests: a server on a port of its own that goes through a script (lines,
+//! prompts, telnet options, waiting to hear things from the client), so a TcpConnectionManager
+//! can be tested end to end, through a ThreadedManager, the way the client uses it.
+//!
+//! Everything's on 127.0.0.1, and everything that waits gives up after a few seconds, so a test
+//! that's gone wrong fails instead of hanging.
+
+use crate::events::ThreadedManager;
+use crate::meta::{Event, EventManager};
+use crate::net::{ConnectionInterface, ErrorClass};
+use crate::net::mccp::Deflater;
+use crate::net::telnet::{self, IAC, DO, WONT, GA, SB, SE, opt};
+use crate::net::tcp::TcpConnectionManager;
+use crate::timer::{TimerID, Timers};
+
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How long anything waits before deciding the test's gone wrong.
+const PATIENCE: Duration = Duration::from_secs(10);
+
+/// One step of a MockMud's script.
+pub enum Step {
+    /// Send bytes as they are.
+    Send(Vec<u8>),
+    /// Send a line, with CRLF after it.
+    Line(&'static str),
+    /// Send a prompt, and mark its end with GA.
+    Prompt(&'static str),
+    /// Offer to turn an option on on the server's side.
+    Will(u8),
+    /// Ask the client to turn an option on on its side.
+    Do(u8),
+    /// Start compressing (MCCP2) with this, which has to be the start of a zlib stream.
+    Compress(Vec<u8>),
+    /// Wait until the client's sent these bytes, after whatever was waited for before.
+    Expect(Vec<u8>),
+    /// Hang up, and stop.
+    Close,
+}
+
+pub struct MockMud {
+    /// Where to connect to it.
+    pub address: String,
+    thread: JoinHandle<Result<Vec<u8>, String>>,
+}
+
+impl MockMud {
+    /// Start listening, for one connection, which gets `script`.
+    pub fn start(script: Vec<Step>) -> MockMud {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Couldn't listen for the mock MUD");
+        let address = listener.local_addr().unwrap().to_string();
+        let thread = thread::spawn(move || {
+            let (stream, _) = listener.accept().map_err(|e| e.to_string())?;
+            play(stream, script)
+        });
+        MockMud { address, thread }
+    }
+
+    /// Wait for the script to be done, and return everything the client sent.  Panics if the
+    /// script didn't go as written.
+    pub fn finish(self) -> Vec<u8> {
+        match self.thread.join() {
+            Ok(Ok(received)) => received,
+            Ok(Err(e)) => panic!("The mock MUD's script went wrong: {}", e),
+            Err(_) => panic!("The mock MUD panicked"),
+        }
+    }
+}
+
+fn play(mut stream: TcpStream, script: Vec<Step>) -> Result<Vec<u8>, String> {
+    stream.set_read_timeout(Some(PATIENCE)).map_err(|e| e.to_string())?;
+    let mut received = vec![];
+    // Where in `received` the next Expect starts looking.
+    let mut seen = 0;
+
+    for step in script {
+        let bytes = match step {
+            Step::Send(bytes) => bytes,
+            Step::Line(text) => format!("{}\r\n", text).into_bytes(),
+            Step::Prompt(text) => [text.as_bytes(), &[IAC, GA]].concat(),
+            Step::Will(option) => vec![IAC, telnet::WILL, option],
+            Step::Do(option) => vec![IAC, telnet::DO, option],
+            Step::Compress(stream) => [&[IAC, SB, telnet::opt::COMPRESS2, IAC, SE], &stream[..]].concat(),
+            Step::Expect(wanted) => {
+                let found = loop {
+                    if let Some(at) = received[seen..].windows(wanted.len()).position(|w| w == &wanted[..]) {
+                        break seen + at;
+                    }
+                    let mut buffer = [0; 1024];
+                    match stream.read(&mut buffer) {
+                        Ok(0) => return Err(format!("The client hung up before sending {:?}", wanted)),
+                        Ok(n) => received.extend_from_slice(&buffer[..n]),
+                        Err(e) => return Err(format!("Didn't get {:?} ({}); got {:?}", wanted, e, received)),
+                    }
+                };
+                seen = found + wanted.len();
+                continue;
+            },
+            Step::Close => break,
+        };
+        stream.write_all(&bytes).map_err(|e| e.to_string())?;
+    }
+
+    Ok(received)
+}
+
+/// A TcpConnectionManager running in a ThreadedManager, as in the client, and a way to wait for
+/// what it says.
+pub struct Harness {
+    pub manager: ThreadedManager,
+    pub tcp: Rc<RefCell<TcpConnectionManager>>,
+    // For giving up on waiting.
+    _timers: Rc<RefCell<Timers>>,
+    deadline: TimerID,
+}
+
+impl Harness {
+    pub fn new() -> Harness {
+        let mut manager = ThreadedManager::new();
+        let tcp = Rc::new(RefCell::new(TcpConnectionManager::new()));
+        manager.start_source(tcp.clone());
+        let timers = Rc::new(RefCell::new(Timers::new()));
+        manager.start_source(timers.clone());
+        let deadline = timers.borrow_mut().schedule(Instant::now() + PATIENCE);
+        Harness { manager, tcp, _timers: timers, deadline }
+    }
+
+    /// Wait for an event `wanted` says is the one.  Panics if it doesn't come in time, saying
+    /// what did come instead.
+    pub fn wait_for(&mut self, wanted: impl Fn(&Event) -> bool) -> Event {
+        let mut skipped = vec![];
+        loop {
+            match self.manager.next_event() {
+                Ok(event) if wanted(&event) => return event,
+                Ok(Event::TimerFired { id }) if id == self.deadline => {
+                    panic!("Gave up waiting; got these instead: {:?}", skipped);
+                },
+                Ok(event) => skipped.push(event),
+                Err(e) => panic!("The manager failed: {}", e),
+            }
+        }
+    }
+}
+
+fn text(event: &Event, wanted: &str) -> bool {
+    matches!(event, Event::ServerText { line, .. } if line.trim_end() == wanted)
+}
+
+#[test]
+fn lines_prompts_and_replies() {
+    let mud = MockMud::start(vec![
+        Step::Line("Welcome!"),
+        Step::Prompt("Name? "),
+        Step::Expect(b"Bob\r\n".to_vec()),
+        Step::Line("Hello, Bob."),
+        Step::Close,
+    ]);
+    let mut harness = Harness::new();
+    let cid = harness.tcp.borrow_mut().start_connection(mud.address.clone()).unwrap();
+
+    harness.wait_for(|e| matches!(e, Event::ConnectionStart { which } if *which == cid));
+    harness.wait_for(|e| text(e, "Welcome!"));
+    harness.wait_for(|e| matches!(e, Event::ServerPrompt { prompt, .. } if prompt == "Name? "));
+    harness.tcp.borrow_mut().write_to_connection(cid, "Bob\n".to_string()).unwrap();
+    harness.wait_for(|e| text(e, "Hello, Bob."));
+    harness.wait_for(|e| matches!(e, Event::ConnectionEnd { error: Some(ErrorClass::Eof), .. }));
+    assert_eq!(mud.finish(), b"Bob\r\n".to_vec());
+}
+
+#[test]
+fn telnet_options() {
+    let mud = MockMud::start(vec![
+        Step::Will(opt::ECHO),
+        Step::Expect(vec![IAC, DO, opt::ECHO]),
+        Step::Do(opt::NAWS),
+        Step::Expect(vec![IAC, WONT, opt::NAWS]),
+        Step::Line("Password:"),
+        Step::Close,
+    ]);
+    let mut harness = Harness::new();
+    harness.tcp.borrow_mut().start_connection(mud.address.clone()).unwrap();
+
+    harness.wait_for(|e| matches!(e, Event::ServerEcho { enabled: true, .. }));
+    harness.wait_for(|e| text(e, "Password:"));
+    mud.finish();
+}
+
+#[test]
+fn compressed_text() {
+    let mut deflater = Deflater::new();
+    let squashed = deflater.deflate(b"Squashed.\r\n");
+    let end = [deflater.finish(), b"Plain again.\r\n".to_vec()].concat();
+    let mud = MockMud::start(vec![
+        Step::Will(opt::COMPRESS2),
+        Step::Expect(vec![IAC, DO, opt::COMPRESS2]),
+        Step::Compress(squashed),
+        Step::Send(end),
+        Step::Close,
+    ]);
+    let mut harness = Harness::new();
+    harness.tcp.borrow_mut().start_connection(mud.address.clone()).unwrap();
+
+    harness.wait_for(|e| matches!(e, Event::Compression { ours: false, enabled: true, .. }));
+    // Whether it stopping comes before or after the line it squashed depends on whether the two
+    // arrived together.
+    harness.wait_for(|e| text(e, "Squashed."));
+    harness.wait_for(|e| text(e, "Plain again."));
+    mud.finish();
+}
+
+#[test]
+fn refused_connection() {
+    // Somewhere nothing's listening, now.
+    let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let mut harness = Harness::new();
+    let cid = harness.tcp.borrow_mut().start_connection(address).unwrap();
+    harness.wait_for(|e| matches!(e, Event::ConnectionEnd { which, error: Some(ErrorClass::Refused), .. }
+                                  if *which == cid));
+}
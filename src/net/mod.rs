@@ -1,5 +1,10 @@
 
-pub type ConnectionID = usize;
+use crate::error::MintError;
+
+use std::fmt;
+use std::io;
+
+pub use self::id::ConnectionID;
 
 /// This type of object knows about servers and contains the low-level logic for connecting and
 /// listening to a particular sort of MUD server.  It returns a secondary object instead of directly
@@ -9,9 +14,82 @@ pub type ConnectionID = usize;
 /// accomodate those types of server that may not be able to be satisfied with a traditional
 /// host/port pair.
 pub trait ConnectionInterface {
-    fn start_connection(&mut self, address: String) -> Result<ConnectionID, String>;
-    fn stop_connection(&mut self, which: ConnectionID) -> Result<(), ()>;
-    fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), ()>;
+    fn start_connection(&mut self, address: String) -> Result<ConnectionID, MintError>;
+    fn stop_connection(&mut self, which: ConnectionID) -> Result<(), MintError>;
+    fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), MintError>;
+}
+
+/// What sort of thing ended a connection that nobody asked to close, so that what's done about
+/// it (like whether to reconnect) can depend on it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorClass {
+    /// Nothing was listening, or something in the way said no.
+    Refused,
+    /// The connection was cut off, by the other end or something in between.
+    Reset,
+    TimedOut,
+    /// The TLS handshake failed, or what came over TLS didn't make sense.
+    Tls,
+    /// The host couldn't be looked up.
+    Dns,
+    /// The server hung up.
+    Eof,
+    Other,
+}
+
+impl ErrorClass {
+    pub const ALL: [ErrorClass; 7] = [ErrorClass::Refused, ErrorClass::Reset, ErrorClass::TimedOut,
+                                      ErrorClass::Tls, ErrorClass::Dns, ErrorClass::Eof, ErrorClass::Other];
+
+    /// The class of an error from a socket.  On a TLS connection, data that isn't valid means
+    /// it's TLS that's gone wrong.
+    pub fn of(error: &io::Error, tls: bool) -> ErrorClass {
+        match error.kind() {
+            io::ErrorKind::ConnectionRefused => ErrorClass::Refused,
+            io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::NotConnected => ErrorClass::Reset,
+            io::ErrorKind::TimedOut => ErrorClass::TimedOut,
+            io::ErrorKind::InvalidData if tls => ErrorClass::Tls,
+            io::ErrorKind::UnexpectedEof => ErrorClass::Eof,
+            _ => ErrorClass::Other,
+        }
+    }
+
+    /// The name the user knows it by, e.g. in /reconnect.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorClass::Refused => "refused",
+            ErrorClass::Reset => "reset",
+            ErrorClass::TimedOut => "timeout",
+            ErrorClass::Tls => "tls",
+            ErrorClass::Dns => "dns",
+            ErrorClass::Eof => "eof",
+            ErrorClass::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ErrorClass> {
+        ErrorClass::ALL.iter().copied().find(|class| class.name() == name)
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
+pub mod id;
+pub mod mccp;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod msdp;
+pub mod process;
+pub mod proxy;
+pub mod mxp;
+pub mod reconnect;
+pub mod stack;
 pub mod tcp;
+pub mod telnet;
+pub mod tls;
+pub mod transcode;
@@ -0,0 +1,140 @@
+//! MSDP, the MUD Server Data Protocol: variables like HEALTH or ROOM_NAME sent by the server in
+//! telnet subnegotiations (option 69), so there's no need to scrape them out of prompts.
+//!
+//! Inside IAC SB MSDP ... IAC SE, each variable is VAR name VAL value.  A value can also be a
+//! table (TABLE_OPEN, more VAR/VAL pairs, TABLE_CLOSE) or an array (ARRAY_OPEN, VALs,
+//! ARRAY_CLOSE.)  We keep every value as text, tables and arrays included, written out as
+//! `{NAME=value, ...}` and `[value, ...]`; that's enough for showing them and for expressions.
+
+use crate::net::telnet::{self, opt};
+
+pub const VAR: u8 = 1;
+pub const VAL: u8 = 2;
+pub const TABLE_OPEN: u8 = 3;
+pub const TABLE_CLOSE: u8 = 4;
+pub const ARRAY_OPEN: u8 = 5;
+pub const ARRAY_CLOSE: u8 = 6;
+
+/// The variables in a subnegotiation's data, in the order they came.
+pub fn parse(data: &[u8]) -> Vec<(String, String)> {
+    let mut parser = Parser { data, pos: 0 };
+    let mut pairs = vec![];
+    while parser.pos < data.len() {
+        if data[parser.pos] == VAR {
+            pairs.push(parser.pair());
+        } else {
+            // Junk between variables.  Skip it rather than give up on the rest.
+            parser.pos += 1;
+        }
+    }
+    pairs
+}
+
+/// The whole subnegotiation (IAC SB MSDP ... IAC SE) for a command to the server, like REPORT
+/// with the names of the variables we'd like to be kept up to date on.
+pub fn command(name: &str, args: &[String]) -> Vec<u8> {
+    let mut data = vec![VAR];
+    data.extend_from_slice(name.as_bytes());
+    for arg in args {
+        data.push(VAL);
+        data.extend_from_slice(arg.as_bytes());
+    }
+
+    let mut out = vec![telnet::IAC, telnet::SB, opt::MSDP];
+    out.extend(telnet::escape(&data));
+    out.extend_from_slice(&[telnet::IAC, telnet::SE]);
+    out
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).cloned()
+    }
+
+    /// Text up to the next MSDP control byte.
+    fn text(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b > ARRAY_CLOSE) {
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.data[start..self.pos]).into_owned()
+    }
+
+    /// VAR name, then any number of VAL value.  Some servers send arrays the old way, as one VAR
+    /// with several VALs; those get joined up with spaces.
+    fn pair(&mut self) -> (String, String) {
+        self.pos += 1;
+        let name = self.text();
+        let mut values = vec![];
+        while self.peek() == Some(VAL) {
+            self.pos += 1;
+            values.push(self.value());
+        }
+        (name, values.join(" "))
+    }
+
+    fn value(&mut self) -> String {
+        match self.peek() {
+            Some(TABLE_OPEN) => {
+                self.pos += 1;
+                let mut entries = vec![];
+                loop {
+                    match self.peek() {
+                        Some(VAR) => {
+                            let (name, value) = self.pair();
+                            entries.push(format!("{}={}", name, value));
+                        },
+                        Some(TABLE_CLOSE) => { self.pos += 1; break; },
+                        None => break,
+                        Some(_) => self.pos += 1,
+                    }
+                }
+                format!("{{{}}}", entries.join(", "))
+            },
+            Some(ARRAY_OPEN) => {
+                self.pos += 1;
+                let mut items = vec![];
+                loop {
+                    match self.peek() {
+                        Some(VAL) => { self.pos += 1; items.push(self.value()); },
+                        Some(ARRAY_CLOSE) => { self.pos += 1; break; },
+                        None => break,
+                        Some(_) => self.pos += 1,
+                    }
+                }
+                format!("[{}]", items.join(", "))
+            },
+            _ => self.text(),
+        }
+    }
+}
+
+#[test]
+fn msdp_variables() {
+    let mut data = vec![VAR];
+    data.extend_from_slice(b"HEALTH");
+    data.push(VAL);
+    data.extend_from_slice(b"50");
+    data.push(VAR);
+    data.extend_from_slice(b"ROOM");
+    data.extend_from_slice(&[VAL, TABLE_OPEN, VAR, b'N', VAL, b'3', VAR]);
+    data.extend_from_slice(b"EXITS");
+    data.extend_from_slice(&[VAL, ARRAY_OPEN, VAL, b'n', VAL, b's', ARRAY_CLOSE, TABLE_CLOSE]);
+    data.extend_from_slice(&[VAR, b'X', VAL, b'a', VAL, b'b']);
+
+    assert_eq!(parse(&data), vec![
+        ("HEALTH".to_string(), "50".to_string()),
+        ("ROOM".to_string(), "{N=3, EXITS=[n, s]}".to_string()),
+        ("X".to_string(), "a b".to_string()),
+    ]);
+    // Cut off partway through: as much as there is.
+    assert_eq!(parse(&data[..12]), vec![("HEALTH".to_string(), "50".to_string()), ("R".to_string(), String::new())]);
+
+    let report = command("REPORT", &["HEALTH".to_string()]);
+    assert_eq!(report, b"\xff\xfa\x45\x01REPORT\x02HEALTH\xff\xf0".to_vec());
+}
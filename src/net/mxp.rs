@@ -0,0 +1,277 @@
+//! MXP, the MUD eXtension Protocol: HTML-ish markup in the server's text, once telnet option 91
+//! is on.  We only go as far as links (SEND and A): those come out as Links, every other tag is
+//! stripped, and entities like &lt; are decoded.
+//!
+//! Which tags count depends on the line's mode, set with `ESC [ n z`: in open mode (the default)
+//! only harmless formatting tags are allowed, so SEND and A are dropped without making links; in
+//! secure mode anything goes; in locked mode nothing's markup, and the text comes through as it
+//! is.  Modes 0 to 2 last for the line, 4 for the next tag, and 5 to 7 until further notice.
+
+use crate::ui::ansi;
+
+/// What following a link does.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LinkAction {
+    /// Send a command to the server.
+    Send(String),
+    /// Open a URL, or as near as a terminal can get.
+    Url(String),
+}
+
+/// A piece of a line that does something when it's followed.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Link {
+    /// Where the link's text is, as byte offsets into the line's text once its escape sequences
+    /// are gone (i.e. Line::text().)
+    pub start: usize,
+    pub end: usize,
+    pub action: LinkAction,
+    /// What the server says the link does, if it says.
+    pub hint: Option<String>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Mode {
+    Open,
+    Secure,
+    Locked,
+}
+
+/// A link whose closing tag hasn't turned up yet.
+struct Opening {
+    // Where it starts in the text so far, escape sequences and all.
+    start: usize,
+    send: bool,
+    href: Option<String>,
+    hint: Option<String>,
+}
+
+/// The MXP side of a connection, which only has to remember the mode lines start in.
+pub struct Parser {
+    default: Mode,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser { default: Mode::Open }
+    }
+
+    /// Take the markup out of a line, returning what's left (other escape sequences and all) and
+    /// the links that were in it.  A link that isn't closed by the end of the line ends there.
+    pub fn line(&mut self, raw: &str) -> (String, Vec<Link>) {
+        let mut out = String::with_capacity(raw.len());
+        let mut mode = self.default;
+        let mut temp_secure = false;
+        let mut opening: Option<Opening> = None;
+        let mut links = vec![];
+        let mut rest = raw;
+
+        while let Some(c) = rest.chars().next() {
+            if let Some(n) = mode_change(rest) {
+                let (line_mode, default) = match n {
+                    0 => (Mode::Open, self.default),
+                    1 | 4 => (Mode::Secure, self.default),
+                    2 => (Mode::Locked, self.default),
+                    5 | 3 => (Mode::Open, Mode::Open),
+                    6 => (Mode::Secure, Mode::Secure),
+                    7 => (Mode::Locked, Mode::Locked),
+                    _ => (mode, self.default),
+                };
+                // Temp secure goes back to the mode there was before after one tag.
+                temp_secure = n == 4 && mode != Mode::Secure;
+                mode = line_mode;
+                self.default = default;
+                rest = &rest[rest.find('z').unwrap() + 1..];
+                continue;
+            }
+
+            if c == '<' && mode != Mode::Locked {
+                if let Some(end) = rest.find('>') {
+                    let tag = &rest[1..end];
+                    rest = &rest[end + 1..];
+                    let secure = mode == Mode::Secure;
+                    if temp_secure {
+                        temp_secure = false;
+                        mode = self.default;
+                    }
+                    let (closing, name, attributes) = parse_tag(tag);
+                    match (closing, name.as_str()) {
+                        // A link that was let in gets closed whatever mode it's closed in.
+                        (true, "send") | (true, "a") => {
+                            if let Some(link) = opening.take() {
+                                links.extend(finish(link, &out));
+                            }
+                        },
+                        _ if !secure => { },
+                        (false, "send") | (false, "a") => {
+                            let positional = attributes.iter().find(|(name, _)| name.is_none());
+                            let named = |wanted: &str| attributes.iter()
+                                .find(|(name, _)| name.as_deref() == Some(wanted))
+                                .map(|(_, value)| value.clone());
+                            opening = Some(Opening {
+                                start: out.len(),
+                                send: name == "send",
+                                href: named("href").or_else(|| positional.map(|(_, value)| value.clone())),
+                                hint: named("hint"),
+                            });
+                        },
+                        _ => { },
+                    }
+                    continue;
+                }
+            }
+
+            if c == '&' && mode != Mode::Locked {
+                if let Some((decoded, length)) = entity(rest) {
+                    out.push(decoded);
+                    rest = &rest[length..];
+                    continue;
+                }
+            }
+
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        if let Some(link) = opening {
+            links.extend(finish(link, &out));
+        }
+        (out, links)
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
+/// If `text` starts with a mode change (`ESC [ n z`), the mode.
+fn mode_change(text: &str) -> Option<u32> {
+    let body = text.strip_prefix("\x1b[")?;
+    let end = body.find(|c: char| !c.is_ascii_digit())?;
+    if body[end..].starts_with('z') { body[..end].parse().ok() } else { None }
+}
+
+/// Split a tag (what's between < and >) into whether it's a closing one, its name in lower case,
+/// and its attributes: (Some(name), value) for name=value, and (None, value) for values on their
+/// own.  Values can be quoted with " or '.
+fn parse_tag(tag: &str) -> (bool, String, Vec<(Option<String>, String)>) {
+    let (closing, tag) = match tag.strip_prefix('/') {
+        Some(tag) => (true, tag),
+        None => (false, tag),
+    };
+    let tag = tag.trim().trim_end_matches('/');
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = tag[..name_end].to_lowercase();
+
+    let mut attributes = vec![];
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest.find(|c: char| c == '=' || c.is_whitespace() || c == '"' || c == '\'')
+            .unwrap_or(rest.len());
+        let (key, after) = if rest[key_end..].starts_with('=') {
+            (Some(rest[..key_end].to_lowercase()), &rest[key_end + 1..])
+        } else {
+            (None, rest)
+        };
+        let (value, after) = match after.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                let end = after[1..].find(quote).map_or(after.len(), |end| end + 1);
+                (&after[1..end], after.get(end + 1..).unwrap_or(""))
+            },
+            _ => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            },
+        };
+        attributes.push((key, value.to_string()));
+        rest = after.trim_start();
+    }
+    (closing, name, attributes)
+}
+
+/// If `text` starts with an entity (&lt;, &#65; and so on), what it stands for and how long it
+/// is.
+fn entity(text: &str) -> Option<(char, usize)> {
+    let end = text.char_indices().take(10).find(|(_, c)| *c == ';')?.0;
+    let name = &text[1..end];
+    let decoded = match name {
+        "lt" => '<',
+        "gt" => '>',
+        "amp" => '&',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)?
+        },
+    };
+    Some((decoded, end + 1))
+}
+
+/// Make a Link out of one that's just been closed, at the end of `out`.  Nothing if there's no
+/// text to follow, or nothing for it to do.
+fn finish(link: Opening, out: &str) -> Option<Link> {
+    // Offsets into the text as it'll be once the escape sequences are gone.
+    let visible = |raw: &str| ansi::parse(raw).text.len();
+    let text = ansi::parse(&out[link.start..]).text;
+    let (start, end) = (visible(&out[..link.start]), visible(out));
+    if start == end {
+        return None;
+    }
+
+    // A SEND can have a menu of commands separated by |, of which the first is the one to do, and
+    // &text; stands for the link's text.  Without an href, the text is the command.
+    let href = link.href.map(|href| href.replace("&text;", &text)).unwrap_or(text);
+    let action = if link.send {
+        LinkAction::Send(href.split('|').next().unwrap_or("").to_string())
+    } else {
+        LinkAction::Url(href)
+    };
+    match action {
+        LinkAction::Send(ref command) | LinkAction::Url(ref command) if command.is_empty() => None,
+        action => Some(Link { start, end, action, hint: link.hint }),
+    }
+}
+
+#[test]
+fn mxp_links() {
+    let mut parser = Parser::new();
+
+    // Open mode: formatting goes, links don't happen, entities are decoded.
+    let (text, links) = parser.line("<b>Exits:</b> <send>north</send> &lt;door&gt;");
+    assert_eq!(text, "Exits: north <door>");
+    assert!(links.is_empty());
+
+    // A secure line, with colors in the way.
+    let (text, links) = parser.line("\x1b[1zExits: \x1b[32m<send href='go north|look north' hint=\"Go north\">north</send>\x1b[0m, <SEND>east</SEND>");
+    assert_eq!(text, "Exits: \x1b[32mnorth\x1b[0m, east");
+    assert_eq!(links, vec![
+        Link { start: 7, end: 12, action: LinkAction::Send("go north".to_string()), hint: Some("Go north".to_string()) },
+        Link { start: 14, end: 18, action: LinkAction::Send("east".to_string()), hint: None },
+    ]);
+
+    // Line modes only last the line; locked means no markup at all.
+    assert!(parser.line("<send>north</send>").1.is_empty());
+    assert_eq!(parser.line("\x1b[2z<b>&amp;</b>").0, "<b>&amp;</b>");
+
+    // Locking secure lasts, and the first positional attribute is the href.
+    let (text, links) = parser.line("\x1b[6z<a \"https://example.com/\">site</a> <send \"buy &text;\">sword");
+    assert_eq!(text, "site sword");
+    assert_eq!(links[0].action, LinkAction::Url("https://example.com/".to_string()));
+    assert_eq!((links[1].start, links[1].end), (5, 10));
+    assert_eq!(links[1].action, LinkAction::Send("buy sword".to_string()));
+    assert_eq!(parser.line("<send>up</send>").1.len(), 1);
+
+    // Temp secure is good for one tag.
+    let (text, links) = parser.line("\x1b[5z\x1b[4z<send>up</send> <send>down</send>");
+    assert_eq!(text, "up down");
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].action, LinkAction::Send("up".to_string()));
+}
@@ -0,0 +1,305 @@
+//! Connections to programs instead of servers: a command (ssh to a shell account with a MUD on
+//! it, a game that runs locally, a test harness) is run with `sh -c`, what it prints (on both
+//! stdout and stderr) is taken as what a server sent, and what's sent goes to its standard input.
+//! There's no telnet in between, just lines.
+//!
+//! Their addresses start with "exec:", e.g. `/connect exec:ssh -T games.example.org`.  The
+//! connection ends when the program closes its output (usually by exiting), or when it's
+//! disconnected, which kills it.
+
+use crate::charset::Charset;
+use crate::error::MintError;
+use crate::meta::{Event, EventSource, ReadinessPager, Listener};
+use crate::net::{ConnectionInterface, ConnectionID, ErrorClass};
+use crate::net::id;
+use crate::net::tcp::take_lines;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+pub const SCHEME: &str = "exec:";
+
+const BUFFER_SIZE: usize = 4096;
+// How often the listener thread checks in with the EventManager when nothing's happening.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The command in an address, if it's one for a program.
+pub fn command(address: &str) -> Option<&str> {
+    address.strip_prefix(SCHEME).map(str::trim)
+}
+
+/// What the reading threads (and stop_connection()) have to say, on the way to process().
+enum ProcEvt {
+    Started(ConnectionID),
+    Data(ConnectionID, Vec<u8>),
+    /// The program's closed its output.
+    Eof(ConnectionID),
+    /// The program's been killed, as asked for by stop_connection().
+    Closed(ConnectionID),
+}
+
+/// EventSource (and ConnectionInterface) for programs run as if they were connections.
+pub struct ProcessConnectionManager {
+    ids: id::Allocator,
+    children: HashMap<ConnectionID, Child>,
+    stdins: HashMap<ConnectionID, ChildStdin>,
+    // What's been read that isn't a whole line yet.
+    input_buffers: HashMap<ConnectionID, Vec<u8>>,
+
+    // The reading threads send here; the listener thread passes it all on to process() and pages
+    // the EventManager, since it's the one with the ReadinessPager.  In an Option so it can be
+    // moved into the listener, like in Timers.
+    relay_tx: mpsc::Sender<ProcEvt>,
+    relay_rx: Option<mpsc::Receiver<ProcEvt>>,
+    data_tx: mpsc::Sender<ProcEvt>,
+    data_rx: mpsc::Receiver<ProcEvt>,
+}
+
+impl ProcessConnectionManager {
+    pub fn new() -> ProcessConnectionManager {
+        let (relay_tx, relay_rx) = mpsc::channel();
+        let (data_tx, data_rx) = mpsc::channel();
+
+        ProcessConnectionManager {
+            ids: id::Allocator::with_slots(id::PROCESS_SLOTS),
+            children: HashMap::new(),
+            stdins: HashMap::new(),
+            input_buffers: HashMap::new(),
+            relay_tx,
+            relay_rx: Some(relay_rx),
+            data_tx,
+            data_rx,
+        }
+    }
+
+    /// Whether a connection is one of ours (or would be, if it's over.)
+    pub fn owns(which: ConnectionID) -> bool {
+        id::PROCESS_SLOTS.contains(&which.slot())
+    }
+
+    /// Kill a program, and anything it started: it runs in a process group of its own, so that
+    /// e.g. the ssh that `sh -c` started goes too, instead of keeping the output open.
+    fn kill(child: &mut Child) {
+        // Safe since kill() doesn't touch memory; the worst a stale ID could do is signal a
+        // group that's already gone.
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGTERM);
+        }
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    /// A program's connection is over; say why, and forget it.
+    fn end(&mut self, which: ConnectionID, reason: String, error: Option<ErrorClass>, queue: &mut Vec<Event>) {
+        if let Some(rest) = self.input_buffers.remove(&which).filter(|rest| !rest.is_empty()) {
+            let line = String::from_utf8_lossy(&rest).to_string();
+            queue.push(Event::ServerText { which, line, links: vec![] });
+        }
+        self.stdins.remove(&which);
+        if let Some(mut child) = self.children.remove(&which) {
+            Self::kill(&mut child);
+        }
+        self.ids.release(which);
+        queue.push(Event::ConnectionEnd { which, reason, error });
+    }
+}
+
+impl Default for ProcessConnectionManager {
+    fn default() -> ProcessConnectionManager {
+        ProcessConnectionManager::new()
+    }
+}
+
+impl Drop for ProcessConnectionManager {
+    fn drop(&mut self) {
+        for child in self.children.values_mut() {
+            Self::kill(child);
+        }
+    }
+}
+
+/// Read everything `from` has to say on a thread of its own, and send it on as `which`'s.  If
+/// `eof`, say when it's done.
+fn read_in_background<R: Read + Send + 'static>(mut from: R, which: ConnectionID, eof: bool,
+                                                 tx: mpsc::Sender<ProcEvt>) {
+    thread::spawn(move || {
+        let mut buffer = [0u8; BUFFER_SIZE];
+        loop {
+            match from.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if tx.send(ProcEvt::Data(which, buffer[..n].to_vec())).is_err() {
+                        return;
+                    }
+                },
+            }
+        }
+        if eof {
+            let _ = tx.send(ProcEvt::Eof(which));
+        }
+    });
+}
+
+impl ConnectionInterface for ProcessConnectionManager {
+    /// `address` is "exec:" followed by a command for `sh -c`.
+    fn start_connection(&mut self, address: String) -> Result<ConnectionID, MintError> {
+        let command = command(&address)
+            .ok_or_else(|| MintError::Net(format!("Not a command to run: {}", address)))?;
+        let which = self.ids.allocate()
+            .ok_or_else(|| MintError::Net("Too many programs running at once".to_string()))?;
+        let spawned = Command::new("sh").arg("-c").arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                self.ids.release(which);
+                return Err(MintError::Net(format!("Couldn't run {}: {}", command, e)));
+            },
+        };
+
+        // Started goes first, before anything the program says can.
+        self.relay_tx.send(ProcEvt::Started(which))?;
+        read_in_background(child.stdout.take().expect("child stdout"), which, true, self.relay_tx.clone());
+        read_in_background(child.stderr.take().expect("child stderr"), which, false, self.relay_tx.clone());
+        self.stdins.insert(which, child.stdin.take().expect("child stdin"));
+        self.children.insert(which, child);
+        Ok(which)
+    }
+
+    fn stop_connection(&mut self, which: ConnectionID) -> Result<(), MintError> {
+        let mut child = self.children.remove(&which).ok_or(MintError::NoConnection(which))?;
+        self.stdins.remove(&which);
+        Self::kill(&mut child);
+        self.relay_tx.send(ProcEvt::Closed(which))?;
+        Ok(())
+    }
+
+    /// What's written goes as it is, newlines and all.  A program that isn't reading its input
+    /// can hold this up once the pipe's full.
+    fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), MintError> {
+        let stdin = self.stdins.get_mut(&which).ok_or(MintError::NoConnection(which))?;
+        stdin.write_all(what.as_bytes())?;
+        stdin.flush()?;
+        Ok(())
+    }
+}
+
+impl EventSource for ProcessConnectionManager {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
+        match self.relay_rx.take() {
+            Some(rx) => vec![Box::new(ProcessListener { rx, data_tx: self.data_tx.clone() })],
+            None => panic!("Cannot call get_listeners() on ProcessConnectionManager more than once."),
+        }
+    }
+
+    fn process(&mut self) -> Vec<Event> {
+        let mut queue = vec![];
+
+        while let Ok(evt) = self.data_rx.try_recv() {
+            match evt {
+                ProcEvt::Started(which) => queue.push(Event::ConnectionStart { which }),
+                // From a program that's gone, which the reading threads hadn't noticed yet.
+                ProcEvt::Data(which, _) | ProcEvt::Eof(which) if !self.ids.is_live(which) => { },
+                // Killing it closed its output, but it's the Closed behind this that says so.
+                ProcEvt::Eof(which) if !self.children.contains_key(&which) => { },
+                ProcEvt::Data(which, mut data) => {
+                    let buffer = self.input_buffers.entry(which).or_default();
+                    buffer.append(&mut data);
+                    for line in take_lines(buffer, Charset::Utf8) {
+                        queue.push(Event::ServerText { which, line, links: vec![] });
+                    }
+                },
+                ProcEvt::Eof(which) => {
+                    let reason = match self.children.get_mut(&which).and_then(exited) {
+                        Some(status) => format!("The program ended ({})", status),
+                        None => "The program closed its output".to_string(),
+                    };
+                    self.end(which, reason, Some(ErrorClass::Eof), &mut queue);
+                },
+                ProcEvt::Closed(which) => self.end(which, "closed by user".to_string(), None, &mut queue),
+            }
+        }
+
+        queue
+    }
+}
+
+/// How a program that's closed its output exited, if it has.  Closing it is usually the last
+/// thing a program does, so this waits a moment for it to get round to exiting.
+fn exited(child: &mut Child) -> Option<ExitStatus> {
+    for _ in 0..10 {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) => thread::sleep(Duration::from_millis(10)),
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// Passes on what the reading threads send, paging the EventManager as it goes.
+struct ProcessListener {
+    rx: mpsc::Receiver<ProcEvt>,
+    data_tx: mpsc::Sender<ProcEvt>,
+}
+
+impl Listener for ProcessListener {
+    fn name(&self) -> String {
+        "process-listener".to_string()
+    }
+
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        Some(HEARTBEAT_INTERVAL)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        loop {
+            match self.rx.recv_timeout(HEARTBEAT_INTERVAL) {
+                Ok(evt) => {
+                    self.data_tx.send(evt)?;
+                    flag.ok();
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => flag.heartbeat(),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[test]
+fn process_connections() {
+    use crate::events::ThreadedManager;
+    use crate::meta::EventManager;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut manager = ThreadedManager::new();
+    let processes = Rc::new(RefCell::new(ProcessConnectionManager::new()));
+    manager.start_source(processes.clone());
+
+    let cat = processes.borrow_mut().start_connection("exec:cat".to_string()).unwrap();
+    assert!(ProcessConnectionManager::owns(cat));
+    assert!(matches!(manager.next_event(), Ok(Event::ConnectionStart { which }) if which == cat));
+    processes.borrow_mut().write_to_connection(cat, "hello\n".to_string()).unwrap();
+    assert!(matches!(manager.next_event(), Ok(Event::ServerText { which, ref line, .. }) if which == cat && line == "hello"));
+    processes.borrow_mut().stop_connection(cat).unwrap();
+    assert!(matches!(manager.next_event(), Ok(Event::ConnectionEnd { which, error: None, .. }) if which == cat));
+    assert!(processes.borrow_mut().write_to_connection(cat, "hello\n".to_string()).is_err());
+
+    let echo = processes.borrow_mut().start_connection("exec: printf 'hi\\nthere'; exit 3".to_string()).unwrap();
+    assert_ne!(echo, cat);
+    assert!(matches!(manager.next_event(), Ok(Event::ConnectionStart { .. })));
+    assert!(matches!(manager.next_event(), Ok(Event::ServerText { ref line, .. }) if line == "hi"));
+    // What's left without a newline comes out at the end.
+    assert!(matches!(manager.next_event(), Ok(Event::ServerText { ref line, .. }) if line == "there"));
+    assert!(matches!(manager.next_event(), Ok(Event::ConnectionEnd { which, error: Some(ErrorClass::Eof), .. }) if which == echo));
+}
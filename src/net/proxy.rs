@@ -0,0 +1,284 @@
+//! Connecting through a proxy, for TcpConnectionManager.
+//!
+//! An address can start with a proxy to go through, then a '/', then the address as usual, e.g.
+//! "socks5://127.0.0.1:9050/example.com:4000" or "http://proxy.example.org:3128/tls://example.com:4443".
+//! The socket goes to the proxy, and the listener thread does the handshake with it (see Handshake)
+//! before anything else: before TLS, if it's a TLS connection, and before the connection counts
+//! as made.  The proxy looks up the world's host name itself, so a name that only resolves on the
+//! other side of it (a .onion, say) works.
+//!
+//! TODO: Neither kind of proxy can be given a user name and password yet.
+
+use crate::net::ErrorClass;
+use crate::net::tls;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The most an HTTP proxy's reply can take before we decide it's not going to end.
+const MAX_HTTP_REPLY: usize = 8192;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Socks5,
+    /// A web proxy that understands CONNECT.
+    Http,
+}
+
+impl Kind {
+    fn scheme(self) -> &'static str {
+        match self {
+            Kind::Socks5 => "socks5://",
+            Kind::Http => "http://",
+        }
+    }
+}
+
+/// A proxy to connect through, from the front of an address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proxy {
+    pub kind: Kind,
+    /// Where the proxy is, as host:port.
+    pub address: String,
+}
+
+/// Split a proxy off the front of an address, if there is one, leaving the address to connect to
+/// through it.
+pub fn strip_scheme(address: &str) -> (Option<Proxy>, &str) {
+    for kind in [Kind::Socks5, Kind::Http] {
+        if let Some(rest) = address.strip_prefix(kind.scheme()) {
+            if let Some(slash) = rest.find('/') {
+                let proxy = Proxy { kind, address: rest[..slash].to_string() };
+                return (Some(proxy), &rest[slash + 1..]);
+            }
+        }
+    }
+    (None, address)
+}
+
+/// How far along a handshake is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Nothing's been sent yet; the socket might still be connecting.
+    Idle,
+    /// SOCKS5: waiting to hear which way of authenticating the proxy picked.
+    Method,
+    /// SOCKS5: waiting to hear if the proxy's connected us.
+    Reply,
+    /// HTTP: waiting for the end of the reply's headers.
+    Headers,
+    Done,
+}
+
+/// What to do next in a handshake.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Send this to the proxy, and keep reading.
+    Send(Vec<u8>),
+    /// Keep reading.
+    Waiting,
+    /// The proxy's connected us.  What came after its reply is the server's.
+    Done(Vec<u8>),
+}
+
+/// The conversation with a proxy that gets it to connect to the world.  It only works out what to
+/// say and what was said back; the listener thread does the reading and writing.
+#[derive(Clone, Debug)]
+pub struct Handshake {
+    kind: Kind,
+    host: String,
+    port: u16,
+    state: State,
+    received: Vec<u8>,
+}
+
+impl Handshake {
+    /// A handshake for connecting to `address` (host:port, as in any other address) through a
+    /// proxy of the given kind.
+    pub fn new(kind: Kind, address: &str) -> Result<Handshake, String> {
+        let host = tls::host_name(address).to_string();
+        let port = address.rsplit(':').next()
+            .filter(|_| address.contains(':'))
+            .and_then(|port| port.parse().ok())
+            .ok_or_else(|| format!("Expected host:port after the proxy, not {}", address))?;
+        Ok(Handshake { kind, host, port, state: State::Idle, received: vec![] })
+    }
+
+    pub fn started(&self) -> bool {
+        self.state != State::Idle
+    }
+
+    /// Start over, as when the first of the proxy's addresses didn't work out and the next one's
+    /// being tried.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.received.clear();
+    }
+
+    /// What to send the proxy first, once the socket's connected.
+    pub fn start(&mut self) -> Vec<u8> {
+        match self.kind {
+            Kind::Socks5 => {
+                self.state = State::Method;
+                // Version 5, one method: no authentication.
+                vec![5, 1, 0]
+            },
+            Kind::Http => {
+                self.state = State::Headers;
+                let target = match self.host.parse::<Ipv6Addr>() {
+                    Ok(_) => format!("[{}]:{}", self.host, self.port),
+                    Err(_) => format!("{}:{}", self.host, self.port),
+                };
+                format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, target).into_bytes()
+            },
+        }
+    }
+
+    /// Take in what the proxy sent.  An Err is the proxy saying no (or not making sense), and what
+    /// sort of failure to call it.
+    pub fn receive(&mut self, bytes: &[u8]) -> Result<Progress, (String, ErrorClass)> {
+        self.received.extend_from_slice(bytes);
+        match self.state {
+            State::Idle | State::Done => Err(("The proxy spoke out of turn".to_string(), ErrorClass::Other)),
+            State::Method => {
+                if self.received.len() < 2 {
+                    return Ok(Progress::Waiting);
+                }
+                match (self.received[0], self.received[1]) {
+                    (5, 0) => { },
+                    (5, 0xff) => return Err(("The SOCKS proxy wants a user name and password".to_string(),
+                                             ErrorClass::Refused)),
+                    _ => return Err(("That doesn't look like a SOCKS5 proxy".to_string(), ErrorClass::Other)),
+                }
+                self.received.drain(..2);
+                self.state = State::Reply;
+                Ok(Progress::Send(self.socks_request()))
+            },
+            State::Reply => {
+                // Version, reply, reserved, address type, then the address (whose length depends
+                // on its type) and port, which is where the proxy connected from.
+                if self.received.len() >= 2 && self.received[1] != 0 {
+                    return Err(socks_error(self.received[1]));
+                }
+                if self.received.len() < 5 {
+                    return Ok(Progress::Waiting);
+                }
+                let address_len = match self.received[3] {
+                    1 => 4,
+                    3 => 1 + self.received[4] as usize,
+                    4 => 16,
+                    _ => return Err(("The SOCKS proxy's reply didn't make sense".to_string(), ErrorClass::Other)),
+                };
+                let len = 4 + address_len + 2;
+                if self.received.len() < len {
+                    return Ok(Progress::Waiting);
+                }
+                self.state = State::Done;
+                Ok(Progress::Done(self.received.split_off(len)))
+            },
+            State::Headers => {
+                let end = match self.received.windows(4).position(|w| w == b"\r\n\r\n") {
+                    Some(end) => end,
+                    None if self.received.len() > MAX_HTTP_REPLY => {
+                        return Err(("The proxy's reply went on too long".to_string(), ErrorClass::Other));
+                    },
+                    None => return Ok(Progress::Waiting),
+                };
+                let reply = String::from_utf8_lossy(&self.received[..end]).to_string();
+                let status = reply.lines().next().unwrap_or("");
+                let code = status.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok());
+                let class = match code {
+                    Some(200..=299) if status.starts_with("HTTP/") => {
+                        self.state = State::Done;
+                        return Ok(Progress::Done(self.received.split_off(end + 4)));
+                    },
+                    Some(403) | Some(407) | Some(502) | Some(503) => ErrorClass::Refused,
+                    Some(504) => ErrorClass::TimedOut,
+                    _ => ErrorClass::Other,
+                };
+                Err((format!("The proxy said: {}", status), class))
+            },
+        }
+    }
+
+    /// SOCKS5's CONNECT request.  Host names go as they are, for the proxy to look up.
+    fn socks_request(&self) -> Vec<u8> {
+        let mut request = vec![5, 1, 0];
+        if let Ok(ip) = self.host.parse::<Ipv4Addr>() {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        } else if let Ok(ip) = self.host.parse::<Ipv6Addr>() {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        } else {
+            // Names longer than this can't be sent, and don't exist anyway.
+            let name = &self.host.as_bytes()[..self.host.len().min(255)];
+            request.push(3);
+            request.push(name.len() as u8);
+            request.extend_from_slice(name);
+        }
+        request.extend_from_slice(&self.port.to_be_bytes());
+        request
+    }
+}
+
+/// What a SOCKS5 proxy's reply code means.
+fn socks_error(code: u8) -> (String, ErrorClass) {
+    let (what, class) = match code {
+        1 => ("general failure", ErrorClass::Other),
+        2 => ("not allowed", ErrorClass::Refused),
+        3 => ("network unreachable", ErrorClass::Other),
+        4 => ("host unreachable", ErrorClass::Other),
+        5 => ("connection refused", ErrorClass::Refused),
+        6 => ("timed out", ErrorClass::TimedOut),
+        7 => ("command not supported", ErrorClass::Other),
+        8 => ("address type not supported", ErrorClass::Other),
+        _ => ("unknown error", ErrorClass::Other),
+    };
+    (format!("The SOCKS proxy couldn't connect: {}", what), class)
+}
+
+#[test]
+fn proxy_addresses() {
+    let (proxy, rest) = strip_scheme("socks5://127.0.0.1:9050/tls://example.com:4443");
+    assert_eq!(proxy, Some(Proxy { kind: Kind::Socks5, address: "127.0.0.1:9050".to_string() }));
+    assert_eq!(rest, "tls://example.com:4443");
+    assert_eq!(strip_scheme("http://proxy:3128/[::1]:4000").1, "[::1]:4000");
+    assert_eq!(strip_scheme("example.com:4000"), (None, "example.com:4000"));
+    assert!(Handshake::new(Kind::Http, "example.com").is_err());
+}
+
+#[test]
+fn socks5_handshake() {
+    let mut handshake = Handshake::new(Kind::Socks5, "mud.example:4000").unwrap();
+    assert!(!handshake.started());
+    assert_eq!(handshake.start(), vec![5, 1, 0]);
+
+    let mut request = vec![5, 1, 0, 3, 11];
+    request.extend_from_slice(b"mud.example");
+    request.extend_from_slice(&[0x0f, 0xa0]);
+    assert_eq!(handshake.receive(&[5]), Ok(Progress::Waiting));
+    assert_eq!(handshake.receive(&[0]), Ok(Progress::Send(request)));
+
+    // Bound to 10.0.0.1:1234, and the server's first words arrive along with it.
+    assert_eq!(handshake.receive(&[5, 0, 0, 1, 10, 0]), Ok(Progress::Waiting));
+    assert_eq!(handshake.receive(&[0, 1, 4, 210, b'h', b'i']), Ok(Progress::Done(b"hi".to_vec())));
+
+    handshake.reset();
+    handshake.start();
+    assert_eq!(handshake.receive(&[5, 0]).map(|_| ()), Ok(()));
+    assert_eq!(handshake.receive(&[5, 5, 0, 1]).unwrap_err().1, ErrorClass::Refused);
+}
+
+#[test]
+fn http_handshake() {
+    let mut handshake = Handshake::new(Kind::Http, "[::1]:4000").unwrap();
+    assert_eq!(handshake.start(), b"CONNECT [::1]:4000 HTTP/1.1\r\nHost: [::1]:4000\r\n\r\n".to_vec());
+    assert_eq!(handshake.receive(b"HTTP/1.1 200 Connection established\r\n"), Ok(Progress::Waiting));
+    assert_eq!(handshake.receive(b"Via: squid\r\n\r\nWelcome"), Ok(Progress::Done(b"Welcome".to_vec())));
+
+    handshake.reset();
+    handshake.start();
+    let (reason, class) = handshake.receive(b"HTTP/1.1 403 Forbidden\r\n\r\n").unwrap_err();
+    assert_eq!(reason, "The proxy said: HTTP/1.1 403 Forbidden");
+    assert_eq!(class, ErrorClass::Refused);
+}
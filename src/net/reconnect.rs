@@ -0,0 +1,71 @@
+//! Reconnecting when a connection drops without anyone asking it to: after a short wait at
+//! first, then longer and longer ones, so a server that's down for a while doesn't get hammered.
+
+use crate::net::ErrorClass;
+
+use std::time::Duration;
+
+/// Whether, and how, to reconnect a connection that ends unexpectedly.  Connections the user
+/// closes never reconnect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub enabled: bool,
+    /// How long to wait before the first try.  Each try after that waits twice as long as the
+    /// one before...
+    pub initial_delay: Duration,
+    /// ...up to this.
+    pub max_delay: Duration,
+    /// How many tries to make before giving up, or None to keep trying forever.
+    pub max_attempts: Option<u32>,
+    /// What sorts of failure are worth trying again after.  There's no point for some, like a
+    /// certificate the server will still have next time.
+    pub after: Vec<ErrorClass>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            enabled: false,
+            initial_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(5 * 60),
+            max_attempts: Some(10),
+            after: ErrorClass::ALL.iter().copied().filter(|class| *class != ErrorClass::Tls).collect(),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// How long to wait before try number `attempt` (counting from 1) after a failure of the
+    /// class `error`, or None if there shouldn't be one.
+    pub fn delay(&self, attempt: u32, error: ErrorClass) -> Option<Duration> {
+        if !self.enabled || !self.after.contains(&error) || attempt == 0
+                || self.max_attempts.is_some_and(|max| attempt > max) {
+            return None;
+        }
+        // Doubling more than 31 times is well past any sensible max_delay anyway.
+        let factor = 1u32 << (attempt - 1).min(31);
+        Some(self.initial_delay.saturating_mul(factor).min(self.max_delay))
+    }
+}
+
+#[test]
+fn reconnect_backoff() {
+    let mut policy = ReconnectPolicy::default();
+    assert_eq!(policy.delay(1, ErrorClass::Reset), None);
+
+    policy.enabled = true;
+    let delays: Vec<Option<u64>> = (1..=11).map(|n| policy.delay(n, ErrorClass::Reset).map(|d| d.as_secs())).collect();
+    assert_eq!(delays, vec![
+        Some(5), Some(10), Some(20), Some(40), Some(80), Some(160),
+        Some(300), Some(300), Some(300), Some(300), None,
+    ]);
+
+    policy.max_attempts = None;
+    assert_eq!(policy.delay(1000, ErrorClass::Eof), Some(policy.max_delay));
+
+    // A bad certificate will still be bad next time.
+    assert_eq!(policy.delay(1, ErrorClass::Tls), None);
+    policy.after = vec![ErrorClass::Tls];
+    assert!(policy.delay(1, ErrorClass::Tls).is_some());
+    assert_eq!(policy.delay(1, ErrorClass::Refused), None);
+}
@@ -0,0 +1,115 @@
+use regex::Regex;
+use std::collections::VecDeque;
+
+/// Holds outbound commands back until the server has shown a prompt since the last one was sent,
+/// so automation goes at the server's pace instead of flooding it.  Some servers with strict
+/// input limits will drop or penalize commands that arrive before the previous one is processed.
+///
+/// A prompt is either a line of server output matching `prompt`, or whatever the caller decides
+/// is a prompt and reports with prompt_seen() (e.g. a telnet GA.)  If there's no pattern and
+/// nobody ever calls prompt_seen(), everything after the first command just sits in the queue
+/// forever, so don't turn this on for worlds where neither is going to happen.
+///
+/// Servers that mark their prompts (see prompt_marked()) don't need the pattern, and with both,
+/// every prompt would count twice; so once a marked prompt turns up, the pattern's ignored until
+/// prompts_unmarked() says the marks have stopped.
+pub struct CommandStack {
+    queue: VecDeque<String>,
+    awaiting_prompt: bool,
+    prompt: Option<Regex>,
+    marked: bool,
+}
+
+impl CommandStack {
+    pub fn new(prompt: Option<Regex>) -> CommandStack {
+        CommandStack {
+            queue: VecDeque::new(),
+            awaiting_prompt: false,
+            prompt,
+            marked: false,
+        }
+    }
+
+    /// Queue a command.  If it can go out right away, it's handed straight back.
+    pub fn push(&mut self, command: String) -> Option<String> {
+        if self.awaiting_prompt {
+            self.queue.push_back(command);
+            None
+        } else {
+            self.awaiting_prompt = true;
+            Some(command)
+        }
+    }
+
+    /// Look at a line of server output.  If it's a prompt, return the next command to send, if
+    /// there is one.
+    ///
+    /// NB: Prompts that don't end with a newline (and aren't marked) only become lines once more
+    /// output arrives, so this may lag a little behind the server.
+    pub fn server_line(&mut self, line: &str) -> Option<String> {
+        match self.prompt {
+            Some(ref pattern) if !self.marked && pattern.is_match(line) => self.prompt_seen(),
+            _ => None,
+        }
+    }
+
+    /// The server showed a prompt; return the next command to send, if there is one.
+    pub fn prompt_seen(&mut self) -> Option<String> {
+        let next = self.queue.pop_front();
+        self.awaiting_prompt = next.is_some();
+        next
+    }
+
+    /// The server marked the end of a prompt (with GA or EOR), which is more reliable than the pattern.
+    pub fn prompt_marked(&mut self) -> Option<String> {
+        self.marked = true;
+        self.prompt_seen()
+    }
+
+    /// The server won't be marking prompts any more (it's suppressing GA), so it's back to the
+    /// pattern.
+    pub fn prompts_unmarked(&mut self) {
+        self.marked = false;
+    }
+
+    /// Throw away everything that hasn't been sent yet, returning how many commands that was.
+    pub fn clear(&mut self) -> usize {
+        let n = self.queue.len();
+        self.queue.clear();
+        self.awaiting_prompt = false;
+        n
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[test]
+fn stack_waits_for_prompts() {
+    let mut stack = CommandStack::new(Some(Regex::new(r"^<\d+hp>").unwrap()));
+
+    assert_eq!(stack.push("n".to_string()), Some("n".to_string()));
+    assert_eq!(stack.push("e".to_string()), None);
+    assert_eq!(stack.push("s".to_string()), None);
+
+    assert_eq!(stack.server_line("A dusty road."), None);
+    assert_eq!(stack.server_line("<100hp>"), Some("e".to_string()));
+    assert_eq!(stack.prompt_seen(), Some("s".to_string()));
+    assert_eq!(stack.prompt_seen(), None);
+
+    // Nothing is outstanding any more, so the next command goes straight out.
+    assert_eq!(stack.push("w".to_string()), Some("w".to_string()));
+
+    // With GA, the prompt line itself doesn't count as well.
+    stack.push("u".to_string());
+    stack.push("d".to_string());
+    assert_eq!(stack.prompt_marked(), Some("u".to_string()));
+    assert_eq!(stack.server_line("<100hp>"), None);
+    stack.prompts_unmarked();
+    assert_eq!(stack.server_line("<100hp>"), Some("d".to_string()));
+}
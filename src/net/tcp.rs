@@ -1,33 +1,222 @@
 
+use crate::charset::Charset;
+use crate::error::MintError;
 use crate::meta::{Event, EventSource, ReadinessPager, Listener};
-use crate::net::{ConnectionInterface, ConnectionID}; 
+use crate::net::{ConnectionInterface, ConnectionID, ErrorClass};
+use crate::net::id;
+use crate::net::mccp::Deflater;
+use crate::net::msdp;
+use crate::net::mxp;
+use crate::net::proxy;
+use crate::net::reconnect::ReconnectPolicy;
+use crate::net::telnet::{self, Telnet, opt};
+use crate::net::tls::{self, Handshake, TlsStream};
+use crate::net::transcode::{self, Chain, MakeTranscoder, TelnetLayer, Transcoded};
 
 use mio::{Events, Poll, Ready, PollOpt, Token};
 use mio::net::TcpStream;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::net::{Ipv6Addr, Shutdown, SocketAddr, SocketAddrV6, ToSocketAddrs};
+use std::ffi::CString;
 use std::io::{Read, Write};
 
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
 
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
+
+#[cfg(test)]
+use proptest::prelude::*;
 
 const BUFFER_SIZE: usize = 4096;
 // 10 is ASCII newline
 const LINE_SEPARATOR: u8 = 10;
+// How often the listener thread checks in with the EventManager when nothing's happening.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+// How many connections that are over we remember the stats of, for /netstat.
+const ENDED_HISTORY: usize = 20;
+
+/// Turn an address like "example.com:4000", "127.0.0.1:4000" or "[fe80::1%eth0]:4000" into
+/// addresses we can connect to.
+///
+/// Bracketed IPv6 literals with a zone (the part after the '%') are handled here, since
+/// to_socket_addrs() doesn't understand zones; the zone can be an interface name or an index.
+/// Everything else goes to to_socket_addrs() as-is.
+fn resolve(address: &str) -> Result<Vec<SocketAddr>, String> {
+    if address.starts_with('[') && address.contains('%') {
+        let close = address.find(']')
+            .ok_or_else(|| format!("Missing ']' in address {}", address))?;
+        let port: u16 = match address[close + 1..].strip_prefix(':') {
+            Some(port) => port.parse().map_err(|_| format!("Bad port in address {}", address))?,
+            None => return Err(format!("Expected :port after ']' in address {}", address)),
+        };
+
+        let mut parts = address[1..close].splitn(2, '%');
+        let ip: Ipv6Addr = parts.next().unwrap_or("").parse()
+            .map_err(|_| format!("Bad IPv6 address in {}", address))?;
+        let zone = parts.next().unwrap_or("");
+        let scope_id = match zone.parse::<u32>() {
+            Ok(index) => index,
+            Err(_) => {
+                let name = CString::new(zone)
+                    .map_err(|_| format!("Bad interface name in address {}", address))?;
+                // Safe since if_nametoindex() only reads the string, which outlives the call.
+                match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+                    0 => return Err(format!("No network interface named '{}'", zone)),
+                    index => index,
+                }
+            },
+        };
+
+        return Ok(vec![SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id))]);
+    }
+
+    match address.to_socket_addrs() {
+        Ok(results) => Ok(results.collect()),
+        Err(_) => Err(format!("Couldn't get address for {}", address)),
+    }
+}
+
+/// Take every complete line out of the front of `buffer`, leaving whatever comes after the last
+/// LINE_SEPARATOR for next time.  The separators themselves go, and the lines are decoded from
+/// `charset`.  (The separator's the same byte in all of them, so splitting first is safe.)
+pub(crate) fn take_lines(buffer: &mut Vec<u8>, charset: Charset) -> Vec<String> {
+    let end = match buffer.iter().rposition(|c| *c == LINE_SEPARATOR) {
+        Some(end) => end,
+        None => return vec![],
+    };
+    let lines = buffer[..end].split(|c| *c == LINE_SEPARATOR)
+        .map(|line| charset.decode(line))
+        .collect();
+    buffer.drain(..end + 1);
+    lines
+}
+
+/// The Event for a line from the server, with its MXP (if it's using MXP) taken out and made into
+/// links.
+fn server_text(mxp: Option<&mut mxp::Parser>, which: ConnectionID, line: String) -> Event {
+    match mxp {
+        Some(parser) => {
+            let (line, links) = parser.line(&line);
+            Event::ServerText { which, line, links }
+        },
+        None => Event::ServerText { which, line, links: vec![] },
+    }
+}
+
+/// One end of a connection: either the socket itself, or the socket with TLS on top.
+enum Link {
+    Plain(TcpStream),
+    Tls(TlsStream),
+}
+
+impl Link {
+    /// The socket underneath, for registering with a Poll.
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Link::Plain(stream) => stream,
+            Link::Tls(stream) => stream.socket(),
+        }
+    }
+
+    /// How the TLS handshake went, if there is one and it's done.
+    fn handshake(&self) -> Option<Handshake> {
+        match self {
+            Link::Plain(_) => None,
+            Link::Tls(stream) => stream.handshake(),
+        }
+    }
+
+    fn is_tls(&self) -> bool {
+        matches!(self, Link::Tls(_))
+    }
+
+    fn try_clone(&self) -> std::io::Result<Link> {
+        match self {
+            Link::Plain(stream) => stream.try_clone().map(Link::Plain),
+            Link::Tls(stream) => stream.try_clone().map(Link::Tls),
+        }
+    }
+}
 
-/// Internal event type for events sent back from the listening thread.
+impl Read for Link {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Link::Plain(stream) => stream.read(buf),
+            Link::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Link {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Link::Plain(stream) => stream.write(buf),
+            Link::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Link::Plain(stream) => stream.flush(),
+            Link::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Internal event type for events sent back from the listening thread.  They all come down the
+/// one channel, so what happens to each connection stays in the order it happened, which is what
+/// the EventManager's ordering promise (see meta::Stamped) rests on.
 enum LinkEvt {
-    Established(ConnectionID, TcpStream),
-    CouldntEstablish(ConnectionID),
+    Established(ConnectionID, Link),
+    /// None of the addresses worked; the class is for the last one's failure.
+    CouldntEstablish(ConnectionID, ErrorClass),
     Data(ConnectionID, Vec<u8>),
-    Error(ConnectionID, String),
+    Error(ConnectionID, String, ErrorClass),
     Eof(ConnectionID),
+    /// The link's been shut down, as asked for by stop_connection().
+    Closed(ConnectionID),
+}
+
+impl LinkEvt {
+    fn cid(&self) -> ConnectionID {
+        match *self {
+            LinkEvt::Established(cid, _) | LinkEvt::CouldntEstablish(cid, _) | LinkEvt::Data(cid, _)
+                | LinkEvt::Error(cid, _, _) | LinkEvt::Eof(cid) | LinkEvt::Closed(cid) => cid,
+        }
+    }
+}
+
+/// How a connection's doing, for /stats.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub address: String,
+    /// How long it's been up, or None if it's still connecting (or waiting to reconnect.)
+    pub connected_for: Option<Duration>,
+    /// How many times it's dropped and been reconnected.
+    pub reconnects: u32,
+    pub tls: bool,
+    /// For TLS connections, once the handshake's done.
+    pub handshake: Option<Handshake>,
+    /// When it last came up (which is when it was reconnected, if it has been.)
+    pub connected_at: Option<SystemTime>,
+    /// When it last dropped, and why.  For a connection that's over, that's when it ended.
+    pub dropped: Option<(SystemTime, String)>,
+    /// Bytes received and sent, as they went over the wire (compressed, if they were), over
+    /// every time it's been connected.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// The layers of its read path (see transcode.rs), from the socket up.
+    pub layers: Vec<&'static str>,
 }
 
 /// EventSource for TCP connections.
 pub struct TcpConnectionManager {
-    links: HashMap<ConnectionID, TcpStream>,
-    last_connection_id: ConnectionID,
+    links: HashMap<ConnectionID, Link>,
+    // Which connections there are.  An ID stops being live when its connection ends, and
+    // anything the listener thread says about it after that is ignored.
+    ids: id::Allocator,
 
     // We need a way to register sockets with the Poll object, but mio's Poll objects aren't so
     // easy to share across threads or data structures, and our thread owns the Poll object (see
@@ -35,11 +224,11 @@ pub struct TcpConnectionManager {
     // want to register along a channel, and use mio's Registraton/SetReadiness mechanism to alert
     // the polling loop.
 
-    socketreg_tx: mpsc::Sender<ConnectionRequest>,
+    socketreg_tx: mpsc::Sender<ListenerRequest>,
     // This is wrapped in an Option because we want to create it when calling new(), but it does
     // need to be moved into a struct later.  (Ultimately, it is moved across thread boundaries and
     // the reader thread registers it to a Poll instance.)
-    socketreg_rx: Option<mpsc::Receiver<ConnectionRequest>>,
+    socketreg_rx: Option<mpsc::Receiver<ListenerRequest>>,
 
     socketreg_sr: mio::SetReadiness,
     // This is in an Option for the same reason.
@@ -50,7 +239,51 @@ pub struct TcpConnectionManager {
     listener_tx: mpsc::Sender<LinkEvt>,
 
     // A HashMap of vec<u8> used for buffering input from remote servers.
-    input_buffers: HashMap<ConnectionID, Vec<u8>>
+    input_buffers: HashMap<ConnectionID, Vec<u8>>,
+
+    // Each connection's read path (see transcode.rs): everything from the server goes up through
+    // this before it's split into lines.  Inflating MCCP happens in there and not in the listener
+    // thread, since it's the telnet layer that knows where compression starts.  The telnet layer
+    // is kept here as well, to negotiate with.  Then the layers to put in every time a connection
+    // is made, besides those (see add_transcoder.)
+    chains: HashMap<ConnectionID, Chain>,
+    telnet: HashMap<ConnectionID, Rc<RefCell<Telnet>>>,
+    transcoders: HashMap<ConnectionID, Vec<MakeTranscoder>>,
+
+    // MCCP: connections where we're compressing what we send the server.
+    deflaters: HashMap<ConnectionID, Deflater>,
+    // Connections that aren't to use compression at all (see set_compression.)
+    uncompressed: HashSet<ConnectionID>,
+
+    // The MSDP variables each server has sent, as they were last sent.
+    msdp: HashMap<ConnectionID, HashMap<String, String>>,
+
+    // MXP state for the connections where the server's switched it on.
+    mxp: HashMap<ConnectionID, mxp::Parser>,
+
+    // What each connection's text is in: what the user said (see set_charset), unless the server
+    // said otherwise since it was connected (telnet CHARSET.)  UTF-8 if neither.
+    charsets: HashMap<ConnectionID, Charset>,
+    negotiated: HashMap<ConnectionID, Charset>,
+
+    // Reconnecting: the address each connection was started with, to try again with, how to go
+    // about it (see set_reconnect), and how many tries there have been since it dropped.
+    addresses: HashMap<ConnectionID, String>,
+    reconnect: HashMap<ConnectionID, ReconnectPolicy>,
+    attempts: HashMap<ConnectionID, u32>,
+    // For ConnectionStats: when each connection was last established (going by both clocks: one
+    // to time it with, and one to tell the user), when and why it last dropped, and how many
+    // times it's been reconnected.  Plus the stats of the last few connections that are over.
+    connected: HashMap<ConnectionID, Instant>,
+    connected_at: HashMap<ConnectionID, SystemTime>,
+    dropped: HashMap<ConnectionID, (SystemTime, String)>,
+    reconnects: HashMap<ConnectionID, u32>,
+    traffic: HashMap<ConnectionID, (u64, u64)>,
+    ended: Vec<(ConnectionID, ConnectionStats)>,
+    // Connections we've shut down because something went wrong on our end (like a broken
+    // compressed stream), and why.  Whatever else arrives from them is ignored until the
+    // listener thread says they're gone.
+    broken: HashMap<ConnectionID, String>,
 }
 
 /// This struct represents a request to the listening thread that a new connection be started.
@@ -62,19 +295,31 @@ pub struct TcpConnectionManager {
 struct ConnectionRequest {
     addrs: Vec<SocketAddr>,
     cid: ConnectionID,
+    // The host name to check the certificate against, if this is a TLS connection.
+    tls_host: Option<String>,
+    // What to say to the proxy, if it's going through one.  (`addrs` are then the proxy's.)
+    proxy: Option<proxy::Handshake>,
+    // For reconnecting: not to be tried before then.
+    not_before: Option<Instant>,
+}
+
+/// What the main thread can ask of the listening thread.  These go along the same channel, with
+/// the same alert, as new sockets did back when that was all there was.
+enum ListenerRequest {
+    Connect(ConnectionRequest),
+    /// Shut a connection down and forget it, because the user's done with it.
+    Close(ConnectionID),
 }
 
 impl TcpConnectionManager {
     pub fn new() -> TcpConnectionManager {
         let (registration, set_readiness) = mio::Registration::new2();
-        let (tx, rx) = mpsc::channel::<ConnectionRequest>();
+        let (tx, rx) = mpsc::channel::<ListenerRequest>();
         let (tx2, rx2) = mpsc::channel::<LinkEvt>();
 
         return TcpConnectionManager {
             links: HashMap::new(),
-            // We use 1 since the listener thread wants to use 0 for its 'alert me when there's a
-            // new socket to register' Token.
-            last_connection_id: 1,
+            ids: id::Allocator::with_slots(id::TCP_SLOTS),
 
             socketreg_tx: tx,
             socketreg_rx: Some(rx),
@@ -85,52 +330,363 @@ impl TcpConnectionManager {
             listener_rx: rx2,
 
             input_buffers: HashMap::new(),
+            telnet: HashMap::new(),
+            chains: HashMap::new(),
+            transcoders: HashMap::new(),
+            deflaters: HashMap::new(),
+            uncompressed: HashSet::new(),
+            msdp: HashMap::new(),
+            mxp: HashMap::new(),
+            charsets: HashMap::new(),
+            negotiated: HashMap::new(),
+            addresses: HashMap::new(),
+            reconnect: HashMap::new(),
+            attempts: HashMap::new(),
+            connected: HashMap::new(),
+            connected_at: HashMap::new(),
+            dropped: HashMap::new(),
+            ended: vec![],
+            reconnects: HashMap::new(),
+            traffic: HashMap::new(),
+            broken: HashMap::new(),
         }
     }
-}
 
-impl ConnectionInterface for TcpConnectionManager {
-    fn start_connection(&mut self, address: String) -> Result<ConnectionID, String> {
-        let cid = self.last_connection_id;
+    /// Whether, and how, to reconnect a connection if it drops.  Connections don't reconnect
+    /// unless this says so.
+    pub fn set_reconnect(&mut self, which: ConnectionID, policy: ReconnectPolicy) {
+        self.reconnect.insert(which, policy);
+    }
 
-        let addrs: Vec<SocketAddr> = match address.as_str().to_socket_addrs() {
-            Ok(results) => results.collect(),
-            Err(_) => { return Err(format!("Couldn't get address for {}", address)) },
+    /// Ask the listener thread to connect to `address`, at once or after `delay`.
+    fn request_connection(&mut self, cid: ConnectionID, address: &str, delay: Option<Duration>) -> Result<(), MintError> {
+        let (via, address) = proxy::strip_scheme(address);
+        let (use_tls, address) = tls::strip_scheme(address);
+        // Through a proxy, it's the proxy that looks up the world.
+        let (addrs, proxy) = match via {
+            Some(via) => {
+                let handshake = proxy::Handshake::new(via.kind, address).map_err(MintError::Net)?;
+                (resolve(&via.address).map_err(MintError::Net)?, Some(handshake))
+            },
+            None => (resolve(address).map_err(MintError::Net)?, None),
         };
+        let tls_host = if use_tls { Some(tls::host_name(address).to_string()) } else { None };
 
-        // I consider it OKAY-ISH to panic here? and in similar cases? because if the threads are
-        // unwinding in that way it means something is pretty seriously wrong with the entire
-        // program. IT MIGHT BE A TERRIBLE IDEA.  This might be able to be turned into a ? some
-        // day, when we get to issue 9.
-        self.socketreg_tx.send(ConnectionRequest {
+        self.socketreg_tx.send(ListenerRequest::Connect(ConnectionRequest {
             addrs,
-            cid: self.last_connection_id,
-        }).expect("TcpConnectionManager internal error: Couldn't send() fd to reader for registration");
+            cid,
+            tls_host,
+            proxy,
+            not_before: delay.map(|delay| Instant::now() + delay),
+        }))?;
+        self.wake_listener()
+    }
 
+    /// Let the listener thread know there's a request waiting for it.
+    fn wake_listener(&mut self) -> Result<(), MintError> {
         self.socketreg_sr.set_readiness(Ready::readable())
-              .expect("TcpConnectionManager internal error: Couldn't set_readiness() for socket registration");
+            .map_err(|e| MintError::Channel(format!("Couldn't wake the tcp-listener thread: {}", e)))
+    }
 
-        self.last_connection_id += 1;
-        Ok(cid)
+    /// A connection ended without the user asking, because of something of the class `error`.
+    /// If it's to reconnect, that's started (and an Event::Reconnecting says so); otherwise it's
+    /// over.
+    fn lost(&mut self, which: ConnectionID, reason: String, error: ErrorClass, queue: &mut Vec<Event>) {
+        self.forget(which);
+        let (reason, error) = match self.broken.remove(&which) {
+            Some(broken) => (broken, ErrorClass::Other),
+            None => (reason, error),
+        };
+        self.dropped.insert(which, (SystemTime::now(), reason.clone()));
+
+        let attempt = self.attempts.get(&which).map_or(1, |n| n + 1);
+        let delay = self.reconnect.get(&which).and_then(|policy| policy.delay(attempt, error));
+        let address = self.addresses.get(&which).cloned();
+        if let (Some(delay), Some(address)) = (delay, address) {
+            match self.request_connection(which, &address, Some(delay)) {
+                Ok(()) => {
+                    self.attempts.insert(which, attempt);
+                    queue.push(Event::Reconnecting { which, reason, error, attempt, delay });
+                    return;
+                },
+                // Looking the name up again is the only part of trying again that can fail
+                // here.
+                Err(e) => return self.end(which, e.to_string(), Some(ErrorClass::Dns), queue),
+            }
+        }
+        self.end(which, reason, Some(error), queue);
+    }
+
+    /// A connection's over for good.  `error` is None if it was closed on purpose.
+    fn end(&mut self, which: ConnectionID, reason: String, error: Option<ErrorClass>, queue: &mut Vec<Event>) {
+        self.forget(which);
+        self.dropped.insert(which, (SystemTime::now(), reason.clone()));
+        if let Some(stats) = self.stats(which) {
+            if self.ended.len() == ENDED_HISTORY {
+                self.ended.remove(0);
+            }
+            self.ended.push((which, stats));
+        }
+        queue.push(Event::ConnectionEnd { which, reason, error });
+        self.connected_at.remove(&which);
+        self.dropped.remove(&which);
+        self.uncompressed.remove(&which);
+        self.transcoders.remove(&which);
+        self.charsets.remove(&which);
+        self.addresses.remove(&which);
+        self.reconnect.remove(&which);
+        self.reconnects.remove(&which);
+        self.traffic.remove(&which);
+        self.attempts.remove(&which);
+        self.broken.remove(&which);
+        self.ids.release(which);
+    }
+
+    /// Whether a connection can use MCCP compression, in both directions.  It's on unless this
+    /// says otherwise.  Switching it off stops any compression straight away; switching it back
+    /// on only takes effect when the server next offers, which is usually only when it's
+    /// connected to.
+    pub fn set_compression(&mut self, which: ConnectionID, enabled: bool) {
+        let telnet = self.telnet.get(&which).cloned();
+        if enabled {
+            self.uncompressed.remove(&which);
+            if let Some(telnet) = telnet {
+                let mut telnet = telnet.borrow_mut();
+                telnet.accept_remote(opt::COMPRESS2);
+                telnet.accept_remote(opt::COMPRESS3);
+            }
+            return;
+        }
+
+        self.uncompressed.insert(which);
+        if let Some(telnet) = telnet {
+            // The server ends its stream when it hears DONT; we end ours before saying so.
+            let mut replies = telnet.borrow_mut().refuse(opt::COMPRESS2);
+            replies.extend(telnet.borrow_mut().refuse(opt::COMPRESS3));
+            self.stop_deflating(which);
+            let _ = self.send(which, &replies);
+        }
     }
 
-    fn stop_connection(&mut self, _which: ConnectionID) -> Result<(), ()> {
-        // TODO: Implement this
+    /// Which charset a connection's text is in, both ways.  This overrides whatever the server
+    /// picked, until it picks again; when the server offers a choice, this is the one to take if
+    /// it's there.
+    pub fn set_charset(&mut self, which: ConnectionID, charset: Charset) {
+        self.charsets.insert(which, charset);
+        self.negotiated.remove(&which);
+    }
+
+    /// The charset a connection's text is in right now, and whether that's the server's choice.
+    pub fn charset(&self, which: ConnectionID) -> (Charset, bool) {
+        match self.negotiated.get(&which) {
+            Some(charset) => (*charset, true),
+            None => (self.charsets.get(&which).copied().unwrap_or(Charset::Utf8), false),
+        }
+    }
+
+    /// Send bytes to the server, compressed if we're doing that.
+    fn send(&mut self, which: ConnectionID, bytes: &[u8]) -> std::io::Result<()> {
+        let link = self.links.get_mut(&which)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotConnected))?;
+        let deflated;
+        let bytes = match self.deflaters.get_mut(&which) {
+            Some(deflater) => {
+                deflated = deflater.deflate(bytes);
+                &deflated
+            },
+            None => bytes,
+        };
+        link.write_all(bytes)?;
+        self.traffic.entry(which).or_default().1 += bytes.len() as u64;
         Ok(())
     }
 
-    fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), ()> {
-        // TODO: Error handling here should probably be better; it ought to return a type that
-        // allows using the ? operator on I/O most likely
-        match self.links.get_mut(&which) {
-            Some(link) => {
-                match link.write(what.as_bytes()) {
-                    Err(_) => Err(()),
-                    Ok(_) => Ok(()),
+    /// Start compressing what we send (MCCP3), once we've agreed to.
+    fn start_deflating(&mut self, which: ConnectionID) -> bool {
+        if self.deflaters.contains_key(&which) {
+            return false;
+        }
+        let _ = self.send(which, &[telnet::IAC, telnet::SB, opt::COMPRESS3, telnet::IAC, telnet::SE]);
+        self.deflaters.insert(which, Deflater::new());
+        true
+    }
+
+    /// End our compressed stream, if there is one.  What's sent after this goes as it is.
+    fn stop_deflating(&mut self, which: ConnectionID) -> bool {
+        match self.deflaters.remove(&which) {
+            Some(mut deflater) => {
+                if let Some(link) = self.links.get_mut(&which) {
+                    let _ = link.write_all(&deflater.finish());
                 }
+                true
             },
-            None => Err(()),
+            None => false,
+        }
+    }
+
+    /// Put another layer in a connection's read path (see transcode.rs), under the telnet layer,
+    /// for as long as it's connected, and again each time it's reconnected.  Layers added to the
+    /// same connection go in on top of one another, and all of them under any that come and go
+    /// by themselves, like MCCP's.
+    pub fn add_transcoder(&mut self, which: ConnectionID, make: MakeTranscoder) {
+        if let Some(chain) = self.chains.get_mut(&which) {
+            chain.add(make());
+        }
+        self.transcoders.entry(which).or_default().push(make);
+    }
+
+    /// A read path for a connection that's just been made: telnet, agreeing to the options we
+    /// understand, and whatever's been added with add_transcoder().
+    fn new_chain(&mut self, which: ConnectionID) -> Chain {
+        let mut telnet = Telnet::new();
+        telnet.accept_remote(opt::MSDP);
+        telnet.accept_remote(opt::MXP);
+        telnet.accept_local(opt::CHARSET);
+        telnet.accept_remote(opt::CHARSET);
+        if !self.uncompressed.contains(&which) {
+            telnet.accept_remote(opt::COMPRESS2);
+            telnet.accept_remote(opt::COMPRESS3);
+        }
+        let telnet = Rc::new(RefCell::new(telnet));
+        self.telnet.insert(which, telnet.clone());
+
+        let mut chain = Chain::new(Box::new(TelnetLayer(telnet)));
+        for make in self.transcoders.get(&which).into_iter().flatten() {
+            chain.add(make());
+        }
+        chain
+    }
+
+    /// Run bytes from the server up through the connection's read path.  Compression (MCCP2)
+    /// can start or stop partway through, which is said with a Compression event.
+    fn receive(&mut self, which: ConnectionID, bytes: Vec<u8>, queue: &mut Vec<Event>) -> Transcoded {
+        if !self.chains.contains_key(&which) {
+            let chain = self.new_chain(which);
+            self.chains.insert(which, chain);
+        }
+        match self.chains.get_mut(&which).map(|chain| chain.receive(bytes)) {
+            Some(Ok(received)) => {
+                let changed = received.added.iter().map(|name| (name, true))
+                    .chain(received.removed.iter().map(|name| (name, false)));
+                for (_, enabled) in changed.filter(|(name, _)| **name == transcode::INFLATE) {
+                    queue.push(Event::Compression { which, ours: false, enabled });
+                }
+                received
+            },
+            Some(Err(e)) => {
+                // There's no getting back in step with a broken stream.  Once the socket's
+                // shut, the listener thread says so, and the connection ends (or reconnects)
+                // with this as the reason.
+                if let Some(link) = self.links.get(&which) {
+                    let _ = link.socket().shutdown(Shutdown::Both);
+                }
+                self.broken.insert(which, e);
+                Transcoded::default()
+            },
+            None => Transcoded::default(),
+        }
+    }
+
+    /// Ask the server to send MSDP variables whenever they change.  Does nothing if the server
+    /// hasn't agreed to MSDP (yet.)
+    pub fn msdp_report(&mut self, which: ConnectionID, variables: &[String]) -> Result<(), String> {
+        let on = self.telnet.get(&which).is_some_and(|telnet| telnet.borrow().enabled(opt::MSDP, false));
+        if !on || variables.is_empty() {
+            return Ok(());
+        }
+        self.send(which, &msdp::command("REPORT", variables))
+            .map_err(|e| format!("Couldn't ask for MSDP variables: {}", e))
+    }
+
+    /// How a connection's doing.  None if there's no such connection, or it's over.
+    pub fn stats(&self, which: ConnectionID) -> Option<ConnectionStats> {
+        let address = self.addresses.get(&which)?;
+        Some(ConnectionStats {
+            address: address.clone(),
+            connected_for: self.connected.get(&which).map(|since| since.elapsed()),
+            reconnects: self.reconnects.get(&which).copied().unwrap_or(0),
+            tls: tls::strip_scheme(proxy::strip_scheme(address).1).0,
+            handshake: self.links.get(&which).and_then(|link| link.handshake()),
+            connected_at: self.connected_at.get(&which).copied(),
+            dropped: self.dropped.get(&which).cloned(),
+            bytes_in: self.traffic.get(&which).map_or(0, |t| t.0),
+            bytes_out: self.traffic.get(&which).map_or(0, |t| t.1),
+            layers: self.chains.get(&which).map_or(vec![], |chain| chain.names()),
+        })
+    }
+
+    /// Every connection there is, by ID, then the last few that are over, in the order they
+    /// ended.
+    pub fn all_stats(&self) -> Vec<(ConnectionID, ConnectionStats)> {
+        let mut live: Vec<ConnectionID> = self.addresses.keys().copied().collect();
+        live.sort_unstable();
+        let mut all: Vec<(ConnectionID, ConnectionStats)> = live.into_iter()
+            .filter_map(|cid| self.stats(cid).map(|stats| (cid, stats)))
+            .collect();
+        all.extend(self.ended.iter().cloned());
+        all
+    }
+
+    /// The MSDP variables a server has sent so far, sorted by name.
+    pub fn msdp_variables(&self, which: ConnectionID) -> Vec<(String, String)> {
+        let mut all: Vec<(String, String)> = self.msdp.get(&which).into_iter()
+            .flat_map(|vars| vars.iter().map(|(k, v)| (k.clone(), v.clone())))
+            .collect();
+        all.sort();
+        all
+    }
+
+    pub fn msdp_value(&self, which: ConnectionID, variable: &str) -> Option<&str> {
+        self.msdp.get(&which)?.get(variable).map(|v| v.as_str())
+    }
+
+    /// Throw away a connection's link and everything that goes with it, once it's gone.  What
+    /// it'd need to be reconnected is kept (see end().)
+    fn forget(&mut self, which: ConnectionID) {
+        self.links.remove(&which);
+        self.input_buffers.remove(&which);
+        self.chains.remove(&which);
+        self.telnet.remove(&which);
+        self.deflaters.remove(&which);
+        self.msdp.remove(&which);
+        self.mxp.remove(&which);
+        self.negotiated.remove(&which);
+        self.connected.remove(&which);
+    }
+}
+
+impl ConnectionInterface for TcpConnectionManager {
+    /// `address` is anything `resolve()` understands, optionally with "tls://" in front to
+    /// connect with TLS, and a proxy in front of that to go through one (see the proxy module.)
+    fn start_connection(&mut self, address: String) -> Result<ConnectionID, MintError> {
+        let cid = self.ids.allocate()
+            .ok_or_else(|| MintError::Net("Too many connections at once".to_string()))?;
+        if let Err(e) = self.request_connection(cid, &address, None) {
+            self.ids.release(cid);
+            return Err(e);
         }
+        self.addresses.insert(cid, address);
+        Ok(cid)
+    }
+
+    /// The listener thread shuts the socket down and lets go of it, then says so, and that's
+    /// when a ConnectionEnd comes out.  This works on connections that are still being made,
+    /// too.
+    fn stop_connection(&mut self, which: ConnectionID) -> Result<(), MintError> {
+        if !self.ids.is_live(which) {
+            return Err(MintError::NoConnection(which));
+        }
+        self.socketreg_tx.send(ListenerRequest::Close(which))?;
+        self.wake_listener()
+    }
+
+    fn write_to_connection(&mut self, which: ConnectionID, what: String) -> Result<(), MintError> {
+        if !self.links.contains_key(&which) {
+            return Err(MintError::NoConnection(which));
+        }
+        let charset = self.charset(which).0;
+        self.send(which, &telnet::encode_text(&what, charset))?;
+        Ok(())
     }
 }
 
@@ -145,60 +701,143 @@ impl EventSource for TcpConnectionManager {
                 socketreg_alert: alert,
                 data_tx: self.listener_tx.clone(),
                 pending_requests: HashMap::new(),
+                tls_hosts: HashMap::new(),
+                tls_config: None,
+                proxies: HashMap::new(),
+                delayed: vec![],
             })],
             _ => { panic!("Cannot call listener() on ConnectionInterface more than once.") }
         }
     }
 
     fn process(&mut self) -> Vec<Event> {
-        // Process input from the thread... this mostly just transcribes the input into Events
-        // right now, but it needs to get more complicated and handle at least EOL buffering and
-        // Telnet, later.
+        // Process input from the thread: run it through the telnet state machine, answer any
+        // negotiation, and transcribe what's left into Events a line at a time.
         let mut queue = vec![];
 
         loop {
             match self.listener_rx.try_recv() {
-                Ok(LinkEvt::Data(cid, mut what)) => {
+                // News about a connection that's already over, which the listener thread sent
+                // before it heard.
+                Ok(evt) if !self.ids.is_live(evt.cid()) => { },
+                Ok(LinkEvt::Data(cid, _)) if self.broken.contains_key(&cid) => { },
+                Ok(LinkEvt::Data(cid, what)) => {
+                    self.traffic.entry(cid).or_default().0 += what.len() as u64;
+                    let mut received = self.receive(cid, what, &mut queue);
+
+                    // Our compressed stream has to end before we answer the server switching
+                    // MCCP3 off, and can only start once we've answered it switching it on.
+                    let mccp3 = received.notices.iter().find_map(|notice| match notice {
+                        telnet::Notice::Option { option: opt::COMPRESS3, ours: false, enabled } => Some(*enabled),
+                        _ => None,
+                    });
+                    if mccp3 == Some(false) && self.stop_deflating(cid) {
+                        queue.push(Event::Compression { which: cid, ours: true, enabled: false });
+                    }
+                    if !received.replies.is_empty() {
+                        // If this fails the link's in trouble anyway, and the listener thread
+                        // will tell us about it soon enough.
+                        let _ = self.send(cid, &received.replies);
+                    }
+                    if mccp3 == Some(true) && self.start_deflating(cid) {
+                        queue.push(Event::Compression { which: cid, ours: true, enabled: true });
+                    }
+                    for notice in received.notices {
+                        queue.push(match notice {
+                            telnet::Notice::Option { option: opt::ECHO, ours: false, enabled } => {
+                                Event::ServerEcho { which: cid, enabled }
+                            },
+                            telnet::Notice::Option { option: opt::MXP, ours: false, enabled } => {
+                                if enabled {
+                                    self.mxp.insert(cid, mxp::Parser::new());
+                                } else {
+                                    self.mxp.remove(&cid);
+                                }
+                                Event::TelnetOption { which: cid, option: opt::MXP, ours: false, enabled }
+                            },
+                            telnet::Notice::Option { option, ours, enabled } => {
+                                Event::TelnetOption { which: cid, option, ours, enabled }
+                            },
+                            telnet::Notice::Subnegotiation { option: opt::MSDP, data } => {
+                                let table = self.msdp.entry(cid).or_default();
+                                for (variable, value) in msdp::parse(&data) {
+                                    table.insert(variable.clone(), value.clone());
+                                    queue.push(Event::MsdpUpdate { which: cid, variable, value });
+                                }
+                                continue;
+                            },
+                            telnet::Notice::Subnegotiation { option: opt::CHARSET, data } => {
+                                let (charset, reply) = telnet::charset_reply(&data, self.charsets.get(&cid).copied());
+                                if !reply.is_empty() {
+                                    let _ = self.send(cid, &reply);
+                                }
+                                match charset {
+                                    Some(charset) => {
+                                        self.negotiated.insert(cid, charset);
+                                        Event::Charset { which: cid, charset }
+                                    },
+                                    None => continue,
+                                }
+                            },
+                            telnet::Notice::Subnegotiation { option, data } => {
+                                Event::TelnetSubnegotiation { which: cid, option, data }
+                            },
+                        });
+                    }
+
+                    let charset = self.charset(cid).0;
                     let buffer = self.input_buffers.entry(cid).or_insert(Vec::new());
-                    buffer.append(&mut what);
+                    let base = buffer.len();
+                    buffer.append(&mut received.data);
+
+                    // Up to each marked prompt end, the complete lines go first, then whatever's
+                    // left is the prompt, which doesn't have to wait for a newline.
+                    let mut drained = 0;
+                    for end in received.prompt_ends {
+                        let mut piece: Vec<u8> = buffer.drain(..base + end - drained).collect();
+                        drained = base + end;
+                        for line in take_lines(&mut piece, charset) {
+                            queue.push(server_text(self.mxp.get_mut(&cid), cid, line));
+                        }
+                        let prompt = charset.decode(&piece);
+                        let prompt = match self.mxp.get_mut(&cid) {
+                            Some(parser) => parser.line(&prompt).0,
+                            None => prompt,
+                        };
+                        queue.push(Event::ServerPrompt { which: cid, prompt });
+                    }
 
                     // Drain all the *complete* lines out of the buffer and push them into the
                     // queue as Event::ServerText objects.
-                    while buffer.contains(&LINE_SEPARATOR) {
-                        let line = buffer.split(|c| *c == LINE_SEPARATOR).next().unwrap();
-                        queue.push(Event::ServerText {
-                            which: cid,
-                            line: String::from_utf8_lossy(&line).to_string(),
-                        });
-                        buffer.drain(0..line.len() + 1);
+                    for line in take_lines(buffer, charset) {
+                        queue.push(server_text(self.mxp.get_mut(&cid), cid, line));
                     }
                 },
-                Ok(LinkEvt::Error(cid, msg)) => {
-                    queue.push(Event::ConnectionEnd {
-                        which: cid,
-                        reason: format!("Link error: {}", msg),
-                    });
-                    self.links.remove(&cid); // We...probably don't care if this fails? XXX
+                Ok(LinkEvt::Error(cid, msg, error)) => {
+                    self.lost(cid, format!("Link error: {}", msg), error, &mut queue);
                 },
                 Ok(LinkEvt::Established(cid, stream)) => {
+                    if self.attempts.remove(&cid).is_some() {
+                        *self.reconnects.entry(cid).or_insert(0) += 1;
+                    }
+                    self.connected.insert(cid, Instant::now());
+                    self.connected_at.insert(cid, SystemTime::now());
                     queue.push(Event::ConnectionStart {
                         which: cid,
                     });
                     self.links.insert(cid, stream);
+                    let chain = self.new_chain(cid);
+                    self.chains.insert(cid, chain);
                 },
-                Ok(LinkEvt::CouldntEstablish(cid)) => {
+                Ok(LinkEvt::CouldntEstablish(cid, error)) => {
                     // TODO: Should this have its own event?
-                    queue.push(Event::ConnectionEnd {
-                        which: cid,
-                        reason: "Could not establish connection".to_string(),
-                    });
+                    self.lost(cid, "Could not establish connection".to_string(), error, &mut queue);
                 },
                 Ok(LinkEvt::Eof(cid)) => {
-                    queue.push(Event::ConnectionEnd {
-                        which: cid,
-                        reason: format!("End of connection"),
-                    });
-                    self.links.remove(&cid);
+                    self.lost(cid, "End of connection".to_string(), ErrorClass::Eof, &mut queue);
+                },
+                Ok(LinkEvt::Closed(cid)) => {
+                    self.end(cid, "closed by user".to_string(), None, &mut queue);
                 },
                 Err(_) => break,
             }
@@ -213,7 +852,7 @@ impl EventSource for TcpConnectionManager {
 /// Listener impl for TcpConnectionManager; data/object for the listener thread for TCP
 /// connections.
 struct TcpListener {
-    socketreg_rx: mpsc::Receiver<ConnectionRequest>,
+    socketreg_rx: mpsc::Receiver<ListenerRequest>,
     socketreg_alert: mio::Registration,
     data_tx: mpsc::Sender<LinkEvt>,
 
@@ -224,20 +863,34 @@ struct TcpListener {
     // when a read or write on a connection succeeds, we remove it from pending_requests if it's
     // there.
     pending_requests: HashMap<ConnectionID, Vec<SocketAddr>>,
+
+    // Which connections are TLS ones, and the host names their certificates should have.
+    tls_hosts: HashMap<ConnectionID, String>,
+    // Made the first time somebody asks for TLS.
+    tls_config: Option<Arc<rustls::ClientConfig>>,
+
+    // Connections going through a proxy that haven't finished talking to it yet.
+    proxies: HashMap<ConnectionID, proxy::Handshake>,
+
+    // Requests that aren't to be tried just yet (reconnections waiting out their delay.)
+    delayed: Vec<ConnectionRequest>,
 }
 
 
 impl TcpListener {
-    /// Try to connect to the next option available 
-    fn try_request(&mut self, req: ConnectionID) -> Option<TcpStream> {
+    /// Try to connect to the next option available, or say what went wrong with the last one if
+    /// none of them work.
+    fn try_request(&mut self, req: ConnectionID) -> Result<TcpStream, ErrorClass> {
+        // Nothing at all to try means the name didn't turn into any addresses.
+        let mut error = ErrorClass::Dns;
         if let Some(opts_left) = self.pending_requests.get_mut(&req) {
             while opts_left.len() > 0 {
                 // Can unwrap() here because we know len > 0.
                 let address_to_try = opts_left.pop().unwrap();
 
                 match TcpStream::connect(&address_to_try) {
-                    Ok(stream) => return Some(stream),
-                    Err(_) => { },
+                    Ok(stream) => return Ok(stream),
+                    Err(e) => error = ErrorClass::of(&e, false),
                 };
             }
         }
@@ -248,69 +901,294 @@ impl TcpListener {
         // remove it from pending_requests.
         self.pending_requests.remove(&req);
 
-        None
+        Err(error)
     }
 
     /// Deal with trying a connection request and taking the appropriate actions.  Called
     /// internally.
-    fn handle_request(&mut self, poll: &mio::Poll, links: &mut HashMap<ConnectionID, TcpStream>, flag: &mut Box<ReadinessPager>, cid: ConnectionID) {
+    fn handle_request(&mut self, poll: &mio::Poll, links: &mut HashMap<ConnectionID, Link>, flag: &mut Box<dyn ReadinessPager>, cid: ConnectionID) -> Result<(), MintError> {
         match self.try_request(cid) {
-            Some(stream) => {
+            Ok(stream) => {
                 // We don't send Established here; it would be premature.  It can fail
                 // on a read() still.
-                poll.register(&stream, Token(cid), Ready::readable(), PollOpt::level()).unwrap();
-                links.insert(cid, stream);
+                if let Some(handshake) = self.proxies.get_mut(&cid) {
+                    // The proxy comes first.  Like TLS (below), we want to hear when the socket's
+                    // connected, since that's when to start talking to it.
+                    handshake.reset();
+                    poll.register(&stream, cid.token(), Ready::readable() | Ready::writable(), PollOpt::edge())?;
+                    links.insert(cid, Link::Plain(stream));
+                    return Ok(());
+                }
+                match self.tls_hosts.get(&cid).cloned() {
+                    None => {
+                        poll.register(&stream, cid.token(), Ready::readable(), PollOpt::level())?;
+                        links.insert(cid, Link::Plain(stream));
+                    },
+                    Some(host) => self.begin_tls(poll, links, flag, cid, stream, &host)?,
+                }
             },
-            None => {
-                self.data_tx.send(LinkEvt::CouldntEstablish(cid))
-                    .expect("Couldn't send() LinkEvt");
+            Err(error) => {
+                self.tls_hosts.remove(&cid);
+                self.proxies.remove(&cid);
+                self.data_tx.send(LinkEvt::CouldntEstablish(cid, error))?;
                 flag.ok();
             }
         }
+        Ok(())
+    }
+
+    /// Put TLS on a freshly connected socket, and start listening to it.
+    fn begin_tls(&mut self, poll: &mio::Poll, links: &mut HashMap<ConnectionID, Link>, flag: &mut Box<dyn ReadinessPager>, cid: ConnectionID, stream: TcpStream, host: &str) -> Result<(), MintError> {
+        match self.start_tls(stream, host) {
+            Ok(stream) => {
+                // TLS has to send first, so we want to hear when the socket's connected as well.
+                // Edge-triggered, since otherwise we'd hear about it being writable all the time;
+                // the read loop below reads until WouldBlock anyway.
+                poll.register(stream.socket(), cid.token(), Ready::readable() | Ready::writable(), PollOpt::edge())?;
+                links.insert(cid, Link::Tls(stream));
+            },
+            Err(e) => {
+                // Trying the other addresses won't help; it's the same host name.
+                self.pending_requests.remove(&cid);
+                self.tls_hosts.remove(&cid);
+                self.data_tx.send(LinkEvt::Error(cid, e, ErrorClass::Tls))?;
+                flag.ok();
+            },
+        }
+        Ok(())
+    }
+
+    /// Move a proxy handshake along, now that the socket's `readiness` has changed.  Ok(None)
+    /// while it's still going; what the server sent after the proxy's reply once it's done.
+    fn talk_to_proxy(&mut self, links: &mut HashMap<ConnectionID, Link>, cid: ConnectionID, readiness: Ready)
+                     -> Result<Option<Vec<u8>>, (String, ErrorClass, bool)> {
+        let io_error = |e: std::io::Error| (format!("Problem talking to the proxy: {}", e), ErrorClass::of(&e, false), true);
+        let (link, handshake) = match (links.get_mut(&cid), self.proxies.get_mut(&cid)) {
+            (Some(link), Some(handshake)) => (link, handshake),
+            _ => return Ok(None),
+        };
+        if !handshake.started() {
+            if !readiness.is_writable() {
+                return Ok(None);
+            }
+            // It's only a few bytes, and the socket's just connected, so there's room for them.
+            link.write_all(&handshake.start()).map_err(io_error)?;
+        }
+
+        let mut buffer = [0u8; BUFFER_SIZE];
+        loop {
+            match link.read(&mut buffer) {
+                Ok(0) => return Err(("The proxy hung up".to_string(), ErrorClass::Eof, true)),
+                Ok(n) => match handshake.receive(&buffer[..n]) {
+                    Ok(proxy::Progress::Send(bytes)) => link.write_all(&bytes).map_err(io_error)?,
+                    Ok(proxy::Progress::Waiting) => { },
+                    Ok(proxy::Progress::Done(rest)) => return Ok(Some(rest)),
+                    Err((reason, class)) => return Err((reason, class, false)),
+                },
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+    }
+
+    /// Deal with something happening on a connection that's still talking to its proxy.
+    fn proxy_event(&mut self, poll: &mio::Poll, links: &mut HashMap<ConnectionID, Link>, flag: &mut Box<dyn ReadinessPager>, cid: ConnectionID, readiness: Ready) -> Result<(), MintError> {
+        match self.talk_to_proxy(links, cid, readiness) {
+            Ok(None) => { },
+            Ok(Some(rest)) => {
+                self.proxies.remove(&cid);
+                let stream = match links.remove(&cid) {
+                    Some(Link::Plain(stream)) => stream,
+                    _ => return Ok(()),
+                };
+                match self.tls_hosts.get(&cid).cloned() {
+                    // TLS servers don't speak first, so there shouldn't be anything in `rest`.
+                    Some(_) if !rest.is_empty() => {
+                        let _ = poll.deregister(&stream);
+                        self.pending_requests.remove(&cid);
+                        self.tls_hosts.remove(&cid);
+                        self.data_tx.send(LinkEvt::Error(cid, "The proxy sent more than it should have".to_string(), ErrorClass::Tls))?;
+                        flag.ok();
+                    },
+                    Some(host) => {
+                        poll.deregister(&stream)?;
+                        self.begin_tls(poll, links, flag, cid, stream, &host)?;
+                    },
+                    None => {
+                        // The proxy's connected us, which is as established as it gets; the
+                        // server might well be waiting for us to say something first.
+                        poll.reregister(&stream, cid.token(), Ready::readable(), PollOpt::level())?;
+                        let link = Link::Plain(stream);
+                        self.pending_requests.remove(&cid);
+                        self.data_tx.send(LinkEvt::Established(cid, link.try_clone()?))?;
+                        if !rest.is_empty() {
+                            self.data_tx.send(LinkEvt::Data(cid, rest))?;
+                        }
+                        links.insert(cid, link);
+                        flag.ok();
+                    },
+                }
+            },
+            Err((reason, class, retry)) => {
+                if let Some(link) = links.remove(&cid) {
+                    let _ = poll.deregister(link.socket());
+                }
+                // Like any other connection, the proxy's other addresses are worth a try if this
+                // one didn't work out; but if the proxy said no, it'll say no again.
+                if retry && self.pending_requests.get(&cid).is_some_and(|left| !left.is_empty()) {
+                    return self.handle_request(poll, links, flag, cid);
+                }
+                self.pending_requests.remove(&cid);
+                self.tls_hosts.remove(&cid);
+                self.proxies.remove(&cid);
+                self.data_tx.send(LinkEvt::Error(cid, reason, class))?;
+                flag.ok();
+            },
+        }
+        Ok(())
+    }
+
+    /// Shut a link down for good, and tell the main thread it's gone.  If it went already (the
+    /// server hung up first, say), the main thread's already been told, and there's nothing to
+    /// do.
+    fn close(&mut self, poll: &mio::Poll, links: &mut HashMap<ConnectionID, Link>, flag: &mut dyn ReadinessPager, cid: ConnectionID) -> Result<(), MintError> {
+        self.pending_requests.remove(&cid);
+        self.tls_hosts.remove(&cid);
+        self.proxies.remove(&cid);
+        // One that's waiting to reconnect has nothing to shut down, but it still needs to stop.
+        let waiting = self.delayed.len();
+        self.delayed.retain(|request| request.cid != cid);
+        match links.remove(&cid) {
+            Some(link) => {
+                // Neither of these matter much if they fail; the socket's going either way.
+                let _ = poll.deregister(link.socket());
+                let _ = link.socket().shutdown(Shutdown::Both);
+            },
+            None if self.delayed.len() == waiting => return Ok(()),
+            None => { },
+        }
+
+        self.data_tx.send(LinkEvt::Closed(cid))?;
+        flag.ok();
+        Ok(())
+    }
+
+    /// Start trying the addresses in a request.
+    fn start_request(&mut self, poll: &mio::Poll, links: &mut HashMap<ConnectionID, Link>, flag: &mut Box<dyn ReadinessPager>, request: ConnectionRequest) -> Result<(), MintError> {
+        let cid = request.cid;
+        self.pending_requests.insert(cid, request.addrs);
+        if let Some(host) = request.tls_host {
+            self.tls_hosts.insert(cid, host);
+        }
+        if let Some(handshake) = request.proxy {
+            self.proxies.insert(cid, handshake);
+        }
+        self.handle_request(poll, links, flag, cid)
+    }
+
+    /// How long until the next delayed request is due, if there are any.
+    fn next_due(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.delayed.iter()
+            .filter_map(|request| request.not_before)
+            .min()
+            .map(|when| when.saturating_duration_since(now))
+    }
+
+    fn start_tls(&mut self, stream: TcpStream, host: &str) -> Result<TlsStream, String> {
+        let config = match self.tls_config {
+            Some(ref config) => config.clone(),
+            None => {
+                let config = tls::client_config()?;
+                self.tls_config = Some(config.clone());
+                config
+            },
+        };
+        TlsStream::new(stream, config, host)
     }
 }
 
 impl Listener for TcpListener {
-    fn run(&mut self, mut flag: Box<ReadinessPager>) {
-        // TODO: See the comment in ThreadedManager (events.rs).  Make this thread return an
-        // appropriate Result type to where we can use `?` unstead of unwrap(), and watch for that
-        // as noted there.
-        let poll = Poll::new().unwrap();
+    fn name(&self) -> String {
+        "tcp-listener".to_string()
+    }
+
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        Some(HEARTBEAT_INTERVAL)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        let poll = Poll::new()?;
         let mut events = Events::with_capacity(128);
         let mut links = HashMap::new();
 
         // Register the alert object we're using to wake up when it's time to add a socket to our
         // inventory (e.g. register it with the poll.)
-        poll.register(&self.socketreg_alert, Token(0), Ready::readable(), PollOpt::edge()).unwrap();
+        poll.register(&self.socketreg_alert, Token(0), Ready::readable(), PollOpt::edge())?;
 
         loop {
-            poll.poll(&mut events, None).unwrap();
+            let timeout = self.next_due().map_or(HEARTBEAT_INTERVAL, |due| due.min(HEARTBEAT_INTERVAL));
+            poll.poll(&mut events, Some(timeout))?;
+            if events.is_empty() {
+                // Timed out.  Nothing to do but let the EventManager know we're not stuck.
+                flag.heartbeat();
+            }
+
+            // Reconnections whose time has come.
+            let now = Instant::now();
+            let (due, waiting) = std::mem::take(&mut self.delayed).into_iter()
+                .partition(|request| request.not_before.is_none_or(|when| when <= now));
+            self.delayed = waiting;
+            for request in due {
+                self.start_request(&poll, &mut links, &mut flag, request)?;
+            }
+
             for event in &events {
                 if event.token() == Token(0) {
-                    // A ConnectionRequest has arrived.  Deal with it.
-                    let request: ConnectionRequest = self.socketreg_rx.recv().unwrap();
-                    let cid = request.cid;
-                    self.pending_requests.insert(cid, request.addrs);
-                    self.handle_request(&poll, &mut links, &mut flag, cid);
+                    // Requests from the main thread have arrived.  Since the alert's
+                    // edge-triggered, there might be more than one for it.
+                    while let Ok(request) = self.socketreg_rx.try_recv() {
+                        match request {
+                            ListenerRequest::Connect(request) if request.not_before.is_some() => {
+                                self.delayed.push(request);
+                            },
+                            ListenerRequest::Connect(request) => {
+                                self.start_request(&poll, &mut links, &mut flag, request)?;
+                            },
+                            ListenerRequest::Close(cid) => self.close(&poll, &mut links, &mut *flag, cid)?,
+                        }
+                    }
                 } else {
                     // Read from a socket.  Full disclosure: This code is heavily based on an
                     // example I found randomly in mio's Token documentation.
                     //
                     // TODO: These indents are excessive, figure out how to factor out some of
                     // this.
-                    let cid: usize = event.token().0;
+                    let cid = ConnectionID::from_token(event.token());
+                    if self.proxies.contains_key(&cid) {
+                        self.proxy_event(&poll, &mut links, &mut flag, cid, event.readiness())?;
+                        continue;
+                    }
                     let mut buffer = [0u8; BUFFER_SIZE];
-                    loop {
-                        // TODO: IMPORTANT -- Don't panic if it doesn't exist in the links.  Do
-                        // something else, like sending an internal error and closing/deregistering
-                        // or whatever seems most appropriate.
-                        match links.get_mut(&cid).expect("links.get_mut").read(&mut buffer) {
+
+                    // Only TLS links ask about this: there might be handshake messages (or text
+                    // the main thread wrote while the socket was full) waiting to go out.  If
+                    // something's wrong with the link, reading will tell us.
+                    if event.readiness().is_writable() {
+                        if let Some(link) = links.get_mut(&cid) {
+                            let _ = link.flush();
+                        }
+                    }
+                    // The link might be gone already, if an earlier event in this batch closed
+                    // it; then there's nothing left to read.
+                    while let Some(link) = links.get_mut(&cid) {
+                        match link.read(&mut buffer) {
                             Ok(0) => {
                                 // End of the link.  Drop it on this end.  When we send the Error
                                 // event, the code that owns the other copy of the connection
-                                // should also drop it.
-                                poll.deregister(links.get(&cid).expect("links.get"))
-                                    .expect("deregister");
+                                // should also drop it.  It's going either way, so it doesn't
+                                // matter much if deregistering fails.
+                                let _ = poll.deregister(link.socket());
 
                                 // We PROBABLY don't want to try the next address in a pending
                                 // request here ... if it immediately closed the connection, it's
@@ -320,8 +1198,8 @@ impl Listener for TcpListener {
                                 // that would change.
 
                                 links.remove(&cid);
-                                self.data_tx.send(LinkEvt::Eof(cid))
-                                    .expect("Couldn't send Eof back to main thread");
+                                self.tls_hosts.remove(&cid);
+                                self.data_tx.send(LinkEvt::Eof(cid))?;
                                 flag.ok();
                                 break;
                             },
@@ -339,15 +1217,12 @@ impl Listener for TcpListener {
 
                                 // See the comment on pending_requests for explanation.
                                 if let Some(_) = self.pending_requests.get(&cid) {
-                                    let new_link = links.get_mut(&cid).expect("links.get_mut")
-                                        .try_clone().expect("clone link");
-                                    self.data_tx.send(LinkEvt::Established(cid, new_link))
-                                        .expect("Couldn't send LinkEvt::Established");
+                                    let new_link = link.try_clone()?;
+                                    self.data_tx.send(LinkEvt::Established(cid, new_link))?;
                                     self.pending_requests.remove(&cid);
                                 }
 
-                                self.data_tx.send(LinkEvt::Data(cid, vec))
-                                    .expect("Couldn't send LinkEvt::Data");
+                                self.data_tx.send(LinkEvt::Data(cid, vec))?;
 
                                 flag.ok();
                             },
@@ -359,18 +1234,21 @@ impl Listener for TcpListener {
                                 // We assume the link wrapped up here--that an error means we
                                 // probably can't keep using it.  TODO: Do we need to (or should
                                 // we) do anything to make sure e.g. close()ing?
-                                poll.deregister(links.get(&cid).expect("links.get")).expect("deregister");
+                                let error = ErrorClass::of(e, link.is_tls());
+                                let _ = poll.deregister(link.socket());
                                 links.remove(&cid);
 
-                                // Let the main thread know things went sideways.
-                                self.data_tx.send(LinkEvt::Error(cid, format!("Problem calling read(): {}", e)))
-                                    .expect("Couldn't send Error back to main thread");
-
-                                // If there are more addresses in a pending_request, we'll try the
-                                // next one of those.
-                                self.handle_request(&poll, &mut links, &mut flag, cid);
-
-                                flag.ok();
+                                // If it was still being made and there are more addresses in
+                                // its pending_request, we'll try the next one of those.  Only
+                                // once they've all failed does the main thread hear about it.
+                                if self.pending_requests.get(&cid).is_some_and(|left| !left.is_empty()) {
+                                    self.handle_request(&poll, &mut links, &mut flag, cid)?;
+                                } else {
+                                    self.pending_requests.remove(&cid);
+                                    self.tls_hosts.remove(&cid);
+                                    self.data_tx.send(LinkEvt::Error(cid, format!("Problem calling read(): {}", e), error))?;
+                                    flag.ok();
+                                }
 
                                 break;
                             },
@@ -382,3 +1260,46 @@ impl Listener for TcpListener {
     }
 }
 
+
+#[test]
+fn resolve_ipv6_zones() {
+    let addrs = resolve("[fe80::1%3]:4000").unwrap();
+    match addrs[0] {
+        SocketAddr::V6(v6) => {
+            assert_eq!(v6.ip(), &"fe80::1".parse::<Ipv6Addr>().unwrap());
+            assert_eq!(v6.port(), 4000);
+            assert_eq!(v6.scope_id(), 3);
+        },
+        _ => panic!("expected an IPv6 address"),
+    }
+
+    assert_eq!(resolve("[::1]:23").unwrap()[0].port(), 23);
+    assert!(resolve("[fe80::1%3]").is_err());
+    assert!(resolve("[fe80::1%no-such-interface-here]:4000").is_err());
+}
+
+#[cfg(test)]
+proptest! {
+    // However the data's cut up as it arrives, the same lines come out, and nothing's lost.
+    #[test]
+    fn take_lines_in_pieces(text in "(\\PC|\n|\r){0,200}", cuts in prop::collection::vec(0usize..200, 0..8)) {
+        let bytes = text.as_bytes();
+        let mut cuts: Vec<usize> = cuts.into_iter().map(|c| c.min(bytes.len())).collect();
+        cuts.push(bytes.len());
+        cuts.sort();
+
+        let mut buffer = vec![];
+        let mut lines = vec![];
+        let mut start = 0;
+        for cut in cuts {
+            buffer.extend_from_slice(&bytes[start..cut]);
+            lines.extend(take_lines(&mut buffer, Charset::Utf8));
+            start = cut;
+        }
+
+        prop_assert!(lines.iter().all(|line| !line.contains('\n')));
+        let mut joined: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        joined.push_str(&String::from_utf8_lossy(&buffer));
+        prop_assert_eq!(joined, text);
+    }
+}
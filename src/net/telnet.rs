@@ -0,0 +1,428 @@
+//! Just enough of the telnet protocol (RFC 854) to keep IAC sequences out of the text and answer
+//! servers' option negotiation sensibly.
+//!
+//! There's no attempt at the full RFC 1143 "Q method" here: we never ask for an option ourselves,
+//! only answer the server, and we only answer when the answer changes something.  That's enough
+//! to keep from getting into negotiation loops.
+
+use crate::charset::Charset;
+
+use std::collections::HashSet;
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+/// Go ahead: the server's done talking for now, which in practice means it's shown a prompt.
+pub const GA: u8 = 249;
+pub const SE: u8 = 240;
+/// End of record (RFC 885), which servers that have agreed to the EOR option send after prompts,
+/// the way others send GA.
+pub const EOR: u8 = 239;
+
+/// Option codes we know the names of.
+pub mod opt {
+    pub const ECHO: u8 = 1;
+    pub const SGA: u8 = 3;
+    pub const TTYPE: u8 = 24;
+    pub const EOR: u8 = 25;
+    pub const NAWS: u8 = 31;
+    pub const LINEMODE: u8 = 34;
+    /// CHARSET (RFC 2066): which character set the text's in.
+    pub const CHARSET: u8 = 42;
+    /// MSDP (see net::msdp.)
+    pub const MSDP: u8 = 69;
+    /// MCCP version 2 and 3 (see net::mccp.)
+    pub const COMPRESS2: u8 = 86;
+    pub const COMPRESS3: u8 = 87;
+    /// MXP (see net::mxp.)
+    pub const MXP: u8 = 91;
+}
+
+/// Something about the telnet side of a connection that the layers above might care about.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Notice {
+    /// An option was switched on or off.  `ours` is true for options on our side of the
+    /// connection (the server said DO/DONT) and false for the server's (WILL/WONT.)
+    Option { option: u8, ours: bool, enabled: bool },
+    /// IAC SB option ... IAC SE, with the IAC IAC escapes undone.
+    Subnegotiation { option: u8, data: Vec<u8> },
+}
+
+/// What came out of a chunk of bytes from the server.
+#[derive(Default, Debug)]
+pub struct Received {
+    /// The text, with all the telnet stuff taken out.
+    pub data: Vec<u8>,
+    /// What needs sending back to the server.
+    pub replies: Vec<u8>,
+    pub notices: Vec<Notice>,
+    /// Where in `data` the server marked the end of a prompt, in order: with GA (only while it
+    /// isn't suppressing them (SGA), since a server that's agreed to that has no business sending
+    /// any), or with EOR.
+    pub prompt_ends: Vec<usize>,
+    /// If the server started compressing (IAC SB COMPRESS2 IAC SE), whatever came after that.
+    /// It's compressed, so it has to be inflated before it comes back through here.
+    pub compressed: Option<Vec<u8>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum State {
+    Data,
+    Iac,
+    /// Got IAC and one of DO/DONT/WILL/WONT; waiting for the option.
+    Negotiate(u8),
+    /// Got IAC SB; waiting for the option.
+    SubStart,
+    Sub,
+    /// Got IAC inside a subnegotiation.
+    SubIac,
+    /// Got CR; a NUL after it is just padding.
+    Cr,
+}
+
+/// Telnet state for one connection.
+pub struct Telnet {
+    state: State,
+    sub_option: u8,
+    sub_data: Vec<u8>,
+
+    // Options that are on, on each side of the connection.
+    local: HashSet<u8>,
+    remote: HashSet<u8>,
+
+    // Options we're willing to have on, on each side.  Anything else gets refused.
+    accept_local: HashSet<u8>,
+    accept_remote: HashSet<u8>,
+}
+
+impl Telnet {
+    pub fn new() -> Telnet {
+        Telnet {
+            state: State::Data,
+            sub_option: 0,
+            sub_data: vec![],
+            local: HashSet::new(),
+            remote: HashSet::new(),
+            // Never LINEMODE (RFC 1184): the line's always edited here and sent whole, so there's
+            // nothing for the server to tell us about editing it.  Servers that ask for it and get
+            // WONT fall back to plain line-at-a-time or to ECHO and SGA, and either way all they
+            // see is whole lines.
+            //
+            // We never send GA, so we might as well say so if the server asks.
+            accept_local: [opt::SGA].iter().cloned().collect(),
+            // The server echoing for us is how password prompts work.  GA marks prompts for
+            // servers that send it, but nobody's used it for its original purpose in decades, so
+            // there's no reason to hold on to it if the server would rather not.  EOR does the
+            // same job as GA for servers that offer it.
+            accept_remote: [opt::ECHO, opt::SGA, opt::EOR].iter().cloned().collect(),
+        }
+    }
+
+    /// Agree to turn `option` on on our side if the server asks.
+    pub fn accept_local(&mut self, option: u8) {
+        self.accept_local.insert(option);
+    }
+
+    /// Agree to let the server turn `option` on on its side.
+    pub fn accept_remote(&mut self, option: u8) {
+        self.accept_remote.insert(option);
+    }
+
+    /// Stop agreeing to `option` on either side, and if it's on, say it's off now.  Returns what
+    /// needs sending to the server.
+    pub fn refuse(&mut self, option: u8) -> Vec<u8> {
+        self.accept_local.remove(&option);
+        self.accept_remote.remove(&option);
+
+        let mut replies = vec![];
+        if self.local.remove(&option) {
+            replies.extend_from_slice(&[IAC, WONT, option]);
+        }
+        if self.remote.remove(&option) {
+            replies.extend_from_slice(&[IAC, DONT, option]);
+        }
+        replies
+    }
+
+    /// Whether an option is on, on our side (`ours`) or the server's.
+    pub fn enabled(&self, option: u8, ours: bool) -> bool {
+        if ours { self.local.contains(&option) } else { self.remote.contains(&option) }
+    }
+
+    /// Run some bytes from the server through the state machine.
+    pub fn receive(&mut self, bytes: &[u8]) -> Received {
+        let mut out = Received::default();
+
+        for (i, &b) in bytes.iter().enumerate() {
+            self.state = match (self.state, b) {
+                (State::Data, IAC) | (State::Cr, IAC) => State::Iac,
+                (State::Data, b'\r') | (State::Cr, b'\r') => { out.data.push(b); State::Cr },
+                (State::Cr, 0) => State::Data,
+                (State::Data, _) | (State::Cr, _) => { out.data.push(b); State::Data },
+
+                (State::Iac, IAC) => { out.data.push(IAC); State::Data },
+                (State::Iac, DO) | (State::Iac, DONT) | (State::Iac, WILL) | (State::Iac, WONT) => {
+                    State::Negotiate(b)
+                },
+                (State::Iac, SB) => State::SubStart,
+                (State::Iac, GA) => {
+                    if !self.remote.contains(&opt::SGA) {
+                        out.prompt_ends.push(out.data.len());
+                    }
+                    State::Data
+                },
+                (State::Iac, EOR) => {
+                    if self.remote.contains(&opt::EOR) {
+                        out.prompt_ends.push(out.data.len());
+                    }
+                    State::Data
+                },
+                // NOP and friends.  Nothing to do with them yet.
+                (State::Iac, _) => State::Data,
+
+                (State::Negotiate(verb), option) => {
+                    self.negotiate(verb, option, &mut out);
+                    State::Data
+                },
+
+                (State::SubStart, option) => {
+                    self.sub_option = option;
+                    self.sub_data.clear();
+                    State::Sub
+                },
+                (State::Sub, IAC) => State::SubIac,
+                (State::Sub, _) => { self.sub_data.push(b); State::Sub },
+                (State::SubIac, IAC) => { self.sub_data.push(IAC); State::Sub },
+                // Some servers carry on about LINEMODE after we've said we won't do it; if they
+                // got an answer they'd expect us to start editing their way.
+                (State::SubIac, SE) if self.sub_option == opt::LINEMODE && !self.local.contains(&opt::LINEMODE) => {
+                    self.sub_data.clear();
+                    State::Data
+                },
+                (State::SubIac, SE) => {
+                    out.notices.push(Notice::Subnegotiation {
+                        option: self.sub_option,
+                        data: self.sub_data.split_off(0),
+                    });
+                    // Everything from here on is compressed, if we agreed to that.  It can't go
+                    // through the state machine until it's been inflated.
+                    if self.sub_option == opt::COMPRESS2 && self.remote.contains(&opt::COMPRESS2) {
+                        self.state = State::Data;
+                        out.compressed = Some(bytes[i + 1..].to_vec());
+                        return out;
+                    }
+                    State::Data
+                },
+                // Not supposed to happen.  Drop the subnegotiation rather than guess.
+                (State::SubIac, _) => State::Data,
+            };
+        }
+
+        out
+    }
+
+    fn negotiate(&mut self, verb: u8, option: u8, out: &mut Received) {
+        let ours = verb == DO || verb == DONT;
+        let want = verb == DO || verb == WILL;
+        let (enabled, accept) = if ours {
+            (&mut self.local, &self.accept_local)
+        } else {
+            (&mut self.remote, &self.accept_remote)
+        };
+
+        // Our answers: agree to DO with WILL and to WILL with DO, and so on.
+        let (yes, no) = if ours { (WILL, WONT) } else { (DO, DONT) };
+
+        if want && !enabled.contains(&option) {
+            if accept.contains(&option) {
+                enabled.insert(option);
+                out.replies.extend_from_slice(&[IAC, yes, option]);
+                out.notices.push(Notice::Option { option, ours, enabled: true });
+            } else {
+                out.replies.extend_from_slice(&[IAC, no, option]);
+            }
+        } else if !want && enabled.remove(&option) {
+            out.replies.extend_from_slice(&[IAC, no, option]);
+            out.notices.push(Notice::Option { option, ours, enabled: false });
+        }
+    }
+}
+
+impl Default for Telnet {
+    fn default() -> Telnet {
+        Telnet::new()
+    }
+}
+
+// CHARSET subnegotiation commands.
+const CHARSET_REQUEST: u8 = 1;
+const CHARSET_ACCEPTED: u8 = 2;
+const CHARSET_REJECTED: u8 = 3;
+
+/// Answer the server offering character sets (a CHARSET REQUEST subnegotiation, `data` being
+/// what came after the option), by picking one we know: `preferred` if it's offered, or else
+/// UTF-8, or else whichever came first.  Returns which was picked, if any, and the reply, which
+/// is empty if `data` isn't a request.
+pub fn charset_reply(data: &[u8], preferred: Option<Charset>) -> (Option<Charset>, Vec<u8>) {
+    let mut offer = match data.split_first() {
+        Some((&CHARSET_REQUEST, offer)) => offer,
+        _ => return (None, vec![]),
+    };
+    // A translation table's version can come first, which we've no use for.
+    if let Some(rest) = offer.strip_prefix(b"[TTABLE]") {
+        offer = rest.get(1..).unwrap_or(&[]);
+    }
+    let (separator, names) = match offer.split_first() {
+        Some((separator, names)) => (separator, names),
+        None => return (None, vec![IAC, SB, opt::CHARSET, CHARSET_REJECTED, IAC, SE]),
+    };
+
+    let known: Vec<(&[u8], Charset)> = names.split(|b| b == separator)
+        .filter_map(|name| Some((name, Charset::from_name(std::str::from_utf8(name).ok()?)?)))
+        .collect();
+    let choice = preferred.and_then(|preferred| known.iter().find(|(_, charset)| *charset == preferred))
+        .or_else(|| known.iter().find(|(_, charset)| *charset == Charset::Utf8))
+        .or_else(|| known.first());
+    match choice {
+        Some(&(name, charset)) => {
+            let mut reply = vec![IAC, SB, opt::CHARSET, CHARSET_ACCEPTED];
+            reply.extend_from_slice(&escape(name));
+            reply.extend_from_slice(&[IAC, SE]);
+            (Some(charset), reply)
+        },
+        None => (None, vec![IAC, SB, opt::CHARSET, CHARSET_REJECTED, IAC, SE]),
+    }
+}
+
+/// Double any IAC bytes in outgoing data so the server doesn't take them for commands.
+pub fn escape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        out.push(b);
+        if b == IAC {
+            out.push(IAC);
+        }
+    }
+    out
+}
+
+/// A line of text (or several) ready to go to the server in its charset, with each newline sent
+/// as CR LF, which is the only end of line a telnet server has to understand.  A server that's
+/// gone into character mode can sit waiting for the CR forever otherwise.  (255 never turns up in
+/// UTF-8, but it's a letter in most of the others, so it's escaped like any IAC.)
+pub fn encode_text(text: &str, charset: Charset) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() + 2);
+    let mut last = 0;
+    for b in charset.encode(text) {
+        match b {
+            b'\n' if last != b'\r' => out.extend_from_slice(b"\r\n"),
+            IAC => out.extend_from_slice(&[IAC, IAC]),
+            _ => out.push(b),
+        }
+        last = b;
+    }
+    out
+}
+
+#[test]
+fn telnet_negotiation() {
+    let mut telnet = Telnet::new();
+
+    // Password prompt: the server offers to echo, which we take; TTYPE we don't do.
+    let r = telnet.receive(&[b'P', b'w', IAC, WILL, opt::ECHO, b':', IAC, DO, opt::TTYPE, b' ']);
+    assert_eq!(r.data, b"Pw: ");
+    assert_eq!(r.replies, vec![IAC, DO, opt::ECHO, IAC, WONT, opt::TTYPE]);
+    assert_eq!(r.notices, vec![Notice::Option { option: opt::ECHO, ours: false, enabled: true }]);
+
+    // Saying it again doesn't get another answer, which is what keeps us out of loops.
+    assert!(telnet.receive(&[IAC, WILL, opt::ECHO]).replies.is_empty());
+
+    // Split across reads, with an escaped IAC in the subnegotiation.
+    let r = telnet.receive(&[b'o', b'k', b'\r', 0, IAC, SB, 69, 1, IAC]);
+    assert_eq!(r.data, b"ok\r");
+    let r = telnet.receive(&[IAC, 2, IAC, SE, IAC, WONT, opt::ECHO]);
+    assert_eq!(r.notices, vec![
+        Notice::Subnegotiation { option: 69, data: vec![1, IAC, 2] },
+        Notice::Option { option: opt::ECHO, ours: false, enabled: false },
+    ]);
+    assert_eq!(r.replies, vec![IAC, DONT, opt::ECHO]);
+
+    // Compression starts right after the subnegotiation, and only if we said it could.
+    let r = telnet.receive(&[IAC, SB, opt::COMPRESS2, IAC, SE, 1, 2]);
+    assert!(r.compressed.is_none());
+    telnet.accept_remote(opt::COMPRESS2);
+    let r = telnet.receive(&[IAC, WILL, opt::COMPRESS2, b'a', IAC, SB, opt::COMPRESS2, IAC, SE, 1, 2]);
+    assert_eq!(r.data, b"a");
+    assert_eq!(r.compressed, Some(vec![1, 2]));
+    assert_eq!(telnet.refuse(opt::COMPRESS2), vec![IAC, DONT, opt::COMPRESS2]);
+    assert!(!telnet.enabled(opt::COMPRESS2, false));
+}
+
+#[test]
+fn telnet_linemode() {
+    let mut telnet = Telnet::new();
+
+    // The usual BSD telnetd opening: we won't do LINEMODE, so it asks to echo and suppress GA
+    // instead, and we take those.  What it says about LINEMODE after that doesn't get through.
+    let r = telnet.receive(&[IAC, DO, opt::LINEMODE, IAC, SB, opt::LINEMODE, 1, 0, IAC, SE,
+                             IAC, WILL, opt::ECHO, IAC, WILL, opt::SGA, b'>']);
+    assert_eq!(r.replies, vec![IAC, WONT, opt::LINEMODE, IAC, DO, opt::ECHO, IAC, DO, opt::SGA]);
+    assert_eq!(r.data, b">");
+    assert_eq!(r.notices.len(), 2);
+    assert!(!telnet.enabled(opt::LINEMODE, true));
+
+    // Asked to do the echoing ourselves, we say no; the line's already on screen.
+    assert_eq!(telnet.receive(&[IAC, DO, opt::ECHO]).replies, vec![IAC, WONT, opt::ECHO]);
+
+    assert_eq!(encode_text("look\n", Charset::Utf8), b"look\r\n");
+    assert_eq!(encode_text("a\r\nb\n", Charset::Utf8), b"a\r\nb\r\n");
+    assert_eq!(encode_text("\u{ff}\n", Charset::Latin1), b"\xff\xff\r\n");
+}
+
+#[test]
+fn telnet_go_ahead() {
+    let mut telnet = Telnet::new();
+
+    let r = telnet.receive(&[b'o', b'k', b'\n', b'>', b' ', IAC, GA, IAC, DO, opt::SGA]);
+    assert_eq!(r.data, b"ok\n> ");
+    assert_eq!(r.prompt_ends, vec![5]);
+    assert_eq!(r.notices, vec![Notice::Option { option: opt::SGA, ours: true, enabled: true }]);
+    assert_eq!(r.replies, vec![IAC, WILL, opt::SGA]);
+
+    // Once the server's suppressing them, a stray GA isn't a prompt.
+    telnet.receive(&[IAC, WILL, opt::SGA]);
+    assert!(telnet.receive(&[b'>', IAC, GA]).prompt_ends.is_empty());
+
+    // EOR only counts once it's been agreed to.
+    assert!(telnet.receive(&[b'>', IAC, EOR]).prompt_ends.is_empty());
+    let r = telnet.receive(&[IAC, WILL, opt::EOR, b'>', IAC, EOR]);
+    assert_eq!(r.replies, vec![IAC, DO, opt::EOR]);
+    assert_eq!(r.prompt_ends, vec![1]);
+}
+
+#[test]
+fn telnet_charset() {
+    let request = |offer: &[u8]| [&[CHARSET_REQUEST][..], offer].concat();
+    let accepted = |name: &[u8]| [&[IAC, SB, opt::CHARSET, CHARSET_ACCEPTED][..], name, &[IAC, SE]].concat();
+
+    // UTF-8 if it's there, spelled the way the server spelled it.
+    let (charset, reply) = charset_reply(&request(b";ISO-8859-1;utf-8"), None);
+    assert_eq!(charset, Some(Charset::Utf8));
+    assert_eq!(reply, accepted(b"utf-8"));
+
+    // What the world's set to, if the server has it.
+    let (charset, reply) = charset_reply(&request(b" UTF-8 CP437"), Some(Charset::Cp437));
+    assert_eq!(charset, Some(Charset::Cp437));
+    assert_eq!(reply, accepted(b"CP437"));
+
+    let (charset, reply) = charset_reply(&request(b"[TTABLE]\x01;KOI8-R;latin1"), None);
+    assert_eq!(charset, Some(Charset::Latin1));
+    assert_eq!(reply, accepted(b"latin1"));
+
+    assert_eq!(charset_reply(&request(b";KOI8-R"), None),
+               (None, vec![IAC, SB, opt::CHARSET, CHARSET_REJECTED, IAC, SE]));
+    assert_eq!(charset_reply(&[CHARSET_ACCEPTED], None), (None, vec![]));
+}
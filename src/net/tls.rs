@@ -0,0 +1,208 @@
+//! TLS for TcpConnectionManager, via rustls.
+//!
+//! The socket is still an ordinary non-blocking mio TcpStream registered with the listener
+//! thread's Poll; TlsStream just sits on top of it and makes reading and writing go through the
+//! TLS session.  Both halves of a connection (the listener thread's and the main thread's) share
+//! one session, since they're both talking over the same one.
+//!
+//! Every connection the listener thread makes shares one ClientConfig, and with it one cache of
+//! session tickets, so reconnecting to a server we've talked to before can resume the old session
+//! instead of doing the whole handshake again.
+
+use mio::net::TcpStream;
+use rustls::{ClientConfig, ClientConnection, HandshakeKind, RootCertStore};
+use rustls::client::Resumption;
+use rustls::pki_types::ServerName;
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The prefix on an address that asks for TLS, as in "tls://example.com:4443".
+pub const SCHEME: &str = "tls://";
+
+/// How many servers' session tickets to keep.  Nobody's connected to this many worlds at once.
+const SESSION_CACHE_SIZE: usize = 64;
+
+/// Split a "tls://" prefix off an address, if there is one.
+pub fn strip_scheme(address: &str) -> (bool, &str) {
+    match address.strip_prefix(SCHEME) {
+        Some(rest) => (true, rest),
+        None => (false, address),
+    }
+}
+
+/// The host name part of an address like "example.com:4000" or "[::1]:4000", which is what the
+/// server's certificate has to match.
+pub fn host_name(address: &str) -> &str {
+    if address.starts_with('[') {
+        let end = address.find(']').unwrap_or(address.len());
+        // A zone isn't part of the name anybody would put on a certificate.
+        address[1..end].split('%').next().unwrap_or("")
+    } else {
+        match address.rfind(':') {
+            Some(colon) => &address[..colon],
+            None => address,
+        }
+    }
+}
+
+/// Settings shared by every TLS connection: the ring crypto provider and the Mozilla root
+/// certificates from webpki-roots.
+///
+/// TODO: Plenty of MUDs use self-signed certificates.  There should be some way to trust one
+/// anyway, which there isn't yet.
+pub fn client_config() -> Result<Arc<ClientConfig>, String> {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let mut config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Couldn't set up TLS: {}", e))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    // Tickets are kept by server name, so each world resumes its own sessions.  (This is what
+    // rustls does anyway, but we're counting on it, so it's spelled out.)
+    config.resumption = Resumption::in_memory_sessions(SESSION_CACHE_SIZE);
+    Ok(Arc::new(config))
+}
+
+/// How a connection's handshake went.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    /// From when the socket started connecting, so it includes the TCP handshake as well.
+    pub took: Duration,
+    /// Whether an old session was resumed, instead of starting over.
+    pub resumed: bool,
+}
+
+/// The TLS session, and how long it took to get going.
+struct Session {
+    conn: ClientConnection,
+    started: Instant,
+    handshake: Option<Handshake>,
+}
+
+impl Session {
+    /// Note when the handshake finished, the first time it's found to have.
+    fn check_handshake(&mut self) {
+        if self.handshake.is_none() && !self.conn.is_handshaking() {
+            self.handshake = Some(Handshake {
+                took: self.started.elapsed(),
+                resumed: self.conn.handshake_kind() == Some(HandshakeKind::Resumed),
+            });
+        }
+    }
+}
+
+/// A TcpStream with a TLS session on top.  Reads and writes are of the plain text; the socket
+/// underneath is left non-blocking, so reads give WouldBlock when there's nothing decrypted to
+/// hand out, the same as the socket would.
+pub struct TlsStream {
+    sock: TcpStream,
+    session: Arc<Mutex<Session>>,
+}
+
+impl TlsStream {
+    /// Start a session on a socket that's connecting to `host`.  Nothing is sent until the socket
+    /// is writable and someone calls flush() (or read() or write().)
+    pub fn new(sock: TcpStream, config: Arc<ClientConfig>, host: &str) -> Result<TlsStream, String> {
+        let name = ServerName::try_from(host.to_string())
+            .map_err(|_| format!("Can't use {} as a TLS server name", host))?;
+        let conn = ClientConnection::new(config, name)
+            .map_err(|e| format!("Couldn't start TLS session with {}: {}", host, e))?;
+
+        Ok(TlsStream {
+            sock,
+            session: Arc::new(Mutex::new(Session { conn, started: Instant::now(), handshake: None })),
+        })
+    }
+
+    pub fn socket(&self) -> &TcpStream {
+        &self.sock
+    }
+
+    /// How the handshake went, once it's done.
+    pub fn handshake(&self) -> Option<Handshake> {
+        self.session.lock().expect("TLS session lock poisoned").handshake
+    }
+
+    /// Another handle to the same socket and the same session.
+    pub fn try_clone(&self) -> io::Result<TlsStream> {
+        Ok(TlsStream {
+            sock: self.sock.try_clone()?,
+            session: self.session.clone(),
+        })
+    }
+}
+
+/// Send whatever the session has waiting, as far as the socket will take it.  The rest stays in
+/// the session until the next try.
+fn send_pending(session: &mut ClientConnection, sock: &mut TcpStream) -> io::Result<()> {
+    while session.wants_write() {
+        match session.write_tls(sock) {
+            Ok(_) => { },
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            // Still connecting; we'll hear that it's writable when it's done.
+            Err(ref e) if e.kind() == io::ErrorKind::NotConnected => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut session = self.session.lock().expect("TLS session lock poisoned");
+        loop {
+            // Hand out anything that's already been decrypted first.  Ok(0) here means the
+            // server closed the session properly.
+            match session.conn.reader().read(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => { },
+                result => return result,
+            }
+
+            // Answer handshake messages and the like before waiting for more.
+            send_pending(&mut session.conn, &mut self.sock)?;
+
+            match session.conn.read_tls(&mut self.sock)? {
+                0 => return Ok(0),
+                _ => {
+                    session.conn.process_new_packets()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    session.check_handshake();
+                },
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut session = self.session.lock().expect("TLS session lock poisoned");
+        // Before the handshake's done this just gets buffered in the session.
+        let written = session.conn.writer().write(buf)?;
+        send_pending(&mut session.conn, &mut self.sock)?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut session = self.session.lock().expect("TLS session lock poisoned");
+        send_pending(&mut session.conn, &mut self.sock)
+    }
+}
+
+#[test]
+fn tls_addresses() {
+    assert_eq!(strip_scheme("tls://example.com:4443"), (true, "example.com:4443"));
+    assert_eq!(strip_scheme("example.com:4000"), (false, "example.com:4000"));
+
+    assert_eq!(host_name("example.com:4443"), "example.com");
+    assert_eq!(host_name("[fe80::1%eth0]:4443"), "fe80::1");
+    assert_eq!(host_name("127.0.0.1:4443"), "127.0.0.1");
+
+    // Nothing's been said yet, let alone a handshake finished.
+    let server = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let sock = TcpStream::connect(&server.local_addr().unwrap()).unwrap();
+    let stream = TlsStream::new(sock, client_config().unwrap(), "localhost").unwrap();
+    assert_eq!(stream.handshake(), None);
+}
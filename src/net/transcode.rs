@@ -0,0 +1,205 @@
+//! The read path of a connection, as a chain of layers between the socket and the lines: each
+//! takes what the one below it (nearer the socket) handed on, and hands on what's left once it's
+//! done its part.  Over TCP that's telnet at the top, with MCCP's inflating slotted in under it
+//! for as long as the server's compressing what it sends.
+//!
+//! Layers can come and go partway through a chunk of bytes.  Compression, say, starts in the
+//! middle of one, when the telnet layer sees the server say so: it hands on what came before, and
+//! asks for an inflating layer to go in under it, for the rest to go through from there.  The
+//! inflating layer takes itself out again when the compressed stream ends.
+//!
+//! Decoding the text from the connection's charset isn't a layer: it has to be done a line at a
+//! time, once the lines are split (see tcp::take_lines), since a character can be split across
+//! chunks.
+
+use crate::net::mccp::Inflater;
+use crate::net::telnet::{Notice, Telnet};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// One layer of a connection's read path.
+pub trait LineTranscoder {
+    /// What the layer's called, e.g. for saying which layers a connection has.
+    fn name(&self) -> &'static str;
+
+    /// Take bytes from the layer below, and return what goes to the layer above.  Anything else
+    /// that comes of it goes in `out`.  An error means the connection can't carry on.
+    fn incoming(&mut self, bytes: Vec<u8>, out: &mut Step) -> Result<Vec<u8>, String>;
+}
+
+/// Something that makes a layer, for each time a connection's made (see
+/// TcpConnectionManager::add_transcoder.)
+pub type MakeTranscoder = fn() -> Box<dyn LineTranscoder>;
+
+/// What comes of a layer's go at some bytes, apart from the bytes it hands on.
+#[derive(Default)]
+pub struct Step {
+    /// What needs sending back to the server.
+    pub replies: Vec<u8>,
+    pub notices: Vec<Notice>,
+    /// Where prompts end in the bytes handed on (see telnet::Received.)  These only count from
+    /// the top layer, since layers above would move them.
+    pub prompt_ends: Vec<usize>,
+    // A layer to put in under this one, and what to run through it from there.
+    insert: Option<(Box<dyn LineTranscoder>, Vec<u8>)>,
+    done: bool,
+}
+
+impl Step {
+    /// Put `layer` in under this layer, and run `rest` through the chain from there, once what
+    /// this layer's handed on has gone up.
+    pub fn insert_below(&mut self, layer: Box<dyn LineTranscoder>, rest: Vec<u8>) {
+        self.insert = Some((layer, rest));
+    }
+
+    /// Take this layer out of the chain, once it's handed on what it was given.
+    pub fn finished(&mut self) {
+        self.done = true;
+    }
+}
+
+/// What came out of the top of a chain.
+#[derive(Default, Debug)]
+pub struct Transcoded {
+    pub data: Vec<u8>,
+    pub replies: Vec<u8>,
+    pub notices: Vec<Notice>,
+    /// Where prompts end in `data`, in order.
+    pub prompt_ends: Vec<usize>,
+    /// The names of layers that went in, and that took themselves out, in the order they did.
+    pub added: Vec<&'static str>,
+    pub removed: Vec<&'static str>,
+}
+
+/// A connection's layers, bottom first.
+pub struct Chain {
+    layers: Vec<Box<dyn LineTranscoder>>,
+}
+
+impl Chain {
+    /// A chain with just `top` in it, which stays on top.
+    pub fn new(top: Box<dyn LineTranscoder>) -> Chain {
+        Chain { layers: vec![top] }
+    }
+
+    /// Put a layer in, just under the top one.
+    pub fn add(&mut self, layer: Box<dyn LineTranscoder>) {
+        self.layers.insert(self.layers.len() - 1, layer);
+    }
+
+    /// The layers' names, bottom first.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.layers.iter().map(|layer| layer.name()).collect()
+    }
+
+    /// Run bytes from the socket up through the chain.
+    pub fn receive(&mut self, bytes: Vec<u8>) -> Result<Transcoded, String> {
+        let mut out = Transcoded::default();
+        let mut pending = vec![(0, bytes)];
+
+        while let Some((start, mut bytes)) = pending.pop() {
+            let mut i = start;
+            while i < self.layers.len() {
+                let mut step = Step::default();
+                bytes = self.layers[i].incoming(bytes, &mut step)?;
+                out.replies.append(&mut step.replies);
+                out.notices.append(&mut step.notices);
+                if i == self.layers.len() - 1 {
+                    let offset = out.data.len();
+                    out.prompt_ends.extend(step.prompt_ends.iter().map(|end| end + offset));
+                }
+
+                if step.done {
+                    out.removed.push(self.layers.remove(i).name());
+                } else {
+                    i += 1;
+                }
+                if let Some((layer, rest)) = step.insert.take() {
+                    // It goes where the layer that asked for it was, which moves up one.
+                    let below = i - if step.done { 0 } else { 1 };
+                    out.added.push(layer.name());
+                    self.layers.insert(below, layer);
+                    i += 1;
+                    pending.push((below, rest));
+                }
+            }
+            out.data.append(&mut bytes);
+        }
+
+        Ok(out)
+    }
+}
+
+/// The telnet layer, which is shared with the TcpConnectionManager so it can still negotiate.
+pub struct TelnetLayer(pub Rc<RefCell<Telnet>>);
+
+impl LineTranscoder for TelnetLayer {
+    fn name(&self) -> &'static str {
+        "telnet"
+    }
+
+    fn incoming(&mut self, bytes: Vec<u8>, out: &mut Step) -> Result<Vec<u8>, String> {
+        let received = self.0.borrow_mut().receive(&bytes);
+        out.replies = received.replies;
+        out.notices = received.notices;
+        out.prompt_ends = received.prompt_ends;
+        if let Some(compressed) = received.compressed {
+            out.insert_below(Box::new(InflateLayer(Inflater::new())), compressed);
+        }
+        Ok(received.data)
+    }
+}
+
+/// MCCP2: inflating what the server sends, until its compressed stream ends.
+pub struct InflateLayer(pub Inflater);
+
+/// What InflateLayer's called.
+pub const INFLATE: &str = "mccp";
+
+impl LineTranscoder for InflateLayer {
+    fn name(&self) -> &'static str {
+        INFLATE
+    }
+
+    fn incoming(&mut self, bytes: Vec<u8>, out: &mut Step) -> Result<Vec<u8>, String> {
+        let mut inflated = self.0.inflate(&bytes)?;
+        if let Some(mut rest) = inflated.rest {
+            // The server's stopped compressing, and the rest is plain.
+            out.finished();
+            inflated.data.append(&mut rest);
+        }
+        Ok(inflated.data)
+    }
+}
+
+#[test]
+fn layers_come_and_go() {
+    use crate::net::mccp::Deflater;
+    use crate::net::telnet::{IAC, DO, WILL, SB, SE, opt};
+
+    let telnet = Rc::new(RefCell::new(Telnet::new()));
+    telnet.borrow_mut().accept_remote(opt::COMPRESS2);
+    let mut chain = Chain::new(Box::new(TelnetLayer(telnet)));
+
+    // Compression starts partway through a chunk, and ends partway through a later one.
+    let mut deflater = Deflater::new();
+    let mut bytes = vec![IAC, WILL, opt::COMPRESS2];
+    bytes.extend_from_slice(b"plain\n");
+    bytes.extend_from_slice(&[IAC, SB, opt::COMPRESS2, IAC, SE]);
+    bytes.extend(deflater.deflate(b"squashed\n"));
+    let out = chain.receive(bytes).unwrap();
+    assert_eq!(out.data, b"plain\nsquashed\n".to_vec());
+    assert_eq!(out.replies, vec![IAC, DO, opt::COMPRESS2]);
+    assert_eq!(out.added, vec![INFLATE]);
+    assert_eq!(chain.names(), vec![INFLATE, "telnet"]);
+
+    let mut bytes = deflater.finish();
+    bytes.extend_from_slice(b"plain again\n");
+    let out = chain.receive(bytes).unwrap();
+    assert_eq!(out.data, b"plain again\n".to_vec());
+    assert_eq!(out.removed, vec![INFLATE]);
+    assert_eq!(chain.names(), vec!["telnet"]);
+
+    assert!(chain.receive(vec![IAC, SB, opt::COMPRESS2, IAC, SE, 1, 2, 3]).is_err());
+}
@@ -0,0 +1,50 @@
+//! Notes the user jots down mid-game with /note (quest hints, who to talk to), kept in a file for
+//! each world so they're still there next time, and shown with /notes.
+
+use crate::clock::LocalTime;
+use crate::config;
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Where the notes for `world` go: a file in the notes directory in the config directory, which
+/// is made if it isn't there yet.  None if there's no config directory.
+pub fn path(world: &str) -> Option<PathBuf> {
+    let dir = config::ensure_config_dir()?.join("notes");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(format!("{}.txt", config::file_name(world))))
+}
+
+/// Add a note to the end of a notes file, with when it was written.
+pub fn append(path: &Path, when: LocalTime, text: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "[{} {}] {}", when.ymd(), when.hm(), text)
+}
+
+/// Every note in a notes file, oldest first.  No file just means no notes yet.
+pub fn read(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().map(|line| line.to_string()).collect()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+#[test]
+fn notes_file() {
+    assert_eq!(config::file_name("../Discworld"), "_Discworld");
+
+    let path = std::env::temp_dir().join(format!("mint-notes-test-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+    assert_eq!(read(&path).unwrap(), Vec::<String>::new());
+
+    let when = LocalTime { year: 2024, month: 3, day: 9, hour: 22, minute: 14, second: 5 };
+    append(&path, when, "Ask the smith about the key").unwrap();
+    append(&path, when, "Vimes is in the Watch House").unwrap();
+    assert_eq!(read(&path).unwrap(), vec![
+        "[2024-03-09 22:14] Ask the smith about the key".to_string(),
+        "[2024-03-09 22:14] Vimes is in the Watch House".to_string(),
+    ]);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,128 @@
+//! Repeats: lines run every so often (or once, after a while), as if they'd been typed again in
+//! the window they were set up in, e.g. "/repeat 60 save", or a keepalive to stop a server
+//! timing out an idle connection.  Each gets a number to tell it apart by when it's listed or
+//! stopped.
+
+use crate::meta::WindowID;
+use crate::timer::{TimerID, Timers};
+
+use std::time::{Duration, Instant};
+
+/// The shortest time between goes, so a typo can't flood a server.
+pub const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug)]
+pub struct Repeat {
+    pub number: usize,
+    pub window: WindowID,
+    pub every: Duration,
+    /// How many goes are left, or None if it carries on until it's stopped.
+    pub left: Option<u32>,
+    pub line: String,
+    timer: TimerID,
+}
+
+#[derive(Default)]
+pub struct Repeats {
+    repeats: Vec<Repeat>,
+    last_number: usize,
+}
+
+impl Repeats {
+    pub fn new() -> Repeats {
+        Repeats::default()
+    }
+
+    /// Run `line` in `window` every `every`, `times` times (or forever), starting `every` from
+    /// now.  Returns its number.
+    pub fn add(&mut self, timers: &mut Timers, window: WindowID, every: Duration, times: Option<u32>,
+               line: String) -> usize {
+        self.last_number += 1;
+        let timer = timers.schedule(Instant::now() + every);
+        self.repeats.push(Repeat { number: self.last_number, window, every, left: times, line, timer });
+        self.last_number
+    }
+
+    /// If timer `id` belongs to a repeat, it's time for its next go: returns the window and the
+    /// line to run, and sets the timer for the one after (if there is one.)
+    pub fn fired(&mut self, timers: &mut Timers, id: TimerID) -> Option<(WindowID, String)> {
+        let i = self.repeats.iter().position(|r| r.timer == id)?;
+        let repeat = &mut self.repeats[i];
+        let go = (repeat.window, repeat.line.clone());
+        repeat.left = repeat.left.map(|n| n.saturating_sub(1));
+        if repeat.left == Some(0) {
+            self.repeats.remove(i);
+        } else {
+            repeat.timer = timers.schedule(Instant::now() + repeat.every);
+        }
+        Some(go)
+    }
+
+    /// Stop a repeat.  Returns false if there isn't one with that number.
+    pub fn remove(&mut self, timers: &mut Timers, number: usize) -> bool {
+        match self.repeats.iter().position(|r| r.number == number) {
+            Some(i) => {
+                timers.cancel(self.repeats.remove(i).timer);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Stop all of them.
+    pub fn clear(&mut self, timers: &mut Timers) {
+        for repeat in self.repeats.drain(..) {
+            timers.cancel(repeat.timer);
+        }
+    }
+
+    pub fn list(&self) -> &[Repeat] {
+        &self.repeats
+    }
+}
+
+/// A length of time like "90", "1.5", "30s", "5m" or "2h"; plain numbers are seconds.
+pub fn parse_interval(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.char_indices().last()? {
+        (i, 's') => (&text[..i], 1.0),
+        (i, 'm') => (&text[..i], 60.0),
+        (i, 'h') => (&text[..i], 3600.0),
+        _ => (text, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => Some(Duration::from_secs_f64(n * unit)),
+        _ => None,
+    }
+}
+
+#[test]
+fn intervals() {
+    assert_eq!(parse_interval("90"), Some(Duration::from_secs(90)));
+    assert_eq!(parse_interval("1.5"), Some(Duration::from_millis(1500)));
+    assert_eq!(parse_interval("30s"), Some(Duration::from_secs(30)));
+    assert_eq!(parse_interval("5m"), Some(Duration::from_secs(300)));
+    assert_eq!(parse_interval("2h"), Some(Duration::from_secs(7200)));
+    assert_eq!(parse_interval("m"), None);
+    assert_eq!(parse_interval("-1"), None);
+    assert_eq!(parse_interval("soon"), None);
+}
+
+#[test]
+fn repeating() {
+    let mut timers = Timers::new();
+    let mut repeats = Repeats::new();
+    let twice = repeats.add(&mut timers, 0, Duration::from_secs(60), Some(2), "save".to_string());
+    let forever = repeats.add(&mut timers, 1, Duration::from_secs(5), None, "look".to_string());
+
+    let timer = repeats.list()[0].timer;
+    assert_eq!(repeats.fired(&mut timers, timer), Some((0, "save".to_string())));
+    assert_eq!(repeats.fired(&mut timers, timer), None);
+    let timer = repeats.list()[0].timer;
+    assert_eq!(repeats.fired(&mut timers, timer), Some((0, "save".to_string())));
+    assert_eq!(repeats.list().len(), 1);
+    assert_eq!(repeats.list()[0].number, forever);
+
+    assert!(!repeats.remove(&mut timers, twice));
+    assert!(repeats.remove(&mut timers, forever));
+    assert!(repeats.list().is_empty());
+}
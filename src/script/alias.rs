@@ -0,0 +1,72 @@
+//! Aliases: short words the user types that stand for longer commands, like `gg` for `go gate`.
+
+use super::substitute;
+
+use std::collections::BTreeMap;
+
+/// The user's aliases, by name.  Kept sorted so they list in order.
+#[derive(Default)]
+pub struct Aliases {
+    aliases: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    pub fn new() -> Aliases {
+        Aliases { aliases: BTreeMap::new() }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(|body| body.as_str())
+    }
+
+    /// Define (or redefine) an alias.  The name can't have spaces in it, since it's the first word
+    /// of the line that's matched.
+    pub fn set(&mut self, name: &str, body: &str) -> Result<(), String> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("Can't use '{}' as an alias name", name));
+        }
+        self.aliases.insert(name.to_string(), body.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.aliases.remove(name)
+    }
+
+    /// All the aliases and what they stand for, sorted by name.
+    pub fn list(&self) -> Vec<(&str, &str)> {
+        self.aliases.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+    }
+
+    /// If the first word of `line` is an alias, what it expands to: the alias's body with the rest
+    /// of the words substituted in for %1, %2 and so on, or %* for all of them.  None if it isn't
+    /// an alias, in which case the line should go on as it is.
+    ///
+    /// The expansion isn't expanded again, so an alias can't go round in circles (and `n` can be
+    /// an alias for `north` without `north` needing to be anything.)
+    pub fn expand(&self, line: &str) -> Option<String> {
+        let mut words = line.split_whitespace();
+        let body = self.aliases.get(words.next()?)?;
+        let args: Vec<&str> = words.collect();
+        Some(substitute(body, &args))
+    }
+}
+
+#[test]
+fn alias_expansion() {
+    let mut aliases = Aliases::new();
+    aliases.set("gg", "go gate").unwrap();
+    aliases.set("k", "kill %1").unwrap();
+    aliases.set("tell", "/wall -sbold %1 says: %*").unwrap();
+    assert!(aliases.set("two words", "nope").is_err());
+
+    assert_eq!(aliases.expand("gg"), Some("go gate".to_string()));
+    assert_eq!(aliases.expand("k rat now"), Some("kill rat".to_string()));
+    assert_eq!(aliases.expand("tell bob hi  there"), Some("/wall -sbold bob says: bob hi there".to_string()));
+    assert_eq!(aliases.expand("ggg"), None);
+    assert_eq!(aliases.expand(""), None);
+
+    assert_eq!(aliases.remove("gg"), Some("go gate".to_string()));
+    assert_eq!(aliases.expand("gg"), None);
+    assert_eq!(aliases.list(), vec![("k", "kill %1"), ("tell", "/wall -sbold %1 says: %*")]);
+}
@@ -0,0 +1,107 @@
+//! Dice, for expressions like `2d6+3` (see script::expr) and the /roll command.
+//!
+//! The random numbers come from a little xorshift generator of our own, seeded from the clock.
+//! That's nowhere near good enough for anything secret, but it's plenty for rolling dice, and it
+//! saves pulling in a crate for it.
+
+use std::cell::RefCell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most dice in one roll; more than this is almost certainly a typo.
+const MAX_DICE: i64 = 1000;
+
+thread_local! {
+    static RNG: RefCell<Rng> = RefCell::new(Rng::from_clock());
+    // Every roll since the last take_rolls(), so /roll can show the dice as well as the total.
+    static ROLLS: RefCell<Vec<Roll>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A pseudo-random number generator (xorshift64*.)
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn seeded(seed: u64) -> Rng {
+        // Zero is the one state xorshift never gets out of.
+        Rng { state: seed.max(1) }
+    }
+
+    fn from_clock() -> Rng {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Rng::seeded(now.as_nanos() as u64 ^ (std::process::id() as u64) << 32)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number from 1 to `n`, all equally likely.
+    pub fn one_to(&mut self, n: u64) -> u64 {
+        // Throw away the top sliver of the range that doesn't divide evenly by n, or the low
+        // numbers would come up slightly more often.
+        let limit = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < limit {
+                return x % n + 1;
+            }
+        }
+    }
+}
+
+/// One lot of dice that were rolled, and what they came up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Roll {
+    pub count: i64,
+    pub sides: i64,
+    pub results: Vec<i64>,
+}
+
+/// Roll `count` dice with `sides` sides, and add them up.
+pub fn roll(count: i64, sides: i64) -> Result<i64, String> {
+    if !(0..=MAX_DICE).contains(&count) {
+        return Err(format!("Can't roll {} dice (at most {})", count, MAX_DICE));
+    }
+    if sides < 1 {
+        return Err(format!("Can't roll a die with {} sides", sides));
+    }
+
+    let results: Vec<i64> = RNG.with(|rng| {
+        let mut rng = rng.borrow_mut();
+        (0..count).map(|_| rng.one_to(sides as u64) as i64).collect()
+    });
+    let total = results.iter().try_fold(0i64, |total, &n| total.checked_add(n))
+        .ok_or_else(|| format!("{}d{} came to too much to add up", count, sides))?;
+    ROLLS.with(|rolls| rolls.borrow_mut().push(Roll { count, sides, results }));
+    Ok(total)
+}
+
+/// Every roll since the last time this was called, oldest first.
+pub fn take_rolls() -> Vec<Roll> {
+    ROLLS.with(|rolls| std::mem::take(&mut *rolls.borrow_mut()))
+}
+
+#[test]
+fn dice_rolls() {
+    let mut rng = Rng::seeded(0);
+    assert!((0..1000).map(|_| rng.one_to(6)).all(|n| (1..=6).contains(&n)));
+
+    take_rolls();
+    for _ in 0..100 {
+        let total = roll(3, 6).unwrap();
+        assert!((3..=18).contains(&total));
+    }
+    let rolls = take_rolls();
+    assert_eq!(rolls.len(), 100);
+    assert!(rolls.iter().all(|r| r.results.len() == 3 && r.results.iter().sum::<i64>() >= 3));
+    assert!(take_rolls().is_empty());
+
+    assert_eq!(roll(0, 6), Ok(0));
+    assert!(roll(2, 0).is_err());
+    assert!(roll(MAX_DICE + 1, 6).is_err());
+    assert!(roll(MAX_DICE, i64::MAX).is_err());
+}
@@ -0,0 +1,437 @@
+use crate::script::dice;
+use crate::script::vars::Variables;
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// The result of evaluating an expression.  Like variables, values are either numbers or strings,
+/// and strings that look like numbers get treated as numbers wherever that matters.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value {
+    Num(i64),
+    Str(String),
+}
+
+impl Value {
+    pub fn as_num(&self) -> Option<i64> {
+        match *self {
+            Value::Num(n) => Some(n),
+            Value::Str(ref s) => s.trim().parse().ok(),
+        }
+    }
+
+    /// Zero and the empty string are false; everything else is true.
+    pub fn is_true(&self) -> bool {
+        match *self {
+            Value::Num(n) => n != 0,
+            Value::Str(ref s) => match s.trim().parse::<i64>() {
+                Ok(n) => n != 0,
+                Err(_) => !s.is_empty(),
+            },
+        }
+    }
+
+    fn from_bool(b: bool) -> Value {
+        Value::Num(if b { 1 } else { 0 })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Num(n) => write!(f, "{}", n),
+            Value::Str(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BinOp {
+    Or, And,
+    Eq, Ne, Lt, Le, Gt, Ge,
+    Add, Sub, Mul, Div, Rem,
+}
+
+/// A parsed expression, e.g. `autoheal = 1 and hp < 50`.  Parse once with parse(), then
+/// eval() as many times as you like against the current variables.  In a trigger's condition,
+/// `%1`, `%2`... are the pattern's groups, which are only ever values, whatever's in them.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Num(i64),
+    Str(String),
+    Var(String),
+    /// `%1` to `%9`; empty if there aren't that many.
+    Capture(usize),
+    /// `count` dice with `sides` sides, like `2d6`; rolled afresh every time it's evaluated.
+    Dice(i64, i64),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, vars: &Variables) -> Result<Value, String> {
+        self.eval_with(vars, &[])
+    }
+
+    /// Evaluate the expression with `captures` for `%1`, `%2`...
+    pub fn eval_with(&self, vars: &Variables, captures: &[&str]) -> Result<Value, String> {
+        match *self {
+            Expr::Num(n) => Ok(Value::Num(n)),
+            Expr::Str(ref s) => Ok(Value::Str(s.clone())),
+            // Unset variables are just empty, rather than an error; it makes conditions on
+            // variables that haven't been captured yet a lot less annoying to write.
+            Expr::Var(ref name) => Ok(Value::Str(vars.get(name).unwrap_or("").to_string())),
+            Expr::Capture(n) => Ok(Value::Str(captures.get(n - 1).copied().unwrap_or("").to_string())),
+            Expr::Dice(count, sides) => Ok(Value::Num(dice::roll(count, sides)?)),
+            Expr::Not(ref e) => Ok(Value::from_bool(!e.eval_with(vars, captures)?.is_true())),
+            Expr::Neg(ref e) => Ok(Value::Num(number(&e.eval_with(vars, captures)?)?.wrapping_neg())),
+            Expr::Binary(BinOp::Or, ref l, ref r) => {
+                let either = l.is_true(vars, captures)? || r.is_true(vars, captures)?;
+                Ok(Value::from_bool(either))
+            },
+            Expr::Binary(BinOp::And, ref l, ref r) => {
+                let both = l.is_true(vars, captures)? && r.is_true(vars, captures)?;
+                Ok(Value::from_bool(both))
+            },
+            Expr::Binary(op, ref l, ref r) => {
+                binary(op, l.eval_with(vars, captures)?, r.eval_with(vars, captures)?)
+            },
+        }
+    }
+
+    /// Evaluate the expression as a condition, with `captures` for `%1`, `%2`...
+    pub fn is_true(&self, vars: &Variables, captures: &[&str]) -> Result<bool, String> {
+        Ok(self.eval_with(vars, captures)?.is_true())
+    }
+}
+
+fn number(v: &Value) -> Result<i64, String> {
+    v.as_num().ok_or_else(|| format!("'{}' is not a number", v))
+}
+
+fn binary(op: BinOp, l: Value, r: Value) -> Result<Value, String> {
+    // Comparisons are numeric if both sides look like numbers, and alphabetical otherwise.
+    let compare = || match (l.as_num(), r.as_num()) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => l.to_string().cmp(&r.to_string()),
+    };
+
+    let result = match op {
+        BinOp::Eq => Value::from_bool(compare() == Ordering::Equal),
+        BinOp::Ne => Value::from_bool(compare() != Ordering::Equal),
+        BinOp::Lt => Value::from_bool(compare() == Ordering::Less),
+        BinOp::Le => Value::from_bool(compare() != Ordering::Greater),
+        BinOp::Gt => Value::from_bool(compare() == Ordering::Greater),
+        BinOp::Ge => Value::from_bool(compare() != Ordering::Less),
+        BinOp::Add => Value::Num(number(&l)?.wrapping_add(number(&r)?)),
+        BinOp::Sub => Value::Num(number(&l)?.wrapping_sub(number(&r)?)),
+        BinOp::Mul => Value::Num(number(&l)?.wrapping_mul(number(&r)?)),
+        BinOp::Div | BinOp::Rem => {
+            let (a, b) = (number(&l)?, number(&r)?);
+            if b == 0 {
+                return Err("Division by zero".to_string());
+            }
+            Value::Num(if op == BinOp::Div { a.wrapping_div(b) } else { a.wrapping_rem(b) })
+        },
+        BinOp::Or | BinOp::And => unreachable!("and/or are short-circuited in eval()"),
+    };
+    Ok(result)
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Num(i64),
+    Str(String),
+    Ident(String),
+    Capture(usize),
+    Dice(i64, i64),
+    Op(BinOp),
+    Not,
+    LParen,
+    RParen,
+}
+
+fn take_word(chars: &mut Peekable<Chars>) -> String {
+    let mut word = String::new();
+    while let Some(&d) = chars.peek() {
+        if !(d.is_alphanumeric() || d == '_') {
+            break;
+        }
+        word.push(d);
+        chars.next();
+    }
+    word
+}
+
+/// For the "d6" part of dice, how many sides.
+fn dice_sides(word: &str) -> Option<i64> {
+    let digits = word.strip_prefix('d')?;
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// If `c` and what's after it is `%1` to `%9`, take the digit and say which it is.  After
+/// something with a value (`hp%2`), a `%` is the remainder instead.
+fn capture(c: char, chars: &mut Peekable<Chars>, last: Option<&Token>) -> Option<usize> {
+    let operand = matches!(last, Some(Token::Num(_) | Token::Str(_) | Token::Ident(_)
+                                      | Token::Capture(_) | Token::Dice(..) | Token::RParen));
+    if c != '%' || operand {
+        return None;
+    }
+    let mut ahead = chars.clone();
+    ahead.next();
+    let n = ahead.next()?.to_digit(10).filter(|&n| n != 0)? as usize;
+    chars.next();
+    chars.next();
+    Some(n)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek() {
+                if !d.is_ascii_digit() {
+                    break;
+                }
+                digits.push(d);
+                chars.next();
+            }
+            let n = digits.parse().map_err(|_| format!("Number too big: {}", digits))?;
+            // A number followed straight away by d and another number is dice, like 2d6.
+            if chars.peek() == Some(&'d') {
+                let word = take_word(&mut chars);
+                let sides = dice_sides(&word).ok_or_else(|| format!("Expected dice like 2d6, not {}{}", digits, word))?;
+                tokens.push(Token::Dice(n, sides));
+            } else {
+                tokens.push(Token::Num(n));
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let word = take_word(&mut chars);
+            tokens.push(match word.as_str() {
+                "and" => Token::Op(BinOp::And),
+                "or" => Token::Op(BinOp::Or),
+                "not" => Token::Not,
+                // Just d20 is one die.
+                _ => match dice_sides(&word) {
+                    Some(sides) => Token::Dice(1, sides),
+                    None => Token::Ident(word),
+                },
+            });
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(escaped) => s.push(escaped),
+                        None => return Err("Unterminated string".to_string()),
+                    },
+                    Some(other) => s.push(other),
+                    None => return Err("Unterminated string".to_string()),
+                }
+            }
+            tokens.push(Token::Str(s));
+        } else if let Some(n) = capture(c, &mut chars, tokens.last()) {
+            tokens.push(Token::Capture(n));
+        } else {
+            chars.next();
+            let next = chars.peek().cloned();
+            // Two-character operators first, then one-character ones.
+            let (token, two) = match (c, next) {
+                ('=', Some('=')) => (Token::Op(BinOp::Eq), true),
+                ('!', Some('=')) => (Token::Op(BinOp::Ne), true),
+                ('<', Some('=')) => (Token::Op(BinOp::Le), true),
+                ('>', Some('=')) => (Token::Op(BinOp::Ge), true),
+                ('&', Some('&')) => (Token::Op(BinOp::And), true),
+                ('|', Some('|')) => (Token::Op(BinOp::Or), true),
+                ('=', _) => (Token::Op(BinOp::Eq), false),
+                ('<', _) => (Token::Op(BinOp::Lt), false),
+                ('>', _) => (Token::Op(BinOp::Gt), false),
+                ('!', _) => (Token::Not, false),
+                ('+', _) => (Token::Op(BinOp::Add), false),
+                ('-', _) => (Token::Op(BinOp::Sub), false),
+                ('*', _) => (Token::Op(BinOp::Mul), false),
+                ('/', _) => (Token::Op(BinOp::Div), false),
+                ('%', _) => (Token::Op(BinOp::Rem), false),
+                ('(', _) => (Token::LParen, false),
+                (')', _) => (Token::RParen, false),
+                _ => return Err(format!("Unexpected character '{}'", c)),
+            };
+            if two {
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the token list.  Each level of precedence gets a method, from
+/// loosest (or) to tightest (unary minus and atoms.)
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    /// Parse a left-associative run of any of the operators in `ops`, with operands parsed by
+    /// `operand`.
+    fn binary_level(&mut self, ops: &[BinOp], operand: fn(&mut Parser) -> Result<Expr, String>)
+                    -> Result<Expr, String> {
+        let mut left = operand(self)?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            if !ops.contains(&op) {
+                break;
+            }
+            self.next();
+            let right = operand(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary_level(&[BinOp::Or], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary_level(&[BinOp::And], Parser::not)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        // Comparisons don't chain; `a < b < c` is an error rather than something surprising.
+        let left = self.sum()?;
+        if let Some(&Token::Op(op)) = self.peek() {
+            if [BinOp::Eq, BinOp::Ne, BinOp::Lt, BinOp::Le, BinOp::Gt, BinOp::Ge].contains(&op) {
+                self.next();
+                let right = self.sum()?;
+                return Ok(Expr::Binary(op, Box::new(left), Box::new(right)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary_level(&[BinOp::Add, BinOp::Sub], Parser::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        self.binary_level(&[BinOp::Mul, BinOp::Div, BinOp::Rem], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Op(BinOp::Sub)) {
+            self.next();
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Str(s)) => Ok(Expr::Str(s)),
+            Some(Token::Ident(name)) => Ok(Expr::Var(name)),
+            Some(Token::Capture(n)) => Ok(Expr::Capture(n)),
+            Some(Token::Dice(count, sides)) => Ok(Expr::Dice(count, sides)),
+            Some(Token::LParen) => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(inner),
+                    _ => Err("Missing ')'".to_string()),
+                }
+            },
+            Some(t) => Err(format!("Unexpected {:?}", t)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// Parse an expression.
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(t) => Err(format!("Unexpected {:?} after end of expression", t)),
+    }
+}
+
+#[test]
+fn conditions_over_variables() {
+    let mut vars = Variables::new();
+    let cond = parse("autoheal=1 and hp<50").unwrap();
+
+    assert_eq!(cond.is_true(&vars, &[]), Ok(false));
+    vars.set("autoheal".to_string(), "1".to_string());
+    vars.set("hp".to_string(), "120".to_string());
+    assert_eq!(cond.is_true(&vars, &[]), Ok(false));
+    vars.set("hp".to_string(), "49".to_string());
+    assert_eq!(cond.is_true(&vars, &[]), Ok(true));
+
+    assert_eq!(parse("(hp + 1) * 2 - -3").unwrap().eval(&vars), Ok(Value::Num(103)));
+    assert_eq!(parse("not name == \"Bob\" || 0").unwrap().eval(&vars), Ok(Value::Num(1)));
+    assert!(parse("hp <").is_err());
+    assert!(parse("hp / 0").unwrap().eval(&vars).is_err());
+    assert_eq!(parse("-(0-9223372036854775807-1)").unwrap().eval(&vars), Ok(Value::Num(i64::MIN)));
+
+    // Dice, with and without a count, mixed in with everything else.
+    let roll = parse("2d6 + d4 + hp").unwrap();
+    for _ in 0..50 {
+        let n = roll.eval(&vars).unwrap().as_num().unwrap();
+        assert!((52..=65).contains(&n));
+    }
+    assert!(parse("2dx").is_err());
+}
+
+#[test]
+fn captures_are_values() {
+    let vars = Variables::new();
+    let cond = parse("%1 == \"Bob\" && %2<50").unwrap();
+
+    assert_eq!(cond.is_true(&vars, &["Bob", "12"]), Ok(true));
+    assert_eq!(cond.is_true(&vars, &["Bob", "120"]), Ok(false));
+    // Whatever's in a capture stays a value, rather than turning into more of the expression.
+    assert_eq!(cond.is_true(&vars, &["1||1", "120"]), Ok(false));
+    assert_eq!(cond.is_true(&vars, &["\"", "12"]), Ok(false));
+    // Missing ones are empty, and % after a value is still the remainder.
+    assert_eq!(parse("%3").unwrap().eval_with(&vars, &["a"]), Ok(Value::Str(String::new())));
+    assert_eq!(parse("%1 % %2").unwrap().eval_with(&vars, &["7", "4"]), Ok(Value::Num(3)));
+    assert_eq!(parse("(7)%2").unwrap().eval(&vars), Ok(Value::Num(1)));
+}
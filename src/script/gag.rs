@@ -0,0 +1,143 @@
+//! Gags: patterns for lines from the server that shouldn't be shown, like spam or a channel
+//! that won't stop.  A gag either drops every line that matches it, or collapses them: the
+//! first of a run shows, the rest in the same window don't, and when something else turns up
+//! there, a line saying how many there were goes in front of it.
+//!
+//! The last few lines gagged (by gags or by triggers) are kept, so that they can be looked over
+//! in a review window if something important went missing.
+
+use crate::line::Line;
+
+use fnv::FnvHashMap;
+use regex::Regex;
+
+use std::collections::VecDeque;
+
+/// How many gagged lines are kept for reviewing, and the window they're reviewed in.
+pub const REVIEW_LINES: usize = 500;
+pub const REVIEW_WINDOW: &str = "gagged";
+
+pub struct Gag {
+    pub pattern: Regex,
+    /// Show the first of a run of lines that match, instead of none of them.
+    pub collapse: bool,
+}
+
+#[derive(Default)]
+pub struct Gags {
+    gags: Vec<Gag>,
+    // For each window in the middle of a collapsed run: the pattern, and how many lines it's
+    // swallowed since the one that was shown.
+    runs: FnvHashMap<String, (String, usize)>,
+    recent: VecDeque<Line>,
+}
+
+impl Gags {
+    pub fn new() -> Gags {
+        Gags::default()
+    }
+
+    /// Gag lines that match `pattern`.  A gag with the same pattern is replaced.
+    pub fn add(&mut self, pattern: &str, collapse: bool) -> Result<(), String> {
+        let pattern = Regex::new(pattern).map_err(|e| format!("Bad pattern: {}", e))?;
+        let gag = Gag { pattern, collapse };
+        match self.gags.iter_mut().find(|g| g.pattern.as_str() == gag.pattern.as_str()) {
+            Some(existing) => *existing = gag,
+            None => self.gags.push(gag),
+        }
+        Ok(())
+    }
+
+    /// Returns false if there wasn't a gag with that pattern.
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let before = self.gags.len();
+        self.gags.retain(|g| g.pattern.as_str() != pattern);
+        self.runs.retain(|_, (run, _)| run != pattern);
+        self.gags.len() != before
+    }
+
+    pub fn list(&self) -> &[Gag] {
+        &self.gags
+    }
+
+    /// Gag `line`, on its way to `window`, if a gag says to.  If it ends a collapsed run, the
+    /// line saying so is returned, to show before it.  Lines that are gagged already (by a
+    /// trigger) are left alone.
+    pub fn check(&mut self, window: &str, line: &mut Line) -> Option<Line> {
+        if line.gag {
+            return None;
+        }
+        let gag = self.gags.iter().find(|g| g.pattern.is_match(line.text()));
+
+        let collapsing = match gag {
+            Some(gag) if !gag.collapse => {
+                // Lines nobody sees don't break a run.
+                line.gag = true;
+                return None;
+            },
+            Some(gag) => Some(gag.pattern.as_str().to_string()),
+            None => None,
+        };
+        if let (Some(pattern), Some((run, count))) = (&collapsing, self.runs.get_mut(window)) {
+            if pattern == run {
+                line.gag = true;
+                *count += 1;
+                return None;
+            }
+        }
+
+        let ended = match collapsing {
+            Some(pattern) => self.runs.insert(window.to_string(), (pattern, 0)),
+            None => self.runs.remove(window),
+        };
+        match ended {
+            Some((_, count)) if count > 0 => {
+                Some(Line::client(&format!("({} more like that)", count)))
+            },
+            _ => None,
+        }
+    }
+
+    /// Keep a gagged line for reviewing, forgetting the oldest if there are too many.
+    pub fn keep(&mut self, line: Line) {
+        if self.recent.len() == REVIEW_LINES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(line);
+    }
+
+    /// The lines kept for reviewing, oldest first.
+    pub fn recent(&self) -> impl Iterator<Item = &Line> {
+        self.recent.iter()
+    }
+}
+
+#[test]
+fn gagging() {
+    use crate::net::ConnectionID;
+
+    let mut gags = Gags::new();
+    gags.add("^\\[spam\\]", false).unwrap();
+    gags.add("^\\[ooc\\]", true).unwrap();
+    assert!(gags.add("(", false).is_err());
+
+    fn check(gags: &mut Gags, window: &str, text: &str) -> (Option<String>, bool) {
+        let mut line = Line::server(text, ConnectionID::new(1, 0));
+        let before = gags.check(window, &mut line).map(|l| l.text().to_string());
+        (before, line.gag)
+    }
+
+    assert_eq!(check(&mut gags, "main", "[spam] Buy gold!"), (None, true));
+    assert_eq!(check(&mut gags, "main", "[ooc] Annie: hi"), (None, false));
+    assert_eq!(check(&mut gags, "main", "[ooc] Bob: hello"), (None, true));
+    // A dropped line, or a collapsed one in another window, doesn't end the run.
+    assert_eq!(check(&mut gags, "main", "[spam] Buy gold!"), (None, true));
+    assert_eq!(check(&mut gags, "other", "[ooc] Carol: hey"), (None, false));
+    assert_eq!(check(&mut gags, "main", "[ooc] Carol: hey"), (None, true));
+    assert_eq!(check(&mut gags, "main", "You are hungry."), (Some("(2 more like that)".to_string()), false));
+    assert_eq!(check(&mut gags, "main", "You are thirsty."), (None, false));
+
+    assert!(gags.remove("^\\[ooc\\]"));
+    assert!(!gags.remove("^\\[ooc\\]"));
+    assert_eq!(check(&mut gags, "other", "[ooc] Dave: yo"), (None, false));
+}
@@ -0,0 +1,375 @@
+//! Lua scripting, via mlua.  Scripts see a global table `mint` with:
+//!
+//! - `mint.send(text)`: send a line to the server (the one whose line is being handled, or else
+//!   the one in front.)
+//! - `mint.echo(text [, window [, style]])`: show a line, in `window` or whichever one is on
+//!   screen, in `style` (as for `/echo -s`) if there is one.
+//! - `mint.on_line([pattern,] function(line, captures) ... end)`: call the function for every
+//!   line from the server, or just the ones matching the regex `pattern`.  `captures` is a list
+//!   of the pattern's groups.
+//! - `mint.on(name, function(captures) ... end)`: call the function when a trigger raises the
+//!   event `name` (see `/trigger -e`.)  The ticker (see `/tick`) raises "tick" on every tick and
+//!   "tick-warning" (with the seconds left) before one.  A world that's gone quiet (see `/idle`)
+//!   raises "server-idle", with the world's name and how many seconds it's been quiet.  A window
+//!   out of sight that's had lines put in it raises "window-activity", with the window's name and
+//!   how many lines it has that haven't been seen (0 once it's been looked at.)
+//! - `mint.alias(name, body)`: the same as `/alias name body`.
+//! - `mint.trigger{pattern = ..., name, priority, fall_through, condition, gag, highlight, flash,
+//!   send, event, copy, clear, echo = {window, text, style}, rename = {window, to}, complete = {list,
+//!   word}, shell = {command, seconds, window}}`: the same as `/trigger`, with the options spelled
+//!   out.  The condition can have spaces in.
+//! - `mint.window(name)`: make a window, if there isn't one by that name already.
+//! - `mint.clear(window)`: empty a window out.
+//! - `mint.rename(window, name)`: give a window a new name.
+//! - `mint.status(segment [, text])`: set a segment of the status bar, or take it away, like
+//!   `/status`.
+//! - `mint.complete(list, word)`: add `word` to the word list `list` for completing, in the window
+//!   of the world whose line is being handled (or the one in front), like `/complete`.
+//! - `mint.every(seconds, line [, times])`: the same as `/repeat`, in the window of the world
+//!   whose line is being handled (or the one in front); `mint.after(seconds, line)` is the same
+//!   as `/after`.
+//!
+//! Nothing a script asks for happens while it's running: the requests are queued up as
+//! ScriptActions, and the client does them once the script's done.  That way Lua never needs to
+//! get hold of the client itself.
+
+use super::trigger::{self, Response, Trigger};
+use crate::action::DEFAULT_TIME_LIMIT;
+use crate::repeat::MIN_INTERVAL;
+use crate::ui::style;
+
+use mlua::{FromLuaMulti, Function, Lua, RegistryKey, Table};
+use regex::Regex;
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Something a script asked the client to do.
+pub enum ScriptAction {
+    Send(String),
+    Echo { text: String, window: Option<String> },
+    Alias { name: String, body: String },
+    Trigger(Trigger),
+    Window(String),
+    Clear(String),
+    Rename { window: String, to: String },
+    Repeat { every: Duration, times: Option<u32>, line: String },
+    Complete { list: String, word: String },
+    /// Set a segment of the status bar, or take it away if there's no text.
+    Status { segment: String, text: Option<String> },
+}
+
+// What the functions in the `mint` table share with Scripts.
+#[derive(Default)]
+struct State {
+    actions: Vec<ScriptAction>,
+    line_handlers: Vec<(Option<Regex>, RegistryKey)>,
+    event_handlers: Vec<(String, RegistryKey)>,
+}
+
+/// The Lua interpreter and everything scripts have set up in it.
+pub struct Scripts {
+    lua: Lua,
+    state: Rc<RefCell<State>>,
+}
+
+impl Scripts {
+    pub fn new() -> Result<Scripts, String> {
+        let scripts = Scripts {
+            lua: Lua::new(),
+            state: Rc::new(RefCell::new(State::default())),
+        };
+        scripts.api().map_err(|e| format!("Couldn't set up Lua: {}", e))?;
+        Ok(scripts)
+    }
+
+    /// Build the `mint` table.
+    fn api(&self) -> mlua::Result<()> {
+        let lua = &self.lua;
+        let mint = lua.create_table()?;
+
+        let state = self.state.clone();
+        mint.set("send", lua.create_function(move |_, text: String| {
+            state.borrow_mut().actions.push(ScriptAction::Send(text));
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("echo", lua.create_function(move |_, (text, window, spec): (String, Option<String>, Option<String>)| {
+            let text = match spec {
+                Some(spec) => style::styled(&text, &spec).map_err(mlua::Error::RuntimeError)?,
+                None => text,
+            };
+            state.borrow_mut().actions.push(ScriptAction::Echo { text, window });
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("on_line", lua.create_function(move |lua, args: mlua::MultiValue| {
+            // Either (handler) or (pattern, handler).
+            let (pattern, handler) = match args.len() {
+                1 => (None, Function::from_lua_multi(args, lua)?),
+                _ => {
+                    let (pattern, handler) = <(String, Function)>::from_lua_multi(args, lua)?;
+                    (Some(regex(&pattern)?), handler)
+                },
+            };
+            let key = lua.create_registry_value(handler)?;
+            state.borrow_mut().line_handlers.push((pattern, key));
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("on", lua.create_function(move |lua, (name, handler): (String, Function)| {
+            let key = lua.create_registry_value(handler)?;
+            state.borrow_mut().event_handlers.push((name, key));
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("alias", lua.create_function(move |_, (name, body): (String, String)| {
+            state.borrow_mut().actions.push(ScriptAction::Alias { name, body });
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("trigger", lua.create_function(move |_, spec: Table| {
+            let trigger = trigger(spec)?;
+            state.borrow_mut().actions.push(ScriptAction::Trigger(trigger));
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("window", lua.create_function(move |_, name: String| {
+            state.borrow_mut().actions.push(ScriptAction::Window(name));
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("clear", lua.create_function(move |_, window: String| {
+            state.borrow_mut().actions.push(ScriptAction::Clear(window));
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("rename", lua.create_function(move |_, (window, to): (String, String)| {
+            state.borrow_mut().actions.push(ScriptAction::Rename { window, to });
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("status", lua.create_function(move |_, (segment, text): (String, Option<String>)| {
+            state.borrow_mut().actions.push(ScriptAction::Status { segment, text });
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("complete", lua.create_function(move |_, (list, word): (String, String)| {
+            state.borrow_mut().actions.push(ScriptAction::Complete { list, word });
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("every", lua.create_function(move |_, (seconds, line, times): (f64, String, Option<u32>)| {
+            let every = interval(seconds)?;
+            state.borrow_mut().actions.push(ScriptAction::Repeat { every, times, line });
+            Ok(())
+        })?)?;
+
+        let state = self.state.clone();
+        mint.set("after", lua.create_function(move |_, (seconds, line): (f64, String)| {
+            let every = interval(seconds)?;
+            state.borrow_mut().actions.push(ScriptAction::Repeat { every, times: Some(1), line });
+            Ok(())
+        })?)?;
+
+        lua.globals().set("mint", mint)
+    }
+
+    /// Run a chunk of Lua.  `name` is what error messages call it.
+    pub fn run(&mut self, code: &str, name: &str) -> Result<(), String> {
+        self.lua.load(code).set_name(name).exec().map_err(|e| e.to_string())
+    }
+
+    /// Run every .lua file in `dir`, in order by name.  Each one that fails gives an error
+    /// message, but doesn't stop the rest.  A directory that doesn't exist just has no scripts
+    /// in it.
+    pub fn load_dir(&mut self, dir: &Path) -> Vec<String> {
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+                .filter(|p| p.extension().is_some_and(|ext| ext == "lua"))
+                .collect(),
+            Err(_) => return vec![],
+        };
+        paths.sort();
+
+        let mut errors = vec![];
+        for path in paths {
+            let result = fs::read_to_string(&path).map_err(|e| e.to_string())
+                .and_then(|code| self.run(&code, &path.display().to_string()));
+            if let Err(e) = result {
+                errors.push(format!("Error in script {}: {}", path.display(), e));
+            }
+        }
+        errors
+    }
+
+    /// Hand a line from the server to the on_line handlers.  Returns error messages from any that
+    /// failed.
+    pub fn line(&mut self, text: &str) -> Vec<String> {
+        // Get the functions out first, so the handlers can add more handlers without running
+        // into our borrow of the state.
+        let handlers: Vec<(Function, Vec<String>)> = {
+            let state = self.state.borrow();
+            state.line_handlers.iter().filter_map(|(pattern, key)| {
+                let captures = match pattern {
+                    Some(pattern) => pattern.captures(text)?.iter().skip(1)
+                        .map(|c| c.map_or(String::new(), |c| c.as_str().to_string()))
+                        .collect(),
+                    None => vec![],
+                };
+                self.lua.registry_value(key).ok().map(|f| (f, captures))
+            }).collect()
+        };
+
+        handlers.into_iter()
+            .filter_map(|(f, captures)| f.call::<_, ()>((text, captures)).err())
+            .map(|e| format!("Error in on_line handler: {}", e))
+            .collect()
+    }
+
+    /// Hand an event raised by a trigger to its handlers.  Returns error messages from any that
+    /// failed.
+    pub fn event(&mut self, name: &str, captures: &[String]) -> Vec<String> {
+        let handlers: Vec<Function> = {
+            let state = self.state.borrow();
+            state.event_handlers.iter()
+                .filter(|(event, _)| event == name)
+                .filter_map(|(_, key)| self.lua.registry_value(key).ok())
+                .collect()
+        };
+
+        handlers.into_iter()
+            .filter_map(|f| f.call::<_, ()>(captures.to_vec()).err())
+            .map(|e| format!("Error in handler for {}: {}", name, e))
+            .collect()
+    }
+
+    /// Everything scripts have asked for since the last time, in the order they asked.
+    pub fn take_actions(&mut self) -> Vec<ScriptAction> {
+        std::mem::take(&mut self.state.borrow_mut().actions)
+    }
+}
+
+fn regex(pattern: &str) -> mlua::Result<Regex> {
+    Regex::new(pattern).map_err(|e| mlua::Error::RuntimeError(format!("Bad pattern: {}", e)))
+}
+
+/// A number of seconds given to mint.every() or mint.after(), as long as it's not too short.
+fn interval(seconds: f64) -> mlua::Result<Duration> {
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(every) if every >= MIN_INTERVAL => Ok(every),
+        _ => Err(mlua::Error::RuntimeError(format!("Not a usable number of seconds: {}", seconds))),
+    }
+}
+
+/// Make a Trigger out of the table given to mint.trigger().
+fn trigger(spec: Table) -> mlua::Result<Trigger> {
+    let pattern: String = spec.get("pattern")?;
+    let pattern = regex(&pattern)?;
+    let condition = spec.get::<_, Option<String>>("condition")?;
+    if let Some(ref condition) = condition {
+        trigger::check_condition(condition).map_err(mlua::Error::RuntimeError)?;
+    }
+
+    let mut responses = vec![];
+    if spec.get::<_, Option<bool>>("gag")?.unwrap_or(false) {
+        responses.push(Response::Gag);
+    }
+    if let Some(spec) = spec.get::<_, Option<String>>("highlight")? {
+        responses.push(Response::Highlight(style::style(&spec).map_err(mlua::Error::RuntimeError)?));
+    }
+    if spec.get::<_, Option<bool>>("flash")?.unwrap_or(false) {
+        responses.push(Response::Flash);
+    }
+    if let Some(name) = spec.get::<_, Option<String>>("event")? {
+        responses.push(Response::Event(name));
+    }
+    if spec.get::<_, Option<bool>>("copy")?.unwrap_or(false) {
+        responses.push(Response::Copy);
+    }
+    if let Some(window) = spec.get::<_, Option<String>>("clear")? {
+        responses.push(Response::Clear(window));
+    }
+    if let Some(echo) = spec.get::<_, Option<Table>>("echo")? {
+        let text: String = echo.get("text")?;
+        let text = match echo.get::<_, Option<String>>("style")? {
+            Some(spec) => style::styled(&text, &spec).map_err(mlua::Error::RuntimeError)?,
+            None => text,
+        };
+        responses.push(Response::Echo { window: echo.get("window")?, text });
+    }
+    if let Some(rename) = spec.get::<_, Option<Table>>("rename")? {
+        responses.push(Response::Rename { window: rename.get("window")?, to: rename.get("to")? });
+    }
+    if let Some(complete) = spec.get::<_, Option<Table>>("complete")? {
+        responses.push(Response::Complete { list: complete.get("list")?, word: complete.get("word")? });
+    }
+    if let Some(shell) = spec.get::<_, Option<Table>>("shell")? {
+        let limit = match shell.get::<_, Option<f64>>("seconds")? {
+            Some(seconds) => Duration::try_from_secs_f64(seconds)
+                .map_err(|_| mlua::Error::RuntimeError(format!("Not a usable number of seconds: {}", seconds)))?,
+            None => DEFAULT_TIME_LIMIT,
+        };
+        responses.push(Response::Shell { command: shell.get("command")?, limit, window: shell.get("window")? });
+    }
+    if let Some(text) = spec.get::<_, Option<String>>("send")? {
+        responses.push(Response::Send(text));
+    }
+
+    Ok(Trigger {
+        name: spec.get::<_, Option<String>>("name")?.unwrap_or_else(|| pattern.as_str().to_string()),
+        pattern,
+        priority: spec.get::<_, Option<i32>>("priority")?.unwrap_or(0),
+        fall_through: spec.get::<_, Option<bool>>("fall_through")?.unwrap_or(false),
+        condition,
+        responses,
+    })
+}
+
+#[test]
+fn lua_api() {
+    let mut scripts = Scripts::new().unwrap();
+    scripts.run(r#"
+        mint.window("chat")
+        mint.on_line("^(\\w+) tells you: (.*)", function(line, caps)
+            mint.echo(caps[1] .. ": " .. caps[2], "chat")
+        end)
+        mint.on("hp", function(caps) if tonumber(caps[1]) < 20 then mint.send("quaff") end end)
+        mint.trigger{pattern = "^HP: (\\d+)", event = "hp", gag = true}
+        mint.trigger{pattern = "^\\[(.*)\\]$", clear = "room", echo = {window = "room", text = "%1", style = "bold"}}
+        mint.rename("chat", "tells")
+        mint.status("target", "Target: rat")
+    "#, "test").unwrap();
+    assert!(scripts.run("mint.trigger{pattern = '('}", "bad").is_err());
+
+    let actions = scripts.take_actions();
+    assert_eq!(actions.len(), 5);
+    assert!(matches!(actions[0], ScriptAction::Window(ref w) if w == "chat"));
+    assert!(matches!(actions[1], ScriptAction::Trigger(ref t) if t.responses.len() == 2));
+    assert!(matches!(actions[2], ScriptAction::Trigger(ref t)
+                     if matches!(t.responses[1], Response::Echo { ref text, .. } if text == "\x1b[1m%1\x1b[0m")));
+    assert!(matches!(actions[3], ScriptAction::Rename { ref window, ref to } if window == "chat" && to == "tells"));
+    assert!(matches!(actions[4], ScriptAction::Status { ref segment, text: Some(ref text) }
+                     if segment == "target" && text == "Target: rat"));
+
+    assert!(scripts.line("Bob says hi").is_empty());
+    assert!(scripts.line("Bob tells you: hi").is_empty());
+    assert!(scripts.event("hp", &["12".to_string()]).is_empty());
+    let actions = scripts.take_actions();
+    assert!(matches!(actions[0], ScriptAction::Echo { ref text, window: Some(_) } if text == "Bob: hi"));
+    assert!(matches!(actions[1], ScriptAction::Send(ref text) if text == "quaff"));
+}
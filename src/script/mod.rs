@@ -0,0 +1,56 @@
+//! Automation: the pieces that let the user make the client do things on its own, like variables
+//! and the expressions used in trigger conditions.
+
+pub mod alias;
+pub mod dice;
+pub mod expr;
+pub mod gag;
+pub mod lua;
+pub mod template;
+pub mod trigger;
+pub mod vars;
+
+/// Fill in a template like "Target: %1 (%2%%)" from a list of arguments (e.g. the capture groups
+/// from a trigger's pattern match.)  `%1` through `%9` are replaced with the corresponding
+/// argument, or nothing if there aren't that many; `%*` is all of them, separated by spaces; `%%`
+/// is a literal percent sign.  A `%` followed by anything else is left alone.
+pub fn substitute(template: &str, args: &[&str]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.peek() {
+            Some('%') => {
+                result.push('%');
+                chars.next();
+            },
+            Some('*') => {
+                result.push_str(&args.join(" "));
+                chars.next();
+            },
+            Some(&d) if d.is_ascii_digit() && d != '0' => {
+                // Can unwrap() since we just checked it's a digit.
+                let n = d.to_digit(10).unwrap() as usize;
+                if let Some(arg) = args.get(n - 1) {
+                    result.push_str(arg);
+                }
+                chars.next();
+            },
+            _ => result.push('%'),
+        }
+    }
+
+    result
+}
+
+#[test]
+fn substitute_args() {
+    assert_eq!(substitute("Target: %1 (%2%%)", &["rat", "40"]), "Target: rat (40%)");
+    assert_eq!(substitute("%3 missing, %x kept", &["a"]), " missing, %x kept");
+    assert_eq!(substitute("say %* (%%*)", &["hi", "there"]), "say hi there (%*)");
+}
@@ -0,0 +1,112 @@
+//! Templates: named bits of text, like emotes or the answers to an application, that the user
+//! sends on purpose with /template, with arguments and variables filled in.  Unlike aliases they
+//! aren't matched against what's typed, and one can be several lines.
+
+use super::substitute;
+use super::vars::Variables;
+
+use std::collections::BTreeMap;
+
+/// What separates the lines of a template, tinyfugue style.
+pub const LINE_SEPARATOR: &str = "%;";
+
+/// Names /template uses for itself, which can't be templates.
+const RESERVED: &[&str] = &["add", "remove", "list"];
+
+/// The user's templates, by name.
+#[derive(Default)]
+pub struct Templates {
+    templates: BTreeMap<String, String>,
+}
+
+impl Templates {
+    pub fn new() -> Templates {
+        Templates { templates: BTreeMap::new() }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates.get(name).map(|body| body.as_str())
+    }
+
+    /// Define (or redefine) a template.
+    pub fn set(&mut self, name: &str, body: &str) -> Result<(), String> {
+        if name.is_empty() || name.contains(char::is_whitespace) || RESERVED.contains(&name) {
+            return Err(format!("Can't use '{}' as a template name", name));
+        }
+        self.templates.insert(name.to_string(), body.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.templates.remove(name)
+    }
+
+    /// All the templates, sorted by name.
+    pub fn list(&self) -> Vec<(&str, &str)> {
+        self.templates.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+    }
+
+    /// The lines a template comes out as: split at each `%;`, with `%{name}` replaced by the
+    /// variable `name` (or nothing, if it isn't set), then %1, %2... and %* from `args` as for
+    /// substitute().  None if there's no such template.
+    pub fn expand(&self, name: &str, args: &[&str], vars: &Variables) -> Option<Vec<String>> {
+        let body = self.templates.get(name)?;
+        Some(body.split(LINE_SEPARATOR)
+            .map(|line| substitute(&fill_variables(line, vars), args))
+            .collect())
+    }
+}
+
+/// Replace `%{name}` with variables.  Percent signs in the values are doubled, so that
+/// substitute() puts them back the way they were instead of taking them for arguments.
+fn fill_variables(template: &str, vars: &Variables) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(at) = rest.find('%') {
+        result.push_str(&rest[..at]);
+        rest = &rest[at..];
+        if rest.starts_with("%%") {
+            result.push_str("%%");
+            rest = &rest[2..];
+            continue;
+        }
+        let end = match rest.strip_prefix("%{").and_then(|r| r.find('}')) {
+            Some(end) => end,
+            None => {
+                result.push('%');
+                rest = &rest[1..];
+                continue;
+            },
+        };
+        let value = vars.get(&rest[2..2 + end]).unwrap_or("");
+        result.push_str(&value.replace('%', "%%"));
+        rest = &rest[3 + end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[test]
+fn template_expansion() {
+    let mut vars = Variables::new();
+    vars.set("me".to_string(), "Ash".to_string());
+    vars.set("mood".to_string(), "100%".to_string());
+
+    let mut templates = Templates::new();
+    templates.set("greet", "bow %1").unwrap();
+    templates.set("apply", "say I am %{me}, %{age} and %{mood} sure.%;say Sponsor: %*%;say %%{me}").unwrap();
+    assert!(templates.set("add", "nope").is_err());
+
+    assert_eq!(templates.expand("greet", &["Bob"], &vars), Some(vec!["bow Bob".to_string()]));
+    assert_eq!(templates.expand("apply", &["Bob", "%{me}"], &vars), Some(vec![
+        "say I am Ash,  and 100% sure.".to_string(),
+        "say Sponsor: Bob %{me}".to_string(),
+        "say %{me}".to_string(),
+    ]));
+    assert_eq!(templates.expand("nope", &[], &vars), None);
+
+    assert!(templates.remove("greet").is_some());
+    assert_eq!(templates.list().len(), 1);
+}
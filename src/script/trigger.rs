@@ -0,0 +1,329 @@
+//! Triggers: patterns matched against every line from the server, which can gag the line,
+//! highlight it, send something back, raise an event of their own for something else to pick up,
+//! or look after other windows (clearing them, putting lines of their own in them, renaming
+//! them), e.g. to keep a dashboard up to date.  A trigger can have a condition on the variables
+//! too, so that it only goes off when, say, autoheal is on and hp is low.
+
+use super::expr;
+use super::substitute;
+use super::vars::Variables;
+use crate::line::Line;
+use crate::ui::ansi::Style;
+
+use regex::Regex;
+
+use std::time::Duration;
+
+/// What a trigger does when its pattern matches.
+#[derive(Clone, Debug)]
+pub enum Response {
+    /// Don't show the line.
+    Gag,
+    /// Show the line in this style.
+    Highlight(Style),
+    /// Flash the screen when the line's shown.
+    Flash,
+    /// Send this back, with %1, %2... filled in from the pattern's capture groups.  Client
+    /// commands (starting with "/" before anything's filled in) are run instead of sent.
+    Send(String),
+    /// Raise an event with this name and the capture groups.
+    Event(String),
+    /// Copy the line to the clipboard.
+    Copy,
+    /// Empty a window out.
+    Clear(String),
+    /// Put a line of the client's own in a window, with %1, %2... filled in.  It can have SGR
+    /// sequences in it (e.g. from ui::style.)
+    Echo { window: String, text: String },
+    /// Give a window a new name, with %1, %2... filled in.
+    Rename { window: String, to: String },
+    /// Add a word, with %1, %2... filled in, to one of the word lists for completing in the
+    /// window of the world the line came from.
+    Complete { list: String, word: String },
+    /// Run a shell command in the background (see action.rs), with %1, %2... filled in, quoted
+    /// so the shell takes each as one word, whatever the server put in it.  It's killed if it
+    /// takes longer than `limit`.  What it prints is sent back to the world the line came from,
+    /// like a Send, or put in `window` if there is one.
+    Shell { command: String, limit: Duration, window: Option<String> },
+}
+
+pub struct Trigger {
+    pub name: String,
+    pub pattern: Regex,
+    /// Higher priorities are checked first.
+    pub priority: i32,
+    /// Let triggers with lower priorities have a go at the line too, even if this one matched.
+    pub fall_through: bool,
+    /// An expression (see script::expr) that has to come out true for the trigger to go off,
+    /// where %1, %2... are the pattern's groups, e.g. "autoheal=1 && %1<50".
+    pub condition: Option<String>,
+    pub responses: Vec<Response>,
+}
+
+/// Check a trigger's condition will parse, so mistakes show up when the trigger's made rather
+/// than never going off.
+pub fn check_condition(condition: &str) -> Result<(), String> {
+    expr::parse(condition).map(|_| ()).map_err(|e| format!("Bad condition: {}", e))
+}
+
+/// Things a line set off that the trigger module can't do by itself, since it doesn't know about
+/// connections or events.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fired {
+    Send(String),
+    /// A Send that was a client command to begin with, so isn't sent but run.
+    Command(String),
+    Event { name: String, captures: Vec<String> },
+    Copy(String),
+    Clear(String),
+    Echo { window: String, text: String },
+    Rename { window: String, to: String },
+    Complete { list: String, word: String },
+    Shell { command: String, limit: Duration, window: Option<String> },
+}
+
+/// Put a capture group in single quotes for the shell, so nothing in it gets taken as more of the
+/// command.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// All the triggers, kept in the order they're checked in.
+#[derive(Default)]
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+}
+
+impl Triggers {
+    pub fn new() -> Triggers {
+        Triggers { triggers: vec![] }
+    }
+
+    /// Add a trigger, replacing any other with the same name.  Among triggers with the same
+    /// priority, older ones are checked first.
+    pub fn add(&mut self, trigger: Trigger) {
+        self.remove(&trigger.name);
+        let index = self.triggers.iter().position(|t| t.priority < trigger.priority)
+            .unwrap_or(self.triggers.len());
+        self.triggers.insert(index, trigger);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.triggers.len();
+        self.triggers.retain(|t| t.name != name);
+        self.triggers.len() != before
+    }
+
+    /// Remove every trigger.  Returns how many there were.
+    pub fn clear(&mut self) -> usize {
+        std::mem::take(&mut self.triggers).len()
+    }
+
+    /// The triggers in the order they're checked.
+    pub fn list(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    /// Run a line past the triggers.  Gags and highlights are done to the line itself; sends,
+    /// events and what's to be done to windows are handed back for the caller to deal with, in
+    /// order.  Checking stops at the first trigger that matches (and whose condition, if it has
+    /// one, is true) without falling through.  A condition that can't be worked out, e.g.
+    /// because a group that should be a number isn't, counts as false.
+    pub fn check(&self, line: &mut Line, vars: &Variables) -> Vec<Fired> {
+        let mut fired = vec![];
+
+        for trigger in self.triggers.iter() {
+            let captures: Vec<String> = match trigger.pattern.captures(line.text()) {
+                Some(caps) => caps.iter().skip(1)
+                    .map(|c| c.map_or(String::new(), |c| c.as_str().to_string()))
+                    .collect(),
+                None => continue,
+            };
+
+            let args: Vec<&str> = captures.iter().map(|c| c.as_str()).collect();
+            if let Some(ref condition) = trigger.condition {
+                let met = expr::parse(condition).and_then(|e| e.is_true(vars, &args));
+                if met != Ok(true) {
+                    continue;
+                }
+            }
+            for response in trigger.responses.iter() {
+                match response {
+                    Response::Gag => line.gag = true,
+                    Response::Highlight(style) => line.highlight = Some(*style),
+                    Response::Flash => line.flash = true,
+                    Response::Send(text) if text.starts_with('/') => {
+                        fired.push(Fired::Command(substitute(text, &args)));
+                    },
+                    Response::Send(text) => fired.push(Fired::Send(substitute(text, &args))),
+                    Response::Event(name) => {
+                        fired.push(Fired::Event { name: name.clone(), captures: captures.clone() });
+                    },
+                    Response::Copy => fired.push(Fired::Copy(line.text().to_string())),
+                    Response::Clear(window) => fired.push(Fired::Clear(window.clone())),
+                    Response::Echo { window, text } => {
+                        fired.push(Fired::Echo { window: window.clone(), text: substitute(text, &args) });
+                    },
+                    Response::Rename { window, to } => {
+                        fired.push(Fired::Rename { window: window.clone(), to: substitute(to, &args) });
+                    },
+                    Response::Complete { list, word } => {
+                        fired.push(Fired::Complete { list: list.clone(), word: substitute(word, &args) });
+                    },
+                    Response::Shell { command, limit, window } => {
+                        let quoted: Vec<String> = args.iter().map(|arg| shell_quote(arg)).collect();
+                        let quoted: Vec<&str> = quoted.iter().map(|arg| arg.as_str()).collect();
+                        fired.push(Fired::Shell { command: substitute(command, &quoted), limit: *limit, window: window.clone() });
+                    },
+                }
+            }
+
+            if !trigger.fall_through {
+                break;
+            }
+        }
+
+        fired
+    }
+}
+
+#[test]
+fn trigger_priority() {
+    let trigger = |name: &str, pattern: &str, priority, fall_through, responses| Trigger {
+        name: name.to_string(),
+        pattern: Regex::new(pattern).unwrap(),
+        priority,
+        fall_through,
+        condition: None,
+        responses,
+    };
+
+    let mut triggers = Triggers::new();
+    triggers.add(trigger("spam", "^\\[OOC\\]", 0, false, vec![Response::Gag]));
+    triggers.add(trigger("hp", "^HP: (\\d+)", 10, true, vec![Response::Event("hp".to_string()), Response::Flash]));
+    triggers.add(trigger("heal", "^HP: (\\d+)", 5, false, vec![Response::Send("say %1 hp".to_string())]));
+    triggers.add(trigger("low", "^HP: 1?\\d$", 5, false, vec![Response::Send("flee".to_string())]));
+    assert_eq!(triggers.list().iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+               vec!["hp", "heal", "low", "spam"]);
+
+    // "hp" falls through to "heal", which doesn't, so "low" never gets a look.
+    let vars = Variables::new();
+    let source = crate::net::ConnectionID::new(1, 0);
+    let mut line = Line::server("HP: 12", source);
+    assert_eq!(triggers.check(&mut line, &vars), vec![
+        Fired::Event { name: "hp".to_string(), captures: vec!["12".to_string()] },
+        Fired::Send("say 12 hp".to_string()),
+    ]);
+    assert!(!line.gag);
+    assert!(line.flash);
+
+    let mut line = Line::server("[OOC] Bob: lol", source);
+    assert!(triggers.check(&mut line, &vars).is_empty());
+    assert!(line.gag);
+    assert!(!line.flash);
+
+    assert!(triggers.remove("heal"));
+    assert_eq!(triggers.check(&mut Line::server("HP: 12", source), &vars).len(), 2);
+}
+
+#[test]
+fn trigger_conditions() {
+    let mut triggers = Triggers::new();
+    triggers.add(Trigger {
+        name: "autoheal".to_string(),
+        pattern: Regex::new("^HP: (\\d+)").unwrap(),
+        priority: 0,
+        fall_through: false,
+        condition: Some("autoheal=1 && %1<50".to_string()),
+        responses: vec![Response::Send("quaff".to_string())],
+    });
+    assert!(check_condition("autoheal=1 && %1<50").is_ok());
+    assert!(check_condition("autoheal=").is_err());
+
+    let mut vars = Variables::new();
+    let source = crate::net::ConnectionID::new(1, 0);
+    assert!(triggers.check(&mut Line::server("HP: 12", source), &vars).is_empty());
+    vars.set("autoheal".to_string(), "1".to_string());
+    assert_eq!(triggers.check(&mut Line::server("HP: 12", source), &vars), vec![Fired::Send("quaff".to_string())]);
+    assert!(triggers.check(&mut Line::server("HP: 80", source), &vars).is_empty());
+
+    // A group's only ever compared, so what the server sends can't change the condition.
+    triggers.add(Trigger {
+        name: "friend".to_string(),
+        pattern: Regex::new("^(.*) tells you").unwrap(),
+        priority: 0,
+        fall_through: false,
+        condition: Some("%1==\"Alice\"".to_string()),
+        responses: vec![Response::Send("grin".to_string())],
+    });
+    assert_eq!(triggers.check(&mut Line::server("Alice tells you", source), &vars), vec![Fired::Send("grin".to_string())]);
+    assert!(triggers.check(&mut Line::server("1||1 tells you", source), &vars).is_empty());
+    assert!(triggers.check(&mut Line::server("\" tells you", source), &vars).is_empty());
+}
+
+#[test]
+fn trigger_window_responses() {
+    let mut triggers = Triggers::new();
+    triggers.add(Trigger {
+        name: "room".to_string(),
+        pattern: Regex::new("^\\[(.*)\\]$").unwrap(),
+        priority: 0,
+        fall_through: false,
+        condition: None,
+        responses: vec![
+            Response::Clear("room".to_string()),
+            Response::Echo { window: "room".to_string(), text: "You're in %1.".to_string() },
+            Response::Rename { window: "map".to_string(), to: "map: %1".to_string() },
+            Response::Complete { list: "areas".to_string(), word: "%1".to_string() },
+        ],
+    });
+
+    let mut line = Line::server("[Town Square]", crate::net::ConnectionID::new(1, 0));
+    assert_eq!(triggers.check(&mut line, &Variables::new()), vec![
+        Fired::Clear("room".to_string()),
+        Fired::Echo { window: "room".to_string(), text: "You're in Town Square.".to_string() },
+        Fired::Rename { window: "map".to_string(), to: "map: Town Square".to_string() },
+        Fired::Complete { list: "areas".to_string(), word: "Town Square".to_string() },
+    ]);
+}
+
+#[test]
+fn trigger_sends_and_commands() {
+    let mut triggers = Triggers::new();
+    triggers.add(Trigger {
+        name: "echo".to_string(),
+        pattern: Regex::new("^(\\w+) says (.*)$").unwrap(),
+        priority: 0,
+        fall_through: false,
+        condition: None,
+        responses: vec![Response::Send("%2".to_string()), Response::Send("/echo %1 spoke".to_string())],
+    });
+
+    // Only the second started out as a command; the first starts with "/" because of what Bob said.
+    let mut line = Line::server("Bob says /sh rm -rf ~", crate::net::ConnectionID::new(1, 0));
+    assert_eq!(triggers.check(&mut line, &Variables::new()), vec![
+        Fired::Send("/sh rm -rf ~".to_string()),
+        Fired::Command("/echo Bob spoke".to_string()),
+    ]);
+}
+
+#[test]
+fn trigger_shell_commands() {
+    let limit = Duration::from_secs(5);
+    let mut triggers = Triggers::new();
+    triggers.add(Trigger {
+        name: "lookup".to_string(),
+        pattern: Regex::new("^(\\w+) asks about (.*)$").unwrap(),
+        priority: 0,
+        fall_through: false,
+        condition: None,
+        responses: vec![Response::Shell { command: "grep -i %2 notes.txt".to_string(), limit, window: None }],
+    });
+
+    let mut line = Line::server("Bob asks about Bob's $(rm -rf ~)", crate::net::ConnectionID::new(1, 0));
+    assert_eq!(triggers.check(&mut line, &Variables::new()), vec![Fired::Shell {
+        command: "grep -i 'Bob'\\''s $(rm -rf ~)' notes.txt".to_string(),
+        limit,
+        window: None,
+    }]);
+}
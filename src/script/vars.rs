@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+/// The user's variables, e.g. `hp` or `autoheal`, which triggers, conditions and so on can read
+/// and set.  Everything is stored as a string, tinyfugue style; it's up to whoever reads a
+/// variable to decide if it's a number.
+#[derive(Default)]
+pub struct Variables {
+    vars: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new() -> Variables {
+        Variables { vars: HashMap::new() }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(|v| v.as_str())
+    }
+
+    pub fn set(&mut self, name: String, value: String) {
+        self.vars.insert(name, value);
+    }
+
+    pub fn unset(&mut self, name: &str) -> Option<String> {
+        self.vars.remove(name)
+    }
+
+    /// All the variables, sorted by name.
+    pub fn list(&self) -> Vec<(&str, &str)> {
+        let mut all: Vec<(&str, &str)> = self.vars.iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        all.sort();
+        all
+    }
+}
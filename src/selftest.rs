@@ -0,0 +1,96 @@
+//! Quick checks on the parts of the client most likely to be behind a bug report: the regex
+//! engine, the clock, and the config directory and what's in it.  The terminal UI has checks of
+//! its own (see TermUiManager::self_test.)  None of them change anything that's there already.
+
+use crate::config;
+
+use regex::Regex;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the timer check sleeps for, and how far off it can be before it counts as a failure.
+const TIMER_SLEEP: Duration = Duration::from_millis(50);
+const TIMER_TOLERANCE: Duration = Duration::from_millis(40);
+
+/// How one check went: what it found if it went fine, or what's wrong if it didn't.
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Result<String, String>,
+}
+
+impl Check {
+    pub fn new(name: &'static str, outcome: Result<String, String>) -> Check {
+        Check { name, outcome }
+    }
+}
+
+/// Everything that doesn't need a UI.
+pub fn run() -> Vec<Check> {
+    vec![
+        Check::new("regex", regex()),
+        Check::new("timer", timer()),
+        Check::new("config", config()),
+    ]
+}
+
+/// The sort of pattern triggers use, with captures and non-ASCII text.
+fn regex() -> Result<String, String> {
+    let pattern = Regex::new(r"^(\w+) tells you, '(.*)'$").map_err(|e| e.to_string())?;
+    let captures = pattern.captures("Zoë tells you, 'héllo'").ok_or("The pattern didn't match")?;
+    match (&captures[1], &captures[2]) {
+        ("Zoë", "héllo") => Ok("Patterns match, captures and all.".to_string()),
+        (name, text) => Err(format!("Captured {:?} and {:?} instead of \"Zoë\" and \"héllo\"", name, text)),
+    }
+}
+
+/// Sleep for a bit, and see whether it took as long as it should've.
+fn timer() -> Result<String, String> {
+    let start = Instant::now();
+    thread::sleep(TIMER_SLEEP);
+    let took = start.elapsed();
+    let off = took.checked_sub(TIMER_SLEEP).ok_or("Woke up early")?;
+    let report = format!("A {}ms sleep took {:.1}ms.", TIMER_SLEEP.as_millis(), took.as_secs_f64() * 1000.0);
+    if off > TIMER_TOLERANCE { Err(report) } else { Ok(report) }
+}
+
+/// Whether the config directory's there and can be written to, and whether the scripts in it
+/// would compile.
+fn config() -> Result<String, String> {
+    let dir = config::ensure_config_dir().ok_or("Couldn't find or make the config directory")?;
+    let probe = dir.join(format!(".selftest-{}", std::process::id()));
+    fs::write(&probe, "").and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| format!("Can't write to {}: {}", dir.display(), e))?;
+
+    let lua = mlua::Lua::new();
+    let mut scripts = 0;
+    let mut broken = vec![];
+    if let Ok(entries) = fs::read_dir(dir.join("scripts")) {
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "lua") {
+                continue;
+            }
+            scripts += 1;
+            let compiled = fs::read_to_string(&path).map_err(|e| e.to_string())
+                .and_then(|code| lua.load(&code).into_function().map(|_| ()).map_err(|e| e.to_string()));
+            if let Err(e) = compiled {
+                broken.push(format!("{}: {}", path.display(), e));
+            }
+        }
+    }
+
+    if broken.is_empty() {
+        Ok(format!("{} is writable, and its {} script(s) compile.", dir.display(), scripts))
+    } else {
+        Err(format!("Scripts that don't compile: {}", broken.join("; ")))
+    }
+}
+
+#[test]
+fn selftest_checks() {
+    assert!(regex().is_ok());
+    // The clock can't be relied on to be accurate on a busy test machine, but it can be relied on
+    // to not go backwards.
+    assert!(timer().map_or_else(|e| e, |ok| ok).contains("sleep took"));
+}
@@ -0,0 +1,189 @@
+//! Sessions: one for each connection, with the world it's connected to and the window its text
+//! goes in.
+
+use crate::log::SessionLog;
+use crate::net::ConnectionID;
+use crate::net::stack::CommandStack;
+use crate::timer::TimerID;
+use crate::world::World;
+
+use std::collections::BTreeSet;
+use std::time::Instant;
+
+/// Everything we're keeping track of for the connection to a world.
+pub struct Session {
+    pub world: World,
+    pub cid: ConnectionID,
+    /// The window the server's text goes to.  Whatever's typed in that window goes to the server.
+    pub window: String,
+    pub stack: CommandStack,
+    pub log: Option<SessionLog>,
+    /// The server's echoing what's typed itself (see Event::ServerEcho), which means it's asking
+    /// for a password, so what's sent isn't logged.
+    pub server_echo: bool,
+    /// When the server last sent anything, whether it's been quiet for long enough since then to
+    /// count as idle (see world::IdlePolicy), and the timer for when it next might have been.
+    pub last_heard: Instant,
+    pub idle: bool,
+    pub idle_timer: Option<TimerID>,
+    /// The other windows its triggers and scripts have put things in, to make again next time
+    /// (see layouts.)
+    pub windows: BTreeSet<String>,
+}
+
+impl Session {
+    pub fn new(world: World, cid: ConnectionID, window: String) -> Session {
+        Session {
+            stack: CommandStack::new(world.prompt.clone()),
+            world,
+            cid,
+            window,
+            log: None,
+            server_echo: false,
+            last_heard: Instant::now(),
+            idle: false,
+            idle_timer: None,
+            windows: BTreeSet::new(),
+        }
+    }
+
+    /// Note that the server's said something.  Returns whether it was idle until now.
+    pub fn heard(&mut self, now: Instant) -> bool {
+        self.last_heard = now;
+        std::mem::replace(&mut self.idle, false)
+    }
+
+    /// When the server will have been quiet for long enough to count as idle, unless it says
+    /// something first.  None if it already is, or never will be.
+    pub fn idle_due(&self) -> Option<Instant> {
+        match self.idle {
+            true => None,
+            false => self.world.idle.after.map(|after| self.last_heard + after),
+        }
+    }
+
+    /// Start logging, if the world has a log file.
+    pub fn open_log(&mut self) -> Result<(), String> {
+        if let Some(ref path) = self.world.log_file {
+            let mut log = SessionLog::open(path, self.world.log_format)
+                .map_err(|e| format!("Couldn't open log file {}: {}", path.display(), e))?;
+            log.log_sent = self.world.log_sent;
+            self.log = Some(log);
+        }
+        Ok(())
+    }
+}
+
+/// All the sessions there are, and which of them is in front.
+pub struct Sessions {
+    sessions: Vec<Session>,
+    // The session that gets what's typed in windows that don't belong to any session, like the
+    // debug window: the one the user looked at last.
+    foreground: Option<ConnectionID>,
+}
+
+impl Sessions {
+    pub fn new() -> Sessions {
+        Sessions {
+            sessions: vec![],
+            foreground: None,
+        }
+    }
+
+    /// Add a session, which becomes the foreground one.
+    pub fn add(&mut self, session: Session) {
+        self.foreground = Some(session.cid);
+        self.sessions.push(session);
+    }
+
+    pub fn remove(&mut self, cid: ConnectionID) -> Option<Session> {
+        let index = self.sessions.iter().position(|s| s.cid == cid)?;
+        if self.foreground == Some(cid) {
+            self.foreground = None;
+        }
+        Some(self.sessions.remove(index))
+    }
+
+    /// The session whose window `window` is, if there is one.  Unlike for_window(), this doesn't
+    /// fall back on the foreground session.
+    pub fn by_window(&self, window: &str) -> Option<&Session> {
+        self.sessions.iter().find(|s| s.window == window)
+    }
+
+    pub fn by_connection(&mut self, cid: ConnectionID) -> Option<&mut Session> {
+        self.sessions.iter_mut().find(|s| s.cid == cid)
+    }
+
+    /// The session that input in `window` should go to: the one whose window it is, or failing
+    /// that, the foreground session.  The window's session (if there is one) becomes the
+    /// foreground session.
+    pub fn for_window(&mut self, window: &str) -> Option<&mut Session> {
+        if let Some(session) = self.sessions.iter().find(|s| s.window == window) {
+            self.foreground = Some(session.cid);
+        }
+        let foreground = self.foreground?;
+        self.by_connection(foreground)
+    }
+
+    /// A window name for a new session that no other session is using: `wanted`, or if that's
+    /// taken, `wanted` with a number after it.
+    pub fn free_window_name(&self, wanted: &str) -> String {
+        let taken = |name: &str| self.sessions.iter().any(|s| s.window == name);
+        if !taken(wanted) {
+            return wanted.to_string();
+        }
+        (2..).map(|n| format!("{}-{}", wanted, n)).find(|name| !taken(name)).unwrap()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Session> {
+        self.sessions.iter_mut()
+    }
+}
+
+impl Default for Sessions {
+    fn default() -> Sessions {
+        Sessions::new()
+    }
+}
+
+#[test]
+fn sessions_by_window() {
+    let world = |name: &str| World::new(name.to_string(), format!("{}:4000", name));
+    let (a, b) = (ConnectionID::new(1, 0), ConnectionID::new(2, 0));
+    let mut sessions = Sessions::new();
+    sessions.add(Session::new(world("a"), a, "a".to_string()));
+    sessions.add(Session::new(world("b"), b, "b".to_string()));
+    assert_eq!(sessions.free_window_name("a"), "a-2");
+    assert_eq!(sessions.free_window_name("c"), "c");
+
+    // Windows without a session of their own go to whichever one was used last.
+    assert_eq!(sessions.for_window("debug").map(|s| s.cid), Some(b));
+    assert_eq!(sessions.for_window("a").map(|s| s.cid), Some(a));
+    assert_eq!(sessions.for_window("debug").map(|s| s.cid), Some(a));
+
+    sessions.remove(a);
+    assert!(sessions.for_window("debug").is_none());
+    assert_eq!(sessions.for_window("b").map(|s| s.cid), Some(b));
+}
+
+#[test]
+fn idle_sessions() {
+    use std::time::Duration;
+    let mut session = Session::new(World::new("a".to_string(), "a:4000".to_string()), ConnectionID::new(1, 0),
+                                   "a".to_string());
+    assert_eq!(session.idle_due(), None);
+
+    let now = Instant::now();
+    session.world.idle.after = Some(Duration::from_secs(60));
+    assert!(!session.heard(now));
+    assert_eq!(session.idle_due(), Some(now + Duration::from_secs(60)));
+
+    session.idle = true;
+    assert_eq!(session.idle_due(), None);
+    assert!(session.heard(now + Duration::from_secs(90)));
+    assert_eq!(session.idle_due(), Some(now + Duration::from_secs(150)));
+}
@@ -0,0 +1,104 @@
+//! A ticker for MUDs where things happen on a tick (regaining hit points, the weather changing)
+//! every so many seconds.  It counts down to the next one, which is in the status bar, and can
+//! give a warning a few seconds before, to time things by.  Servers don't say when a tick is,
+//! but most show something that gives it away ("The sun rises."), so a trigger can line the
+//! ticker up with the real thing: /trigger ^The sun rises = /tick sync
+
+use std::time::{Duration, Instant};
+
+/// What's happened since the ticker was last asked.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tock {
+    /// The tick's this close.
+    Warning(Duration),
+    Tick,
+}
+
+pub struct Ticker {
+    pub length: Duration,
+    /// How long before each tick to give a warning, if at all.
+    pub warning: Option<Duration>,
+    next: Instant,
+    warned: bool,
+}
+
+impl Ticker {
+    /// A ticker whose first tick is `length` from `now`.
+    pub fn new(length: Duration, now: Instant) -> Ticker {
+        Ticker { length, warning: None, next: now + length, warned: false }
+    }
+
+    /// A tick's just happened, so the next one is a whole tick from `now`.
+    pub fn sync(&mut self, now: Instant) {
+        self.next = now + self.length;
+        self.warned = false;
+    }
+
+    /// How long until the next tick.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.next.saturating_duration_since(now)
+    }
+
+    /// Catch up to `now`, saying what's happened since the last time.  Ticks missed while
+    /// nobody was asking only count once.
+    pub fn poll(&mut self, now: Instant) -> Vec<Tock> {
+        let mut tocks = vec![];
+        if now >= self.next {
+            tocks.push(Tock::Tick);
+            while self.next <= now {
+                self.next += self.length;
+            }
+            self.warned = false;
+        }
+        if let Some(warning) = self.warning {
+            let left = self.remaining(now);
+            if !self.warned && left <= warning {
+                tocks.push(Tock::Warning(left));
+                self.warned = true;
+            }
+        }
+        tocks
+    }
+
+    /// When it's next worth asking: when the countdown next goes down by a whole second, which
+    /// is when warnings (in whole seconds) and ticks fall as well.
+    pub fn next_wake(&self, now: Instant) -> Duration {
+        match self.remaining(now).subsec_nanos() {
+            0 => Duration::from_secs(1),
+            nanos => Duration::from_nanos(nanos as u64),
+        }
+    }
+
+    /// The countdown, e.g. "tick 23s", rounded up like a clock counting down.
+    pub fn describe(&self, now: Instant) -> String {
+        let left = self.remaining(now);
+        let secs = left.as_secs() + if left.subsec_nanos() > 0 { 1 } else { 0 };
+        format!("tick {}s", secs)
+    }
+}
+
+#[test]
+fn ticking() {
+    let start = Instant::now();
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mut ticker = Ticker::new(Duration::from_secs(60), start);
+    ticker.warning = Some(Duration::from_secs(5));
+
+    assert_eq!(ticker.poll(at(10)), vec![]);
+    assert_eq!(ticker.describe(at(10)), "tick 50s");
+    assert_eq!(ticker.describe(start + Duration::from_millis(10_500)), "tick 50s");
+    assert_eq!(ticker.next_wake(start + Duration::from_millis(10_250)), Duration::from_millis(750));
+
+    assert_eq!(ticker.poll(at(55)), vec![Tock::Warning(Duration::from_secs(5))]);
+    assert_eq!(ticker.poll(at(57)), vec![]);
+    assert_eq!(ticker.poll(at(60)), vec![Tock::Tick]);
+    assert_eq!(ticker.describe(at(60)), "tick 60s");
+
+    // Asleep through a couple of ticks, and close to the next one on waking.
+    assert_eq!(ticker.poll(at(237)), vec![Tock::Tick, Tock::Warning(Duration::from_secs(3))]);
+
+    // Lined up with the server's tick, which was a bit early.
+    ticker.sync(at(238));
+    assert_eq!(ticker.describe(at(238)), "tick 60s");
+    assert_eq!(ticker.poll(at(293)), vec![Tock::Warning(Duration::from_secs(5))]);
+}
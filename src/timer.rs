@@ -0,0 +1,145 @@
+use crate::error::MintError;
+use crate::meta::{Event, EventSource, ReadinessPager, Listener};
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+pub type TimerID = usize;
+
+/// How often the timer thread checks in with the EventManager when it has nothing to do.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Requests from the main thread to the timer thread.
+enum TimerRequest {
+    Schedule(TimerID, Instant),
+    Cancel(TimerID),
+}
+
+/// EventSource for things that need to happen at a particular time.  Schedule a timer and you get
+/// back an ID; when the time comes, an Event::TimerFired with that ID comes out of the
+/// EventManager.  What the timer was *for* is the business of whoever scheduled it.
+pub struct Timers {
+    last_id: TimerID,
+
+    request_tx: mpsc::Sender<TimerRequest>,
+    // In an Option so it can be moved into the listener, like in TcpConnectionManager.
+    request_rx: Option<mpsc::Receiver<TimerRequest>>,
+
+    fired_tx: mpsc::Sender<TimerID>,
+    fired_rx: mpsc::Receiver<TimerID>,
+}
+
+impl Timers {
+    pub fn new() -> Timers {
+        let (request_tx, request_rx) = mpsc::channel();
+        let (fired_tx, fired_rx) = mpsc::channel();
+
+        Timers {
+            last_id: 0,
+            request_tx,
+            request_rx: Some(request_rx),
+            fired_tx,
+            fired_rx,
+        }
+    }
+
+    /// Schedule a timer to go off at `when`.  If that's already past, it goes off right away.
+    pub fn schedule(&mut self, when: Instant) -> TimerID {
+        self.last_id += 1;
+        self.request_tx.send(TimerRequest::Schedule(self.last_id, when))
+            .expect("Timers internal error: Couldn't send() request to timer thread");
+        self.last_id
+    }
+
+    /// Cancel a timer.  Cancelling one that has already gone off does nothing.
+    pub fn cancel(&mut self, id: TimerID) {
+        self.request_tx.send(TimerRequest::Cancel(id))
+            .expect("Timers internal error: Couldn't send() request to timer thread");
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Timers {
+        Timers::new()
+    }
+}
+
+impl EventSource for Timers {
+    fn get_listeners(&mut self) -> Vec<Box<dyn Listener>> {
+        match self.request_rx.take() {
+            Some(rx) => vec![Box::new(TimerListener {
+                rx,
+                fired_tx: self.fired_tx.clone(),
+                pending: HashMap::new(),
+            })],
+            None => panic!("Cannot call get_listeners() on Timers more than once."),
+        }
+    }
+
+    fn process(&mut self) -> Vec<Event> {
+        self.fired_rx.try_iter().map(|id| Event::TimerFired { id }).collect()
+    }
+}
+
+/// The timer thread.  It sleeps until either the next timer is due or a request comes in,
+/// whichever happens first.
+struct TimerListener {
+    rx: mpsc::Receiver<TimerRequest>,
+    fired_tx: mpsc::Sender<TimerID>,
+    pending: HashMap<TimerID, Instant>,
+}
+
+impl Listener for TimerListener {
+    fn name(&self) -> String {
+        "timer".to_string()
+    }
+
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        Some(HEARTBEAT_INTERVAL)
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        loop {
+            // Sleep until the next timer is due, but never longer than the heartbeat interval.
+            let now = Instant::now();
+            let wait = match self.pending.values().min() {
+                Some(due) if *due <= now => Duration::from_secs(0),
+                Some(due) => (*due - now).min(HEARTBEAT_INTERVAL),
+                None => HEARTBEAT_INTERVAL,
+            };
+
+            let request = if wait > Duration::from_secs(0) {
+                match self.rx.recv_timeout(wait) {
+                    Ok(request) => Some(request),
+                    Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            } else {
+                None
+            };
+
+            match request {
+                Some(TimerRequest::Schedule(id, when)) => { self.pending.insert(id, when); },
+                Some(TimerRequest::Cancel(id)) => { self.pending.remove(&id); },
+                None if !self.pending.values().any(|due| *due <= Instant::now()) => {
+                    flag.heartbeat();
+                },
+                None => {
+                    // Something's due.  Fire everything that is, not just the one we woke up
+                    // for, in case several were scheduled for (nearly) the same time.
+                    let now = Instant::now();
+                    let due: Vec<TimerID> = self.pending.iter()
+                        .filter(|(_, when)| **when <= now)
+                        .map(|(id, _)| *id)
+                        .collect();
+                    for id in due {
+                        self.pending.remove(&id);
+                        self.fired_tx.send(id)?;
+                    }
+                    flag.ok();
+                },
+            }
+        }
+    }
+}
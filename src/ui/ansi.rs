@@ -0,0 +1,387 @@
+use crate::ui::term::caps::ColorDepth;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A color, in whichever of the ways ANSI escapes have of naming one.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Color {
+    /// Whatever the terminal's default is.
+    Default,
+    /// The 16 basic colors; 8-15 are the bright versions of 0-7.
+    Ansi(u8),
+    /// xterm's 256-color palette.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+// xterm's idea of what the 16 basic colors look like.
+const ANSI_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+impl Color {
+    /// The color as red, green and blue, going by xterm's palette for the ones that are only
+    /// numbers.  None for the default, which is up to whoever's showing the text.
+    pub fn rgb(&self) -> Option<(u8, u8, u8)> {
+        let level = |l: u8| if l == 0 { 0 } else { 55 + 40 * l };
+        match *self {
+            Color::Default => None,
+            Color::Ansi(n) | Color::Indexed(n) if n < 16 => Some(ANSI_RGB[n as usize]),
+            Color::Ansi(_) => None,
+            Color::Indexed(n) if n < 232 => {
+                let n = n - 16;
+                Some((level(n / 36), level(n / 6 % 6), level(n % 6)))
+            },
+            Color::Indexed(n) => {
+                let gray = 8 + 10 * (n - 232);
+                Some((gray, gray, gray))
+            },
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+        }
+    }
+}
+
+/// How a piece of text looks.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub reverse: bool,
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style {
+            fg: Color::Default,
+            bg: Color::Default,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+            blink: false,
+            reverse: false,
+        }
+    }
+}
+
+impl Style {
+    /// Update the style according to the parameters of an SGR ("ESC [ ... m") sequence.
+    /// Parameters we don't understand are skipped.
+    pub fn apply_sgr(&mut self, params: &[u16]) {
+        // "ESC [ m" means the same as "ESC [ 0 m".
+        if params.is_empty() {
+            *self = Style::default();
+            return;
+        }
+
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                5 | 6 => self.blink = true,
+                7 => self.reverse = true,
+                22 => { self.bold = false; self.dim = false; },
+                23 => self.italic = false,
+                24 => self.underline = false,
+                25 => self.blink = false,
+                27 => self.reverse = false,
+                n @ 30..=37 => self.fg = Color::Ansi((n - 30) as u8),
+                39 => self.fg = Color::Default,
+                n @ 40..=47 => self.bg = Color::Ansi((n - 40) as u8),
+                49 => self.bg = Color::Default,
+                n @ 90..=97 => self.fg = Color::Ansi((n - 90 + 8) as u8),
+                n @ 100..=107 => self.bg = Color::Ansi((n - 100 + 8) as u8),
+                n @ 38 | n @ 48 => {
+                    // Extended colors: 38;5;n for the 256-color palette, 38;2;r;g;b for RGB.
+                    let color = match params.get(i + 1) {
+                        Some(5) if i + 2 < params.len() => {
+                            i += 2;
+                            Some(Color::Indexed(params[i] as u8))
+                        },
+                        Some(2) if i + 4 < params.len() => {
+                            i += 4;
+                            Some(Color::Rgb(params[i - 2] as u8, params[i - 1] as u8, params[i] as u8))
+                        },
+                        // Malformed; we can't know how much of the rest belongs to it, so give up.
+                        _ => return,
+                    };
+                    if let Some(color) = color {
+                        if n == 38 { self.fg = color } else { self.bg = color }
+                    }
+                },
+                _ => { },
+            }
+            i += 1;
+        }
+    }
+
+    /// An SGR sequence that sets exactly this style from scratch, on a terminal that can show
+    /// `depth` colors.  Colors it can't show are approximated.
+    pub fn sgr(&self, depth: ColorDepth) -> String {
+        let mut out = "\x1b[0".to_string();
+        let flags = [(self.bold, "1"), (self.dim, "2"), (self.italic, "3"), (self.underline, "4"),
+                     (self.blink, "5"), (self.reverse, "7")];
+        for (on, code) in flags.iter() {
+            if *on {
+                out.push(';');
+                out.push_str(code);
+            }
+        }
+        for (color, bg) in [(self.fg, false), (self.bg, true)].iter() {
+            if let Some(params) = color_params(*color, *bg, depth) {
+                out.push(';');
+                out.push_str(&params);
+            }
+        }
+        out.push('m');
+        out
+    }
+}
+
+/// The SGR parameters for a foreground (or background) color, downgraded to fit `depth`.
+fn color_params(color: Color, bg: bool, depth: ColorDepth) -> Option<String> {
+    let color = match (color, depth) {
+        (_, ColorDepth::None) | (Color::Default, _) => return None,
+        (Color::Rgb(r, g, b), ColorDepth::Indexed256) => Color::Indexed(rgb_to_indexed(r, g, b)),
+        (Color::Rgb(r, g, b), ColorDepth::Ansi16) => {
+            Color::Ansi(indexed_to_ansi(rgb_to_indexed(r, g, b)))
+        },
+        (Color::Indexed(n), ColorDepth::Ansi16) => Color::Ansi(indexed_to_ansi(n)),
+        (color, _) => color,
+    };
+
+    let base = if bg { 40 } else { 30 };
+    Some(match color {
+        Color::Ansi(n) if n < 8 => format!("{}", base + n as u16),
+        Color::Ansi(n) => format!("{}", base + 60 + (n - 8) as u16),
+        Color::Indexed(n) => format!("{};5;{}", base + 8, n),
+        Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        Color::Default => unreachable!(),
+    })
+}
+
+/// The nearest color in the 6x6x6 cube of the 256-color palette.
+fn rgb_to_indexed(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+/// A rough approximation of one of the 256 colors with one of the basic 16.
+fn indexed_to_ansi(n: u8) -> u8 {
+    match n {
+        0..=15 => n,
+        16..=231 => {
+            let (r, g, b) = ((n - 16) / 36, (n - 16) / 6 % 6, (n - 16) % 6);
+            let bits = (r > 2) as u8 | ((g > 2) as u8) << 1 | ((b > 2) as u8) << 2;
+            if r.max(g).max(b) > 4 { bits + 8 } else { bits }
+        },
+        // The grayscale ramp.
+        _ => match n - 232 {
+            0..=5 => 0,
+            6..=11 => 8,
+            12..=17 => 7,
+            _ => 15,
+        },
+    }
+}
+
+/// A line of text and the styles of the pieces of it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StyledLine {
+    pub text: String,
+    // (byte offset, style from there on), in order.  The first always starts at 0.
+    spans: Vec<(usize, Style)>,
+}
+
+impl StyledLine {
+    pub fn plain(text: String) -> StyledLine {
+        StyledLine { text, spans: vec![(0, Style::default())] }
+    }
+
+    /// Give the whole line one style, forgetting the ones it had.
+    pub fn restyle(&mut self, style: Style) {
+        self.spans = vec![(0, style)];
+    }
+
+    /// Change the style of the text from byte offset `start` to `end`, leaving the rest alone.
+    pub fn restyle_range(&mut self, start: usize, end: usize, change: impl Fn(&mut Style)) {
+        let end = end.min(self.text.len());
+        if start >= end {
+            return;
+        }
+        self.split_at(start);
+        self.split_at(end);
+        for span in self.spans.iter_mut().filter(|(offset, _)| *offset >= start && *offset < end) {
+            change(&mut span.1);
+        }
+    }
+
+    // Make sure a span starts at `at`, unless it's the end of the text.
+    fn split_at(&mut self, at: usize) {
+        if at >= self.text.len() {
+            return;
+        }
+        let i = self.spans.iter().rposition(|(offset, _)| *offset <= at).unwrap_or(0);
+        if self.spans[i].0 != at {
+            let style = self.spans[i].1;
+            self.spans.insert(i + 1, (at, style));
+        }
+    }
+
+    /// Put `text`, in `style`, in front of the line.
+    pub fn prepend(&mut self, text: &str, style: Style) {
+        for span in self.spans.iter_mut() {
+            span.0 += text.len();
+        }
+        self.spans.insert(0, (0, style));
+        self.text.insert_str(0, text);
+    }
+
+    /// The pieces of the line that have one style all the way through, in order.
+    pub fn spans(&self) -> impl Iterator<Item = (&str, Style)> + '_ {
+        self.spans.iter().enumerate().filter_map(move |(i, (offset, style))| {
+            let end = self.spans.get(i + 1).map_or(self.text.len(), |s| s.0);
+            if *offset < end { Some((&self.text[*offset..end], *style)) } else { None }
+        })
+    }
+
+    /// Each grapheme (a character, with any accents and the like that go on it) with its style.
+    /// A grapheme takes the style its first character has.
+    pub fn styled_graphemes(&self) -> impl Iterator<Item = (&str, Style)> + '_ {
+        let mut span = 0;
+        self.text.grapheme_indices(true).map(move |(i, g)| {
+            while span + 1 < self.spans.len() && self.spans[span + 1].0 <= i {
+                span += 1;
+            }
+            (g, self.spans[span].1)
+        })
+    }
+
+    /// The text from byte offset `start` to `end`, with SGR sequences embedded for its styles.
+    /// Starts from the default style and goes back to it at the end, so the result can be put
+    /// anywhere.
+    pub fn slice_with_sgr(&self, start: usize, end: usize) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+
+        for (i, (offset, style)) in self.spans.iter().enumerate() {
+            let span_end = self.spans.get(i + 1).map_or(self.text.len(), |s| s.0);
+            let (from, to) = ((*offset).max(start), span_end.min(end));
+            if from >= to {
+                continue;
+            }
+            if *style != current {
+                out.push_str(&style.sgr(ColorDepth::TrueColor));
+                current = *style;
+            }
+            out.push_str(&self.text[from..to]);
+        }
+
+        if current != Style::default() {
+            out.push_str("\x1b[0m");
+        }
+        out
+    }
+}
+
+/// Split text with ANSI escapes in it into the plain text and its styles.  SGR sequences are
+/// understood; every other escape sequence (cursor movement, window titles and so on) is dropped,
+/// since servers have no business moving our cursor around.  So are control characters other
+/// than tab.
+pub fn parse(text: &str) -> StyledLine {
+    let mut line = StyledLine::plain(String::with_capacity(text.len()));
+    let mut style = Style::default();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            if c == '\t' || !c.is_control() {
+                line.text.push(c);
+            }
+            continue;
+        }
+
+        match chars.next() {
+            Some('[') => {
+                // CSI: parameters and intermediates, then a final byte from @ to ~.
+                let mut body = String::new();
+                let mut last = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        last = Some(c);
+                        break;
+                    }
+                    body.push(c);
+                }
+                if last == Some('m') {
+                    let params: Vec<u16> = body.split(&[';', ':'][..])
+                        .map(|p| p.parse().unwrap_or(0))
+                        .collect();
+                    // "ESC [ m" splits into one empty parameter, which is the same as 0 anyway.
+                    style.apply_sgr(&params);
+                    let at = line.text.len();
+                    match line.spans.last_mut() {
+                        Some(last) if last.0 == at => last.1 = style,
+                        _ => line.spans.push((at, style)),
+                    }
+                }
+            },
+            Some(']') => {
+                // OSC, which ends with BEL or ESC \.  BELs have usually been stripped out by the
+                // time we get here, so if there's no ESC \ just drop the introducer.
+                let rest: String = chars.clone().collect();
+                if let Some(end) = rest.find("\x1b\\") {
+                    for _ in rest[..end + 2].chars() {
+                        chars.next();
+                    }
+                } else if let Some(end) = rest.find('\x07') {
+                    for _ in rest[..end + 1].chars() {
+                        chars.next();
+                    }
+                }
+            },
+            // Two-character escapes like ESC 7, or a stray ESC at the end of the line.
+            _ => { },
+        }
+    }
+
+    // A style set at the very end of the line doesn't style anything.
+    if line.spans.len() > 1 && line.spans.last().map(|s| s.0) == Some(line.text.len()) {
+        line.spans.pop();
+    }
+    line
+}
+
+#[test]
+fn ansi_parsing() {
+    let line = parse("\x1b[1;31mDanger!\x1b[0m Run\x1b[2J away\x1b[m");
+    assert_eq!(line.text, "Danger! Run away");
+
+    let styles: Vec<Style> = line.styled_graphemes().map(|(_, s)| s).collect();
+    assert!(styles[0].bold && styles[0].fg == Color::Ansi(1));
+    assert_eq!(styles[7], Style::default());
+
+    // Cutting a piece out keeps its style and closes it off.
+    assert_eq!(line.slice_with_sgr(3, 10), "\x1b[0;1;31mger!\x1b[0m Ru");
+
+    // Restyling part of the line splits the spans it runs across.
+    let mut restyled = line.clone();
+    restyled.restyle_range(5, 9, |style| style.underline = true);
+    assert_eq!(restyled.slice_with_sgr(0, 16), "\x1b[0;1;31mDange\x1b[0;1;4;31mr!\x1b[0;4m R\x1b[0mun away");
+
+    let mut style = Style::default();
+    style.apply_sgr(&[38, 2, 255, 0, 0, 48, 5, 21]);
+    assert_eq!(style.sgr(ColorDepth::Ansi16), "\x1b[0;91;104m");
+}
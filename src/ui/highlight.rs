@@ -0,0 +1,96 @@
+//! Highlight rules: a pattern and a style, which every piece of a line from the server matching
+//! the pattern is shown in.  Unlike a trigger's highlight, which restyles the whole line, these
+//! only touch what matched, and they add to the style that's there (so "bold" on some red text
+//! makes it bold and red.)  Every rule gets a go at every line.  A rule can flash the screen
+//! when it matches too, as a visual bell.
+
+use crate::ui::ansi::StyledLine;
+use crate::ui::style;
+
+use regex::Regex;
+
+pub struct Highlight {
+    pub pattern: Regex,
+    /// The style, as it was given (e.g. "bold,yellow").
+    pub spec: String,
+    /// Flash the screen when it matches.
+    pub flash: bool,
+    codes: Vec<u16>,
+}
+
+#[derive(Default)]
+pub struct HighlightRules {
+    rules: Vec<Highlight>,
+}
+
+impl HighlightRules {
+    pub fn new() -> HighlightRules {
+        HighlightRules { rules: vec![] }
+    }
+
+    /// Add a rule, replacing any with the same pattern.  Rules added later go over the top of
+    /// earlier ones where they both match.
+    pub fn add(&mut self, pattern: &str, spec: &str, flash: bool) -> Result<(), String> {
+        let pattern = Regex::new(pattern).map_err(|e| format!("Bad pattern: {}", e))?;
+        let codes = style::codes(spec)?;
+        self.remove(pattern.as_str());
+        self.rules.push(Highlight { pattern, spec: spec.to_string(), flash, codes });
+        Ok(())
+    }
+
+    /// Returns false if there wasn't a rule with that pattern.
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|rule| rule.pattern.as_str() != pattern);
+        self.rules.len() != before
+    }
+
+    pub fn list(&self) -> &[Highlight] {
+        &self.rules
+    }
+
+    /// Style whatever the rules match in a line.  Returns true if a rule that flashes matched.
+    pub fn apply(&self, line: &mut StyledLine) -> bool {
+        let mut flash = false;
+        for rule in &self.rules {
+            let matches: Vec<(usize, usize)> = rule.pattern.find_iter(&line.text)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+            flash |= rule.flash && !matches.is_empty();
+            for (start, end) in matches {
+                line.restyle_range(start, end, |style| style.apply_sgr(&rule.codes));
+            }
+        }
+        flash
+    }
+}
+
+#[test]
+fn highlight_rules() {
+    use crate::ui::ansi::{self, Color};
+
+    let mut rules = HighlightRules::new();
+    assert!(rules.add("(", "bold", false).is_err());
+    assert!(rules.add("Annie", "sparkly", false).is_err());
+    rules.add(r"\bAnnie\b", "bold", false).unwrap();
+    rules.add("tells you", "yellow", false).unwrap();
+    rules.add("tells you", "bright-cyan", true).unwrap();
+    assert_eq!(rules.list().len(), 2);
+
+    let mut line = ansi::parse("\x1b[31mAnnie\x1b[0m tells you, 'Annie!'");
+    assert!(rules.apply(&mut line));
+    let styles: Vec<(String, bool, Color)> = line.styled_graphemes()
+        .map(|(g, style)| (g.to_string(), style.bold, style.fg))
+        .filter(|(g, _, _)| g == "A" || g == "t" || g == ",")
+        .collect();
+    assert_eq!(styles, vec![
+        ("A".to_string(), true, Color::Ansi(1)),
+        ("t".to_string(), false, Color::Ansi(14)),
+        (",".to_string(), false, Color::Default),
+        ("A".to_string(), true, Color::Default),
+    ]);
+
+    assert!(!rules.apply(&mut ansi::parse("Annie says hi")));
+    assert!(rules.remove("tells you"));
+    assert!(!rules.remove("tells you"));
+}
@@ -1,6 +1,35 @@
-// TODO: Consider how specification of the arguments for commands ought to work, or if it ought
-// to be a thing in the first place.
-pub type Command = String;
+use crate::error::MintError;
+use crate::line::Line;
+use crate::meta::WindowID;
+
+/// The window where the client puts notes about its own workings, warnings about the
+/// environment and so on.
+pub const DEBUG_WINDOW: &str = "debug";
+
+/// The status bar segment /showkey describes keys in.  The UI clears it by itself on the next key
+/// after that.
+pub const SHOWKEY_SEGMENT: &str = "showkey";
+
+/// What to do when a BEL character shows up in text from the server.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BellPolicy {
+    /// Ring the terminal's own bell, which is what would've happened anyway if we let the BEL
+    /// through untouched.
+    Ring,
+    /// Flash the screen.
+    Visual,
+    /// Ask the terminal to raise a desktop notification.
+    Notify,
+    Ignore,
+}
+
+/// Which lines of a window's history to take, for UserInterface::snapshot().
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Lines {
+    Last(usize),
+    /// Everything from the last divider put in with mark() on.
+    SinceMark,
+}
 
 /// UserInterface trait: This object type knows about the logistical details of handling UI, like drawing to the screens.
 ///
@@ -8,8 +37,79 @@ pub trait UserInterface {
     /// The way windows work is that any unique named window you try to send text to should be
     /// created by the UI code. Which windows are visible at any given time, and how that activity
     /// is surface to the user, is the UI code's business.
-    fn push_to_window(&mut self, window: String, line: String) -> Result<(), ()>;
-    fn register_command(&mut self, c: Command);
+    ///
+    /// Gagged lines aren't shown at all, and highlighted ones are shown in their highlight style.
+    fn push_to_window(&mut self, window: String, line: Line) -> Result<(), MintError>;
+
+    /// Set how BEL characters in text sent to `window` are handled.  They're always stripped out
+    /// of the text itself.
+    fn set_bell_policy(&mut self, window: String, policy: BellPolicy);
+
+    /// Set the text of a named segment of the status bar, creating the segment if needed.
+    fn set_status(&mut self, segment: String, text: String);
+    fn remove_status(&mut self, segment: String);
+
+    /// The name of the window the user is looking at right now.
+    fn active_window(&self) -> String;
+
+    /// Bring a window into view, creating it if it doesn't exist.
+    fn show_window(&mut self, window: String);
+
+    /// Put a timestamped divider line in a window, to mark where the user got up to.
+    fn mark(&mut self, window: String);
+    /// Scroll a window back to the last divider put in it with mark().  Returns false if it
+    /// doesn't have one.
+    fn jump_to_mark(&mut self, window: String) -> bool;
+    /// Scroll a window back down to its newest lines.
+    fn scroll_to_end(&mut self, window: String);
+
+    /// Copy some lines of `from`'s history into a brand new window `to`, which won't change when
+    /// `from` does.  Returns how many lines were copied, or why nothing was.
+    fn snapshot(&mut self, from: String, to: String, lines: Lines) -> Result<usize, MintError>;
+
+    /// Don't act on the next key pressed; send an Event::KeyShown describing it instead.
+    fn show_next_key(&mut self);
+
+    /// The names of all the windows there are, sorted.
+    fn window_names(&self) -> Vec<String>;
+
+    /// The number a window goes by in Events, e.g. the `which` in Event::UserInput.  A window
+    /// gets its number the first time anything asks (or anything's put in it) and keeps it for as
+    /// long as the UI lasts; numbers aren't reused.
+    fn window_id(&mut self, window: &str) -> WindowID;
+    /// The window a number belongs to, if it belongs to one.
+    fn window_name(&self, id: WindowID) -> Option<String>;
+
+    /// Make a window called `window`, if there isn't one already.  (Pushing a line to a window
+    /// that doesn't exist makes it too; this is for windows that should be there before anything's
+    /// been said in them.)
+    fn create_window(&mut self, window: String);
+
+    /// Give a window a new name.  Everything about it goes with it: its lines, its number, its
+    /// panes and its settings.  Fails if there's no window `from`, or there's already one called
+    /// `to`.
+    fn rename_window(&mut self, from: String, to: String) -> Result<(), MintError>;
+
+    /// Set what the status bar says about `window` (e.g. what it's connected to) while it's the
+    /// one on screen.
+    fn set_window_status(&mut self, window: String, text: String);
+
+    /// How many columns of text fit across a window.
+    fn text_width(&self) -> usize;
+
+    /// Show what's typed in `window` as asterisks, or stop, e.g. while the server's asking for a
+    /// password.  Lines typed while it's on aren't kept in the input history.
+    fn set_input_masked(&mut self, window: String, masked: bool);
+
+    /// Put some text on the system clipboard, if the UI has any way to do that.
+    fn copy(&mut self, text: String);
+
+    /// Ask for a desktop notification, if the UI has any way to do that.
+    fn notify(&mut self, text: String);
 }
 
+pub mod ansi;
+pub mod highlight;
+pub mod raise;
+pub mod style;
 pub mod term;
@@ -0,0 +1,103 @@
+use regex::Regex;
+use std::time::{Duration, Instant};
+
+/// A rule saying that a window should be brought into view when a line matching `pattern` arrives
+/// in it.  If `window` is None, the rule applies to lines arriving in any window.
+pub struct RaiseRule {
+    pub window: Option<String>,
+    pub pattern: Regex,
+}
+
+/// The set of auto-raise rules, plus the state needed to rate-limit them.
+///
+/// The rate limit is global rather than per rule: what we're trying to prevent is the layout
+/// flipping back and forth every time some spammy channel says your name, and it doesn't matter
+/// much to the user *which* rule caused that.
+pub struct RaiseRules {
+    rules: Vec<RaiseRule>,
+    min_interval: Duration,
+    last_raise: Option<Instant>,
+}
+
+impl RaiseRules {
+    pub fn new(min_interval: Duration) -> RaiseRules {
+        RaiseRules {
+            rules: vec![],
+            min_interval,
+            last_raise: None,
+        }
+    }
+
+    /// Add a rule.  Fails if `pattern` isn't a valid regular expression.
+    pub fn add(&mut self, window: Option<String>, pattern: &str) -> Result<(), regex::Error> {
+        let pattern = Regex::new(pattern)?;
+        self.rules.push(RaiseRule { window, pattern });
+        Ok(())
+    }
+
+    /// Remove every rule with the given pattern text, returning how many were removed.
+    pub fn remove(&mut self, pattern: &str) -> usize {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.pattern.as_str() != pattern);
+        before - self.rules.len()
+    }
+
+    /// Make the rules for window `from` apply to `to` instead, for when it's renamed.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        for rule in self.rules.iter_mut().filter(|r| r.window.as_deref() == Some(from)) {
+            rule.window = Some(to.to_string());
+        }
+    }
+
+    /// The rules, in the order they were added.
+    pub fn list(&self) -> &[RaiseRule] {
+        &self.rules
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    pub fn set_min_interval(&mut self, min_interval: Duration) {
+        self.min_interval = min_interval;
+    }
+
+    /// Decide whether a line arriving in `window` at time `now` should raise that window.  If it
+    /// should, this counts as a raise for the purposes of the rate limit, so only call it once
+    /// per line.
+    pub fn should_raise(&mut self, window: &str, line: &str, now: Instant) -> bool {
+        if let Some(last) = self.last_raise {
+            if now.duration_since(last) < self.min_interval {
+                return false;
+            }
+        }
+
+        let matched = self.rules.iter().any(|r| {
+            r.window.as_ref().is_none_or(|w| w == window) && r.pattern.is_match(line)
+        });
+
+        if matched {
+            self.last_raise = Some(now);
+        }
+        matched
+    }
+}
+
+#[test]
+fn raise_rate_limit() {
+    let mut rules = RaiseRules::new(Duration::from_secs(5));
+    rules.add(None, "tells you").unwrap();
+    rules.add(Some("chat".to_string()), "Bob").unwrap();
+
+    let start = Instant::now();
+    assert!(!rules.should_raise("chat", "Alice says hi", start));
+    assert!(rules.should_raise("chat", "Alice tells you hi", start));
+    // Matches, but we raised less than five seconds ago.
+    assert!(!rules.should_raise("chat", "Bob waves", start + Duration::from_secs(1)));
+    assert!(rules.should_raise("chat", "Bob waves", start + Duration::from_secs(6)));
+    // The Bob rule only applies to the chat window, whatever it's called.
+    assert!(!rules.should_raise("main", "Bob waves", start + Duration::from_secs(20)));
+    rules.rename("chat", "tells");
+    assert!(!rules.should_raise("chat", "Bob waves", start + Duration::from_secs(30)));
+    assert!(rules.should_raise("tells", "Bob waves", start + Duration::from_secs(40)));
+}
@@ -0,0 +1,127 @@
+use crate::ui::ansi::Style;
+
+/// Names for the colors in the order ANSI numbers them.
+const COLORS: [&str; 8] = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// Turn a style spec like "bold,red" or "underline,bg-blue,bright-white" into the ANSI SGR
+/// escape sequence that switches it on.
+///
+/// The attributes are bold, dim, italic, underline, blink and reverse; colors are the eight ANSI
+/// ones, optionally prefixed with "bright-", or one of xterm's 256 by number, like "color208", or
+/// "#rrggbb" for terminals that can show any color.  Any of them can have "bg-" in front, for the
+/// background.  (/colors shows what there is.)
+pub fn sgr(spec: &str) -> Result<String, String> {
+    let codes: Vec<String> = codes(spec)?.iter().map(|c| c.to_string()).collect();
+    if codes.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(format!("\x1b[{}m", codes.join(";")))
+    }
+}
+
+/// The Style a spec (see `sgr()`) stands for, e.g. for highlighting a line that's already been
+/// parsed.
+pub fn style(spec: &str) -> Result<Style, String> {
+    let mut style = Style::default();
+    style.apply_sgr(&codes(spec)?);
+    Ok(style)
+}
+
+/// The SGR parameters for a style spec, e.g. to put it on top of a style that's already there
+/// with Style::apply_sgr().
+pub fn codes(spec: &str) -> Result<Vec<u16>, String> {
+    let mut codes: Vec<u16> = vec![];
+
+    for name in spec.split(',').map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()) {
+        let extended = match name.strip_prefix("bg-") {
+            Some(color) => extended(color).map(|color| [&[48][..], &color].concat()),
+            None => extended(&name).map(|color| [&[38][..], &color].concat()),
+        };
+        if let Some(mut extended) = extended {
+            codes.append(&mut extended);
+            continue;
+        }
+
+        let code = match name.as_str() {
+            "bold" => 1,
+            "dim" => 2,
+            "italic" => 3,
+            "underline" => 4,
+            "blink" => 5,
+            "reverse" => 7,
+            _ => {
+                let (base, color) = match name.strip_prefix("bg-") {
+                    Some(color) => (40, color),
+                    None => (30, name.as_str()),
+                };
+                let (base, color) = match color.strip_prefix("bright-") {
+                    Some(color) => (base + 60, color),
+                    None => (base, color),
+                };
+                match COLORS.iter().position(|c| *c == color) {
+                    Some(n) => base + n,
+                    None => return Err(format!("Unknown style '{}'", name)),
+                }
+            },
+        };
+        codes.push(code as u16);
+    }
+
+    Ok(codes)
+}
+
+/// What goes after 38 or 48 for a color from the 256 (5;n) or an RGB one (2;r;g;b), if that's
+/// what `color` is.
+fn extended(color: &str) -> Option<Vec<u16>> {
+    if let Some(n) = color.strip_prefix("color") {
+        return n.parse::<u8>().ok().map(|n| vec![5, n as u16]);
+    }
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii())?;
+    let part = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(u16::from);
+    Some(vec![2, part(0)?, part(2)?, part(4)?])
+}
+
+/// What a style spec calls color `n` of xterm's 256: the names for the first 16 (like "red" or
+/// "bright-blue"), and "color" and the number for the rest.
+pub fn color_name(n: u8) -> String {
+    match n {
+        0..=7 => COLORS[n as usize].to_string(),
+        8..=15 => format!("bright-{}", COLORS[n as usize - 8]),
+        n => format!("color{}", n),
+    }
+}
+
+/// A spec without any foreground colors in it, for putting a different one on the end of: the
+/// attributes and backgrounds, as they were.
+pub fn without_foreground(spec: &str) -> String {
+    let kept: Vec<&str> = spec.split(',').map(str::trim)
+        .filter(|name| name.starts_with("bg-") || codes(name).is_ok_and(|codes| codes.len() == 1 && codes[0] < 10))
+        .collect();
+    kept.join(",")
+}
+
+/// Wrap `text` in the style from `spec` (see `sgr()`), resetting everything afterwards.
+pub fn styled(text: &str, spec: &str) -> Result<String, String> {
+    let on = sgr(spec)?;
+    if on.is_empty() {
+        Ok(text.to_string())
+    } else {
+        Ok(format!("{}{}\x1b[0m", on, text))
+    }
+}
+
+#[test]
+fn style_specs() {
+    assert_eq!(sgr("bold,red").unwrap(), "\x1b[1;31m");
+    assert_eq!(sgr("bg-bright-blue, underline").unwrap(), "\x1b[104;4m");
+    assert_eq!(styled("hi", "").unwrap(), "hi");
+    assert!(sgr("plaid").is_err());
+
+    assert_eq!(sgr("color208,bg-#0080ff").unwrap(), "\x1b[38;5;208;48;2;0;128;255m");
+    assert_eq!(color_name(208), "color208");
+    assert_eq!(color_name(12), "bright-blue");
+    assert_eq!(sgr(&color_name(12)).unwrap(), "\x1b[94m");
+    assert!(sgr("color256").is_err());
+    assert!(sgr("#12345").is_err());
+    assert_eq!(without_foreground("bold, red,bg-blue,color99"), "bold,bg-blue");
+}
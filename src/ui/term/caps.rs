@@ -0,0 +1,120 @@
+use crate::charset::Charset;
+
+use std::env;
+
+/// How many colors the terminal can show, as best we can guess from the environment.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ColorDepth {
+    None,
+    Ansi16,
+    Indexed256,
+    TrueColor,
+}
+
+/// What the terminal we're running in can do.  This is probed once at startup, and the renderer
+/// checks it before doing anything fancy.
+///
+/// There's no reliable way to ask a terminal most of these things, so this is mostly educated
+/// guessing from $TERM and friends, the same as every other terminal program does.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    pub term: String,
+    /// None if we couldn't get the size at all (which usually means we're not in a terminal.)
+    pub size: Option<(usize, usize)>,
+    pub colors: ColorDepth,
+    /// Whether the locale says the terminal speaks UTF-8.
+    pub utf8: bool,
+    /// What the keyboard input is encoded in: $MINT_INPUT_ENCODING if that's set, otherwise
+    /// whatever the locale says.
+    pub input_charset: Charset,
+    /// $MINT_INPUT_ENCODING, if it was set to something we don't know.
+    pub bad_input_encoding: Option<String>,
+    /// Whether the terminal probably understands xterm-style mouse reporting.
+    pub mouse: bool,
+    /// Whether the terminal is capable of cursor movement and the like at all.
+    pub dumb: bool,
+}
+
+impl Capabilities {
+    pub fn probe() -> Capabilities {
+        let term = env::var("TERM").unwrap_or_default();
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let dumb = term.is_empty() || term == "dumb";
+
+        let colors = if dumb {
+            ColorDepth::None
+        } else if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Indexed256
+        } else {
+            ColorDepth::Ansi16
+        };
+
+        // The first of these that's set (and not empty) decides the locale, per POSIX.
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_lowercase();
+        let utf8 = locale.contains("utf-8") || locale.contains("utf8");
+
+        let (input_charset, bad_input_encoding) = match env::var("MINT_INPUT_ENCODING") {
+            Ok(ref name) if !name.is_empty() => match Charset::from_name(name) {
+                Some(charset) => (charset, None),
+                None => (Charset::from_locale(), Some(name.clone())),
+            },
+            _ => (Charset::from_locale(), None),
+        };
+
+        let mouse_terms = ["xterm", "rxvt", "screen", "tmux", "alacritty", "kitty", "foot", "st-",
+                           "konsole", "gnome", "vte", "wezterm"];
+        let mouse = !dumb && mouse_terms.iter().any(|t| term.starts_with(t));
+
+        let size = termion::terminal_size().ok().map(|(w, h)| (w as usize, h as usize));
+
+        Capabilities { term, size, colors, utf8, input_charset, bad_input_encoding, mouse, dumb }
+    }
+
+    /// What a headless TermUiManager (see headless.rs) goes by: a plain 80x24 screen that
+    /// nothing's drawn on, so there's nothing to warn about.
+    pub fn headless() -> Capabilities {
+        Capabilities {
+            term: "headless".to_string(),
+            size: Some((80, 24)),
+            colors: ColorDepth::TrueColor,
+            utf8: true,
+            input_charset: Charset::Utf8,
+            bad_input_encoding: None,
+            mouse: false,
+            dumb: false,
+        }
+    }
+
+    /// Things about the environment the user should probably know about, because they're likely
+    /// to make mint look or act strangely.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+
+        if self.dumb {
+            warnings.push(format!("TERM is '{}'; this terminal probably can't run a full-screen \
+                                   program like mint properly.", self.term));
+        }
+        if self.size.is_none() {
+            warnings.push("Couldn't get the terminal size; assuming 80x24.".to_string());
+        }
+        if !self.utf8 {
+            warnings.push("The locale doesn't look like UTF-8; non-ASCII text may come out \
+                           garbled.".to_string());
+        }
+        if let Some(ref name) = self.bad_input_encoding {
+            warnings.push(format!("Unknown MINT_INPUT_ENCODING '{}'; decoding input as {} \
+                                   instead.", name, self.input_charset.name()));
+        }
+        if self.colors == ColorDepth::None {
+            warnings.push("No color support detected.".to_string());
+        }
+
+        warnings
+    }
+}
@@ -0,0 +1,229 @@
+//! Tab completion for the input line: the word before the cursor is completed from words the
+//! server has said lately, or at the start of the line, from the client's commands.  Pressing Tab
+//! again goes on to the next candidate, and after the last, back to what was typed.
+//!
+//! Each window can have word lists of its own as well, by name ("players", "areas"), which
+//! triggers and scripts fill in from what the world it's connected to says.  Words from those
+//! come before the ones that were just seen somewhere.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use unicode_width::UnicodeWidthStr;
+
+/// How many different words from the server are remembered for completing.
+const MAX_WORDS: usize = 2000;
+
+/// Words shorter than this aren't worth remembering; typing them is as quick as completing them.
+const MIN_WORD_LENGTH: usize = 3;
+
+/// How many words each word list keeps.  The ones added longest ago go first.
+const MAX_LIST_WORDS: usize = 500;
+
+/// What words can be completed to.
+#[derive(Default)]
+pub struct Dictionary {
+    // Newest first, with no repeats.
+    words: VecDeque<String>,
+    // With the "/" on the front, sorted.
+    commands: Vec<String>,
+    // The word lists for each window, by name; newest first, with no repeats.
+    lists: HashMap<String, BTreeMap<String, VecDeque<String>>>,
+}
+
+impl Dictionary {
+    pub fn new() -> Dictionary {
+        Dictionary::default()
+    }
+
+    /// Remember the words in a line of text.  Ones seen before (in any case) move back up to the
+    /// front, spelled the way they were this time.
+    pub fn learn(&mut self, text: &str) {
+        let words = text.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .map(|word| word.trim_matches('-'))
+            .filter(|word| word.chars().count() >= MIN_WORD_LENGTH);
+        for word in words {
+            if let Some(i) = self.words.iter().position(|w| w.to_lowercase() == word.to_lowercase()) {
+                self.words.remove(i);
+            }
+            self.words.push_front(word.to_string());
+        }
+        self.words.truncate(MAX_WORDS);
+    }
+
+    /// Set the command names (without their "/") that can be completed at the start of a line.
+    pub fn set_commands(&mut self, names: Vec<String>) {
+        self.commands = names.into_iter().map(|name| format!("/{}", name)).collect();
+        self.commands.sort();
+    }
+
+    /// Add a word to one of `window`'s word lists, making the list if it has to.  A word that's
+    /// there already (in any case) moves back up to the front.
+    pub fn add_to_list(&mut self, window: &str, list: &str, word: &str) {
+        let word = word.trim();
+        if word.is_empty() {
+            return;
+        }
+        let words = self.lists.entry(window.to_string()).or_default().entry(list.to_string()).or_default();
+        words.retain(|w| w.to_lowercase() != word.to_lowercase());
+        words.push_front(word.to_string());
+        words.truncate(MAX_LIST_WORDS);
+    }
+
+    /// Take a word out of one of `window`'s word lists, or with no word, get rid of the whole
+    /// list.  Returns false if there was nothing to take out.
+    pub fn remove_from_list(&mut self, window: &str, list: &str, word: Option<&str>) -> bool {
+        let lists = match self.lists.get_mut(window) {
+            Some(lists) => lists,
+            None => return false,
+        };
+        match word {
+            None => lists.remove(list).is_some(),
+            Some(word) => match lists.get_mut(list) {
+                Some(words) => {
+                    let before = words.len();
+                    words.retain(|w| w.to_lowercase() != word.to_lowercase());
+                    words.len() != before
+                },
+                None => false,
+            },
+        }
+    }
+
+    /// `window`'s word lists, by name, with the words in each, newest first.
+    pub fn lists(&self, window: &str) -> Vec<(String, Vec<String>)> {
+        self.lists.get(window).map_or(vec![], |lists| {
+            lists.iter().map(|(name, words)| (name.clone(), words.iter().cloned().collect())).collect()
+        })
+    }
+
+    /// A window's word lists go with it when it's renamed.
+    pub fn rename_window(&mut self, from: &str, to: &str) {
+        if let Some(lists) = self.lists.remove(from) {
+            self.lists.insert(to.to_string(), lists);
+        }
+    }
+
+    /// What `typed` could be the start of: commands, if it's at the start of the line and starts
+    /// with "/", or otherwise words from `window`'s word lists, then the words seen lately, the
+    /// most recent first.  Case doesn't matter, and nothing's a candidate for itself (or twice.)
+    /// Nothing typed means no candidates, rather than all of them.
+    pub fn candidates(&self, typed: &str, line_start: bool, window: &str) -> Vec<String> {
+        if typed.is_empty() {
+            return vec![];
+        }
+        let typed = typed.to_lowercase();
+        let fits = |word: &&String| {
+            let word = word.to_lowercase();
+            word.starts_with(&typed) && word != typed
+        };
+        if line_start && typed.starts_with('/') {
+            return self.commands.iter().filter(fits).cloned().collect();
+        }
+
+        let listed = self.lists.get(window).into_iter().flat_map(|lists| lists.values()).flatten();
+        let mut candidates: Vec<String> = vec![];
+        for word in listed.chain(self.words.iter()).filter(fits) {
+            if !candidates.iter().any(|c| c.to_lowercase() == word.to_lowercase()) {
+                candidates.push(word.clone());
+            }
+        }
+        candidates
+    }
+}
+
+/// A completion in progress, from the first Tab until some other key.
+pub struct Completion {
+    /// Where the word being completed starts in the input line, in graphemes.
+    pub start: usize,
+    typed: String,
+    candidates: Vec<String>,
+    // Which candidate is in the line; one past the last means what was typed is.
+    shown: usize,
+}
+
+impl Completion {
+    /// Start completing `typed`, which starts at `start` in the input line, with the first of
+    /// `candidates`.  None if there aren't any.
+    pub fn new(start: usize, typed: String, candidates: Vec<String>) -> Option<Completion> {
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(Completion { start, typed, candidates, shown: 0 })
+    }
+
+    /// What should be in the line in place of what was typed.
+    pub fn current(&self) -> &str {
+        self.candidates.get(self.shown).unwrap_or(&self.typed)
+    }
+
+    /// Go on to the next candidate (or back to what was typed, after the last) and return it.
+    pub fn next(&mut self) -> &str {
+        self.shown = (self.shown + 1) % (self.candidates.len() + 1);
+        self.current()
+    }
+
+    /// The popup listing the candidates, `h` lines high at most, scrolled so the one in the line
+    /// is in sight.  Every line is as wide as the widest.
+    pub fn render(&self, h: usize) -> Vec<String> {
+        let width = self.candidates.iter().map(|c| c.width()).max().unwrap_or(0);
+        let first = (self.shown.min(self.candidates.len() - 1) + 1).saturating_sub(h);
+        self.candidates.iter().enumerate().skip(first).take(h)
+            .map(|(i, candidate)| {
+                let marker = if i == self.shown { ">" } else { " " };
+                format!("{} {}{} ", marker, candidate, " ".repeat(width - candidate.width()))
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn completion_candidates() {
+    let mut dictionary = Dictionary::new();
+    dictionary.learn("The goblin-king's guards glare at you.");
+    dictionary.learn("A Goblin arrives from the north.");
+    dictionary.set_commands(vec!["connect".to_string(), "compress".to_string(), "quit".to_string()]);
+
+    // Newest first, and "Goblin" only once even though it's been seen twice.
+    assert_eq!(dictionary.candidates("g", false, "main"), vec!["Goblin", "glare", "guards", "goblin-king"]);
+    assert_eq!(dictionary.candidates("th", false, "main"), vec!["the"]);
+    assert_eq!(dictionary.candidates("goblin", false, "main"), vec!["goblin-king"]);
+    assert_eq!(dictionary.candidates("/co", true, "main"), vec!["/compress", "/connect"]);
+    assert_eq!(dictionary.candidates("/co", false, "main"), Vec::<String>::new());
+    assert_eq!(dictionary.candidates("", false, "main"), Vec::<String>::new());
+    // Too short to remember.
+    assert_eq!(dictionary.candidates("a", false, "main"), vec!["arrives"]);
+
+    let mut completion = Completion::new(4, "/co".to_string(), dictionary.candidates("/co", true, "main")).unwrap();
+    assert_eq!(completion.current(), "/compress");
+    assert_eq!(completion.render(5), vec!["> /compress ", "  /connect  "]);
+    assert_eq!(completion.next(), "/connect");
+    assert_eq!(completion.render(1), vec!["> /connect  "]);
+    assert_eq!(completion.next(), "/co");
+    assert_eq!(completion.next(), "/compress");
+    assert!(Completion::new(0, "zz".to_string(), vec![]).is_none());
+}
+
+#[test]
+fn completion_word_lists() {
+    let mut dictionary = Dictionary::new();
+    dictionary.learn("Gandalf waves at Gary.");
+    dictionary.add_to_list("mud", "players", "Galadriel");
+    dictionary.add_to_list("mud", "players", "gandalf");
+    dictionary.add_to_list("mud", "areas", "Gardens");
+    dictionary.add_to_list("mud", "players", "Galadriel");
+
+    // The lists come first, a name at a time, and a word that's in one doesn't come up again.
+    assert_eq!(dictionary.candidates("ga", false, "mud"), vec!["Gardens", "Galadriel", "gandalf", "Gary"]);
+    // Other windows have lists of their own.
+    assert_eq!(dictionary.candidates("ga", false, "other"), vec!["Gary", "Gandalf"]);
+    assert_eq!(dictionary.lists("mud"), vec![
+        ("areas".to_string(), vec!["Gardens".to_string()]),
+        ("players".to_string(), vec!["Galadriel".to_string(), "gandalf".to_string()]),
+    ]);
+
+    assert!(dictionary.remove_from_list("mud", "players", Some("GANDALF")));
+    assert!(!dictionary.remove_from_list("mud", "players", Some("gandalf")));
+    dictionary.rename_window("mud", "mud2");
+    assert!(dictionary.remove_from_list("mud2", "areas", None));
+    assert_eq!(dictionary.candidates("ga", false, "mud2"), vec!["Galadriel", "Gary", "Gandalf"]);
+}
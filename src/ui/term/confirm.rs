@@ -0,0 +1,61 @@
+//! Asking the user whether to go ahead with a client command that throws things away (see
+//! command::Dispatcher::destructive): a popup in the middle of the screen, like the switcher,
+//! that takes y or n and nothing else until it's answered.
+
+use crate::command::Command;
+
+use termion::event::Key;
+
+/// What a key did to the question.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Still waiting for an answer.
+    Open,
+    No,
+    Yes,
+}
+
+pub struct Confirm {
+    question: String,
+    /// What to run if the answer's yes.
+    command: Command,
+}
+
+impl Confirm {
+    pub fn new(question: String, command: Command) -> Confirm {
+        Confirm { question, command }
+    }
+
+    pub fn key(&self, key: Key) -> Outcome {
+        match key {
+            Key::Char('y') | Key::Char('Y') => Outcome::Yes,
+            Key::Char('n') | Key::Char('N') | Key::Esc | Key::Ctrl('g') | Key::Ctrl('c') => Outcome::No,
+            _ => Outcome::Open,
+        }
+    }
+
+    /// The command, for once the user's said yes.
+    pub fn command(self) -> Command {
+        self.command
+    }
+
+    /// The popup: the question, then what to press.  Lines aren't cut to any width.
+    pub fn render(&self) -> Vec<String> {
+        vec![
+            self.question.clone(),
+            format!("(y runs /{} {}, n or Esc doesn't)", self.command.name, self.command.args),
+        ]
+    }
+}
+
+#[test]
+fn confirm_keys() {
+    let command = Command::parse("/trigger purge --yes").unwrap();
+    let confirm = Confirm::new("Remove every trigger?".to_string(), command.clone());
+    assert_eq!(confirm.key(Key::Char('x')), Outcome::Open);
+    assert_eq!(confirm.key(Key::Char('\n')), Outcome::Open);
+    assert_eq!(confirm.key(Key::Esc), Outcome::No);
+    assert_eq!(confirm.key(Key::Char('Y')), Outcome::Yes);
+    assert_eq!(confirm.render(), vec!["Remove every trigger?", "(y runs /trigger purge --yes, n or Esc doesn't)"]);
+    assert_eq!(confirm.command(), command);
+}
@@ -0,0 +1,125 @@
+//! Running the TermUiManager with no terminal at all, not even stdin and stdout: for tests that
+//! want to drive the whole client and see what it shows, and for bots.
+//!
+//! TermUiManager::headless() makes one in line mode (see Screen), except that the lines pushed to
+//! its windows are kept for the HeadlessUi to look at instead of being printed, and what's typed
+//! comes from the HeadlessUi instead of stdin.  Everything else (windows, status, the lot) works
+//! as usual, it just never gets drawn.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::error::MintError;
+use crate::line::Line;
+use crate::meta::{Listener, ReadinessPager};
+
+use super::TermEvent;
+
+/// Every line pushed to a window, and the window it went to, oldest first.  Shared between the
+/// TermUiManager and its HeadlessUi.
+pub(super) type Shown = Rc<RefCell<Vec<(String, Line)>>>;
+
+/// The other end of a headless TermUiManager.
+pub struct HeadlessUi {
+    shown: Shown,
+    input: Sender<String>,
+}
+
+impl HeadlessUi {
+    /// A HeadlessUi, and what its TermUiManager needs to keep: somewhere to put what's shown,
+    /// and where what's typed comes from.
+    pub(super) fn new() -> (HeadlessUi, Shown, Receiver<String>) {
+        let shown = Rc::new(RefCell::new(vec![]));
+        let (input, rx) = mpsc::channel();
+        (HeadlessUi { shown: shown.clone(), input }, shown, rx)
+    }
+
+    /// Type a line into the window in front, as if Enter had been pressed after it.
+    pub fn type_line(&self, line: &str) {
+        // The listener only goes if the TermUiManager does, and then there's no one to type to.
+        let _ = self.input.send(line.to_string());
+    }
+
+    /// Somewhere to type lines from, for another thread.
+    pub fn keyboard(&self) -> Sender<String> {
+        self.input.clone()
+    }
+
+    /// Everything shown so far, and the window it went to, oldest first.
+    pub fn shown(&self) -> Vec<(String, Line)> {
+        self.shown.borrow().clone()
+    }
+
+    /// The text of what's been shown in `window`, oldest first.
+    pub fn text(&self, window: &str) -> Vec<String> {
+        self.shown.borrow().iter()
+            .filter(|(w, _)| w == window)
+            .map(|(_, line)| line.text().to_string())
+            .collect()
+    }
+
+    /// Forget what's been shown so far, e.g. between the steps of a test.
+    pub fn clear(&self) {
+        self.shown.borrow_mut().clear();
+    }
+}
+
+/// Listener for what's typed into a HeadlessUi.
+pub(super) struct TypedListener {
+    pub rx: Receiver<String>,
+    pub tx: Sender<TermEvent>,
+}
+
+impl Listener for TypedListener {
+    fn name(&self) -> String {
+        "headless-input".to_string()
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        for line in self.rx.iter() {
+            self.tx.send(TermEvent::Line { line })?;
+            flag.ok();
+        }
+        // The HeadlessUi's gone, and with it anything more to type, but as with stdin in line
+        // mode, the rest of the client carries on.
+        loop {
+            std::thread::park();
+        }
+    }
+}
+
+#[test]
+fn headless_ui() {
+    use crate::events::ThreadedManager;
+    use crate::meta::{Event, EventManager};
+    use crate::ui::UserInterface;
+    use super::TermUiManager;
+
+    let (tui, headless) = TermUiManager::headless();
+    let tui = Rc::new(RefCell::new(tui));
+    let mut manager = ThreadedManager::new();
+    manager.start_source(tui.clone());
+
+    tui.borrow_mut().push_to_window("default".to_string(), Line::client("Hello")).unwrap();
+    tui.borrow_mut().push_to_window("other".to_string(), Line::client("Elsewhere")).unwrap();
+    assert_eq!(headless.text("default"), vec!["Hello".to_string()]);
+    assert_eq!(headless.shown().len(), 2);
+    // Only the window out of sight has anything unread.
+    assert_eq!(tui.borrow_mut().take_activity(), vec![("other".to_string(), 1)]);
+    assert_eq!(tui.borrow_mut().take_activity(), vec![]);
+    tui.borrow_mut().show_window("other".to_string());
+    assert_eq!(tui.borrow_mut().take_activity(), vec![("other".to_string(), 0)]);
+    tui.borrow_mut().show_window("default".to_string());
+    headless.clear();
+    assert!(headless.shown().is_empty());
+
+    headless.type_line("look");
+    match manager.next_event() {
+        Ok(Event::UserInput { line, which }) => {
+            assert_eq!(line, "look");
+            assert_eq!(tui.borrow().window_name(which).as_deref(), Some("default"));
+        },
+        other => panic!("Expected the line typed, not {:?}", other),
+    }
+}
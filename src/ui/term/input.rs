@@ -1,6 +1,31 @@
 
 use crate::ui::term::{Window, text::force_width};
 
+use unicode_normalization::char::{compose, is_combining_mark};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use std::collections::VecDeque;
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use unicode_width::UnicodeWidthChar;
+
+/// How many submitted lines the input history remembers.
+const HISTORY_SIZE: usize = 500;
+
+/// How many pieces of killed text the kill ring holds for yanking back.
+const KILL_RING_SIZE: usize = 16;
+
+/// Where we are while going through the history with history_prev() and history_next().
+struct Browsing {
+    // Index into the history of the entry being shown.
+    index: usize,
+    // What was in the line when we started, which is also what entries have to start with.
+    typed: String,
+}
+
 /// UI for input/editing of a single line of text on the terminal.
 pub struct InputLine {
     // We could have used a more clever data structure, but as best I could tell from a cursory
@@ -8,47 +33,59 @@ pub struct InputLine {
     // shuffling memory around, either.  Maybe we'll need/want to upgrade, but we can start simple
     // and see if it performs unacceptably for the kind of editing we need to do.
     //
-    // TODO: Should this be Vec<String> later because of unicode?  char is probably faster for now
-    // though.
-    buffer: Vec<char>,
+    // Each entry is a grapheme: a character along with any accents, skin tones and so on that go
+    // with it, which the terminal draws as one thing and which the cursor moves over (and delete
+    // deletes) all at once.
+    buffer: Vec<String>,
     // The cursor is 0-indexed... but keep in mind that we usually think of a cursor as BETWEEN two
-    // characters.
+    // graphemes.
     cursor: usize,
     target_width: usize,
+    // The input area is always at least this many lines, even if there's less to show, so the
+    // rest of the screen doesn't jump around as the line gets longer and shorter.
+    reserved_height: usize,
+
+    // Lines that have been submitted, oldest first.
+    history: VecDeque<String>,
+    browsing: Option<Browsing>,
+
+    // Text taken out by the kill commands, newest first, and if the last thing done was a yank,
+    // where the yanked text starts and which entry it was (for yank_pop() to swap it for the one
+    // before.)
+    kill_ring: VecDeque<String>,
+    yanked: Option<(usize, usize)>,
+
+    // Show every grapheme as a "*", because what's being typed is a password.
+    masked: bool,
 }
 
 impl Window for InputLine {
     fn render(&self) -> Vec<String> {
-        // Split the buffer up into chunks of size `target_width`, turn them into strings and
-        // force_width() them.
-        let chunks: Vec<String> = self.buffer.chunks(self.target_width).map(|chunk| {
-            let chunk: String = chunk.iter().collect();
-            force_width(chunk, self.target_width)
+        // Split the buffer up into screen lines, turn them into strings and force_width() them
+        // (which mostly just pads them; they're already cut to fit.)
+        let mut chunks: Vec<String> = self.rows().into_iter().map(|(start, end)| {
+            let text: String = self.buffer[start..end].iter().map(|g| self.shown(g)).collect();
+            force_width(&text, self.target_width)
         }).collect();
 
-        // It's possible for there to be no results if the buffer is completely empty, which
-        // happens when someone erases everything in the line or it's been cleared.  In that case,
-        // we want to still return a single line of spaces so the screen clears.
-        if chunks.len() > 0 {
-            chunks
-        } else {
-            vec![" ".repeat(self.target_width)]
+        // Fill out any reserved lines we aren't using with spaces, so the screen clears.
+        let height = self.get_size().1;
+        while chunks.len() < height {
+            chunks.push(" ".repeat(self.target_width));
         }
+        chunks
     }
 
     fn get_size(&self) -> (usize, usize) {
-        // This is probably stupid, but casting to a float and using ceil seemed even more stupid.
-        let mut lines: usize = self.buffer.len() / self.target_width;
-        let remainder: usize = self.buffer.len() % self.target_width;
-        if remainder > 0 || lines == 0 {
-            lines += 1;
-        }
-        (self.target_width, lines)
+        (self.target_width, self.rows().len().max(self.reserved_height))
     }
 
     fn get_cursor_pos(&self) -> (usize, usize) {
-        let x: usize = self.cursor % self.target_width;
-        let y: usize = self.cursor / self.target_width;
+        let rows = self.rows();
+        // The cursor's on the first line that ends after it.  At the very end of the buffer, that
+        // means the last line.
+        let y = rows.iter().position(|(_, end)| self.cursor < *end).unwrap_or(rows.len() - 1);
+        let x = self.buffer[rows[y].0..self.cursor].iter().map(|g| self.shown(g).width()).sum();
 
         (x, y)
     }
@@ -68,26 +105,160 @@ impl InputLine {
             buffer: vec![],
             cursor: 0,
             target_width: width,
+            reserved_height: 1,
+            history: VecDeque::new(),
+            browsing: None,
+            kill_ring: VecDeque::new(),
+            yanked: None,
+            masked: false,
+        }
+    }
+
+    /// Where the buffer breaks into screen lines, as (start, end) indexes into it, going by how
+    /// many columns each grapheme takes up.  There's always at least one line, and if the last
+    /// one's full there's an empty one after it, for the cursor to sit on at the end.
+    fn rows(&self) -> Vec<(usize, usize)> {
+        let mut rows = vec![];
+        let (mut start, mut used) = (0, 0);
+
+        for (i, g) in self.buffer.iter().enumerate() {
+            let w = self.shown(g).width();
+            // A line always gets at least one grapheme, even if the screen's too narrow for it.
+            if used + w > self.target_width && i > start {
+                rows.push((start, i));
+                start = i;
+                used = 0;
+            }
+            used += w;
+        }
+
+        rows.push((start, self.buffer.len()));
+        if used >= self.target_width {
+            rows.push((self.buffer.len(), self.buffer.len()));
+        }
+        rows
+    }
+
+    /// Hide what's typed behind asterisks, or stop.  Lines submitted while it's hidden aren't
+    /// remembered in the history.
+    pub fn set_masked(&mut self, masked: bool) {
+        self.masked = masked;
+    }
+
+    /// How a grapheme in the buffer looks on the screen.
+    fn shown<'a>(&self, grapheme: &'a str) -> &'a str {
+        if self.masked { "*" } else { grapheme }
+    }
+
+    /// Take the line out of the buffer, leaving it empty, and remember it in the history.
+    pub fn submit(&mut self) -> String {
+        let line = self.as_text();
+        self.set_string(String::new());
+        self.browsing = None;
+
+        // Blank lines and the same thing twice in a row aren't worth remembering, and passwords
+        // shouldn't be.
+        if !self.masked && !line.trim().is_empty() && self.history.back() != Some(&line) {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+        line
+    }
+
+    /// Replace the line with the previous history entry that starts with whatever was typed
+    /// before going into the history (so with nothing typed, that's any entry.)  Does nothing if
+    /// there isn't one.
+    pub fn history_prev(&mut self) {
+        let (start, typed) = match self.browsing {
+            Some(ref b) => (b.index, b.typed.clone()),
+            None => (self.history.len(), self.as_text()),
+        };
+
+        if let Some(index) = (0..start).rev().find(|i| self.history[*i].starts_with(&typed)) {
+            self.show_entry(self.history[index].clone());
+            self.browsing = Some(Browsing { index, typed });
+        }
+    }
+
+    /// The opposite of history_prev().  Going past the newest entry puts back what was typed
+    /// before.
+    pub fn history_next(&mut self) {
+        let (start, typed) = match self.browsing {
+            Some(ref b) => (b.index + 1, b.typed.clone()),
+            None => return,
+        };
+
+        match (start..self.history.len()).find(|i| self.history[*i].starts_with(&typed)) {
+            Some(index) => {
+                self.show_entry(self.history[index].clone());
+                self.browsing = Some(Browsing { index, typed });
+            },
+            None => {
+                self.show_entry(typed);
+                self.browsing = None;
+            },
         }
     }
 
+    // Put a line from the history in the buffer, with the cursor at the end to carry on typing.
+    fn show_entry(&mut self, line: String) {
+        self.set_string(line);
+        self.cursor = self.buffer.len();
+    }
+
+    /// Always take up at least `lines` lines of the screen, leaving them blank if there isn't that
+    /// much input.  Input that needs more lines than that still gets them.
+    pub fn reserve_height(&mut self, lines: usize) {
+        self.reserved_height = lines.max(1);
+    }
+
     /// Insert a single character at the current cursor position.
+    ///
+    /// A character that belongs with the grapheme before the cursor (an accent, a skin tone, the
+    /// next part of an emoji sequence) goes on the end of it instead of starting a new one.
+    /// Combining marks (which is what some terminals send for dead keys, e.g. 'e' followed by
+    /// U+0301 instead of 'é') are folded into the character before them when there's a
+    /// precomposed version of the pair.
     pub fn insert_char(&mut self, what: char) {
-        // The cursor is considered to be between two characters.  So, taken as an array index, it
-        // will point to the character directly after itself, unless it's at the end, in which case
+        // Editing a line from the history makes it a new line, and the next trip into the
+        // history starts over with it.
+        self.browsing = None;
+
+        if self.cursor > 0 {
+            let before = &mut self.buffer[self.cursor - 1];
+            let last = before.chars().next_back();
+            if let Some(composed) = last.filter(|_| is_combining_mark(what)).and_then(|c| compose(c, what)) {
+                before.pop();
+                before.push(composed);
+                return;
+            }
+            let mut joined = before.clone();
+            joined.push(what);
+            if joined.graphemes(true).count() == 1 {
+                *before = joined;
+                return;
+            }
+        }
+
+        // The cursor is considered to be between two graphemes.  So, taken as an array index, it
+        // will point to the grapheme directly after itself, unless it's at the end, in which case
         // using it like an index will probably cause a panic.
         if self.cursor >= self.buffer.len() {
-            self.buffer.push(what);
+            self.buffer.push(what.to_string());
             self.cursor = self.buffer.len();
         } else {
-            self.buffer.insert(self.cursor, what);
+            self.buffer.insert(self.cursor, what.to_string());
             self.cursor += 1;
         }
     }
 
-    /// Delete n chars ahead of the cursor (positive input) or behind it (negative input), moving
+    /// Delete n graphemes ahead of the cursor (positive input) or behind it (negative input), moving
     /// it backward if appropriate.
     pub fn delete_chars(&mut self, n: isize) {
+        self.browsing = None;
+
         if n.is_negative() {
             // What we do is split the Vec in half, truncate the first half (e.g. what's before the
             // cursor) by however much we need to, and then glue the two halves back together.  The
@@ -123,17 +294,19 @@ impl InputLine {
             // chars.  We don't have to move the cursor since we're only deleting things to the
             // right.
 
-            let splitpoint = self.cursor + n as usize;
+            // Running off the end only deletes as far as the end.
+            let n = (n as usize).min(self.buffer.len() - self.cursor);
+            let splitpoint = self.cursor + n;
             let mut remainder = if splitpoint < self.buffer.len() {
                 self.buffer.split_off(splitpoint)
             } else {
                 vec![]
             };
 
-            if n as usize >= self.buffer.len() {
+            if n >= self.buffer.len() {
                 self.buffer = vec![];
             } else {
-                self.buffer.truncate(self.buffer.len() - n as usize);
+                self.buffer.truncate(self.buffer.len() - n);
             }
 
             self.buffer.append(&mut remainder);
@@ -142,12 +315,12 @@ impl InputLine {
 
     /// Set the contents of the input to some String.
     pub fn set_string(&mut self, what: String) {
-        self.buffer = what.chars().collect();
+        self.buffer = what.graphemes(true).map(|g| g.to_string()).collect();
         // We have to reset the cursor to somewhere anyway.
         self.cursor = 0;
     }
 
-    /// Move the cursor `offset` chars to the left or right in the buffer, not allowing it to go
+    /// Move the cursor `offset` graphemes to the left or right in the buffer, not allowing it to go
     /// out-of-bounds.
     pub fn move_cursor(&mut self, offset: isize) {
         if offset.is_negative() {
@@ -165,9 +338,372 @@ impl InputLine {
         }
     }
 
+    pub fn cursor_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn cursor_to_end(&mut self) {
+        self.cursor = self.buffer.len();
+    }
+
+    /// Kill the word before the cursor, and any spaces between it and the cursor, the way C-w
+    /// does in a shell.
+    pub fn delete_word(&mut self) {
+        let before = &self.buffer[..self.cursor];
+        let blank = |g: &String| g.chars().all(char::is_whitespace);
+        let spaces = before.iter().rev().take_while(|g| blank(g)).count();
+        let word = before.iter().rev().skip(spaces).take_while(|g| !blank(g)).count();
+        self.kill(self.cursor - spaces - word, self.cursor);
+    }
+
+    /// Kill from the cursor to the end of the word it's in (or the next word, if it's between
+    /// words), like M-d in readline.
+    pub fn delete_word_forward(&mut self) {
+        self.kill(self.cursor, self.word_end());
+    }
+
+    /// Kill everything from the cursor to the end of the line.
+    pub fn kill_to_end(&mut self) {
+        self.kill(self.cursor, self.buffer.len());
+    }
+
+    /// Move the cursor back to the start of the word it's in, or the one before.  Words are
+    /// letters and digits; anything else is between words.
+    pub fn word_left(&mut self) {
+        let before = &self.buffer[..self.cursor];
+        let gap = before.iter().rev().take_while(|g| !in_word(g)).count();
+        let word = before.iter().rev().skip(gap).take_while(|g| in_word(g)).count();
+        self.cursor -= gap + word;
+    }
+
+    /// Move the cursor on to the end of the word it's in, or the next one.
+    pub fn word_right(&mut self) {
+        self.cursor = self.word_end();
+    }
+
+    // Where word_right() would leave the cursor.
+    fn word_end(&self) -> usize {
+        let after = &self.buffer[self.cursor..];
+        let gap = after.iter().take_while(|g| !in_word(g)).count();
+        let word = after.iter().skip(gap).take_while(|g| in_word(g)).count();
+        self.cursor + gap + word
+    }
+
+    // Take graphemes `start` to `end` out of the line and put them on the kill ring, leaving the
+    // cursor where they were.
+    fn kill(&mut self, start: usize, end: usize) {
+        self.browsing = None;
+        if start == end {
+            return;
+        }
+        let killed: String = self.buffer.drain(start..end).collect();
+        self.cursor = start;
+        if self.kill_ring.len() == KILL_RING_SIZE {
+            self.kill_ring.pop_back();
+        }
+        self.kill_ring.push_front(killed);
+    }
+
+    /// Put the most recently killed text back in at the cursor.
+    pub fn yank(&mut self) {
+        if let Some(text) = self.kill_ring.front().cloned() {
+            let start = self.cursor;
+            self.replace_before_cursor(start, &text);
+            self.yanked = Some((start, 0));
+        }
+    }
+
+    /// Straight after a yank (or another of these), swap what was yanked for the text killed
+    /// before it, going round to the newest again after the oldest.
+    pub fn yank_pop(&mut self) {
+        if let Some((start, index)) = self.yanked {
+            let index = (index + 1) % self.kill_ring.len();
+            let text = self.kill_ring[index].clone();
+            self.replace_before_cursor(start, &text);
+            self.yanked = Some((start, index));
+        }
+    }
+
+    /// Anything but another yank_pop() after a yank means the yank's finished with.
+    pub fn end_yank(&mut self) {
+        self.yanked = None;
+    }
+
+    /// Where the word before the cursor starts (in graphemes), and what it is so far.  It's empty
+    /// if there's a space (or nothing) right before the cursor.
+    pub fn word_before_cursor(&self) -> (usize, String) {
+        let before = &self.buffer[..self.cursor];
+        let length = before.iter().rev().take_while(|g| !g.chars().all(char::is_whitespace)).count();
+        let start = self.cursor - length;
+        (start, self.buffer[start..self.cursor].concat())
+    }
+
+    /// Replace everything from `start` up to the cursor with `text`, leaving the cursor after it.
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        self.browsing = None;
+        let start = start.min(self.cursor);
+        let graphemes: Vec<String> = text.graphemes(true).map(|g| g.to_string()).collect();
+        let length = graphemes.len();
+        self.buffer.splice(start..self.cursor, graphemes);
+        self.cursor = start + length;
+    }
+
     pub fn as_text(&self) -> String {
-        let result: String = self.buffer.iter().collect();
-        result
+        self.buffer.concat()
+    }
+}
+
+/// Whether a grapheme is part of a word, for moving and killing by words.
+fn in_word(grapheme: &str) -> bool {
+    grapheme.chars().any(char::is_alphanumeric)
+}
+
+
+#[test]
+fn input_composes_dead_keys() {
+    let mut input = InputLine::new(80, 1);
+    for c in "cafe\u{301} au lait".chars() {
+        input.insert_char(c);
     }
+    assert_eq!(input.as_text(), "café au lait");
+    assert_eq!(input.get_cursor_pos(), (12, 0));
 }
 
+#[test]
+fn input_delete_word() {
+    let mut input = InputLine::new(80, 1);
+    input.set_string("say hello  there".to_string());
+    input.cursor_to_end();
+    input.delete_word();
+    assert_eq!(input.as_text(), "say hello  ");
+    input.delete_word();
+    assert_eq!(input.as_text(), "say ");
+    input.cursor_to_start();
+    input.delete_word();
+    assert_eq!(input.as_text(), "say ");
+}
+
+#[test]
+fn input_completion_edits() {
+    let mut input = InputLine::new(80, 1);
+    input.set_string("kill gob now".to_string());
+    input.move_cursor(8);
+    assert_eq!(input.word_before_cursor(), (5, "gob".to_string()));
+    input.replace_before_cursor(5, "goblin");
+    assert_eq!(input.as_text(), "kill goblin now");
+    assert_eq!(input.get_cursor_pos(), (11, 0));
+    input.move_cursor(1);
+    assert_eq!(input.word_before_cursor(), (12, String::new()));
+}
+
+#[test]
+fn input_words_and_kill_ring() {
+    let mut input = InputLine::new(80, 1);
+    input.set_string("get sword, from chest".to_string());
+    input.word_right();
+    input.word_right();
+    assert_eq!(input.cursor, 9);
+    input.word_right();
+    input.word_left();
+    input.word_left();
+    assert_eq!(input.cursor, 4);
+
+    input.delete_word_forward();
+    assert_eq!(input.as_text(), "get , from chest");
+    input.cursor_to_end();
+    input.word_left();
+    input.kill_to_end();
+    assert_eq!(input.as_text(), "get , from ");
+
+    input.cursor_to_start();
+    input.yank();
+    assert_eq!(input.as_text(), "chestget , from ");
+    input.yank_pop();
+    assert_eq!(input.as_text(), "swordget , from ");
+    assert_eq!(input.cursor, 5);
+    input.yank_pop();
+    assert_eq!(input.as_text(), "chestget , from ");
+
+    // Once something else has happened, yank_pop() has nothing to swap.
+    input.end_yank();
+    input.yank_pop();
+    assert_eq!(input.as_text(), "chestget , from ");
+}
+
+#[test]
+fn input_reserved_height() {
+    let mut input = InputLine::new(4, 1);
+    input.reserve_height(3);
+    input.set_string("abcde".to_string());
+    assert_eq!(input.get_size(), (4, 3));
+    assert_eq!(input.render(), vec!["abcd", "e   ", "    "]);
+
+    // It still grows past what's reserved.
+    input.set_string("abcdefghijklm".to_string());
+    assert_eq!(input.get_size(), (4, 4));
+}
+
+#[test]
+fn input_history() {
+    let mut input = InputLine::new(80, 1);
+    for line in &["look", "say hi", "look", "score"] {
+        input.set_string(line.to_string());
+        input.submit();
+    }
+
+    input.history_prev();
+    assert_eq!(input.as_text(), "score");
+    input.history_prev();
+    input.history_prev();
+    assert_eq!(input.as_text(), "say hi");
+    input.history_next();
+    input.history_next();
+    assert_eq!(input.as_text(), "score");
+
+    // Prefix search, and back to what was typed when we run out.
+    input.history_next();
+    input.set_string("lo".to_string());
+    input.history_prev();
+    assert_eq!(input.as_text(), "look");
+    input.history_prev();
+    assert_eq!(input.as_text(), "look");
+    input.history_next();
+    input.history_next();
+    assert_eq!(input.as_text(), "lo");
+}
+
+#[test]
+fn input_masked() {
+    let mut input = InputLine::new(4, 1);
+    input.set_masked(true);
+    input.set_string("s3cr\u{e9}t".to_string());
+    input.cursor_to_end();
+    assert_eq!(input.render(), vec!["****", "**  "]);
+    assert_eq!(input.get_cursor_pos(), (2, 1));
+    assert_eq!(input.submit(), "s3cr\u{e9}t");
+
+    // It wasn't remembered.
+    input.set_masked(false);
+    input.history_prev();
+    assert_eq!(input.as_text(), "");
+}
+
+#[test]
+fn input_wide_chars() {
+    // Two columns each, so only two fit on a line of five, and the cursor moves two at a time.
+    let mut input = InputLine::new(5, 1);
+    input.set_string("日本語".to_string());
+    assert_eq!(input.render(), vec!["日本 ", "語   "]);
+    input.move_cursor(3);
+    assert_eq!(input.get_cursor_pos(), (2, 1));
+
+    // A full last line gets an empty one after it for the cursor.
+    input.set_string("abcde".to_string());
+    assert_eq!(input.get_size(), (5, 2));
+    input.move_cursor(5);
+    assert_eq!(input.get_cursor_pos(), (0, 1));
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug)]
+enum Edit {
+    Insert(char),
+    Move(isize),
+    Delete(isize),
+    HistoryPrev,
+    HistoryNext,
+    Submit,
+}
+
+#[cfg(test)]
+fn edit() -> impl Strategy<Value = Edit> {
+    // Characters that can join up with others into one grapheme (combining marks, regional
+    // indicators, Hangul jamo and the like) are left out, since the model below doesn't know
+    // about that.
+    let alone = |c: &char| [format!("a{}", c), format!("{}a", c), format!("{}{}", c, c)].iter()
+        .all(|s| s.graphemes(true).count() == 2);
+    prop_oneof![
+        4 => any::<char>().prop_filter("joins up", move |c| alone(c)
+                                       && c.width().is_some()).prop_map(Edit::Insert),
+        1 => (-10isize..10).prop_map(Edit::Move),
+        1 => (-10isize..10).prop_map(Edit::Delete),
+        1 => Just(Edit::HistoryPrev),
+        1 => Just(Edit::HistoryNext),
+        1 => Just(Edit::Submit),
+    ]
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn input_edits(edits in prop::collection::vec(edit(), 0..100), width in 1usize..30) {
+        let mut input = InputLine::new(width, 1);
+        // What the buffer and cursor ought to be, done the simple way.
+        let (mut model, mut cursor): (Vec<char>, usize) = (vec![], 0);
+
+        for edit in edits {
+            match edit {
+                Edit::Insert(c) => {
+                    input.insert_char(c);
+                    model.insert(cursor, c);
+                    cursor += 1;
+                },
+                Edit::Move(n) => {
+                    input.move_cursor(n);
+                    cursor = (cursor as isize + n).clamp(0, model.len() as isize) as usize;
+                },
+                Edit::Delete(n) if n < 0 => {
+                    input.delete_chars(n);
+                    let from = cursor.saturating_sub(n.unsigned_abs());
+                    model.drain(from..cursor);
+                    cursor = from;
+                },
+                Edit::Delete(n) => {
+                    input.delete_chars(n);
+                    model.drain(cursor..(cursor + n as usize).min(model.len()));
+                },
+                // The history's checked elsewhere; here it's just another way to change the line.
+                Edit::HistoryPrev | Edit::HistoryNext | Edit::Submit => {
+                    match edit {
+                        Edit::HistoryPrev => input.history_prev(),
+                        Edit::HistoryNext => input.history_next(),
+                        _ => { input.submit(); },
+                    }
+                    model = input.as_text().chars().collect();
+                    cursor = input.cursor;
+                },
+            }
+
+            prop_assert_eq!(input.as_text(), model.iter().collect::<String>());
+            prop_assert_eq!(input.buffer.len(), model.len());
+            prop_assert_eq!(input.cursor, cursor);
+
+            let rows = input.render();
+            let (w, h) = input.get_size();
+            prop_assert_eq!(w, width);
+            prop_assert_eq!(rows.len(), h);
+            for row in &rows {
+                prop_assert_eq!(row.width(), width, "{:?}", row);
+            }
+            let (x, y) = input.get_cursor_pos();
+            prop_assert!(y < h);
+            prop_assert!(x <= width);
+        }
+    }
+}
+
+#[test]
+fn input_graphemes() {
+    // An accent with no precomposed version, and a family emoji built from five characters: the
+    // cursor goes over each of them in one step, and backspace takes each out whole.
+    let mut input = InputLine::new(80, 1);
+    for c in "q\u{323}\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}!".chars() {
+        input.insert_char(c);
+    }
+    assert_eq!(input.buffer.len(), 3);
+    assert_eq!(input.get_cursor_pos(), (4, 0));
+    input.move_cursor(-1);
+    input.delete_chars(-1);
+    assert_eq!(input.as_text(), "q\u{323}!");
+    assert_eq!(input.get_cursor_pos(), (1, 0));
+}
@@ -0,0 +1,447 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Stdin, stdin};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use termion::event::{Event, Key, MouseEvent, parse_event};
+
+use crate::charset::{Charset, DecodingReader};
+
+/// Things the terminal UI does by itself when certain keys are pressed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    Submit,
+    CursorLeft,
+    CursorRight,
+    LineStart,
+    LineEnd,
+    DeleteBack,
+    DeleteForward,
+    DeleteWord,
+    DeleteWordForward,
+    KillToEnd,
+    Yank,
+    YankPop,
+    WordLeft,
+    WordRight,
+    Mark,
+    JumpToMark,
+    ScrollToEnd,
+    CopyLastLine,
+    SwitchWindow,
+    PageUp,
+    PageDown,
+    ScrollUp,
+    ScrollDown,
+    HistoryPrev,
+    HistoryNext,
+    Complete,
+    NextLink,
+    Select,
+}
+
+impl Action {
+    pub const ALL: [Action; 29] = [
+        Action::Quit, Action::Submit, Action::CursorLeft, Action::CursorRight, Action::LineStart,
+        Action::LineEnd, Action::DeleteBack, Action::DeleteForward, Action::DeleteWord,
+        Action::DeleteWordForward, Action::KillToEnd, Action::Yank, Action::YankPop, Action::WordLeft,
+        Action::WordRight, Action::Mark,
+        Action::JumpToMark, Action::ScrollToEnd, Action::CopyLastLine, Action::SwitchWindow,
+        Action::PageUp, Action::PageDown, Action::ScrollUp, Action::ScrollDown, Action::HistoryPrev,
+        Action::HistoryNext, Action::Complete, Action::NextLink, Action::Select,
+    ];
+
+    /// What the action's called when binding keys to it, e.g. "cursor-left".
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::Quit => "quit",
+            Action::Submit => "send-line",
+            Action::CursorLeft => "cursor-left",
+            Action::CursorRight => "cursor-right",
+            Action::LineStart => "line-start",
+            Action::LineEnd => "line-end",
+            Action::DeleteBack => "delete-back",
+            Action::DeleteForward => "delete-forward",
+            Action::DeleteWord => "delete-word",
+            Action::DeleteWordForward => "delete-word-forward",
+            Action::KillToEnd => "kill-line",
+            Action::Yank => "yank",
+            Action::YankPop => "yank-pop",
+            Action::WordLeft => "word-left",
+            Action::WordRight => "word-right",
+            Action::Mark => "mark",
+            Action::JumpToMark => "jump-to-mark",
+            Action::ScrollToEnd => "scroll-to-end",
+            Action::CopyLastLine => "copy-last-line",
+            Action::SwitchWindow => "switch-window",
+            Action::PageUp => "page-up",
+            Action::PageDown => "page-down",
+            Action::ScrollUp => "scroll-up",
+            Action::ScrollDown => "scroll-down",
+            Action::HistoryPrev => "history-prev",
+            Action::HistoryNext => "history-next",
+            Action::Complete => "complete",
+            Action::NextLink => "next-link",
+            Action::Select => "select",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().cloned().find(|action| action.name() == name)
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match *self {
+            Action::Quit => "quit",
+            Action::Submit => "send the input line",
+            Action::CursorLeft => "move the cursor left",
+            Action::CursorRight => "move the cursor right",
+            Action::LineStart => "move the cursor to the start of the line",
+            Action::LineEnd => "move the cursor to the end of the line",
+            Action::DeleteBack => "delete backwards",
+            Action::DeleteForward => "delete forwards",
+            Action::DeleteWord => "delete the word before the cursor",
+            Action::DeleteWordForward => "delete to the end of the word",
+            Action::KillToEnd => "delete to the end of the line",
+            Action::Yank => "put back the last thing deleted",
+            Action::YankPop => "after putting something back, swap it for what was deleted before",
+            Action::WordLeft => "move the cursor back a word",
+            Action::WordRight => "move the cursor forward a word",
+            Action::Mark => "put a mark in the window",
+            Action::JumpToMark => "jump to the last mark",
+            Action::ScrollToEnd => "scroll to the newest lines",
+            Action::CopyLastLine => "copy the newest line to the clipboard",
+            Action::SwitchWindow => "pick a window to switch to",
+            Action::PageUp => "scroll back a page",
+            Action::PageDown => "scroll forward a page",
+            Action::ScrollUp => "scroll back a line",
+            Action::ScrollDown => "scroll forward a line",
+            Action::HistoryPrev => "recall an earlier input line",
+            Action::HistoryNext => "recall a later input line",
+            Action::Complete => "complete the word before the cursor",
+            Action::NextLink => "pick a link in the window, further back each time (then Enter follows it)",
+            Action::Select => "select text in the window with the keyboard, to copy it",
+        }
+    }
+}
+
+/// Which keys the UI does things with by itself.  Keys that aren't bound to anything here go up
+/// to the client as KeyPresses (if they have names) or get typed (if they're printable.)
+pub struct KeyBindings {
+    bound: HashMap<Key, Action>,
+}
+
+impl KeyBindings {
+    /// What, if anything, the UI does by itself with a key.
+    pub fn action(&self, key: &Key) -> Option<Action> {
+        self.bound.get(key).cloned()
+    }
+
+    pub fn bind(&mut self, key: Key, action: Action) {
+        self.bound.insert(key, action);
+    }
+
+    /// Returns false if the key wasn't bound to anything.
+    pub fn unbind(&mut self, key: &Key) -> bool {
+        self.bound.remove(key).is_some()
+    }
+
+    /// Every binding, by key name, sorted by name.
+    pub fn list(&self) -> Vec<(String, Action)> {
+        let mut list: Vec<(String, Action)> = self.bound.iter()
+            .filter_map(|(key, action)| key_name(key).map(|name| (name, *action)))
+            .collect();
+        list.sort_by(|a, b| a.0.cmp(&b.0));
+        list
+    }
+}
+
+impl Default for KeyBindings {
+    /// Emacs-ish, like a shell.
+    fn default() -> KeyBindings {
+        let defaults = [
+            (Key::Ctrl('c'), Action::Quit),
+            (Key::Char('\n'), Action::Submit),
+            (Key::Ctrl('b'), Action::CursorLeft),
+            (Key::Left, Action::CursorLeft),
+            (Key::Ctrl('f'), Action::CursorRight),
+            (Key::Right, Action::CursorRight),
+            (Key::Ctrl('h'), Action::DeleteBack),
+            (Key::Ctrl('d'), Action::DeleteForward),
+            // What's deleted by words or to the end of the line goes on the kill ring, for C-y
+            // (and M-y after it) to put back, like readline.
+            (Key::Ctrl('w'), Action::DeleteWord),
+            (Key::Alt('d'), Action::DeleteWordForward),
+            (Key::Ctrl('k'), Action::KillToEnd),
+            (Key::Ctrl('y'), Action::Yank),
+            (Key::Alt('y'), Action::YankPop),
+            (Key::Alt('b'), Action::WordLeft),
+            (Key::Alt('f'), Action::WordRight),
+            // Dividers, for keeping your place when you step away.
+            (Key::Alt('m'), Action::Mark),
+            (Key::Alt('j'), Action::JumpToMark),
+            (Key::Alt('e'), Action::ScrollToEnd),
+            (Key::Alt('c'), Action::CopyLastLine),
+            (Key::Alt('w'), Action::SwitchWindow),
+            (Key::PageUp, Action::PageUp),
+            (Key::PageDown, Action::PageDown),
+            // Typing something first and then going back only finds lines that start with it.
+            (Key::Ctrl('p'), Action::HistoryPrev),
+            (Key::Up, Action::HistoryPrev),
+            (Key::Ctrl('n'), Action::HistoryNext),
+            (Key::Down, Action::HistoryNext),
+            (Key::Char('\t'), Action::Complete),
+            (Key::Alt('l'), Action::NextLink),
+            (Key::Alt('v'), Action::Select),
+        ];
+        KeyBindings { bound: defaults.iter().cloned().collect() }
+    }
+}
+
+/// Give a key a name that can be used to bind things to it, e.g. "F5", "C-x" or "M-x" (for
+/// Alt-x).  Ordinary printable characters don't get names, since they're just text; neither do
+/// keys termion couldn't make sense of.
+///
+/// There's no way to tell numpad keys apart from their counterparts elsewhere on the keyboard;
+/// that would need the terminal in application keypad mode, and termion can't decode what it
+/// sends in that mode.
+pub fn key_name(key: &Key) -> Option<String> {
+    let name = match *key {
+        Key::F(n) => format!("F{}", n),
+        Key::Ctrl(c) => format!("C-{}", c),
+        Key::Alt(c) => format!("M-{}", c),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+        Key::Home => "Home".to_string(),
+        Key::End => "End".to_string(),
+        Key::PageUp => "PageUp".to_string(),
+        Key::PageDown => "PageDown".to_string(),
+        Key::Insert => "Insert".to_string(),
+        Key::Delete => "Delete".to_string(),
+        Key::Backspace => "Backspace".to_string(),
+        Key::Esc => "Esc".to_string(),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The key a name from key_name() belongs to.
+pub fn key_from_name(name: &str) -> Option<Key> {
+    let single = |rest: &str| {
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let key = match name {
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Home" => Key::Home,
+        "End" => Key::End,
+        "PageUp" => Key::PageUp,
+        "PageDown" => Key::PageDown,
+        "Insert" => Key::Insert,
+        "Delete" => Key::Delete,
+        "Backspace" => Key::Backspace,
+        "Esc" => Key::Esc,
+        "Enter" => Key::Char('\n'),
+        "Tab" => Key::Char('\t'),
+        _ => {
+            if let Some(rest) = name.strip_prefix("C-") {
+                Key::Ctrl(single(rest)?)
+            } else if let Some(rest) = name.strip_prefix("M-") {
+                Key::Alt(single(rest)?)
+            } else {
+                Key::F(name.strip_prefix('F')?.parse().ok()?)
+            }
+        },
+    };
+    Some(key)
+}
+
+/// Switch bracketed paste on and off.  While it's on, the terminal puts pasted text between
+/// PASTE_START and PASTE_END, so it can be told apart from typing.
+pub const BRACKETED_PASTE_ON: &str = "\x1b[?2004h";
+pub const BRACKETED_PASTE_OFF: &str = "\x1b[?2004l";
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Something that came in from the terminal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Key(Key),
+    Mouse(MouseEvent),
+    /// Text pasted in all at once, line breaks and all.
+    Paste(String),
+}
+
+/// The lines in pasted text, with whatever line endings it had.  A line break at the very end
+/// doesn't make another line.
+pub fn pasted_lines(text: &str) -> Vec<String> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    text.split('\n').map(String::from).collect()
+}
+
+/// Reads keys and mouse events from stdin.  This does the same job as termion's `events()`,
+/// except that it decides whether an ESC is a key of its own or the start of Alt+something by how
+/// soon the next byte arrives, rather than by whether the two happened to come in the same
+/// read().  The timeout is shared so it can be changed while the reader is running.
+pub struct KeyReader {
+    reader: DecodingReader<Stdin>,
+    buffer: VecDeque<u8>,
+    escape_timeout: Arc<AtomicUsize>,
+}
+
+impl KeyReader {
+    /// `escape_timeout` is in milliseconds.
+    pub fn new(charset: Charset, escape_timeout: Arc<AtomicUsize>) -> KeyReader {
+        KeyReader {
+            reader: DecodingReader::new(stdin(), charset),
+            buffer: VecDeque::new(),
+            escape_timeout,
+        }
+    }
+
+    /// Get another byte, waiting for one if there's nothing buffered.  None at end of input.
+    fn next_byte(&mut self) -> Option<io::Result<u8>> {
+        if self.buffer.is_empty() {
+            match self.read_more() {
+                Ok(false) => return None,
+                Ok(true) => { },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+
+    /// Add whatever there is to read (waiting for something) to the buffer.  False at end of
+    /// input.
+    fn read_more(&mut self) -> io::Result<bool> {
+        let mut chunk = [0u8; 64];
+        let n = self.reader.read(&mut chunk)?;
+        self.buffer.extend(&chunk[..n]);
+        Ok(n > 0)
+    }
+
+    /// Whether more input turns up within the escape timeout.
+    fn more_input_soon(&self) -> bool {
+        !self.buffer.is_empty() || self.more_to_read_soon()
+    }
+
+    /// Whether there'll be something for read_more() within the escape timeout.
+    fn more_to_read_soon(&self) -> bool {
+        if self.reader.has_buffered() {
+            return true;
+        }
+        let timeout = self.escape_timeout.load(Ordering::Relaxed);
+        let mut fds = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        // Safe since we're handing poll() exactly one pollfd that lives for the whole call.
+        unsafe { libc::poll(&mut fds, 1, timeout as libc::c_int) > 0 }
+    }
+
+    /// After an ESC, whether the rest of PASTE_START comes next, in which case it's taken off
+    /// the buffer.  Termion doesn't know that sequence, so this has to be looked for first.
+    fn paste_starts(&mut self) -> io::Result<bool> {
+        let rest = &PASTE_START[1..];
+        loop {
+            let have = self.buffer.len().min(rest.len());
+            if !self.buffer.iter().take(have).eq(rest[..have].iter()) {
+                return Ok(false);
+            }
+            if have == rest.len() {
+                self.buffer.drain(..have);
+                return Ok(true);
+            }
+            if !self.more_to_read_soon() || !self.read_more()? {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// The text of a bracketed paste, up to PASTE_END (which goes.)
+    fn read_paste(&mut self) -> io::Result<String> {
+        let mut bytes = vec![];
+        while !bytes.ends_with(PASTE_END) {
+            match self.next_byte() {
+                Some(Ok(b)) => bytes.push(b),
+                Some(Err(e)) => return Err(e),
+                None => break,
+            }
+        }
+        if bytes.ends_with(PASTE_END) {
+            bytes.truncate(bytes.len() - PASTE_END.len());
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl Iterator for KeyReader {
+    type Item = io::Result<Input>;
+
+    fn next(&mut self) -> Option<io::Result<Input>> {
+        loop {
+            let first = match self.next_byte()? {
+                Ok(b) => b,
+                Err(e) => return Some(Err(e)),
+            };
+            if first == b'\x1b' && !self.more_input_soon() {
+                return Some(Ok(Input::Key(Key::Esc)));
+            }
+            if first == b'\x1b' {
+                match self.paste_starts() {
+                    Ok(true) => return Some(self.read_paste().map(Input::Paste)),
+                    Ok(false) => { },
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            let parsed = parse_event(first, &mut std::iter::from_fn(|| self.next_byte()));
+            match parsed {
+                Ok(Event::Key(key)) => return Some(Ok(Input::Key(key))),
+                Ok(Event::Mouse(event)) => return Some(Ok(Input::Mouse(event))),
+                // Sequences termion doesn't know; nobody wants these.
+                Ok(Event::Unsupported(_)) => continue,
+                // Likewise, garbage (or a sequence cut off by the end of input) isn't worth
+                // crashing the input thread over.
+                Err(ref e) if e.kind() == io::ErrorKind::Other => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[test]
+fn key_bindings() {
+    for name in &["F5", "C-w", "M-x", "PageUp", "Enter", "Tab", "Backspace"] {
+        assert_eq!(key_from_name(name).and_then(|key| key_name(&key)).as_deref(), Some(*name));
+    }
+    assert_eq!(key_from_name("C-"), None);
+    assert_eq!(key_from_name("Fish"), None);
+    for action in Action::ALL.iter() {
+        assert_eq!(Action::from_name(action.name()), Some(*action));
+    }
+
+    let mut bindings = KeyBindings::default();
+    assert_eq!(bindings.action(&Key::Ctrl('b')), Some(Action::CursorLeft));
+    bindings.bind(Key::Ctrl('w'), Action::Yank);
+    assert!(bindings.unbind(&Key::Ctrl('b')));
+    assert!(!bindings.unbind(&Key::Ctrl('b')));
+    assert_eq!(bindings.action(&Key::Ctrl('b')), None);
+    assert!(bindings.list().contains(&("C-w".to_string(), Action::Yank)));
+}
+
+#[test]
+fn pasting() {
+    assert_eq!(pasted_lines("look"), vec!["look"]);
+    assert_eq!(pasted_lines("look\r\n"), vec!["look"]);
+    assert_eq!(pasted_lines("n\re\n\ns\n"), vec!["n", "e", "", "s"]);
+}
@@ -0,0 +1,289 @@
+//! Tiling the part of the screen above the status bar between several windows.
+//!
+//! A layout is a tree: panes, each showing a window, and splits, which divide their room between
+//! the layouts inside them.  There's always one pane that shows whichever window is current (the
+//! one /window switches); any others show the same window whatever's current.
+
+/// Which way a split divides up its room.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// One above the other, with a row of dashes between each.
+    Stacked,
+    /// Next to each other, with a column of bars between each.
+    SideBySide,
+}
+
+/// A rectangle of the screen, in cells, with the origin at the top left.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Shows a window: the one named, or for None, whichever is current.
+    Pane(Option<String>),
+    Split(Direction, Vec<Layout>),
+}
+
+/// Where everything in a layout goes, once it's been fitted to the room there is.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Arrangement {
+    /// What each pane shows (as in Layout::Pane) and where.
+    pub panes: Vec<(Option<String>, Rect)>,
+    /// The dividers between panes.  A Stacked divider is one row high, a SideBySide one is one
+    /// column wide.
+    pub dividers: Vec<(Direction, Rect)>,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout::Pane(None)
+    }
+}
+
+impl Layout {
+    /// Add a pane showing `window`, above (for Stacked) or left of (for SideBySide) everything
+    /// else.  Several panes added the same way share that side between them.
+    pub fn add(&mut self, window: String, direction: Direction) {
+        let pane = Layout::Pane(Some(window));
+        match self {
+            Layout::Split(d, children) if *d == direction => children.insert(0, pane),
+            _ => {
+                let rest = std::mem::take(self);
+                *self = Layout::Split(direction, vec![pane, rest]);
+            },
+        }
+    }
+
+    /// Take away every pane showing `window`.  Splits left with only one thing in them go, too.
+    /// The pane showing the current window is never removed.  Returns false if there weren't
+    /// any panes for the window.
+    pub fn remove(&mut self, window: &str) -> bool {
+        match self {
+            Layout::Pane(_) => false,
+            Layout::Split(_, children) => {
+                let before = children.len();
+                children.retain(|child| *child != Layout::Pane(Some(window.to_string())));
+                let mut removed = children.len() != before;
+                for child in children.iter_mut() {
+                    removed |= child.remove(window);
+                }
+                if children.len() == 1 {
+                    *self = children.remove(0);
+                }
+                removed
+            },
+        }
+    }
+
+    /// Make every pane showing `from` show `to` instead, for when a window's renamed.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        match self {
+            Layout::Pane(Some(window)) if window == from => *window = to.to_string(),
+            Layout::Pane(_) => { },
+            Layout::Split(_, children) => {
+                for child in children.iter_mut() {
+                    child.rename(from, to);
+                }
+            },
+        }
+    }
+
+    /// The windows with panes of their own, in no particular order.
+    pub fn windows(&self) -> Vec<&str> {
+        match self {
+            Layout::Pane(Some(window)) => vec![window.as_str()],
+            Layout::Pane(None) => vec![],
+            Layout::Split(_, children) => children.iter().flat_map(|c| c.windows()).collect(),
+        }
+    }
+
+    /// The layout as lines of text, for keeping in a file: "main" for the pane showing the
+    /// current window, "pane" and a window's name for the others, and "stacked" or
+    /// "side-by-side" for a split, with what's in it on the lines after, indented two more spaces.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![];
+        self.write_lines(0, &mut lines);
+        lines
+    }
+
+    fn write_lines(&self, depth: usize, out: &mut Vec<String>) {
+        let indent = "  ".repeat(depth);
+        match self {
+            Layout::Pane(None) => out.push(format!("{}main", indent)),
+            Layout::Pane(Some(window)) => out.push(format!("{}pane {}", indent, window)),
+            Layout::Split(direction, children) => {
+                let name = match direction {
+                    Direction::Stacked => "stacked",
+                    Direction::SideBySide => "side-by-side",
+                };
+                out.push(format!("{}{}", indent, name));
+                for child in children {
+                    child.write_lines(depth + 1, out);
+                }
+            },
+        }
+    }
+
+    /// Read a layout back from the lines to_lines() made of it.
+    pub fn from_lines(lines: &[&str]) -> Result<Layout, String> {
+        let mut rest = lines;
+        let layout = Layout::read_lines(0, &mut rest)?;
+        if let Some(line) = rest.first() {
+            return Err(format!("Didn't expect {:?} after the end of the layout", line));
+        }
+        match layout.mains() {
+            1 => Ok(layout),
+            _ => Err("The layout needs exactly one main pane".to_string()),
+        }
+    }
+
+    // Read the layout at `depth` from the front of `lines`, leaving the rest.
+    fn read_lines(depth: usize, lines: &mut &[&str]) -> Result<Layout, String> {
+        let (line, rest) = lines.split_first().ok_or("The layout stops partway through")?;
+        let text = line.strip_prefix(&"  ".repeat(depth)).filter(|text| !text.starts_with(' '))
+            .ok_or_else(|| format!("{:?} isn't indented right", line))?;
+        *lines = rest;
+
+        let direction = match text {
+            "main" => return Ok(Layout::Pane(None)),
+            "stacked" => Direction::Stacked,
+            "side-by-side" => Direction::SideBySide,
+            _ => return match text.strip_prefix("pane ") {
+                Some(window) => Ok(Layout::Pane(Some(window.to_string()))),
+                None => Err(format!("Expected main, pane, stacked or side-by-side, not {:?}", text)),
+            },
+        };
+        let inside = "  ".repeat(depth + 1);
+        let mut children = vec![];
+        while lines.first().is_some_and(|line| line.starts_with(&inside)) {
+            children.push(Layout::read_lines(depth + 1, lines)?);
+        }
+        if children.len() < 2 {
+            return Err(format!("A {} split needs at least two things in it", text));
+        }
+        Ok(Layout::Split(direction, children))
+    }
+
+    // How many panes show the current window.
+    fn mains(&self) -> usize {
+        match self {
+            Layout::Pane(window) => window.is_none() as usize,
+            Layout::Split(_, children) => children.iter().map(|c| c.mains()).sum(),
+        }
+    }
+
+    /// Fit the layout into `area`.  Each split shares its room out as evenly as it can, with any
+    /// left over going to the first few; since this is worked out fresh every time, a resize
+    /// redistributes everything.  If there isn't room for a line or column for everything, the
+    /// first panes (the ones added last) don't get shown at all.
+    pub fn arrange(&self, area: Rect) -> Arrangement {
+        let mut arrangement = Arrangement::default();
+        self.arrange_into(area, &mut arrangement);
+        arrangement
+    }
+
+    fn arrange_into(&self, area: Rect, out: &mut Arrangement) {
+        if area.w == 0 || area.h == 0 {
+            return;
+        }
+
+        let (direction, children) = match self {
+            Layout::Pane(window) => {
+                out.panes.push((window.clone(), area));
+                return;
+            },
+            Layout::Split(direction, children) => (*direction, children),
+        };
+
+        let room = match direction {
+            Direction::Stacked => area.h,
+            Direction::SideBySide => area.w,
+        };
+        // n children need n cells, plus n - 1 for the dividers between them.
+        let shown = children.len().min(room.div_ceil(2));
+        if shown == 0 {
+            return;
+        }
+        let children = &children[children.len() - shown..];
+        let space = room - (shown - 1);
+
+        let mut offset = 0;
+        for (i, child) in children.iter().enumerate() {
+            let size = space / shown + if i < space % shown { 1 } else { 0 };
+            let (child_area, divider) = match direction {
+                Direction::Stacked => (
+                    Rect { y: area.y + offset, h: size, ..area },
+                    Rect { y: area.y + offset + size, h: 1, ..area },
+                ),
+                Direction::SideBySide => (
+                    Rect { x: area.x + offset, w: size, ..area },
+                    Rect { x: area.x + offset + size, w: 1, ..area },
+                ),
+            };
+            child.arrange_into(child_area, out);
+            if i + 1 < shown {
+                out.dividers.push((direction, divider));
+            }
+            offset += size + 1;
+        }
+    }
+}
+
+#[test]
+fn layout_tiling() {
+    let mut layout = Layout::default();
+    layout.add("chat".to_string(), Direction::Stacked);
+    layout.add("map".to_string(), Direction::SideBySide);
+    assert_eq!(layout, Layout::Split(Direction::SideBySide, vec![
+        Layout::Pane(Some("map".to_string())),
+        Layout::Split(Direction::Stacked, vec![Layout::Pane(Some("chat".to_string())), Layout::Pane(None)]),
+    ]));
+
+    // 21 columns: 10 for the map, one for the bar, 10 for the rest.  Nine rows: four for chat,
+    // the divider, and four for the current window.
+    let arrangement = layout.arrange(Rect { x: 0, y: 0, w: 21, h: 9 });
+    assert_eq!(arrangement.panes, vec![
+        (Some("map".to_string()), Rect { x: 0, y: 0, w: 10, h: 9 }),
+        (Some("chat".to_string()), Rect { x: 11, y: 0, w: 10, h: 4 }),
+        (None, Rect { x: 11, y: 5, w: 10, h: 4 }),
+    ]);
+    assert_eq!(arrangement.dividers, vec![
+        (Direction::SideBySide, Rect { x: 10, y: 0, w: 1, h: 9 }),
+        (Direction::Stacked, Rect { x: 11, y: 4, w: 10, h: 1 }),
+    ]);
+
+    // Only room for the current window.
+    let arrangement = layout.arrange(Rect { x: 0, y: 0, w: 2, h: 2 });
+    assert_eq!(arrangement.panes, vec![(None, Rect { x: 0, y: 0, w: 2, h: 2 })]);
+
+    layout.rename("map", "room");
+    assert_eq!(layout.windows(), vec!["room", "chat"]);
+
+    assert!(layout.remove("chat"));
+    assert!(!layout.remove("chat"));
+    assert_eq!(layout.windows(), vec!["room"]);
+    assert!(layout.remove("room"));
+    assert_eq!(layout, Layout::Pane(None));
+}
+
+#[test]
+fn layout_lines() {
+    let mut layout = Layout::default();
+    layout.add("chat".to_string(), Direction::Stacked);
+    layout.add("the map".to_string(), Direction::SideBySide);
+    let lines = layout.to_lines();
+    assert_eq!(lines, vec!["side-by-side", "  pane the map", "  stacked", "    pane chat", "    main"]);
+    let lines: Vec<&str> = lines.iter().map(|line| line.as_str()).collect();
+    assert_eq!(Layout::from_lines(&lines), Ok(layout));
+
+    assert_eq!(Layout::from_lines(&["main"]), Ok(Layout::default()));
+    assert!(Layout::from_lines(&["stacked", "  main"]).is_err());
+    assert!(Layout::from_lines(&["stacked", "  pane chat", "  pane map"]).is_err());
+    assert!(Layout::from_lines(&["stacked", "  pane chat", "   main"]).is_err());
+    assert!(Layout::from_lines(&["main", "main"]).is_err());
+}
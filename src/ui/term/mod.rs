@@ -1,26 +1,105 @@
 
 use std::sync::mpsc;
 use std::sync::mpsc::{Sender, Receiver};
-use std::io::{Write, stdout, stdin};
+use std::io::{BufRead, Write, stdout};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::io;
+use std::time::{Duration, Instant};
 
-use termion::event::Key;
+use termion::event::{Key, MouseButton, MouseEvent};
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
-use termion::input::TermRead;
 
 use signal_hook::iterator::Signals;
 
-// TODO: We should just scrape the `Command' type out. It's pointless indirection and introduces
-// confusion as to what Commands even are, plus the possibility to break stuff less-obviously by
-// changing it.
-use crate::meta::{Event, EventSource, ReadinessPager, Listener};
-use crate::ui::{UserInterface, Command};
+use fnv::{FnvHashMap, FnvHashSet};
 
+use crate::audit::IdleAudit;
+use crate::error::MintError;
+use crate::line::Line;
+use crate::net::mxp::Link;
+use crate::ui::ansi::{self, Color, Style, StyledLine};
+use crate::meta::{Event, EventSource, ReadinessPager, Listener, WindowID};
+use crate::ui::{UserInterface, BellPolicy, Lines, DEBUG_WINDOW, SHOWKEY_SEGMENT};
+use crate::ui::raise::RaiseRules;
+use crate::ui::highlight::HighlightRules;
+use crate::charset::Charset;
+use crate::clock::LocalTime;
+use crate::command::Command;
+use crate::selftest::Check;
+
+use unicode_width::UnicodeWidthStr;
+
+pub mod caps;
+mod clipboard;
+mod complete;
+mod confirm;
+pub mod headless;
 mod input;
+mod keys;
+pub mod layout;
+mod mouse;
+pub mod picker;
 mod screen;
+mod select;
+mod status;
+mod switcher;
 mod text;
 
+pub use text::Display;
+
+/// How many seconds must pass after a window is automatically raised before any rule is allowed to
+/// raise a window again.
+const DEFAULT_RAISE_INTERVAL: u64 = 10;
+
+/// How long the screen stays inverted for a visual bell, in milliseconds.
+const FLASH_DURATION: u64 = 150;
+
+/// How long to wait after an ESC for another byte before deciding it was the Esc key on its own
+/// and not the start of Alt+something, in milliseconds.  Terminals send both bytes of an Alt
+/// combination at once, so this can be short; it mostly matters over slow links (e.g. ssh.)
+const DEFAULT_ESCAPE_TIMEOUT: usize = 50;
+
+/// The status bar segment that says there's more to see below, when the window's scrolled back
+/// (or frozen while text's selected from it.)
+const MORE_SEGMENT: &str = "more";
+
+/// The status bar segment with what the window on screen has to say for itself (see
+/// UserInterface::set_window_status), like which world it's connected to.
+const WINDOW_SEGMENT: &str = "window";
+
+/// The status bar segment listing the other windows that have had lines since they were last on
+/// screen.
+const ACTIVITY_SEGMENT: &str = "activity";
+
+/// How many lines the live pane of a split window gets if nobody says.
+pub const DEFAULT_SPLIT_LINES: usize = 8;
+
+/// What goes between the two panes of a split window, and between panes one above the other.
+const SPLIT_DIVIDER: char = '-';
+
+/// What goes between panes side by side.
+const COLUMN_DIVIDER: char = '|';
+
+/// The status bar segment saying which link is picked, while one is.
+const LINK_SEGMENT: &str = "link";
+
+/// How far back next-link looks for links, in lines.
+const LINK_LINES: usize = 200;
+
+/// The status bar segment with what to do in selection mode, or about a multi-line paste.
+const SELECT_SEGMENT: &str = "select";
+const PASTE_SEGMENT: &str = "paste";
+
+/// The most completion candidates shown at once, above the input line.
+const COMPLETION_POPUP_LINES: usize = 8;
+
+// DECSCNM: turns reverse video on and off for the entire screen.  This is what most terminals do
+// for their own built-in visual bell, and we don't have a status bar or window borders to flash
+// instead yet.
+const REVERSE_VIDEO_ON: &str = "\x1b[?5h";
+const REVERSE_VIDEO_OFF: &str = "\x1b[?5l";
 
 /// Trait for objects that can be conceptualized as a rectangle on a grid of characters and drawn
 /// on screen.
@@ -52,18 +131,115 @@ pub struct TermUiManager {
     // fields would make it a lot less potentially confusing.
     /// Stored as (width, height).
     term_size: (usize, usize),
-    stdout: AlternateScreen<termion::raw::RawTerminal<io::Stdout>>,
+    caps: caps::Capabilities,
+    stdout: Screen,
+    // Line mode, for when there's no terminal (see Screen): lines are printed as they come, and
+    // lines read from stdin are what's typed.
+    line_mode: bool,
+    // When headless (see headless.rs), where the lines go instead of stdout, and where what's
+    // typed comes from instead of stdin, until the listener takes it.
+    shown: Option<headless::Shown>,
+    typed: Option<Receiver<String>>,
 
     // The DamageBuffer here is used as an abstraction on the entire terminal; we only need one of
     // these.  It allows us to think about the terminal as more of a grid buffer than a stream
     // with external state on the other end.
     db: screen::DamageBuffer,
 
-    // Eventually, we're going to want multiple views, an input line and something like window
-    // management. Placeholder:
-    view: text::WrappedView,
+    // Every named window gets its own view, created the first time something is pushed to it.
+    // `current` is the name of the one in the main pane; the layout says which others have panes
+    // of their own, and where.
+    windows: FnvHashMap<String, text::WrappedView>,
+    current: String,
+    layout: layout::Layout,
+
+    // Rules for bringing a window into view automatically when certain lines arrive in it.
+    raise: RaiseRules,
+    // Rules for styling what matches them in lines from the server.
+    highlights: HighlightRules,
+
+    // What to do about BEL characters in each window, and for windows with no setting of their
+    // own.
+    bell_policies: FnvHashMap<String, BellPolicy>,
+    default_bell: BellPolicy,
+
+    flashing: bool,
+    // Requests to the FlashListener to tell us when to stop flashing.  The receiving end is
+    // wrapped in an Option because it's moved into the listener by get_listeners().
+    flash_tx: Sender<Duration>,
+    flash_rx: Option<Receiver<Duration>>,
 
     input: input::InputLine,
+    // Windows whose input is hidden (see set_input_masked.)  The input line's masked while one
+    // of them is on screen.
+    masked: FnvHashSet<String>,
+    // Shared with the KeyReader in the input thread, which is why it's an atomic.  Milliseconds.
+    escape_timeout: Arc<AtomicUsize>,
+    keys: keys::KeyBindings,
+
+    // Words for Tab to complete, and the completion Tab is cycling through, if the last key was
+    // a Tab.  The candidates are shown in a popup above the input line while it lasts.
+    dictionary: complete::Dictionary,
+    completion: Option<complete::Completion>,
+
+    // The links next-link picks from, while it's picking.
+    links: Option<LinkPicker>,
+
+    showkey: ShowKey,
+
+    // Only drawn if something has put a segment in it.
+    status: status::StatusBar,
+
+    // Counts redraws that didn't change anything on the screen, when it's switched on.
+    audit: IdleAudit,
+    // How many times the screen's been redrawn, and how long it took all together.
+    redraws: (u64, Duration),
+
+    // How many lines of history each window keeps, and how they're shown.
+    max_history: usize,
+    display: Display,
+
+    // Background colors for windows that have one of their own, and whether panes that aren't
+    // the main one (where typing goes) are dimmed, to make it plain which one that is.
+    backgrounds: FnvHashMap<String, Color>,
+    dim_unfocused: bool,
+
+    // Window names, indexed by their WindowIDs.  Like the ThreadedManager's lists, this only
+    // ever grows.
+    window_ids: Vec<String>,
+
+    // What the status bar says for each window (see set_window_status), and how many lines each
+    // window that isn't on screen has had since it was.
+    window_status: FnvHashMap<String, String>,
+    activity: FnvHashMap<String, usize>,
+    // The windows whose counts in `activity` have changed since take_activity() was last called.
+    activity_changed: FnvHashSet<String>,
+
+    // If the window on screen is split, how many lines the live pane at the bottom gets.  The
+    // pane above it scrolls as usual; the live one always shows the newest lines.
+    split: Option<usize>,
+
+    // The window switcher, while it's open.  It gets all the keys until it's closed.
+    switcher: Option<switcher::Switcher>,
+
+    // Whether the terminal's reporting the mouse to us, where each pane went the last time the
+    // screen was drawn (the main one as None, like in the layout), and the text being dragged
+    // over, if there is any.
+    mouse: bool,
+    panes: Vec<(Option<String>, layout::Rect)>,
+    selection: Option<select::Selection>,
+
+    // Selection mode, while it's on.  Like the switcher, it gets all the keys.
+    selecting: Option<select::Selecting>,
+    // Lines pasted all at once that are waiting for a y or n before they're sent.
+    paste: Option<Vec<String>>,
+    // A command that's waiting for the user to say yes to it, which gets all the keys until
+    // they've answered.
+    confirming: Option<confirm::Confirm>,
+    // The color picker, while it's open.  Like the switcher, it gets all the keys.
+    picking: Option<picker::Picker>,
+    // What to pipe copied text into, instead of asking the terminal to copy it with OSC 52.
+    copy_command: Option<String>,
 }
 
 impl TermUiManager {
@@ -71,29 +247,145 @@ impl TermUiManager {
     /// able to grab a stdout() instance, write to that instance (clearing/setting up the terminal)
     /// and construct the TermUiManager object with ownership of it.
     pub fn new() -> TermUiManager {
-        let (tx, rx) = mpsc::channel();
+        let caps = caps::Capabilities::probe();
 
-        let (term_w, term_h) = termion::terminal_size().unwrap();
-
-        let mut stdout = AlternateScreen::from(stdout().into_raw_mode().unwrap());
+        // Without a terminal at both ends (piped, or run by a service manager), there's nothing
+        // to draw on, so it's line mode.
+        let line_mode = !(termion::is_tty(&io::stdin()) && termion::is_tty(&io::stdout()));
+        let raw = if line_mode { Err(()) } else { stdout().into_raw_mode().map_err(|_| ()) };
+        let (mut stdout, line_mode) = match raw {
+            Ok(raw) => (Screen::Raw(AlternateScreen::from(raw)), false),
+            Err(()) => (Screen::Lines, true),
+        };
         write!(stdout, "{}{}", termion::clear::All, termion::cursor::Hide).unwrap();
+        if caps.mouse {
+            write!(stdout, "{}", mouse::REPORTING_ON).unwrap();
+        }
+        if !caps.dumb {
+            write!(stdout, "{}", keys::BRACKETED_PASTE_ON).unwrap();
+        }
         stdout.flush().unwrap();
 
+        let warnings = caps.warnings();
+        let mut ui = TermUiManager::with_screen(stdout, line_mode, caps);
+
+        if line_mode {
+            let note = "There's no terminal, so lines are shown as they come, and what's read from stdin is \
+                        what's typed.";
+            ui.push_to_window(DEBUG_WINDOW.to_string(), Line::client(note)).unwrap();
+        }
+        for warning in warnings {
+            ui.push_to_window(DEBUG_WINDOW.to_string(), Line::client(&warning)).unwrap();
+        }
+
+        ui
+    }
+
+    /// A TermUiManager that leaves the terminal (and stdin and stdout) alone, and the HeadlessUi
+    /// for seeing what it shows and typing into it (see headless.rs.)  There can be any number of
+    /// these.
+    pub fn headless() -> (TermUiManager, headless::HeadlessUi) {
+        let (headless, shown, typed) = headless::HeadlessUi::new();
+        let mut ui = TermUiManager::with_screen(Screen::Lines, true, caps::Capabilities::headless());
+        ui.shown = Some(shown);
+        ui.typed = Some(typed);
+        (ui, headless)
+    }
+
+    fn with_screen(stdout: Screen, line_mode: bool, caps: caps::Capabilities) -> TermUiManager {
+        let (tx, rx) = mpsc::channel();
+        let (flash_tx, flash_rx) = mpsc::channel();
+        let (term_w, term_h) = caps.size.unwrap_or((80, 24));
+        let db = screen::DamageBuffer::new(term_w, term_h, caps.colors);
+        let mouse = caps.mouse;
+
         TermUiManager {
             stdout,
+            line_mode,
+            shown: None,
+            typed: None,
             rx,
             tx_template: tx,
-            term_size: (term_w as usize, term_h as usize),
-            db: screen::DamageBuffer::new(term_w as usize, term_h as usize),
-            view: text::WrappedView::new(term_w as usize, term_h as usize),
-            input: input::InputLine::new(term_w as usize, term_h as usize),
+            term_size: (term_w, term_h),
+            caps,
+            db,
+            windows: FnvHashMap::default(),
+            current: "default".to_string(),
+            layout: layout::Layout::default(),
+            raise: RaiseRules::new(Duration::from_secs(DEFAULT_RAISE_INTERVAL)),
+            highlights: HighlightRules::new(),
+            bell_policies: FnvHashMap::default(),
+            default_bell: BellPolicy::Ring,
+            flashing: false,
+            flash_tx,
+            flash_rx: Some(flash_rx),
+            input: input::InputLine::new(term_w, term_h),
+            masked: FnvHashSet::default(),
+            escape_timeout: Arc::new(AtomicUsize::new(DEFAULT_ESCAPE_TIMEOUT)),
+            keys: keys::KeyBindings::default(),
+            dictionary: complete::Dictionary::new(),
+            completion: None,
+            links: None,
+            showkey: ShowKey::Off,
+            status: status::StatusBar::new(term_w),
+            audit: IdleAudit::new(),
+            redraws: (0, Duration::from_secs(0)),
+            max_history: text::DEFAULT_MAX_HISTORY,
+            display: Display::default(),
+            backgrounds: FnvHashMap::default(),
+            dim_unfocused: false,
+            window_ids: vec![],
+            split: None,
+            switcher: None,
+            window_status: FnvHashMap::default(),
+            activity: FnvHashMap::default(),
+            activity_changed: FnvHashSet::default(),
+            mouse,
+            panes: vec![],
+            selection: None,
+            selecting: None,
+            paste: None,
+            confirming: None,
+            picking: None,
+            copy_command: None,
         }
     }
+
+    /// What we found out about the terminal at startup.
+    pub fn capabilities(&self) -> &caps::Capabilities {
+        &self.caps
+    }
+
+    /// The terminal side of /selftest: what we think the terminal is, and whether the screen
+    /// code still draws what it's given.
+    pub fn self_test(&self) -> Vec<Check> {
+        let caps = &self.caps;
+        let size = caps.size.map_or("unknown".to_string(), |(w, h)| format!("{}x{}", w, h));
+        let found = format!("TERM={}, {}, {:?} colors, {}, input in {}, {}mouse.", caps.term, size,
+                            caps.colors, if caps.utf8 { "UTF-8" } else { "not UTF-8" },
+                            caps.input_charset.name(), if caps.mouse { "" } else { "no " });
+        let warnings = caps.warnings();
+        let terminal = if warnings.is_empty() { Ok(found) } else { Err(format!("{}  {}", found, warnings.join("  "))) };
+
+        vec![
+            Check::new("terminal", terminal),
+            Check::new("screen", screen::round_trip(caps.colors)),
+        ]
+    }
 }
 
 /// Clean up the terminal when the TermUiManager is dropped.
 impl Drop for TermUiManager {
     fn drop(&mut self) {
+        if self.flashing {
+            write!(self.stdout, "{}", REVERSE_VIDEO_OFF).unwrap();
+        }
+        if self.mouse {
+            write!(self.stdout, "{}", mouse::REPORTING_OFF).unwrap();
+        }
+        if !self.caps.dumb {
+            write!(self.stdout, "{}", keys::BRACKETED_PASTE_OFF).unwrap();
+        }
         write!(self.stdout, "{}", termion::cursor::Show).unwrap();
         self.stdout.flush().unwrap();
     }
@@ -101,19 +393,57 @@ impl Drop for TermUiManager {
 
 impl EventSource for TermUiManager {
     fn get_listeners(&mut self) -> Vec<Box<Listener>> {
+        let flash_rx = self.flash_rx.take()
+            .expect("Cannot call get_listeners() on TermUiManager more than once.");
+
+        let flash = Box::new(FlashListener {
+            rx: flash_rx,
+            tx: self.tx_template.clone(),
+        });
+        if let Some(rx) = self.typed.take() {
+            return vec![Box::new(headless::TypedListener { rx, tx: self.tx_template.clone() }), flash];
+        }
+        if self.line_mode {
+            return vec![Box::new(LineListener { tx: self.tx_template.clone() }), flash];
+        }
+
         vec![
             Box::new(TermionListener {
                 tx: self.tx_template.clone(),
+                charset: self.caps.input_charset,
+                escape_timeout: self.escape_timeout.clone(),
             }),
             Box::new(ResizeListener {
                 tx: self.tx_template.clone(),
             }),
+            flash,
         ]
     }
 
+    /// Everything here listens to something that's still there after the thread that was listening
+    /// has gone (stdin, a signal, or the flash channel, which is replaced.)
+    fn restart_listener(&mut self, name: &str) -> Option<Box<dyn Listener>> {
+        let tx = self.tx_template.clone();
+        match name {
+            "termion-input" => Some(Box::new(TermionListener {
+                tx,
+                charset: self.caps.input_charset,
+                escape_timeout: self.escape_timeout.clone(),
+            })),
+            "line-input" => Some(Box::new(LineListener { tx })),
+            "resize" => Some(Box::new(ResizeListener { tx })),
+            "flash" => {
+                let (flash_tx, rx) = mpsc::channel();
+                self.flash_tx = flash_tx;
+                Some(Box::new(FlashListener { rx, tx }))
+            },
+            _ => None,
+        }
+    }
+
     fn process(&mut self) -> Vec<Event> {
         // The events from the thread in this case will be either terminal resize or some kind of
-        // event from Termion---key, maybe eventually mouse, whatever.  So, when this is called
+        // event from Termion---a key or the mouse.  So, when this is called
         // we'll deal with as many as we can read right now.
         let mut out = vec![];
 
@@ -123,38 +453,136 @@ impl EventSource for TermUiManager {
                     break;
                 }
                 Ok(TermEvent::Resize) => {
-                    let (term_w, term_h) = termion::terminal_size().unwrap();
-                    let (term_w, term_h) = (term_w as usize, term_h as usize);
+                    let (term_w, term_h) = match termion::terminal_size() {
+                        Ok((w, h)) => (w as usize, h as usize),
+                        Err(_) => continue,
+                    };
 
                     self.db.resize(term_w, term_h);
                     self.term_size = (term_w, term_h);
+                    // The panes they were in have moved.
+                    self.selection = None;
+                    self.selecting = None;
 
                     self.redraw();
                 },
-                Ok(TermEvent::Input { key: k }) => {
-                    match k {
-                        Key::Ctrl('c') => { out.push(Event::QuitRequest) },
-
-                        Key::Ctrl('b') => { self.input.move_cursor(-1) },
-                        Key::Left      => { self.input.move_cursor(-1) },
-                        Key::Ctrl('f') => { self.input.move_cursor(1) },
-                        Key::Right     => { self.input.move_cursor(1) },
-
-                        Key::Char('\n') => {
-                            out.push(Event::UserInput {
-                                line: self.input.as_text(),
-                                which: 0,
+                Ok(TermEvent::FlashEnd) => {
+                    if self.flashing {
+                        write!(self.stdout, "{}", REVERSE_VIDEO_OFF).unwrap();
+                        self.stdout.flush().unwrap();
+                        self.flashing = false;
+                    }
+                },
+                Ok(TermEvent::Paste { text }) => {
+                    self.paste(text);
+                    self.redraw();
+                },
+                Ok(TermEvent::Line { line }) => {
+                    let which = self.current_id();
+                    out.push(Event::UserInput { line, which });
+                },
+                Ok(TermEvent::Mouse { event }) => {
+                    self.mouse_event(event);
+                    self.redraw();
+                },
+                Ok(TermEvent::Input { key }) => {
+                    match self.showkey {
+                        ShowKey::Waiting => {
+                            // Swallow this one and say what it is instead of doing it.
+                            self.showkey = ShowKey::Showing;
+                            out.push(Event::KeyShown {
+                                key: format!("{:?}", key),
+                                name: keys::key_name(&key),
+                                builtin: self.keys.action(&key).map(|a| a.describe().to_string()),
+                                which: self.current_id(),
                             });
-                            self.input.set_string("".to_string());
-                            self.redraw();
+                            continue;
                         },
-                        Key::Char(chr) => { self.input.insert_char(chr) },
+                        ShowKey::Showing => {
+                            self.showkey = ShowKey::Off;
+                            self.status.remove_segment(SHOWKEY_SEGMENT);
+                        },
+                        ShowKey::Off => { },
+                    }
+
+                    if let Some(confirm) = self.confirming.take() {
+                        match confirm.key(key) {
+                            confirm::Outcome::Open => self.confirming = Some(confirm),
+                            confirm::Outcome::No => { },
+                            confirm::Outcome::Yes => out.push(Event::UserCommand { cmd: confirm.command() }),
+                        }
+                        self.redraw();
+                        continue;
+                    }
+
+                    if let Some(mut picker) = self.picking.take() {
+                        match picker.key(key) {
+                            picker::Outcome::Open => self.picking = Some(picker),
+                            picker::Outcome::Cancelled => { },
+                            picker::Outcome::Picked(cmd) => out.push(Event::UserCommand { cmd }),
+                        }
+                        self.redraw();
+                        continue;
+                    }
+
+                    if let Some(mut switcher) = self.switcher.take() {
+                        let matches = switcher.matches(self.switcher_entries());
+                        match switcher.key(key, &matches) {
+                            switcher::Outcome::Open => self.switcher = Some(switcher),
+                            switcher::Outcome::Cancelled => { },
+                            switcher::Outcome::Picked(window) => self.switch_to(window),
+                        }
+                        self.redraw();
+                        continue;
+                    }
+
+                    if let Some(mut selecting) = self.selecting.take() {
+                        match selecting.key(key) {
+                            select::Outcome::Open => self.selecting = Some(selecting),
+                            select::Outcome::Cancelled => { },
+                            select::Outcome::Copy => {
+                                let text = self.selected_text(selecting.selection());
+                                self.copy(text);
+                            },
+                        }
+                        self.redraw();
+                        continue;
+                    }
+
+                    if let Some(lines) = self.paste.take() {
+                        if key == Key::Char('y') {
+                            let which = self.current_id();
+                            out.extend(lines.into_iter().map(|line| Event::UserInput { line, which }));
+                        }
+                        self.redraw();
+                        continue;
+                    }
 
-                        Key::Ctrl('h') => { self.input.delete_chars(-1) },
-                        Key::Ctrl('d') => { self.input.delete_chars(1) },
+                    // Anything but another Tab means the completion's done with, and likewise
+                    // anything but another M-y for a yank, or following the link that's picked
+                    // or picking another.
+                    let action = self.keys.action(&key);
+                    if action != Some(keys::Action::Complete) {
+                        self.completion = None;
+                    }
+                    if action != Some(keys::Action::NextLink) && action != Some(keys::Action::Submit) {
+                        self.links = None;
+                    }
+                    if action != Some(keys::Action::YankPop) {
+                        self.input.end_yank();
+                    }
 
-                        // Obviously, huge TODO here.
-                        _ => { },
+                    match (action, key) {
+                        (Some(action), _) => self.do_action(action, &mut out),
+                        (None, Key::Char(chr)) => { self.input.insert_char(chr) },
+
+                        // Anything we don't use ourselves goes back up to whoever's in charge,
+                        // in case they've bound something to it.
+                        (None, other) => {
+                            if let Some(name) = keys::key_name(&other) {
+                                out.push(Event::KeyPress { key: name, which: self.current_id() });
+                            }
+                        },
                     };
                     self.redraw();
                 },
@@ -167,27 +595,841 @@ impl EventSource for TermUiManager {
 
 /// Implements the public API for adding new text data to windows in the user interface.
 impl UserInterface for TermUiManager {
-    fn push_to_window(&mut self, _window: String, line: String) -> Result<(), ()> {
-        // Just for testing, we throw everything into a single view...  We could
-        // ultimately make it a single view that drew in variously filtered ways as
-        // well, or multiple views on the same text that could be filtered however you
-        // like.
-        self.view.push(line);
+    fn push_to_window(&mut self, window: String, mut line: Line) -> Result<(), MintError> {
+        if line.gag {
+            return Ok(());
+        }
+        if line.bell {
+            let policy = *self.bell_policies.get(&window).unwrap_or(&self.default_bell);
+            self.bell(policy, &window);
+        }
+        if let Some(style) = line.highlight {
+            line.styled.restyle(style);
+        }
+        if line.source.is_some() && self.highlights.apply(&mut line.styled) {
+            line.flash = true;
+        }
+        if line.flash {
+            self.flash();
+        }
+        for link in &line.links {
+            line.styled.restyle_range(link.start, link.end, |style| style.underline = true);
+        }
+        if line.source.is_some() {
+            self.dictionary.learn(line.text());
+        }
+
+        // If the line isn't going to a window on screen, it might still be important enough to
+        // switch over to that window.  Check this before we give the line away to the view.
+        let on_screen = self.on_screen(&window);
+        let raise = !on_screen && self.raise.should_raise(&window, line.text(), Instant::now());
+
+        if let Some(ref shown) = self.shown {
+            shown.borrow_mut().push((window.clone(), line.clone()));
+        } else if self.line_mode {
+            let shown = if window == self.current { line.text().to_string() } else { format!("[{}] {}", window, line.text()) };
+            let mut out = io::stdout();
+            // Whoever was reading might have gone; there's no one to tell if so.
+            let _ = writeln!(out, "{}", shown).and_then(|_| out.flush());
+        }
+        self.view(window.clone()).push(line);
+
+        if raise {
+            self.switch_to(window);
+        } else if !on_screen {
+            *self.activity.entry(window.clone()).or_insert(0) += 1;
+            self.activity_changed.insert(window);
+        }
 
         self.redraw();
         Ok(())
     }
 
-    fn register_command(&mut self, _c: Command) {
-        // TODO
+    fn set_bell_policy(&mut self, window: String, policy: BellPolicy) {
+        self.bell_policies.insert(window, policy);
+    }
+
+    fn set_status(&mut self, segment: String, text: String) {
+        self.status.set_segment(segment, text);
+        self.redraw();
+    }
+
+    fn remove_status(&mut self, segment: String) {
+        self.status.remove_segment(&segment);
+        self.redraw();
+    }
+
+    fn active_window(&self) -> String {
+        self.current.clone()
+    }
+
+    fn show_window(&mut self, window: String) {
+        self.switch_to(window);
+        self.redraw();
+    }
+
+    fn mark(&mut self, window: String) {
+        let line = format!("--- {} ---", LocalTime::now().hm());
+        self.view(window).push_mark(Line::from(StyledLine::plain(line)));
+        self.redraw();
+    }
+
+    fn jump_to_mark(&mut self, window: String) -> bool {
+        let jumped = match self.windows.get_mut(&window) {
+            Some(view) => view.jump_to_last_mark(),
+            None => false,
+        };
+        self.redraw();
+        jumped
+    }
+
+    fn scroll_to_end(&mut self, window: String) {
+        if let Some(view) = self.windows.get_mut(&window) {
+            view.scroll_to_end();
+        }
+        self.redraw();
+    }
+
+    fn snapshot(&mut self, from: String, to: String, lines: Lines) -> Result<usize, MintError> {
+        if self.windows.contains_key(&to) {
+            return Err(MintError::Ui(format!("There's already a window called {}.", to)));
+        }
+        let copied = match self.windows.get(&from) {
+            Some(view) => match lines {
+                Lines::Last(n) => view.last_lines(n),
+                Lines::SinceMark => view.lines_since_mark()
+                    .ok_or_else(|| MintError::Ui(format!("There are no marks in {}.", from)))?,
+            },
+            None => return Err(MintError::Ui(format!("There's no window called {}.", from))),
+        };
+
+        // Straight into the view: these lines have already been through push_to_window() once,
+        // and ringing bells or raising windows over them again would be silly.
+        let (w, h) = self.term_size;
+        let mut view = text::WrappedView::new(w, h);
+        view.set_max_history(self.max_history);
+        view.set_display(self.display);
+        for line in &copied {
+            view.push(line.clone());
+        }
+        self.window_id(&to);
+        self.windows.insert(to, view);
+        Ok(copied.len())
+    }
+
+    fn rename_window(&mut self, from: String, to: String) -> Result<(), MintError> {
+        if self.windows.contains_key(&to) {
+            return Err(MintError::Ui(format!("There's already a window called {}.", to)));
+        }
+        let view = self.windows.remove(&from)
+            .ok_or_else(|| MintError::Ui(format!("There's no window called {}.", from)))?;
+        self.windows.insert(to.clone(), view);
+
+        for name in self.window_ids.iter_mut().filter(|name| **name == from) {
+            *name = to.clone();
+        }
+        if self.current == from {
+            self.current = to.clone();
+        }
+        self.layout.rename(&from, &to);
+        self.raise.rename(&from, &to);
+        if let Some(policy) = self.bell_policies.remove(&from) {
+            self.bell_policies.insert(to.clone(), policy);
+        }
+        if let Some(status) = self.window_status.remove(&from) {
+            self.window_status.insert(to.clone(), status);
+        }
+        if let Some(count) = self.activity.remove(&from) {
+            self.activity.insert(to.clone(), count);
+            self.activity_changed.insert(from.clone());
+            self.activity_changed.insert(to.clone());
+        }
+        if let Some(color) = self.backgrounds.remove(&from) {
+            self.backgrounds.insert(to.clone(), color);
+        }
+        self.dictionary.rename_window(&from, &to);
+        if self.masked.remove(&from) {
+            self.masked.insert(to);
+        }
+
+        self.db.damage_all();
+        self.redraw();
+        Ok(())
+    }
+
+    fn show_next_key(&mut self) {
+        self.showkey = ShowKey::Waiting;
+        self.status.set_segment(SHOWKEY_SEGMENT.to_string(), "Press a key...".to_string());
+        self.redraw();
+    }
+
+    fn window_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.windows.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn window_id(&mut self, window: &str) -> WindowID {
+        match self.window_ids.iter().position(|name| name == window) {
+            Some(id) => id,
+            None => {
+                self.window_ids.push(window.to_string());
+                self.window_ids.len() - 1
+            },
+        }
+    }
+
+    fn window_name(&self, id: WindowID) -> Option<String> {
+        self.window_ids.get(id).cloned()
+    }
+
+    fn set_window_status(&mut self, window: String, text: String) {
+        self.window_status.insert(window, text);
+        self.redraw();
+    }
+
+    fn create_window(&mut self, window: String) {
+        self.view(window);
+    }
+
+    fn text_width(&self) -> usize {
+        self.term_size.0
+    }
+
+    fn set_input_masked(&mut self, window: String, masked: bool) {
+        if masked {
+            self.masked.insert(window);
+        } else {
+            self.masked.remove(&window);
+        }
+        self.redraw();
+    }
+
+    fn copy(&mut self, text: String) {
+        if let Some(ref command) = self.copy_command {
+            if let Err(e) = pipe_to(command, &text) {
+                let line = Line::client(&format!("Couldn't copy with {}: {}", command, e));
+                self.push_to_window(DEBUG_WINDOW.to_string(), line).unwrap();
+            }
+            return;
+        }
+        if self.caps.dumb {
+            return;
+        }
+        write!(self.stdout, "{}", clipboard::osc52(&text)).unwrap();
+        self.stdout.flush().unwrap();
+    }
+
+    fn notify(&mut self, mut text: String) {
+        // OSC 777 is the closest thing there is to a standard way for a program in a terminal to
+        // ask for a desktop notification.  Terminals that don't understand it should just
+        // swallow it.  Any control characters in the text could end the sequence early, so
+        // they have to go.
+        text.retain(|c| !c.is_control());
+        write!(self.stdout, "\x1b]777;notify;mint;{}\x07", text).unwrap();
+        self.stdout.flush().unwrap();
     }
 }
 
 impl TermUiManager {
+    /// Add a rule raising `window` (or whichever window the line arrives in, if None) when a line
+    /// matching the regular expression `pattern` shows up.
+    pub fn add_raise_rule(&mut self, window: Option<String>, pattern: &str) -> Result<(), String> {
+        self.raise.add(window, pattern).map_err(|e| format!("Bad pattern: {}", e))
+    }
+
+    /// Remove the raise rules with the pattern `pattern`.  Returns how many there were.
+    pub fn remove_raise_rule(&mut self, pattern: &str) -> usize {
+        self.raise.remove(pattern)
+    }
+
+    /// The raise rules, as (window, pattern), in the order they were added.
+    pub fn raise_rules(&self) -> Vec<(Option<String>, String)> {
+        self.raise.list().iter().map(|rule| (rule.window.clone(), rule.pattern.as_str().to_string())).collect()
+    }
+
+    /// Show whatever matches the regular expression `pattern` in lines from the server in the
+    /// style `spec` (e.g. "bold,yellow"), on top of the style it has already, and flash the
+    /// screen too if `flash`.
+    pub fn add_highlight(&mut self, pattern: &str, spec: &str, flash: bool) -> Result<(), String> {
+        self.highlights.add(pattern, spec, flash)
+    }
+
+    /// Returns false if there wasn't a highlight with that pattern.
+    pub fn remove_highlight(&mut self, pattern: &str) -> bool {
+        self.highlights.remove(pattern)
+    }
+
+    /// Add a word to one of `window`'s word lists for completing (see complete.rs.)
+    pub fn add_completion(&mut self, window: &str, list: &str, word: &str) {
+        self.dictionary.add_to_list(window, list, word);
+    }
+
+    /// Take a word out of one of `window`'s word lists, or the whole list with no word.  Returns
+    /// false if there was nothing to take out.
+    pub fn remove_completion(&mut self, window: &str, list: &str, word: Option<&str>) -> bool {
+        self.dictionary.remove_from_list(window, list, word)
+    }
+
+    /// `window`'s word lists, by name, with the words in each, newest first.
+    pub fn completion_lists(&self, window: &str) -> Vec<(String, Vec<String>)> {
+        self.dictionary.lists(window)
+    }
+
+    /// The highlights, as (pattern, style, whether it flashes), in the order they're applied.
+    pub fn highlights(&self) -> Vec<(String, String, bool)> {
+        self.highlights.list().iter()
+            .map(|rule| (rule.pattern.as_str().to_string(), rule.spec.clone(), rule.flash))
+            .collect()
+    }
+
+    /// Set the minimum time between automatic raises, to keep spam from churning the layout.
+    pub fn set_raise_interval(&mut self, interval: Duration) {
+        self.raise.set_min_interval(interval);
+    }
+
+    pub fn raise_interval(&self) -> Duration {
+        self.raise.min_interval()
+    }
+
+    /// Set how long an ESC waits for a following key before it counts as the Esc key by itself,
+    /// rather than Alt held down with that key.
+    pub fn set_escape_timeout(&mut self, timeout: Duration) {
+        self.escape_timeout.store(timeout.as_millis() as usize, Ordering::Relaxed);
+    }
+
+    pub fn escape_timeout(&self) -> Duration {
+        Duration::from_millis(self.escape_timeout.load(Ordering::Relaxed) as u64)
+    }
+
+    /// Make the key called `key` (as in KeyPress events, e.g. "C-w") do `action` (e.g.
+    /// "delete-word"), or with no action, nothing by itself any more.
+    pub fn bind_key(&mut self, key: &str, action: Option<&str>) -> Result<(), String> {
+        let key = keys::key_from_name(key).ok_or_else(|| format!("There's no key called {}.", key))?;
+        match action {
+            Some(name) => {
+                let action = keys::Action::from_name(name).ok_or_else(|| {
+                    let names: Vec<&str> = keys::Action::ALL.iter().map(|a| a.name()).collect();
+                    format!("There's no action called {} (try one of: {}.)", name, names.join(", "))
+                })?;
+                self.keys.bind(key, action);
+            },
+            None => {
+                if !self.keys.unbind(&key) {
+                    return Err("That key isn't bound to anything.".to_string());
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Copy by piping the text into a command (run by the shell, e.g. "xclip -selection
+    /// clipboard"), or with None, by asking the terminal to do it.
+    pub fn set_copy_command(&mut self, command: Option<String>) {
+        self.copy_command = command;
+    }
+
+    pub fn copy_command(&self) -> Option<&str> {
+        self.copy_command.as_deref()
+    }
+
+    /// Whether the terminal's reporting the mouse to us.
+    pub fn mouse(&self) -> bool {
+        self.mouse
+    }
+
+    /// Switch mouse reporting on or off.  While it's on, the terminal's own way of selecting and
+    /// pasting with the mouse usually needs Shift held down.
+    pub fn set_mouse(&mut self, on: bool) {
+        if on != self.mouse {
+            write!(self.stdout, "{}", if on { mouse::REPORTING_ON } else { mouse::REPORTING_OFF }).unwrap();
+            self.stdout.flush().unwrap();
+            self.mouse = on;
+            self.selection = None;
+        }
+    }
+
+    /// Set the commands (by name, without the "/") that Tab can complete at the start of the
+    /// input line.
+    pub fn set_command_names(&mut self, names: Vec<String>) {
+        self.dictionary.set_commands(names);
+    }
+
+    /// What the keys do, as (key name, action name, what the action does), sorted by key.
+    pub fn key_bindings(&self) -> Vec<(String, &'static str, &'static str)> {
+        self.keys.list().into_iter().map(|(key, action)| (key, action.name(), action.describe())).collect()
+    }
+
+    /// Keep at least `lines` lines at the bottom of the screen for the input line, whether or not
+    /// it needs them, so the window above doesn't change size as it grows and shrinks.
+    pub fn set_input_height(&mut self, lines: usize) {
+        self.input.reserve_height(lines);
+        self.redraw();
+    }
+
+    /// Split the window on screen in two: a pane at the bottom `lines` high that always shows the
+    /// newest lines, and the rest above it to scroll back through.  None puts it back together.
+    pub fn set_split(&mut self, lines: Option<usize>) {
+        self.split = lines;
+        self.redraw();
+    }
+
+    /// Give `window` a background color of its own, or with None, the terminal's.  Text that has
+    /// a background already keeps it.
+    pub fn set_background(&mut self, window: String, color: Option<Color>) {
+        match color {
+            Some(color) => self.backgrounds.insert(window, color),
+            None => self.backgrounds.remove(&window),
+        };
+        self.redraw();
+    }
+
+    /// Dim the panes besides the main one, or stop.
+    pub fn set_dim_unfocused(&mut self, dim: bool) {
+        self.dim_unfocused = dim;
+        self.redraw();
+    }
+
+    /// Give `window` a pane of its own, above the rest (Stacked) or to the left of it
+    /// (SideBySide.)
+    pub fn add_pane(&mut self, window: String, direction: layout::Direction) {
+        self.view(window.clone());
+        self.seen(&window);
+        self.layout.add(window, direction);
+        self.redraw();
+    }
+
+    /// Take away `window`'s panes, or all of them but the main one for None.  Returns false if
+    /// there weren't any.
+    pub fn remove_pane(&mut self, window: Option<&str>) -> bool {
+        let removed = match window {
+            Some(window) => self.layout.remove(window),
+            None => std::mem::take(&mut self.layout) != layout::Layout::default(),
+        };
+        self.redraw();
+        removed
+    }
+
+    /// Ask the user whether to go ahead with a command that throws things away, and if they say
+    /// yes, send an Event::UserCommand with it.  This replaces any question that's still open.
+    pub fn confirm(&mut self, question: String, command: Command) {
+        self.confirming = Some(confirm::Confirm::new(question, command));
+        self.redraw();
+    }
+
+    /// Open the color picker (see picker::Picker), and once a color's picked, send an
+    /// Event::UserCommand with `command`, with the color's name where picker::COLOR is in it.
+    pub fn pick_color(&mut self, command: String, sample: String, background: bool, selected: u8) {
+        self.picking = Some(picker::Picker::new(command, sample, background, selected));
+        self.redraw();
+    }
+
+    /// What a segment of the status bar says, if it's there.
+    pub fn status_segment(&self, segment: &str) -> Option<String> {
+        self.status.segment(segment).map(|text| text.to_string())
+    }
+
+    /// How many lines have gone to `window` while it was out of sight, since it was last on
+    /// screen.
+    pub fn unread(&self, window: &str) -> usize {
+        self.activity.get(window).cloned().unwrap_or(0)
+    }
+
+    /// The windows whose unread lines have changed since the last time this was called (more of
+    /// them, or they've been seen), and how many each has now, sorted by name.
+    pub fn take_activity(&mut self) -> Vec<(String, usize)> {
+        let changed = std::mem::take(&mut self.activity_changed);
+        let mut counts: Vec<(String, usize)> = changed.into_iter()
+            .map(|window| {
+                let unread = self.unread(&window);
+                (window, unread)
+            })
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    /// How the screen's split between panes.
+    pub fn layout(&self) -> &layout::Layout {
+        &self.layout
+    }
+
+    /// Split the screen up some other way, e.g. one saved from before (see layouts.)
+    pub fn set_layout(&mut self, layout: layout::Layout) {
+        for window in layout.windows() {
+            self.view(window.to_string());
+            self.seen(window);
+        }
+        self.layout = layout;
+        self.redraw();
+    }
+
+    /// Empty a window out, for when it's about to be filled with something new.
+    pub fn clear_window(&mut self, window: &str) {
+        self.windows.remove(window);
+        self.view(window.to_string());
+        self.db.damage_all();
+        self.redraw();
+    }
+
+    /// Keep at most `lines` lines of history in each window.
+    pub fn set_max_history(&mut self, lines: usize) {
+        self.max_history = lines;
+        for view in self.windows.values_mut() {
+            view.set_max_history(lines);
+        }
+        self.redraw();
+    }
+
+    /// How windows show their lines.
+    pub fn display(&self) -> Display {
+        self.display
+    }
+
+    /// Change how windows show their lines.  This applies straight away, to the lines already in
+    /// every window as well as new ones: they're all wrapped again and the whole screen is
+    /// repainted.
+    pub fn set_display(&mut self, display: Display) {
+        self.display = display;
+        for view in self.windows.values_mut() {
+            view.set_display(display);
+        }
+        self.db.damage_all();
+        self.redraw();
+    }
+
+    /// The idle audit for screen updates (see audit.rs.)  Redraws that turn out not to change
+    /// anything count.
+    pub fn audit(&mut self) -> &mut IdleAudit {
+        &mut self.audit
+    }
+
+    /// How many times the screen's been redrawn, and the time that took, all together.
+    pub fn redraws(&self) -> (u64, Duration) {
+        self.redraws
+    }
+
+    /// Set the bell policy for windows that don't have one of their own.
+    pub fn set_default_bell_policy(&mut self, policy: BellPolicy) {
+        self.default_bell = policy;
+    }
+
+    /// Get the user's attention about something in `window` in the way `policy` says to.
+    fn bell(&mut self, policy: BellPolicy, window: &str) {
+        match policy {
+            BellPolicy::Ring => {
+                write!(self.stdout, "\x07").unwrap();
+                self.stdout.flush().unwrap();
+            },
+            BellPolicy::Visual => self.flash(),
+            BellPolicy::Notify => self.notify(format!("Bell in {}", window)),
+            BellPolicy::Ignore => { },
+        }
+    }
+
+    /// Do one of the things keys can be bound to in the UI itself.
+    fn do_action(&mut self, action: keys::Action, out: &mut Vec<Event>) {
+        match action {
+            keys::Action::Quit => { out.push(Event::QuitRequest) },
+            keys::Action::Submit => match self.links.take() {
+                Some(picker) => out.push(Event::LinkFollowed {
+                    action: picker.links[picker.picked].1.action.clone(),
+                    which: self.current_id(),
+                }),
+                None => out.push(Event::UserInput {
+                    line: self.input.submit(),
+                    which: self.current_id(),
+                }),
+            },
+            keys::Action::CursorLeft => { self.input.move_cursor(-1) },
+            keys::Action::CursorRight => { self.input.move_cursor(1) },
+            keys::Action::LineStart => { self.input.cursor_to_start() },
+            keys::Action::LineEnd => { self.input.cursor_to_end() },
+            keys::Action::DeleteBack => { self.input.delete_chars(-1) },
+            keys::Action::DeleteForward => { self.input.delete_chars(1) },
+            keys::Action::DeleteWord => { self.input.delete_word() },
+            keys::Action::DeleteWordForward => { self.input.delete_word_forward() },
+            keys::Action::KillToEnd => { self.input.kill_to_end() },
+            keys::Action::Yank => { self.input.yank() },
+            keys::Action::YankPop => { self.input.yank_pop() },
+            keys::Action::WordLeft => { self.input.word_left() },
+            keys::Action::WordRight => { self.input.word_right() },
+            keys::Action::Mark => { self.mark(self.current.clone()) },
+            keys::Action::JumpToMark => {
+                if !self.jump_to_mark(self.current.clone()) {
+                    self.scroll_to_end(self.current.clone());
+                }
+            },
+            keys::Action::ScrollToEnd => { self.scroll_to_end(self.current.clone()) },
+            keys::Action::SwitchWindow => { self.switcher = Some(switcher::Switcher::new()) },
+            keys::Action::CopyLastLine => {
+                let last = self.windows.get(&self.current).and_then(|view| view.last_lines(1).pop());
+                if let Some(line) = last {
+                    self.copy(line.text().to_string());
+                }
+            },
+            keys::Action::HistoryPrev => { self.input.history_prev() },
+            keys::Action::HistoryNext => { self.input.history_next() },
+            keys::Action::Complete => {
+                match self.completion {
+                    Some(ref mut completion) => self.input.replace_before_cursor(completion.start, completion.next()),
+                    None => {
+                        let (start, typed) = self.input.word_before_cursor();
+                        let candidates = self.dictionary.candidates(&typed, start == 0, &self.current);
+                        if let Some(completion) = complete::Completion::new(start, typed, candidates) {
+                            self.input.replace_before_cursor(start, completion.current());
+                            self.completion = Some(completion);
+                        }
+                    },
+                }
+            },
+            keys::Action::Select => {
+                let main = self.panes.iter().find(|(window, rect)| window.is_none() && rect.w > 0 && rect.h > 0);
+                self.selecting = main.map(|(_, rect)| select::Selecting::new(*rect));
+            },
+            keys::Action::NextLink => {
+                self.links = match self.links.take() {
+                    Some(picker) => Some(LinkPicker { picked: (picker.picked + 1) % picker.links.len(), ..picker }),
+                    None => {
+                        let lines = self.windows.get(&self.current).map_or(vec![], |view| view.last_lines(LINK_LINES));
+                        let links: Vec<(String, Link)> = lines.iter().rev()
+                            .flat_map(|line| line.links.iter().rev().map(move |link| {
+                                (line.text().get(link.start..link.end).unwrap_or("").to_string(), link.clone())
+                            }))
+                            .collect();
+                        if links.is_empty() { None } else { Some(LinkPicker { links, picked: 0 }) }
+                    },
+                };
+            },
+            keys::Action::PageUp | keys::Action::PageDown => {
+                let view = self.view(self.current.clone());
+                // Keep one line from the last page on screen, so there's something to read on
+                // from.
+                let page = view.height().saturating_sub(1).max(1);
+                if action == keys::Action::PageUp {
+                    view.scroll_up(page);
+                } else {
+                    view.scroll_down(page);
+                }
+            },
+            keys::Action::ScrollUp => { self.view(self.current.clone()).scroll_up(1) },
+            keys::Action::ScrollDown => { self.view(self.current.clone()).scroll_down(1) },
+        }
+    }
+
+    /// Scroll the pane under the pointer with the wheel, drag across a pane to select text and
+    /// copy it, or click on a pane to bring its window into the main one.
+    fn mouse_event(&mut self, event: MouseEvent) {
+        match event {
+            MouseEvent::Press(button, x, y) => {
+                // Termion counts from 1.
+                let (x, y) = ((x as usize).saturating_sub(1), (y as usize).saturating_sub(1));
+                match (button, self.pane_at(x, y)) {
+                    (MouseButton::WheelUp, Some((window, _))) => self.view(window).scroll_up(mouse::WHEEL_LINES),
+                    (MouseButton::WheelDown, Some((window, _))) => self.view(window).scroll_down(mouse::WHEEL_LINES),
+                    (MouseButton::Left, Some((_, rect))) => self.selection = Some(select::Selection::new(rect, x, y)),
+                    _ => { },
+                }
+            },
+            MouseEvent::Hold(x, y) => {
+                if let Some(ref mut selection) = self.selection {
+                    selection.extend((x as usize).saturating_sub(1), (y as usize).saturating_sub(1));
+                }
+            },
+            MouseEvent::Release(x, y) => {
+                let (x, y) = ((x as usize).saturating_sub(1), (y as usize).saturating_sub(1));
+                let mut selection = match self.selection.take() {
+                    Some(selection) => selection,
+                    None => return,
+                };
+                selection.extend(x, y);
+                if selection.is_click() {
+                    if let Some((window, _)) = self.pane_at(x, y) {
+                        self.bring_to_main_pane(window);
+                    }
+                } else {
+                    let text = self.selected_text(&selection);
+                    self.copy(text);
+                }
+            },
+        }
+    }
+
+    /// What's on screen in a selection, a line for each row, without the blanks at the ends.
+    fn selected_text(&self, selection: &select::Selection) -> String {
+        let rows: Vec<String> = selection.rows().into_iter()
+            .map(|(x, y, w)| self.db.text(x, y, w).trim_end().to_string())
+            .collect();
+        rows.join("\n")
+    }
+
+    /// Put pasted text in the input line.  Text with more than one line in it would be a command
+    /// for each line, so that has to be confirmed first.
+    fn paste(&mut self, text: String) {
+        if self.switcher.is_some() || self.selecting.is_some() || self.confirming.is_some() || self.picking.is_some() {
+            return;
+        }
+        let mut lines = keys::pasted_lines(&text);
+        if lines.len() > 1 {
+            self.paste = Some(lines);
+        } else {
+            for c in lines.remove(0).chars().filter(|c| !c.is_control()) {
+                self.input.insert_char(c);
+            }
+        }
+    }
+
+    /// The window in the pane at (x, y), and where the pane is.
+    fn pane_at(&self, x: usize, y: usize) -> Option<(String, layout::Rect)> {
+        self.panes.iter()
+            .find(|(_, rect)| (rect.x..rect.x + rect.w).contains(&x) && (rect.y..rect.y + rect.h).contains(&y))
+            .map(|(window, rect)| (window.clone().unwrap_or_else(|| self.current.clone()), *rect))
+    }
+
+    /// Freeze the window text's being selected from, if it is, so the text doesn't move out from
+    /// under the selection; every other window carries on (or starts again.)
+    fn freeze_selected(&mut self) {
+        let selection = self.selection.as_ref().or_else(|| self.selecting.as_ref().map(|s| s.selection()));
+        let frozen = selection
+            .and_then(|selection| self.panes.iter().find(|(_, rect)| *rect == selection.pane))
+            .map(|(window, _)| window.clone().unwrap_or_else(|| self.current.clone()));
+        for (name, view) in self.windows.iter_mut() {
+            view.set_frozen(frozen.as_ref() == Some(name));
+        }
+    }
+
+    /// Swap a window that has a pane of its own with the one in the main pane.
+    fn bring_to_main_pane(&mut self, window: String) {
+        if window != self.current {
+            let current = self.current.clone();
+            self.layout.rename(&window, &current);
+            self.switch_to(window);
+            self.db.damage_all();
+        }
+    }
+
+    // Count all of `window`'s lines as seen.
+    fn seen(&mut self, window: &str) {
+        if self.activity.remove(window).is_some() {
+            self.activity_changed.insert(window.to_string());
+        }
+    }
+
+    /// Put a different window on screen.  Its lines count as seen.
+    fn switch_to(&mut self, window: String) {
+        self.seen(&window);
+        self.current = window;
+    }
+
+    /// Every window, for the switcher.
+    fn switcher_entries(&self) -> Vec<switcher::Entry> {
+        self.windows.keys()
+            .map(|name| switcher::Entry {
+                name: name.clone(),
+                unseen: self.activity.get(name).cloned().unwrap_or(0),
+                status: self.window_status.get(name).cloned(),
+            })
+            .collect()
+    }
+
+    /// Bring the status bar up to date with the window on screen: what it says about itself, where
+    /// it's scrolled to, and which other windows have something new in them.
+    fn update_status(&mut self) {
+        match self.window_status.get(&self.current) {
+            Some(text) => self.status.set_segment(WINDOW_SEGMENT.to_string(), text.clone()),
+            None => self.status.remove_segment(WINDOW_SEGMENT),
+        }
+
+        let view = self.windows.get(&self.current);
+        let held = view.map_or(0, |view| view.held());
+        let scrolled = view.filter(|view| view.unseen() > 0).map(|view| (view.unseen(), view.position()));
+        match scrolled {
+            _ if held > 0 => self.status.set_segment(MORE_SEGMENT.to_string(), format!("+{} new", held)),
+            Some((unseen, (line, lines))) => self.status.set_segment(MORE_SEGMENT.to_string(),
+                    format!("-- More ({}) -- line {}/{}", unseen, line, lines)),
+            None => self.status.remove_segment(MORE_SEGMENT),
+        }
+
+        match self.links {
+            Some(ref picker) => {
+                let (ref text, ref link) = picker.links[picker.picked];
+                let hint = link.hint.as_ref().map_or(String::new(), |hint| format!(" -- {}", hint));
+                self.status.set_segment(LINK_SEGMENT.to_string(), format!("Link {}/{}: {}{} (Enter follows it)",
+                        picker.picked + 1, picker.links.len(), text, hint));
+            },
+            None => self.status.remove_segment(LINK_SEGMENT),
+        }
+
+        match self.selecting {
+            Some(ref selecting) => self.status.set_segment(SELECT_SEGMENT.to_string(), selecting.describe().to_string()),
+            None => self.status.remove_segment(SELECT_SEGMENT),
+        }
+        match self.paste {
+            Some(ref lines) => self.status.set_segment(PASTE_SEGMENT.to_string(),
+                    format!("Send the {} lines pasted as {} commands? (y/n)", lines.len(), lines.len())),
+            None => self.status.remove_segment(PASTE_SEGMENT),
+        }
+
+        let mut active: Vec<(&String, &usize)> = self.activity.iter().collect();
+        active.sort();
+        if active.is_empty() {
+            self.status.remove_segment(ACTIVITY_SEGMENT);
+        } else {
+            let list: Vec<String> = active.iter().map(|(w, n)| format!("{} ({})", w, n)).collect();
+            self.status.set_segment(ACTIVITY_SEGMENT.to_string(), format!("Act: {}", list.join(", ")));
+        }
+    }
+
+    /// Whether `window` is showing in any pane.
+    fn on_screen(&self, window: &str) -> bool {
+        window == self.current || self.layout.windows().contains(&window)
+    }
+
+    /// The WindowID of the window on screen.
+    fn current_id(&mut self) -> WindowID {
+        let current = self.current.clone();
+        self.window_id(&current)
+    }
+
+    /// The view for a window, made if it doesn't exist yet.
+    fn view(&mut self, window: String) -> &mut text::WrappedView {
+        self.window_id(&window);
+        let (w, h) = self.term_size;
+        let (max_history, display) = (self.max_history, self.display);
+        self.windows.entry(window).or_insert_with(|| {
+            let mut view = text::WrappedView::new(w, h);
+            view.set_max_history(max_history);
+            view.set_display(display);
+            view
+        })
+    }
+
+    /// Flash the screen briefly, for a visual bell, or a highlight or trigger that asks for one,
+    /// or anything else that wants the user's attention without making noise.
+    pub fn flash(&mut self) {
+        if self.caps.dumb {
+            // There's no point sending escape sequences to a terminal that won't understand them;
+            // a plain old beep will have to do.
+            write!(self.stdout, "\x07").unwrap();
+            self.stdout.flush().unwrap();
+        } else if !self.flashing {
+            write!(self.stdout, "{}", REVERSE_VIDEO_ON).unwrap();
+            self.stdout.flush().unwrap();
+            self.flashing = true;
+            self.flash_tx.send(Duration::from_millis(FLASH_DURATION))
+                .expect("Couldn't send() flash request to FlashListener");
+        }
+    }
+
     fn redraw(&mut self) {
         // Render everything and just write it wholesale to the damage buffer.
         // Underlying assumption: CPU is much cheaper than I/O to the terminal for the
         // costs we care about.
+        let started = Instant::now();
+        if self.line_mode {
+            // Lines were printed as they came, and there's nothing else to show.
+            return;
+        }
 
         write!(self.stdout, "{}", termion::cursor::Hide).unwrap();
 
@@ -195,46 +1437,198 @@ impl TermUiManager {
         let h = self.term_size.1;
 
         self.input.set_width(w);
+        self.input.set_masked(self.masked.contains(&self.current));
+        self.status.set_width(w);
+
+        self.freeze_selected();
+        // This has to be sorted out before we know how much room the status bar takes.
+        self.update_status();
 
         let edit_h = self.input.get_size().1;
-        let view_h: usize = if edit_h < h {
-            h - edit_h
+        let status_h = if self.status.is_empty() { 0 } else { self.status.get_size().1 };
+        let view_h: usize = if edit_h + status_h < h {
+            h - edit_h - status_h
         } else {
             0
         };
 
-        if view_h > 0 {
+        let arrangement = self.layout.arrange(layout::Rect { x: 0, y: 0, w, h: view_h });
+        self.panes = arrangement.panes.clone();
+        for (direction, rect) in arrangement.dividers {
+            let divider = match direction {
+                layout::Direction::Stacked => SPLIT_DIVIDER,
+                layout::Direction::SideBySide => COLUMN_DIVIDER,
+            };
+            for y in rect.y..rect.y + rect.h {
+                self.db.write_string(rect.x, y, divider.to_string().repeat(rect.w));
+            }
+        }
+
+        for (window, rect) in arrangement.panes {
+            // Only the main pane (the one showing the current window) gets split.  A split only
+            // happens if there's room for at least a line in each half, plus the divider.
+            let tail_h = match self.split {
+                Some(tail_h) if window.is_none() && tail_h + 2 <= rect.h => tail_h,
+                _ => 0,
+            };
+
+            let view = self.view(window.clone().unwrap_or_else(|| self.current.clone()));
+            let review_h = if tail_h > 0 { rect.h - tail_h - 1 } else { rect.h };
+
             // TODO: This should also take a Size type.
-            self.view.resize(self.term_size.0, view_h);
+            view.resize(rect.w, review_h);
 
-            for (y, line) in self.view.render().into_iter().enumerate() {
-                self.db.write_string(0, y, line);
+            let mut lines = view.render();
+            if tail_h > 0 {
+                lines.push(SPLIT_DIVIDER.to_string().repeat(rect.w));
+                lines.extend(view.render_tail(tail_h));
+            }
+
+            // Lines are cut to fit again, since a wide character that sticks out of a pane next
+            // to another one would end up in that one.
+            for (y, line) in lines.into_iter().enumerate() {
+                self.db.write_string(rect.x, rect.y + y, text::force_width(&line, rect.w));
+            }
+
+            let background = self.backgrounds.get(window.as_ref().unwrap_or(&self.current)).copied();
+            let dim = self.dim_unfocused && window.is_some();
+            if background.is_some() || dim {
+                let tint = |style: &mut Style| {
+                    if let (Some(color), Color::Default) = (background, style.bg) {
+                        style.bg = color;
+                    }
+                    style.dim |= dim;
+                };
+                for y in rect.y..rect.y + rect.h {
+                    self.db.restyle(rect.x, y, rect.w, tint);
+                }
+            }
+        }
+
+        let selection = self.selection.as_ref().or_else(|| self.selecting.as_ref().map(|s| s.selection()));
+        if let Some(selection) = selection {
+            for (x, y, w) in selection.rows() {
+                self.db.reverse(x, y, w);
             }
         }
 
-        let input_y = h - edit_h;
-        for (y, line) in self.input.render().into_iter().enumerate() {
+        // The switcher goes on top of the windows, in the middle, as wide as half the screen
+        // (but at least enough to read.)
+        if let Some(ref switcher) = self.switcher {
+            let matches = switcher.matches(self.switcher_entries());
+            let box_w = (w / 2).max(30).min(w);
+            let box_h = (matches.len() + 1).min(view_h.saturating_sub(2)).max(view_h.min(2));
+            let (x, y) = ((w - box_w) / 2, view_h.saturating_sub(box_h) / 2);
+            for (i, line) in switcher.render(&matches, box_h).into_iter().enumerate() {
+                self.db.write_string(x, y + i, text::force_width(&line, box_w));
+            }
+        }
+
+        // The color picker and questions go in the middle too, as wide as they need to be.
+        let popup = match (&self.confirming, &self.picking) {
+            (Some(confirm), _) => Some(confirm.render()),
+            (None, Some(picker)) => Some(picker.render()),
+            (None, None) => None,
+        };
+        if let Some(lines) = popup {
+            let box_w = lines.iter().map(|line| ansi::parse(line).text.width() + 2).max().unwrap_or(0).min(w);
+            let top = view_h.saturating_sub(lines.len()) / 2;
+            for (i, line) in lines.iter().enumerate().take(view_h) {
+                self.db.write_string((w - box_w) / 2, top + i, text::force_width(&format!(" {}", line), box_w));
+            }
+        }
+
+        // Completion candidates go just above the status bar, lined up with the word being
+        // completed (past the "> " in front of them.)
+        if let Some(ref completion) = self.completion {
+            let lines = completion.render(COMPLETION_POPUP_LINES.min(view_h));
+            let box_w = lines.first().map_or(0, |line| line.width()).min(w);
+            let word_x = self.input.get_cursor_pos().0.saturating_sub(completion.current().width());
+            let x = word_x.saturating_sub(2).min(w - box_w);
+            for (i, line) in lines.iter().enumerate() {
+                self.db.write_string(x, view_h - lines.len() + i, text::force_width(line, box_w));
+            }
+        }
+
+        if status_h > 0 && view_h > 0 {
+            for (y, line) in self.status.render().into_iter().enumerate() {
+                self.db.write_string(0, view_h + y, line);
+            }
+        }
+
+        // On a terminal too short for all of the input, its top lines go off the top of the
+        // screen, so the end of it (where the typing usually is) stays in view.
+        let input_y = h.saturating_sub(edit_h);
+        let hidden = edit_h - (h - input_y);
+        for (y, line) in self.input.render().into_iter().skip(hidden).enumerate() {
             self.db.write_string(0, input_y + y, line);
         }
 
+        if !self.db.is_damaged() {
+            self.audit.note("redraw with nothing to draw");
+        }
+
         // Tell the damage buffer to terminal-update.
         self.db.redraw(&mut self.stdout).unwrap();
 
         // Restore the cursor to a correct position...
         let (cursor_x, cursor_y) = self.input.get_cursor_pos();
         let cursor_x = cursor_x as u16;
-        let cursor_y = cursor_y as u16;
+        let cursor_y = cursor_y.saturating_sub(hidden) as u16;
         write!(self.stdout, "{}{}", termion::cursor::Show,
                                     termion::cursor::Goto(cursor_x + 1, cursor_y + 1 + input_y as u16)).unwrap();
 
         self.stdout.flush().unwrap();
+        self.redraws.0 += 1;
+        self.redraws.1 += started.elapsed();
     }
 }
 
+/// Run `command` with the shell and give it `text` on its stdin.  Whatever it says is thrown
+/// away, since it'd be all over the screen otherwise.
+fn pipe_to(command: &str, text: &str) -> io::Result<()> {
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    child.stdin.take().expect("stdin was piped").write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("it finished with {}", status)))
+    }
+}
+
+/// The links in the window on screen, newest first, each with its text, and the one that's
+/// picked.  There's always at least one.
+struct LinkPicker {
+    links: Vec<(String, Link)>,
+    picked: usize,
+}
+
+/// Where we're up to with /showkey.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ShowKey {
+    Off,
+    /// The next key gets described instead of acted on.
+    Waiting,
+    /// A key's been described in the status bar; the next one clears it.
+    Showing,
+}
+
 /// Event type used internally for communication between threads.
 enum TermEvent {
     Resize,
     Input { key: Key },
+    /// A whole line read from stdin, in line mode.
+    Line { line: String },
+    Mouse { event: MouseEvent },
+    Paste { text: String },
+    FlashEnd,
 }
 
 /// Listener for terminal resize events.
@@ -243,28 +1637,124 @@ struct ResizeListener {
 }
 
 impl Listener for ResizeListener {
-    fn run(&mut self, mut flag: Box<ReadinessPager>) {
-        let sigs = Signals::new(&[libc::SIGWINCH]).expect("Couldn't create Signals iterator");
+    fn name(&self) -> String {
+        "resize".to_string()
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        let sigs = Signals::new([libc::SIGWINCH])
+            .map_err(|e| MintError::Ui(format!("Couldn't watch for resizes: {}", e)))?;
         for _signal in sigs.forever() {
-            self.tx.send(TermEvent::Resize).expect("error sending TermEvent::Resize");
+            self.tx.send(TermEvent::Resize)?;
             flag.ok();
         }
+        Ok(())
+    }
+}
+
+/// Where the TermUiManager draws.  Without a terminal, that's nowhere: everything written to it
+/// (which is all about drawing, moving the cursor and the like) goes, and line mode prints lines
+/// to stdout itself.
+enum Screen {
+    Raw(AlternateScreen<termion::raw::RawTerminal<io::Stdout>>),
+    Lines,
+}
+
+impl Write for Screen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Screen::Raw(screen) => screen.write(buf),
+            Screen::Lines => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Screen::Raw(screen) => screen.flush(),
+            Screen::Lines => Ok(()),
+        }
+    }
+}
+
+/// Listener for line mode: each line on stdin is a line typed.
+struct LineListener {
+    tx: Sender<TermEvent>,
+}
+
+impl Listener for LineListener {
+    fn name(&self) -> String {
+        "line-input".to_string()
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        let stdin = io::stdin();
+        let mut line = vec![];
+        loop {
+            line.clear();
+            match stdin.lock().read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line).trim_end_matches(&['\r', '\n'][..]).to_string();
+                    self.tx.send(TermEvent::Line { line: text })?;
+                    flag.ok();
+                },
+                Err(e) => return Err(MintError::Ui(format!("Couldn't read from stdin: {}", e))),
+            }
+        }
+        // Nothing more's coming (stdin's /dev/null, say, when run as a service), but that's no
+        // reason to stop: connections and timers carry on without it.
+        loop {
+            std::thread::park();
+        }
     }
 }
 
 /// Listener for termion (e.g., key, mouse, etc.) events.
 struct TermionListener {
     tx: Sender<TermEvent>,
+    /// What the bytes coming in on stdin are encoded in.  Termion only understands UTF-8, so
+    /// anything else gets decoded before it sees it.
+    charset: Charset,
+    escape_timeout: Arc<AtomicUsize>,
 }
 impl Listener for TermionListener {
-    fn run(&mut self, mut flag: Box<ReadinessPager>) {
-        let stdin = stdin();
-        for c in stdin.keys() {
-            // TODO: In the future, when we have better error handling for EventManaged
-            // threads, bounce this back to the parent thread and let it crash properly....?
-            let c = c.expect("Couldn't read from stdin?!");
-            self.tx.send(TermEvent::Input { key: c }).expect("error sending TermEvent::Input");
+    fn name(&self) -> String {
+        "termion-input".to_string()
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        let keys = keys::KeyReader::new(self.charset, self.escape_timeout.clone());
+        for event in keys {
+            let event = event.map_err(|e| MintError::Ui(format!("Couldn't read from stdin: {}", e)))?;
+            match event {
+                keys::Input::Key(key) => self.tx.send(TermEvent::Input { key })?,
+                keys::Input::Mouse(event) => self.tx.send(TermEvent::Mouse { event })?,
+                keys::Input::Paste(text) => self.tx.send(TermEvent::Paste { text })?,
+            }
             flag.ok();
         }
+        Ok(())
+    }
+}
+
+/// Listener that waits out visual bell flashes, so the main thread doesn't have to sleep.  It gets
+/// a Duration for each flash and says FlashEnd once that much time has passed.
+struct FlashListener {
+    rx: Receiver<Duration>,
+    tx: Sender<TermEvent>,
+}
+
+impl Listener for FlashListener {
+    fn name(&self) -> String {
+        "flash".to_string()
+    }
+
+    fn run(&mut self, mut flag: Box<dyn ReadinessPager>) -> Result<(), MintError> {
+        for duration in self.rx.iter() {
+            std::thread::sleep(duration);
+            self.tx.send(TermEvent::FlashEnd)?;
+            flag.ok();
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,10 @@
+//! The mouse: turning reporting on and off.  Selecting by dragging is in select.rs.
+
+/// Switch on the terminal reporting presses, drags (motion with a button held down) and
+/// releases, in the SGR encoding and urxvt's as well as the original one, which can't count past
+/// column 223.  This is what termion's MouseTerminal sends.
+pub const REPORTING_ON: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+pub const REPORTING_OFF: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+/// How many lines a turn of the wheel scrolls.
+pub const WHEEL_LINES: usize = 3;
@@ -0,0 +1,121 @@
+//! The color picker (see /color-pick): a popup with xterm's 256 colors in a grid, some sample
+//! text in whichever one's picked, and the command Enter will run with it.  Like the switcher, it
+//! gets all the keys until it's closed.
+
+use crate::command::Command;
+use crate::ui::ansi::Color;
+use crate::ui::style;
+
+use termion::event::Key;
+
+/// What's replaced with the name of the color picked (see style::color_name) in a picker's
+/// command.
+pub const COLOR: &str = "%c";
+
+/// How many colors go across the grid, which is also how many rows there are.
+const ACROSS: usize = 16;
+
+/// What a key did to the picker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Still open.
+    Open,
+    Cancelled,
+    /// A color's been picked, and this is the command to run with it.
+    Picked(Command),
+}
+
+pub struct Picker {
+    /// What to run once a color's picked, with COLOR where its name goes.
+    command: String,
+    /// Text to show in the color, e.g. what's being highlighted.
+    sample: String,
+    /// Whether it's a background being picked, so the sample goes on the color instead of in it.
+    background: bool,
+    selected: u8,
+}
+
+impl Picker {
+    pub fn new(command: String, sample: String, background: bool, selected: u8) -> Picker {
+        Picker { command, sample, background, selected }
+    }
+
+    pub fn key(&mut self, key: Key) -> Outcome {
+        let n = self.selected as usize;
+        let moved = match key {
+            Key::Esc | Key::Ctrl('g') | Key::Ctrl('c') => return Outcome::Cancelled,
+            Key::Char('\n') => {
+                return match Command::parse(&self.command()) {
+                    Some(command) => Outcome::Picked(command),
+                    None => Outcome::Cancelled,
+                };
+            },
+            Key::Left | Key::Char('h') if !n.is_multiple_of(ACROSS) => n - 1,
+            Key::Right | Key::Char('l') if n % ACROSS < ACROSS - 1 => n + 1,
+            Key::Up | Key::Char('k') if n >= ACROSS => n - ACROSS,
+            Key::Down | Key::Char('j') if n < 256 - ACROSS => n + ACROSS,
+            _ => n,
+        };
+        self.selected = moved as u8;
+        Outcome::Open
+    }
+
+    /// The command to run with the color that's picked now.
+    pub fn command(&self) -> String {
+        self.command.replace(COLOR, &style::color_name(self.selected))
+    }
+
+    /// The popup: which color's picked, the grid (with the picked one marked), the sample in the
+    /// color, and what the keys do.  Lines aren't cut to any width, and have SGR sequences in.
+    pub fn render(&self) -> Vec<String> {
+        let n = self.selected;
+        let mut lines = vec![format!("Color: {}", style::color_name(n))];
+        for row in 0..ACROSS {
+            let mut line = String::new();
+            for i in row * ACROSS..(row + 1) * ACROSS {
+                let mark = if i == n as usize { "[]" } else { "  " };
+                line.push_str(&format!("\x1b[{};48;5;{}m{}", contrast(i as u8), i, mark));
+            }
+            line.push_str("\x1b[0m");
+            lines.push(line);
+        }
+        let sample = match self.background {
+            true => format!("\x1b[{};48;5;{}m {} \x1b[0m", contrast(n), n, self.sample),
+            false => format!("\x1b[38;5;{}m{}\x1b[0m", n, self.sample),
+        };
+        lines.push(sample);
+        lines.push(format!("Enter runs {}", self.command()));
+        lines.push("Arrows (or hjkl) move, Esc closes".to_string());
+        lines
+    }
+}
+
+/// The SGR code for text that shows up on color `n` of the 256: black on light ones, white on
+/// dark ones.
+fn contrast(n: u8) -> u16 {
+    let (r, g, b) = Color::Indexed(n).rgb().unwrap_or((0, 0, 0));
+    let brightness = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+    if brightness > 128 { 30 } else { 97 }
+}
+
+#[test]
+fn picking_colors() {
+    let mut picker = Picker::new("/highlight orc = bold,%c".to_string(), "orc".to_string(), false, 1);
+    assert_eq!(picker.key(Key::Left), Outcome::Open);
+    assert_eq!(picker.key(Key::Left), Outcome::Open);
+    assert_eq!(picker.command(), "/highlight orc = bold,black");
+    picker.key(Key::Down);
+    picker.key(Key::Right);
+    assert_eq!(picker.command(), "/highlight orc = bold,color17");
+    for _ in 0..20 {
+        picker.key(Key::Down);
+    }
+    assert_eq!(picker.command(), "/highlight orc = bold,color241");
+
+    let lines = picker.render();
+    assert_eq!(lines.len(), 20);
+    assert_eq!(lines[0], "Color: color241");
+    assert_eq!(lines[17], "\x1b[38;5;241morc\x1b[0m");
+    assert_eq!(picker.key(Key::Char('\n')), Outcome::Picked(Command::parse("/highlight orc = bold,color241").unwrap()));
+    assert_eq!(picker.key(Key::Esc), Outcome::Cancelled);
+}
@@ -1,6 +1,11 @@
 use std::io::Write;
 use std::collections::BTreeSet;
 
+use crate::ui::ansi::{self, Style};
+use crate::ui::term::caps::ColorDepth;
+
+use unicode_width::UnicodeWidthStr;
+
 // also uses termion. TODO: Import at top level of term module? Would that even work?
 
 // Note: Rust docs say std::cmp::PartialOrd is derivable and will produce a lexicographic ordering
@@ -39,6 +44,23 @@ fn point_order() {
 }
 
 
+/// One character cell of the screen.
+#[derive(Clone, PartialEq, Eq)]
+struct Cell {
+    // This was chosen to be String not Char because some Unicode characters can take up multiple
+    // chars and so why not.  It's a whole grapheme, accents and all.  A wide character's first
+    // cell has it, and the cell after that is left empty, since the terminal draws the character
+    // across both.
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    fn blank() -> Cell {
+        Cell { text: " ".to_string(), style: Style::default() }
+    }
+}
+
 /// Very work-in-progress 'damage buffer' type of display.
 pub struct DamageBuffer {
     points_to_draw: BTreeSet<Point>,
@@ -47,63 +69,159 @@ pub struct DamageBuffer {
 
     w: usize,
     h: usize,
-    // This was chosen to be String not Char because some Unicode characters can take up multiple
-    // chars and so why not
-    buffer: Vec<String>,
+    buffer: Vec<Cell>,
+
+    // How many colors the terminal can show; styles are toned down to fit when they're drawn.
+    colors: ColorDepth,
 }
 
 impl DamageBuffer {
-    pub fn new(w: usize, h: usize) -> DamageBuffer {
+    pub fn new(w: usize, h: usize, colors: ColorDepth) -> DamageBuffer {
         let buffer = DamageBuffer {
             w, h,
-            buffer: std::iter::repeat(" ".to_string()).take(w*h).collect(),
+            buffer: std::iter::repeat_n(Cell::blank(), w*h).collect(),
             points_to_draw: BTreeSet::new(),
             redraw_all: false,
             clear_all: false,
+            colors,
         };
 
         buffer
     }
 
     pub fn clear(&mut self) {
-        self.buffer = std::iter::repeat(" ".to_string())
-            .take(self.w * self.h)
-            .collect();
+        self.buffer = std::iter::repeat_n(Cell::blank(), self.w * self.h).collect();
         self.points_to_draw.clear();
         self.redraw_all = false;
         self.clear_all = true;
     }
 
+    /// Change the size of the buffer.  Everything in it is blanked, since the old cells don't line
+    /// up with the new rows, and the whole lot gets drawn next time.  Either size can be zero, in
+    /// which case there's nothing to draw at all.
     pub fn resize(&mut self, new_w: usize, new_h: usize) {
         self.w = new_w;
         self.h = new_h;
-        self.buffer.resize(self.w * self.h, " ".to_string());
+        self.buffer = vec![Cell::blank(); self.w * self.h];
+        // Damage from before might be off the edge now; redraw_all covers whatever's left of it.
+        self.points_to_draw.clear();
+        self.redraw_all = true;
+    }
+
+    /// Draw everything next time, not just what's changed; for when the whole screen's different
+    /// (a new color scheme, everything re-wrapped) or might be out of step with the buffer.
+    pub fn damage_all(&mut self) {
         self.redraw_all = true;
     }
 
+    /// Write some text into the buffer, starting at x, y.  Any SGR sequences in it set the style
+    /// of the cells after them; they don't take up any cells themselves.
     pub fn write_string(&mut self, x: usize, y: usize, what: String) {
+        if y >= self.h {
+            return;
+        }
         let mut x = x;
 
-        for c in what.chars() {
-            if x < self.w && y < self.h {
-                let c = c.to_string();
-                // We're indexing into a 2D grid laid out row by row in a 1D memory buffer.  So we
-                // compute the 1D index by multiplying y by the row length, then adding x (the
-                // offset inside that row.)
-                let i = y * self.w + x;
-
-                if c != self.buffer[i] {
-                    self.buffer[i] = c;
-                    self.points_to_draw.insert(Point { x, y });
+        for (grapheme, style) in ansi::parse(&what).styled_graphemes() {
+            let w = grapheme.width();
+            // Things that take up no room at all (a lone zero width joiner, say) have nowhere to
+            // go.
+            if w == 0 {
+                continue;
+            }
+            if x + w > self.w {
+                // Half a wide character can't be drawn, so the end of the row gets blanks.
+                while x < self.w {
+                    self.set_cell(x, y, Cell { text: " ".to_string(), style });
+                    x += 1;
                 }
+                break;
+            }
+
+            self.set_cell(x, y, Cell { text: grapheme.to_string(), style });
+            for i in 1..w {
+                self.set_cell(x + i, y, Cell { text: String::new(), style });
             }
-            x += 1;
+            x += w;
         }
     }
 
+    /// What's in `w` cells of row `y` from `x` on, as it's shown; the halves of a wide character
+    /// are the character once.
+    pub fn text(&self, x: usize, y: usize, w: usize) -> String {
+        if y >= self.h || x >= self.w {
+            return String::new();
+        }
+        let row = y * self.w;
+        let end = (x + w).min(self.w);
+        self.buffer[row + x..row + end].iter().map(|cell| cell.text.as_str()).collect()
+    }
+
+    /// Show `w` cells of row `y` from `x` on in reverse video (or not, if they already were), for
+    /// marking out a selection over whatever was written there.
+    pub fn reverse(&mut self, x: usize, y: usize, w: usize) {
+        self.restyle(x, y, w, |style| style.reverse = !style.reverse);
+    }
+
+    /// Change the style of `w` cells of row `y` from `x` on, after whatever's in them has been
+    /// written, e.g. to give a whole pane a background, blanks and all.
+    pub fn restyle(&mut self, x: usize, y: usize, w: usize, change: impl Fn(&mut Style)) {
+        if y >= self.h {
+            return;
+        }
+        for x in x..(x + w).min(self.w) {
+            let mut cell = self.buffer[y * self.w + x].clone();
+            change(&mut cell.style);
+            self.put(x, y, cell);
+        }
+    }
+
+    /// Put a cell in the buffer.  Writing over part of a wide character wipes out the whole
+    /// thing on the terminal, so the rest of it is blanked here as well, or the buffer and the
+    /// screen would disagree.
+    fn set_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        // We're indexing into a 2D grid laid out row by row in a 1D memory buffer.  So we compute
+        // the 1D index by multiplying y by the row length, then adding x (the offset inside that
+        // row.)
+        let row = y * self.w;
+        if self.buffer[row + x] == cell {
+            return;
+        }
+
+        if self.buffer[row + x].text.is_empty() {
+            // The second half of a wide character, which is going, unless a new wide character is
+            // going in the cell before this one.
+            if !cell.text.is_empty() && x > 0 {
+                self.put(x - 1, y, Cell::blank());
+            }
+        } else {
+            // Whatever was here might have been wide, and the cells it took up after this one
+            // are no good without it.
+            let mut next = x + 1;
+            while next < self.w && self.buffer[row + next].text.is_empty() {
+                self.put(next, y, Cell::blank());
+                next += 1;
+            }
+        }
+        self.put(x, y, cell);
+    }
+
+    fn put(&mut self, x: usize, y: usize, cell: Cell) {
+        let i = y * self.w + x;
+        if cell != self.buffer[i] {
+            self.buffer[i] = cell;
+            self.points_to_draw.insert(Point { x, y });
+        }
+    }
+
+    /// Whether redraw() would have anything to do.
+    pub fn is_damaged(&self) -> bool {
+        self.redraw_all || self.clear_all || !self.points_to_draw.is_empty()
+    }
+
     pub fn redraw(&mut self, term: &mut impl Write) -> std::io::Result<()> {
         let mut last_point = Point { x:0, y:0 };
-        print!("{}", termion::cursor::Goto(1,1));
+        write!(term, "{}", termion::cursor::Goto(1,1))?;
 
         // TODO: Think about ways to refactor this, since we're doing the same thing in two places.
         // You can get an Iterator over all Points with the following:
@@ -117,6 +235,11 @@ impl DamageBuffer {
         // I could probably make a closure then call for_each() in two places depending on the
         // branch, but that seems like it'd be slower.  I should probably try doing it anyway.
 
+        // The style the terminal's currently drawing in.  We don't know what it was left in, so
+        // start from a clean slate.
+        let mut current = Style::default();
+        term.write_all(b"\x1b[0m")?;
+
         if self.clear_all {
             term.write(format!("{}", termion::clear::All).as_bytes())?;
         }
@@ -128,7 +251,12 @@ impl DamageBuffer {
                         term.write(format!("{}", termion::cursor::Goto((x+1) as u16, (y+1) as u16)).as_bytes())?;
                     }
 
-                    term.write(format!("{}", self.buffer[y * self.w + x]).as_bytes())?;
+                    let cell = &self.buffer[y * self.w + x];
+                    if cell.style != current {
+                        term.write_all(cell.style.sgr(self.colors).as_bytes())?;
+                        current = cell.style;
+                    }
+                    term.write_all(cell.text.as_bytes())?;
                     last_point.x = x; last_point.y = y;
                 }
             }
@@ -142,11 +270,21 @@ impl DamageBuffer {
                     term.write(format!("{}", termion::cursor::Goto((x+1) as u16, (y+1) as u16)).as_bytes())?;
                 }
 
-                term.write(format!("{}", self.buffer[y * self.w + x]).as_bytes())?;
+                let cell = &self.buffer[y * self.w + x];
+                if cell.style != current {
+                    term.write_all(cell.style.sgr(self.colors).as_bytes())?;
+                    current = cell.style;
+                }
+                term.write_all(cell.text.as_bytes())?;
                 last_point.x = *x; last_point.y = *y;
             }
         }
 
+        // Leave the terminal in the default style, for whatever gets written outside of us.
+        if current != Style::default() {
+            term.write_all(b"\x1b[0m")?;
+        }
+
         self.points_to_draw.clear();
         self.redraw_all = false;
         self.clear_all = false;
@@ -155,3 +293,101 @@ impl DamageBuffer {
     }
 }
 
+#[test]
+fn restyling() {
+    use crate::ui::ansi::Color;
+
+    let mut db = DamageBuffer::new(6, 2, ColorDepth::Ansi16);
+    db.write_string(0, 0, "a\x1b[41mb\x1b[0mc".to_string());
+    let mut out = vec![];
+    db.redraw(&mut out).unwrap();
+
+    // A tint only goes where there isn't a background already.
+    let tint = |style: &mut Style| if style.bg == Color::Default { style.bg = Color::Ansi(4) };
+    db.restyle(0, 0, 6, tint);
+    assert_eq!(db.buffer[0].style.bg, Color::Ansi(4));
+    assert_eq!(db.buffer[1].style.bg, Color::Ansi(1));
+    assert_eq!(db.buffer[5].style.bg, Color::Ansi(4));
+    assert_eq!(db.buffer[6].style.bg, Color::Default);
+    assert_eq!(db.points_to_draw.len(), 5);
+
+    // Doing it again changes nothing, so there's nothing to draw.
+    db.redraw(&mut out).unwrap();
+    db.restyle(0, 0, 6, tint);
+    assert!(!db.is_damaged());
+
+    db.reverse(0, 1, 2);
+    assert!(db.buffer[7].style.reverse);
+    assert!(!db.buffer[8].style.reverse);
+}
+
+/// Draw some styled text, wide characters and all, into memory and check it all comes out the
+/// other end, for /selftest.
+pub fn round_trip(colors: ColorDepth) -> Result<String, String> {
+    let mut db = DamageBuffer::new(20, 2, colors);
+    db.write_string(0, 1, "\x1b[1;32mmint\x1b[0m 日本 café".to_string());
+    let mut out = vec![];
+    db.redraw(&mut out).map_err(|e| format!("Couldn't draw: {}", e))?;
+    let out = String::from_utf8(out).map_err(|_| "What was drawn isn't UTF-8".to_string())?;
+
+    // Blanks on a blank screen aren't drawn at all (the cursor's moved past them), so they
+    // won't be in what comes back.
+    let drawn = ansi::parse(&out).text;
+    match drawn.contains("mint日本café") {
+        true => Ok(format!("Drew {} bytes, and the text came back.", out.len())),
+        false => Err(format!("Drew {:?}, which isn't the text that went in", drawn)),
+    }
+}
+
+
+#[test]
+fn read_back() {
+    let mut db = DamageBuffer::new(10, 2, ColorDepth::Ansi16);
+    db.write_string(0, 1, "ab日本".to_string());
+    assert_eq!(db.text(1, 1, 4), "b日本");
+    assert_eq!(db.text(0, 0, 99), " ".repeat(10));
+    assert_eq!(db.text(0, 2, 1), "");
+
+    db.redraw(&mut vec![]).unwrap();
+    db.reverse(0, 1, 2);
+    assert!(db.is_damaged());
+    db.reverse(0, 1, 2);
+    db.redraw(&mut vec![]).unwrap();
+    assert_eq!(db.text(0, 1, 2), "ab");
+}
+
+#[test]
+fn round_trips() {
+    assert_eq!(round_trip(ColorDepth::None).map(|_| ()), Ok(()));
+    assert!(round_trip(ColorDepth::TrueColor).is_ok());
+}
+
+#[test]
+fn resize_to_nothing() {
+    let mut db = DamageBuffer::new(10, 3, ColorDepth::None);
+    db.write_string(8, 2, "hi".to_string());
+    db.resize(0, 0);
+    db.write_string(0, 0, "gone".to_string());
+    assert!(db.redraw(&mut vec![]).is_ok());
+
+    db.resize(2, 1);
+    db.write_string(0, 0, "okay".to_string());
+    let mut out = vec![];
+    db.redraw(&mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("ok"));
+}
+
+#[test]
+fn wide_cells() {
+    let mut db = DamageBuffer::new(5, 1, ColorDepth::None);
+    db.write_string(0, 0, "日本語".to_string());
+    // The third one doesn't fit, so there's a blank where its first half would've gone.
+    let cells: Vec<&str> = db.buffer.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(cells, vec!["日", "", "本", "", " "]);
+
+    // Writing over the second half of 日 takes out the first half as well, and writing over the
+    // first half of 本 takes out the second.
+    db.write_string(1, 0, "ab".to_string());
+    let cells: Vec<&str> = db.buffer.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(cells, vec![" ", "a", "b", " ", " "]);
+}
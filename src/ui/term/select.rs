@@ -0,0 +1,190 @@
+//! Selecting text on the screen to copy it, with the mouse (by dragging) or the keyboard (in
+//! selection mode, which gets all the keys until it's done).  A selection runs from one point to
+//! another like in a text editor, or with `rectangular` set, it's the block between them.
+
+use termion::event::Key;
+
+use crate::ui::term::layout::Rect;
+
+/// Text being selected, from where it started (the anchor) to where the pointer or cursor is now
+/// (the head).  It stays inside the pane it started in; going past the edge selects up to the
+/// edge.  Points are (x, y), 0-indexed, on the screen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub pane: Rect,
+    pub rectangular: bool,
+    anchor: (usize, usize),
+    head: (usize, usize),
+}
+
+impl Selection {
+    /// A selection with nothing in it yet, started at (x, y) in `pane`.
+    pub fn new(pane: Rect, x: usize, y: usize) -> Selection {
+        let point = clamp(pane, x, y);
+        Selection { pane, rectangular: false, anchor: point, head: point }
+    }
+
+    /// Move the end of the selection that isn't the anchor.
+    pub fn extend(&mut self, x: usize, y: usize) {
+        self.head = clamp(self.pane, x, y);
+    }
+
+    /// Start the selection again from where the head is.
+    pub fn collapse(&mut self) {
+        self.anchor = self.head;
+    }
+
+    pub fn head(&self) -> (usize, usize) {
+        self.head
+    }
+
+    /// Whether the pointer's never moved off the cell it started on, which makes it a click.
+    pub fn is_click(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    /// The selection as runs of cells, (x, y, width), one per row, top first.  As in a text
+    /// editor, the rows between the first and last are selected all the way across the pane,
+    /// unless it's rectangular.
+    pub fn rows(&self) -> Vec<(usize, usize, usize)> {
+        if self.rectangular {
+            let (left, right) = (self.anchor.0.min(self.head.0), self.anchor.0.max(self.head.0));
+            let (top, bottom) = (self.anchor.1.min(self.head.1), self.anchor.1.max(self.head.1));
+            return (top..=bottom).map(|y| (left, y, right + 1 - left)).collect();
+        }
+
+        // Points compare by row first, which is the order we want.
+        let by_row = |(x, y): (usize, usize)| (y, x);
+        let (start, end) = if by_row(self.anchor) <= by_row(self.head) {
+            (self.anchor, self.head)
+        } else {
+            (self.head, self.anchor)
+        };
+        let right = self.pane.x + self.pane.w;
+
+        (start.1..=end.1)
+            .map(|y| {
+                let from = if y == start.1 { start.0 } else { self.pane.x };
+                let to = if y == end.1 { end.0 + 1 } else { right };
+                (from, y, to - from)
+            })
+            .collect()
+    }
+}
+
+/// The nearest point to (x, y) inside `pane`.  The pane has to have room for at least one cell.
+fn clamp(pane: Rect, x: usize, y: usize) -> (usize, usize) {
+    (x.max(pane.x).min(pane.x + pane.w - 1), y.max(pane.y).min(pane.y + pane.h - 1))
+}
+
+/// What a key did in selection mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Still selecting.
+    Open,
+    Cancelled,
+    /// Done: copy what's selected.
+    Copy,
+}
+
+/// Selection mode.  There's a cursor to move around the pane with the arrow keys (or hjkl) until
+/// Space (or v) starts a selection there, or r starts a rectangular one; after that, moving
+/// selects.  Enter (or y) copies, and Esc gives up.
+pub struct Selecting {
+    selection: Selection,
+    started: bool,
+}
+
+impl Selecting {
+    /// Start with the cursor at the bottom left of `pane`, where the newest lines are.
+    pub fn new(pane: Rect) -> Selecting {
+        Selecting { selection: Selection::new(pane, pane.x, pane.y + pane.h - 1), started: false }
+    }
+
+    pub fn selection(&self) -> &Selection {
+        &self.selection
+    }
+
+    pub fn key(&mut self, key: Key) -> Outcome {
+        let (x, y) = self.selection.head();
+        let pane = self.selection.pane;
+        let to = match key {
+            Key::Esc | Key::Ctrl('g') | Key::Ctrl('c') | Key::Char('q') => return Outcome::Cancelled,
+            Key::Char('\n') | Key::Char('y') => return Outcome::Copy,
+            Key::Char(' ') | Key::Char('v') | Key::Char('r') => {
+                let rectangular = key == Key::Char('r');
+                // Starting again the same way stops selecting, and just moves the cursor.
+                self.started = !(self.started && self.selection.rectangular == rectangular);
+                self.selection.rectangular = rectangular;
+                self.selection.collapse();
+                return Outcome::Open;
+            },
+            Key::Left | Key::Char('h') => (x.saturating_sub(1), y),
+            Key::Right | Key::Char('l') => (x + 1, y),
+            Key::Up | Key::Char('k') => (x, y.saturating_sub(1)),
+            Key::Down | Key::Char('j') => (x, y + 1),
+            Key::Home | Key::Char('0') => (pane.x, y),
+            Key::End | Key::Char('$') => (pane.x + pane.w, y),
+            Key::PageUp => (x, pane.y),
+            Key::PageDown => (x, pane.y + pane.h),
+            _ => return Outcome::Open,
+        };
+        self.selection.extend(to.0, to.1);
+        if !self.started {
+            self.selection.collapse();
+        }
+        Outcome::Open
+    }
+
+    /// What to say in the status bar about it.
+    pub fn describe(&self) -> &'static str {
+        match (self.started, self.selection.rectangular) {
+            (false, _) => "Select: move to the start, then Space (or r for a block); Esc gives up",
+            (true, false) => "Selecting lines: move to the end, then Enter copies; Esc gives up",
+            (true, true) => "Selecting a block: move to the corner, then Enter copies; Esc gives up",
+        }
+    }
+}
+
+#[test]
+fn mouse_selection() {
+    let pane = Rect { x: 10, y: 2, w: 20, h: 5 };
+
+    let mut selection = Selection::new(pane, 15, 3);
+    assert!(selection.is_click());
+    assert_eq!(selection.rows(), vec![(15, 3, 1)]);
+
+    // Backwards across rows: the first row goes to the edge, and the last starts at it.
+    selection.extend(12, 1);
+    assert!(!selection.is_click());
+    assert_eq!(selection.rows(), vec![(12, 2, 18), (10, 3, 6)]);
+
+    // Off the bottom right of the pane.
+    selection.extend(80, 24);
+    assert_eq!(selection.rows(), vec![(15, 3, 15), (10, 4, 20), (10, 5, 20), (10, 6, 20)]);
+}
+
+#[test]
+fn keyboard_selection() {
+    let pane = Rect { x: 0, y: 0, w: 10, h: 4 };
+    let mut selecting = Selecting::new(pane);
+    assert_eq!(selecting.selection().rows(), vec![(0, 3, 1)]);
+
+    // Moving without starting just moves the cursor.
+    for key in &[Key::Up, Key::Up, Key::Right, Key::Char('l')] {
+        assert_eq!(selecting.key(*key), Outcome::Open);
+    }
+    assert_eq!(selecting.selection().rows(), vec![(2, 1, 1)]);
+
+    selecting.key(Key::Char('r'));
+    selecting.key(Key::Down);
+    selecting.key(Key::Char('l'));
+    assert_eq!(selecting.selection().rows(), vec![(2, 1, 2), (2, 2, 2)]);
+
+    // Switching to lines keeps going from where the cursor is.
+    selecting.key(Key::Char(' '));
+    selecting.key(Key::End);
+    assert_eq!(selecting.selection().rows(), vec![(3, 2, 7)]);
+    assert_eq!(selecting.key(Key::Char('\n')), Outcome::Copy);
+    assert_eq!(selecting.key(Key::Esc), Outcome::Cancelled);
+}
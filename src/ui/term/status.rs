@@ -0,0 +1,69 @@
+use crate::ui::term::{Window, text::force_width};
+
+use unicode_width::UnicodeWidthStr;
+
+/// A one-line bar made up of named segments of text, drawn in the order they were first set.
+/// Segments are updated in place, so things like "Target: rat (40%)" can be kept current without
+/// needing a whole window to themselves.
+pub struct StatusBar {
+    segments: Vec<(String, String)>,
+    target_width: usize,
+}
+
+impl StatusBar {
+    pub fn new(width: usize) -> StatusBar {
+        StatusBar {
+            segments: vec![],
+            target_width: width,
+        }
+    }
+
+    /// Set the text of a segment, creating it at the end of the bar if it doesn't exist yet.
+    pub fn set_segment(&mut self, name: String, text: String) {
+        match self.segments.iter_mut().find(|(n, _)| *n == name) {
+            Some(segment) => segment.1 = text,
+            None => self.segments.push((name, text)),
+        }
+    }
+
+    pub fn remove_segment(&mut self, name: &str) {
+        self.segments.retain(|(n, _)| n != name);
+    }
+
+    /// What a segment says, if it's there.
+    pub fn segment(&self, name: &str) -> Option<&str> {
+        self.segments.iter().find(|(n, _)| n == name).map(|(_, text)| text.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+}
+
+impl Window for StatusBar {
+    fn render(&self) -> Vec<String> {
+        let texts: Vec<&str> = self.segments.iter().map(|(_, text)| text.as_str()).collect();
+        let mut line = format!("-- {} ", texts.join(" | "));
+
+        // Fill whatever's left with dashes so the bar reads as a divider between the output and the
+        // input line, like tinyfugue's.
+        line.push_str(&"-".repeat(self.target_width.saturating_sub(line.width())));
+        vec![force_width(&line, self.target_width)]
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        (self.target_width, 1)
+    }
+
+    fn get_cursor_pos(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    fn set_width(&mut self, new_w: usize) {
+        self.target_width = new_w;
+    }
+
+    fn set_height(&mut self, _new_h: usize) {
+        panic!("Can't set the height of a StatusBar: It's always one line.");
+    }
+}
@@ -0,0 +1,145 @@
+//! The window switcher: a popup listing the windows (with how many lines each has that haven't
+//! been seen yet), narrowed down as you type part of a name, and Enter goes to the one picked.
+//! Like "go to file" in an editor.
+
+use termion::event::Key;
+
+/// A window, as the switcher shows it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub unseen: usize,
+    /// What the window says about itself (see UserInterface::set_window_status), like which
+    /// world it's for.
+    pub status: Option<String>,
+}
+
+/// What a key did to the switcher.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Still open.
+    Open,
+    /// Closed without picking anything.
+    Cancelled,
+    Picked(String),
+}
+
+#[derive(Default)]
+pub struct Switcher {
+    query: String,
+    selected: usize,
+}
+
+impl Switcher {
+    pub fn new() -> Switcher {
+        Switcher::default()
+    }
+
+    /// The entries that match what's been typed so far, best first: the ones whose names start
+    /// with it, then the ones with it somewhere in them, then the ones with its letters in order
+    /// with others in between.  Case doesn't matter.
+    pub fn matches(&self, entries: Vec<Entry>) -> Vec<Entry> {
+        let query = self.query.to_lowercase();
+        let mut ranked: Vec<(u8, Entry)> = entries.into_iter()
+            .filter_map(|entry| {
+                let name = entry.name.to_lowercase();
+                let rank = if name.starts_with(&query) {
+                    0
+                } else if name.contains(&query) {
+                    1
+                } else if in_order(&query, &name) {
+                    2
+                } else {
+                    return None;
+                };
+                Some((rank, entry))
+            })
+            .collect();
+        ranked.sort_by(|(a_rank, a), (b_rank, b)| a_rank.cmp(b_rank).then_with(|| a.name.cmp(&b.name)));
+        ranked.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Deal with a key, given what matches() is showing.
+    pub fn key(&mut self, key: Key, matches: &[Entry]) -> Outcome {
+        match key {
+            Key::Esc | Key::Ctrl('g') | Key::Ctrl('c') => return Outcome::Cancelled,
+            Key::Char('\n') => {
+                return match matches.get(self.selected) {
+                    Some(entry) => Outcome::Picked(entry.name.clone()),
+                    None => Outcome::Cancelled,
+                };
+            },
+            Key::Up | Key::Ctrl('p') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Ctrl('n') => {
+                self.selected = (self.selected + 1).min(matches.len().saturating_sub(1));
+            },
+            Key::Backspace | Key::Ctrl('h') => {
+                self.query.pop();
+                self.selected = 0;
+            },
+            Key::Char(c) if !c.is_control() => {
+                self.query.push(c);
+                self.selected = 0;
+            },
+            _ => { },
+        }
+        Outcome::Open
+    }
+
+    /// The popup, `h` lines high at most: what's been typed, then as many of the matches as fit,
+    /// scrolled so the one picked is in sight.  Lines aren't cut to any width.
+    pub fn render(&self, matches: &[Entry], h: usize) -> Vec<String> {
+        let mut lines = vec![format!("Switch to: {}_", self.query)];
+        let room = h.saturating_sub(1);
+        let first = (self.selected + 1).saturating_sub(room);
+        for (i, entry) in matches.iter().enumerate().skip(first).take(room) {
+            let marker = if i == self.selected { ">" } else { " " };
+            let mut line = format!("{} {}", marker, entry.name);
+            if entry.unseen > 0 {
+                line.push_str(&format!(" ({})", entry.unseen));
+            }
+            if let Some(ref status) = entry.status {
+                line.push_str(&format!("  {}", status));
+            }
+            lines.push(line);
+        }
+        if matches.is_empty() && room > 0 {
+            lines.push("  (no windows match)".to_string());
+        }
+        lines.truncate(h);
+        lines
+    }
+}
+
+/// Whether the characters of `query` all appear in `name`, in the same order.
+fn in_order(query: &str, name: &str) -> bool {
+    let mut name = name.chars();
+    query.chars().all(|q| name.any(|c| c == q))
+}
+
+#[test]
+fn switcher_filtering() {
+    let entry = |name: &str, unseen| Entry { name: name.to_string(), unseen, status: None };
+    let windows = vec![entry("default", 0), entry("discworld", 3), entry("chat", 1), entry("ardis", 0)];
+
+    let mut switcher = Switcher::new();
+    assert_eq!(switcher.matches(windows.clone()).len(), 4);
+    for c in "DIS".chars() {
+        assert_eq!(switcher.key(Key::Char(c), &[]), Outcome::Open);
+    }
+    // "discworld" starts with it, "ardis" has it in the middle, and "default" only has the
+    // letters.
+    let matches = switcher.matches(windows.clone());
+    assert_eq!(matches.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["discworld", "ardis"]);
+    assert_eq!(switcher.render(&matches, 10), vec!["Switch to: DIS_", "> discworld (3)", "  ardis"]);
+
+    switcher.key(Key::Down, &matches);
+    switcher.key(Key::Down, &matches);
+    assert_eq!(switcher.render(&matches, 2), vec!["Switch to: DIS_", "> ardis"]);
+    assert_eq!(switcher.key(Key::Char('\n'), &matches), Outcome::Picked("ardis".to_string()));
+
+    switcher.key(Key::Backspace, &matches);
+    switcher.key(Key::Backspace, &matches);
+    assert_eq!(switcher.matches(windows).len(), 3);
+    assert_eq!(switcher.key(Key::Esc, &matches), Outcome::Cancelled);
+}
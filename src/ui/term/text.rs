@@ -1,28 +1,66 @@
 use fnv::FnvHashMap;
-
-
-/// Return a version of `text` that is exactly `width` chars long.  Truncates if it is too long,
-/// and appends space characters if it is not long enough.
-pub fn force_width(mut text: String, width: usize) -> String {
-    // TODO: Do this in a less stupid way...
-    while text.len() > width {
-        text.pop();
-    }
-
-    while text.len() < width {
-        text.push(' ');
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[cfg(test)]
+use proptest::prelude::*;
+
+use crate::clock::LocalTime;
+use crate::line::Line;
+use crate::ui::ansi::{self, Style, StyledLine};
+
+/// How many lines of history a view keeps if nobody says otherwise.
+pub const DEFAULT_MAX_HISTORY: usize = 10000;
+
+/// Return a version of `text` that takes up exactly `width` columns on the terminal.  If it's too
+/// wide it's cut short, between two graphemes (so a wide character that won't fit goes entirely,
+/// and accents stay with their letters); if it isn't wide enough, spaces go on the end.
+///
+/// `text` can have SGR sequences in it.  They don't count towards the width, and whatever style
+/// is on at the end gets switched off again, so cutting a line short can't leave the rest of the
+/// screen bold.
+pub fn force_width(text: &str, width: usize) -> String {
+    let styled = ansi::parse(text);
+    let mut used = 0;
+    let mut end = styled.text.len();
+
+    for (i, grapheme) in styled.text.grapheme_indices(true) {
+        let w = grapheme.width();
+        if used + w > width {
+            end = i;
+            break;
+        }
+        used += w;
     }
 
-    text
+    let mut line = styled.slice_with_sgr(0, end);
+    line.push_str(&" ".repeat(width - used));
+    line
 }
 
-
 #[derive(Copy, Clone, PartialEq, Eq)]
 struct FmtOpts {
     w: usize,
     // `i`: The indent value.  Positive values give a hanging indent like tinyfugue, while negative
     // values give a first line indent.
     i: isize,
+    // Whether words too long for a line are broken somewhere sensible (see smart_cut()), rather
+    // than just wherever the line runs out.
+    hyphenate: bool,
+}
+
+impl FmtOpts {
+    /// How wide the view is, but never less than one column; a view with no room at all still
+    /// gets something to wrap to (it just won't be seen.)
+    fn width(&self) -> usize {
+        self.w.max(1)
+    }
+
+    /// How many columns the indent takes.  An indent as wide as the view would leave no room for
+    /// the text, so on tiny terminals it shrinks to leave at least a column.
+    fn indent(&self) -> usize {
+        self.i.unsigned_abs().min(self.width() - 1)
+    }
 }
 
 #[derive(Clone)]
@@ -31,25 +69,106 @@ struct ScreenLine {
     for_opts: FmtOpts,
 }
 
-fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
+/// Build one line of the screen from an indent and a piece (byte offsets `start` to `end`) of a
+/// styled line, leading whitespace trimmed off the piece, a hyphen after it if it's part of a
+/// word that goes on to the next line, and spaces added to make it `width` wide.  The pieces are
+/// already cut to fit, so this only needs to pad them, which is why it doesn't bother with
+/// force_width().
+fn screen_line(indent: &str, text: &StyledLine, start: usize, end: usize, width: usize, hyphen: bool) -> String {
+    let piece = &text.text[start..end];
+    let start = start + (piece.len() - piece.trim_start().len());
+
+    let used = indent.width() + text.text[start..end].width() + hyphen as usize;
+    let mut line = indent.to_string();
+    line.push_str(&text.slice_with_sgr(start, end));
+    if hyphen {
+        line.push('-');
+    }
+    line.push_str(&" ".repeat(width.saturating_sub(used)));
+    line
+}
+
+/// Whether a word looks like a URL, which mustn't get hyphens put in it.
+fn is_url(word: &str) -> bool {
+    let word = word.to_lowercase();
+    ["http://", "https://", "ftp://", "www."].iter().any(|prefix| word.starts_with(prefix))
+}
+
+/// Where to break a word that's too long for its line, given the latest place that fits (`cut`),
+/// where the line's text starts, and how wide the line can be; and whether the line should end
+/// with a hyphen.
+///
+/// A word with a - or / in the second half of the line breaks after that.  URLs break after any
+/// of the characters that separate their parts, and never get a hyphen, since it'd look like part
+/// of the URL.  Otherwise it's a hyphen, but only between two letters; breaking a row of a map or
+/// a number with a hyphen would only confuse things.
+fn smart_cut(text: &str, start: usize, cut: usize, width: usize) -> (usize, bool) {
+    // The word can have started on a line before this one.
+    let word_start = text[..cut].char_indices().rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let word_end = text[cut..].find(char::is_whitespace).map_or(text.len(), |i| cut + i);
+    let url = is_url(&text[word_start..word_end]);
+
+    let separators: &[char] = if url { &['/', '?', '&', '=', '#', '.', '-', '_'] } else { &['-', '/'] };
+    let from = word_start.max(start);
+    let separator = text[from..cut].rfind(separators).map(|i| from + i + 1);
+    if let Some(at) = separator {
+        if text[start..at].width() * 2 >= width {
+            return (at, false);
+        }
+    }
+    if url {
+        return (cut, false);
+    }
+
+    // Make room for the hyphen, if there isn't any.
+    let mut at = cut;
+    if text[start..at].width() + 1 > width {
+        at = match text[start..at].grapheme_indices(true).next_back() {
+            Some((i, _)) if i > 0 => start + i,
+            _ => return (cut, false),
+        };
+    }
+    let letters = text[..at].chars().next_back().is_some_and(char::is_alphabetic)
+        && text[at..].chars().next().is_some_and(char::is_alphabetic);
+    if letters { (at, true) } else { (cut, false) }
+}
+
+/// The pieces text is wrapped in, with their byte offsets: graphemes, so accents and the like stay
+/// with what they're on, except that one too wide to fit in `width` columns at all is split into
+/// its chars, which is the best that can be done with it.
+fn pieces(text: &str, width: usize) -> impl Iterator<Item = (usize, &str)> {
+    text.grapheme_indices(true).flat_map(move |(i, g)| {
+        if g.width() > width {
+            g.char_indices().map(|(j, c)| (i + j, &g[j..j + c.len_utf8()])).collect()
+        } else {
+            vec![(i, g)]
+        }
+    })
+}
+
+fn format(styled: &StyledLine, opts: FmtOpts) -> Vec<ScreenLine> {
     let mut result = vec![];
+    // The wrapping is all worked out on the plain text; the styles only come back in when the
+    // screen lines are put together.
+    let text = &styled.text;
 
     // We want to walk through the string and, so long as the amount of space it takes up so
     // far (since the last time we specified 'this should break here') is less than our view
     // width, just keep track of the last whitespace ... and keep doing this until we run out
     // of view width, where we record a break and continue on.
     //
-    // We need to track our breakpoints in both characters (which we just OPTIMISTICALLY HOPE
-    // will all be displayed at the same width HAHAHA) and bytes (because Rust's string slicing
-    // methods all want properly aligned byte-offsets into the UTF-8 string.)  The _idx
-    // variables are the byte offsets.
+    // We need to track our breakpoints in both columns on the screen and bytes (because Rust's
+    // string slicing methods all want properly aligned byte-offsets into the UTF-8 string.)  The
+    // _idx variables are the byte offsets.
     let mut last_whitespace: usize = 0;
     let mut last_whitespace_idx: usize = 0;
     let mut last_breakpoint: usize = 0;
     let mut last_breakpoint_idx: usize = 0;
     let mut width_so_far: usize = 0;
 
-    let (view_width, indent) = (opts.w, opts.i);
+    let (view_width, indent) = (opts.width(), opts.i);
 
     let mut indent_first: String = "".to_string();
     let mut indent_rest: String = "".to_string();
@@ -59,9 +178,8 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
     // not duplicate the logic.
     let indentwidth_firstline: usize = if indent < 0 {
         // Negative indents mean the first line of the paragraph is indented...
-        let indent = (indent * -1) as usize;
-        indent_first.push_str(&*(" ".repeat(indent)));
-        view_width - indent
+        indent_first.push_str(&" ".repeat(opts.indent()));
+        view_width - opts.indent()
     } else {
         // ...and positive ones mean all the other lines are (a hanging indent, like in
         // tinyfugue.)
@@ -72,62 +190,102 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
     let indentwidth_textbody: usize = if indent < 0 {
         view_width
     } else {
-        let indent = indent as usize;
-        indent_rest.push_str(&*(" ".repeat(indent)));
-        view_width - indent
+        indent_rest.push_str(&" ".repeat(opts.indent()));
+        view_width - opts.indent()
     };
 
-    // TODO: This shouldn't be iterating on 'chars' since thanks to Rust's concept of a char as
-    // a Unicode scalar, sometimes several chars could take up less space on the terminal than
-    // expected.
+    // This goes a grapheme at a time, not a char at a time: an accent or the rest of an emoji
+    // sequence can't go on a different line from what it's attached to, and the whole thing is
+    // only as wide as the terminal draws it, which isn't always what its chars add up to.
     //
     // TODO: Is there a problem if we encounter input with tab characters? PROBABLY. I think we
     // probably have to special-case that.
 
-    for (idx, character) in text.char_indices() {
-        width_so_far += 1;
+    let narrowest = indentwidth_firstline.min(indentwidth_textbody);
+    for (idx, grapheme) in pieces(text, narrowest) {
+        let grapheme_width = grapheme.width();
+        width_so_far += grapheme_width;
 
-        if character.is_whitespace() {
+        if grapheme.chars().all(char::is_whitespace) {
             last_whitespace = width_so_far;
             last_whitespace_idx = idx;
         }
 
-        // The target width we need to wrap to varies depending on what the indentation value
-        // is. So we have to recalculate it every time.
-        // We take advantage of the fact that last_breakpoint will be 0 on the first line but
-        // not on any later ones.
-        let target_width = match last_breakpoint {
-            0 => indentwidth_firstline,
-            _ => indentwidth_textbody,
-        };
-
-        // This is a while loop and not an if because I was worried about a situation where we have
+        // This is a loop and not an if because I was worried about a situation where we have
         // a spot to break on whitespace but even after doing that there might still be too much
-        // text.  I suspect that might never happen, but I'm not like 100% confident and there's
-        // not much to lose. 
-        while width_so_far - last_breakpoint > target_width {
+        // text.  It does happen: with a hanging indent the lines after the first are narrower,
+        // so what fit on the first line might not fit on the second.
+        loop {
+            // The target width we need to wrap to varies depending on what the indentation value
+            // is. So we have to recalculate it every time, including after a break.
+            // We take advantage of the fact that last_breakpoint will be 0 on the first line but
+            // not on any later ones.
+            let target_width = match last_breakpoint {
+                0 => indentwidth_firstline,
+                _ => indentwidth_textbody,
+            };
+            if width_so_far - last_breakpoint <= target_width {
+                break;
+            }
+
             // We build our line by just cloning the appropriate amount of leading
             // whitespace to start with, then pushing the line itself onto the end.
-            let mut line: String = match last_breakpoint {
-                0 => indent_first.clone(),
-                _ => indent_rest.clone(),
+            let indent = match last_breakpoint {
+                0 => &indent_first,
+                _ => &indent_rest,
             };
 
             // If we have a whitespace point break there, but otherwise just break right
             // where we are (in the middle of, presumably, a long word) as there are no
-            // other options at that point.
-            if last_whitespace > last_breakpoint {
-                line.push_str(text[last_breakpoint_idx..last_whitespace_idx].trim_start());
+            // other options at that point.  Either way the line has to fit: after a break the
+            // target can get narrower, so what's before the whitespace might not.
+            let piece = &text[last_breakpoint_idx..idx];
+            let start = last_breakpoint_idx + (piece.len() - piece.trim_start().len());
+            let fits = |end: usize| end <= start || text[start..end].width() <= target_width;
+
+            let line = if last_whitespace > last_breakpoint && fits(last_whitespace_idx) {
+                let line = screen_line(indent, styled, last_breakpoint_idx, last_whitespace_idx, view_width, false);
                 last_breakpoint = last_whitespace;
                 last_breakpoint_idx = last_whitespace_idx;
+                line
             } else {
-                line.push_str(text[last_breakpoint_idx..idx].trim_start());
-                last_breakpoint = width_so_far;
-                last_breakpoint_idx = idx;
-            }
+                // A character wider than the whole view can't go anywhere that it'll fit, so it
+                // gets a line to itself and sticks out.
+                if start == idx {
+                    break;
+                }
+                // Break as late as still fits, which is usually right before this grapheme.
+                // There's always at least one on the line.
+                let mut cut = idx;
+                let mut used = 0;
+                for (i, g) in pieces(&text[start..idx], narrowest) {
+                    let w = g.width();
+                    if used + w > target_width && i > 0 {
+                        cut = start + i;
+                        break;
+                    }
+                    used += w;
+                }
+
+                let hyphen = if opts.hyphenate && cut > start {
+                    let (smart, hyphen) = smart_cut(text, start, cut, target_width);
+                    cut = smart;
+                    hyphen
+                } else {
+                    false
+                };
+
+                // Everything from the cut on starts the next line, so it counts towards that
+                // one's width.
+                let line = screen_line(indent, styled, last_breakpoint_idx, cut, view_width, hyphen);
+                let carried: usize = pieces(&text[cut..idx], narrowest).map(|(_, g)| g.width()).sum();
+                last_breakpoint = width_so_far - grapheme_width - carried;
+                last_breakpoint_idx = cut;
+                line
+            };
 
             result.push(ScreenLine {
-                text: force_width(line, opts.w),
+                text: line,
                 for_opts: opts,
             });
         }
@@ -139,14 +297,13 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
     if last_chunk.len() > 0 {
         // We still have to decide which of these we need, because some lines are short
         // enough that they're only pushed once, here.
-        let mut last_line: String = match last_breakpoint {
-            0 => indent_first.clone(),
-            _ => indent_rest.clone(),
+        let indent = match last_breakpoint {
+            0 => &indent_first,
+            _ => &indent_rest,
         };
 
-        last_line.push_str(last_chunk);
         result.push(ScreenLine {
-            text: force_width(last_line, opts.w),
+            text: screen_line(indent, styled, last_breakpoint_idx, text.len(), view_width, false),
             for_opts: opts,
         });
     }
@@ -160,7 +317,7 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
     // happens we're going to return a blank line instead of nothing.
     if result.len() == 0 {
         result.push(ScreenLine {
-            text: "".to_string(),
+            text: " ".repeat(view_width),
             for_opts: opts,
         });
     }
@@ -169,16 +326,40 @@ fn format(text: String, opts: FmtOpts) -> Vec<ScreenLine> {
 }
 
 
+/// Settings for how views show their lines.  Changing any of them means every line has to be
+/// wrapped again, which WrappedView::set_display() sees to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Display {
+    /// Positive for a hanging indent, negative to indent the first line instead.
+    pub indent: isize,
+    /// Wrap lines at this many columns, even if the window's wider.
+    pub wrap: Option<usize>,
+    /// Put the time each line arrived in front of it, in this style.
+    pub timestamps: Option<Style>,
+    /// Break words too long for a line after a - or /, or with a hyphen, instead of wherever the
+    /// line runs out.
+    pub hyphenate: bool,
+}
+
+impl Default for Display {
+    fn default() -> Display {
+        Display { indent: 4, wrap: None, timestamps: None, hyphenate: false }
+    }
+}
+
 /// A view onto some word-wrapped lines.
 pub struct WrappedView {
     h: usize,
+    // How wide the view is.  Lines are wrapped to fmt.w, which can be less (see Display::wrap.)
+    w: usize,
     fmt: FmtOpts,
+    display: Display,
 
     // This is our 'history buffer', in ascending order -- that is, the most recent line always has
     // the highest index.  We're usually going to be going in reverse chronological order because
     // we draw up from the bottom of the view and new lines appear on the bottom of the view; it's
     // a chat program, after all.
-    history: Vec<String>,
+    history: Vec<Line>,
 
     // We store a _cache_ of the results of word-wrapping each of the history lines to our view
     // settings (stored in self.fmt) so that we're not calling the relatively expensive
@@ -200,43 +381,262 @@ pub struct WrappedView {
     // next, etc.; the most recent one visible) and a measure of how many view lines within it we
     // throw away before starting to draw.  Think of the second number as a negative index.
     position: (usize, usize),
+
+    // History indexes of the divider lines put in with push_mark(), oldest first.
+    marks: Vec<usize>,
+
+    // How many lines have arrived while the view was scrolled back, for the "more" indicator.
+    unseen: usize,
+
+    // The most history lines we keep.  The oldest ones go once there are more than this.
+    max_history: usize,
+
+    // While the view's frozen (see set_frozen()), the lines that have come in, and whether each
+    // is a mark, waiting to go in the history.
+    frozen: bool,
+    held: Vec<(Line, bool)>,
 }
 
 impl WrappedView {
     pub fn new(w: usize, h: usize) -> WrappedView {
+        let display = Display::default();
         WrappedView {
-            h,
+            h: h.max(1),
+            w,
             fmt: FmtOpts {
-                i: 4, w
+                i: display.indent, w: w.max(1), hyphenate: display.hyphenate,
             },
+            display,
             history: vec![],
             cache: FnvHashMap::default(),
             position: (0,0),
+            marks: vec![],
+            unseen: 0,
+            max_history: DEFAULT_MAX_HISTORY,
+            frozen: false,
+            held: vec![],
         }
     }
 
+    pub fn height(&self) -> usize {
+        self.h
+    }
+
+    /// Keep at most `lines` lines of history, throwing away the oldest ones past that.
+    pub fn set_max_history(&mut self, lines: usize) {
+        self.max_history = lines.max(1);
+        self.trim_history();
+    }
+
+    /// Throw away the oldest lines if there are too many.  To keep from shuffling the whole
+    /// history around for every new line, we let it get a tenth over the limit first.
+    fn trim_history(&mut self) {
+        if self.history.len() <= self.max_history + self.max_history / 10 {
+            return;
+        }
+        let excess = self.history.len() - self.max_history;
+        self.history.drain(0..excess);
+
+        // Everything that refers to lines by index has to shift down to match.
+        self.cache = self.cache.drain()
+            .filter(|(i, _)| *i >= excess)
+            .map(|(i, lines)| (i - excess, lines))
+            .collect();
+        self.marks.retain(|i| *i >= excess);
+        for mark in self.marks.iter_mut() {
+            *mark -= excess;
+        }
+        self.position = if self.position.0 >= excess {
+            (self.position.0 - excess, self.position.1)
+        } else {
+            (0, 0)
+        };
+    }
+
+    /// Whether the view is showing the newest line, i.e. not scrolled back.
+    fn following(&self) -> bool {
+        self.history.is_empty() || self.position == (self.history.len() - 1, 0)
+    }
+
+    /// How many lines have come in since the view was scrolled back.
+    pub fn unseen(&self) -> usize {
+        self.unseen
+    }
+
+    /// Where the view's scrolled to, as (the line at the bottom, counting from 1; how many lines
+    /// there are.)
+    pub fn position(&self) -> (usize, usize) {
+        (self.position.0 + 1, self.history.len())
+    }
+
+    /// Change the size of the view.  It's never less than one line by one column, however small
+    /// the terminal gets; scrolling and jumping to marks need at least a line to work with.
     pub fn resize(&mut self, w: usize, h: usize) {
-        self.h = h;
-        self.fmt.w = w;
+        self.h = h.max(1);
+        self.w = w;
+        self.fmt.w = self.display.wrap.map_or(w, |wrap| wrap.min(w)).max(1);
+    }
+
+    /// Change how lines are shown.  Everything already in the view is wrapped again to suit, not
+    /// just the lines from now on.
+    pub fn set_display(&mut self, display: Display) {
+        self.display = display;
+        self.fmt.i = display.indent;
+        self.fmt.hyphenate = display.hyphenate;
+        self.resize(self.w, self.h);
+        self.invalidate();
+    }
+
+    /// Forget all the wrapped lines, so they're worked out again next time they're drawn.  The
+    /// scroll position stays on the same line, from its bottom, since how many screen lines it
+    /// takes up might have changed.
+    pub fn invalidate(&mut self) {
+        self.cache.clear();
+        self.position.1 = 0;
+    }
+
+    /// Freeze the view, so that nothing on screen moves (text's being selected from it, say):
+    /// lines that come in are held back until it's unfrozen, and then they all go in at once.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+        if !frozen {
+            for (line, mark) in std::mem::take(&mut self.held) {
+                if mark {
+                    self.push_mark(line);
+                } else {
+                    self.push(line);
+                }
+            }
+        }
+    }
+
+    /// How many lines are being held back while the view's frozen.
+    pub fn held(&self) -> usize {
+        self.held.len()
     }
 
     /// Add a line to the View.
     ///
-    /// This function expects that its argument will, logically, be a single line.  If you pass it
-    /// a line with `\n`, `\r` or potentially other similar control characters included, it will
-    /// remove them.
-    pub fn push(&mut self, mut line: String) {
-        line.retain(|c| c != '\n' && c != '\r');
+    /// This function expects that its argument will, logically, be a single line.  Lines made
+    /// with Line::server() or Line::client() are; those drop `\n`, `\r` and other similar
+    /// control characters.
+    pub fn push(&mut self, line: Line) {
+        if self.frozen {
+            return self.held.push((line, false));
+        }
 
-        let current_histlen = self.history.len();
+        // Check if we were previously at the end of the history and if so, make sure we stay at
+        // the end of the history.  Otherwise the view stays put, and we count the line as one
+        // the user hasn't seen yet.
+        let following = self.following();
         self.history.push(line);
 
-        // Check if we were previously at the end of the history and if so, make sure we stay at
-        // the end of the history.  Special case for when the history is empty, as there's not yet
-        // anything to not be at the end of.
-        if current_histlen == 0 || self.position.0 == current_histlen - 1 {
+        if following {
             self.position.0 = self.history.len() - 1;
             self.position.1 = 0;
+        } else {
+            self.unseen += 1;
+        }
+
+        self.trim_history();
+    }
+
+    /// Add a divider line to the View and remember where it is, so jump_to_last_mark() can find
+    /// it again.
+    pub fn push_mark(&mut self, line: Line) {
+        if self.frozen {
+            return self.held.push((line, true));
+        }
+        self.push(line);
+        self.marks.push(self.history.len() - 1);
+    }
+
+    /// Scroll so the most recent mark is at the top of the view (or as near as it can get, if
+    /// there isn't enough after it to fill the view.)  Returns false if there are no marks.
+    pub fn jump_to_last_mark(&mut self) -> bool {
+        let mark = match self.marks.last() {
+            Some(mark) => *mark,
+            None => return false,
+        };
+
+        // Walk forward from the mark, adding up screen lines, until we've got enough to fill the
+        // view; whichever history line does that goes at the bottom.
+        let mut total = 0;
+        for i in mark..self.history.len() {
+            total += self.wrap(i).expect("wrap(i) in jump_to_last_mark()").len();
+            if total >= self.h {
+                self.position = (i, total - self.h);
+                return true;
+            }
+        }
+
+        self.scroll_to_end();
+        true
+    }
+
+    /// The last `n` lines of history (or all of them, if there aren't that many), oldest first.
+    pub fn last_lines(&self, n: usize) -> Vec<Line> {
+        let start = self.history.len().saturating_sub(n);
+        self.history[start..].to_vec()
+    }
+
+    /// Every line of history from the most recent mark on, including the mark itself.  None if
+    /// there are no marks.
+    pub fn lines_since_mark(&self) -> Option<Vec<Line>> {
+        self.marks.last().map(|mark| self.history[*mark..].to_vec())
+    }
+
+    /// Go back to following the newest lines as they come in.
+    pub fn scroll_to_end(&mut self) {
+        self.position = (self.history.len().saturating_sub(1), 0);
+        self.unseen = 0;
+    }
+
+    /// Scroll back `n` screen lines, but not so far that there's nothing at the top of the view.
+    pub fn scroll_up(&mut self, n: usize) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        let (mut i, mut skip) = self.position;
+        // How many screen lines there are from the oldest one down to the bottom of the view.
+        // Once that's down to the height of the view, the oldest line is at the top and we stop.
+        let mut above: usize = (0..i + 1).map(|k| self.wrap(k).map_or(0, |l| l.len())).sum();
+        above -= skip;
+
+        for _ in 0..n {
+            if above <= self.h {
+                break;
+            }
+            let len = self.wrap(i).map_or(1, |l| l.len());
+            if skip + 1 < len {
+                skip += 1;
+            } else {
+                i -= 1;
+                skip = 0;
+            }
+            above -= 1;
+        }
+        self.position = (i, skip);
+    }
+
+    /// Scroll forward `n` screen lines, stopping at the newest line.
+    pub fn scroll_down(&mut self, n: usize) {
+        let (mut i, mut skip) = self.position;
+        for _ in 0..n {
+            if skip > 0 {
+                skip -= 1;
+            } else if i + 1 < self.history.len() {
+                i += 1;
+                skip = self.wrap(i).map_or(1, |l| l.len()) - 1;
+            } else {
+                break;
+            }
+        }
+        self.position = (i, skip);
+
+        if self.following() {
+            self.unseen = 0;
         }
     }
 
@@ -255,7 +655,15 @@ impl WrappedView {
 
         // If we got here, either it hasn't been calculated yet or we changed the format options,
         // which means we'd better recompute.
-        let new_lines = format(self.history[line].clone(), self.fmt);
+        let new_lines = match self.display.timestamps {
+            Some(style) => {
+                let mut styled = self.history[line].styled.clone();
+                let time = LocalTime::at(self.history[line].time);
+                styled.prepend(&format!("[{}] ", time.hm()), style);
+                format(&styled, self.fmt)
+            },
+            None => format(&self.history[line].styled, self.fmt),
+        };
         self.cache.insert(line, new_lines.clone());
         Some(new_lines)
     }
@@ -264,8 +672,21 @@ impl WrappedView {
     /// this view.  The Vec is guaranteed to be self.h items long (index 0 = top of view) and each
     /// String attempts to be self.fmt.w `char`s wide.
     pub fn render(&mut self) -> Vec<String> {
-        let lines_wanted = self.h;
+        self.render_at(self.position, self.h)
+    }
+
+    /// Like render(), but always of the newest `h` screen lines, wherever the view is scrolled
+    /// to.  This is the live half of a split window, which keeps going while the view itself is
+    /// scrolled back.
+    pub fn render_tail(&mut self, h: usize) -> Vec<String> {
+        let end = (self.history.len().saturating_sub(1), 0);
+        self.render_at(end, h)
+    }
+
+    /// Render `lines_wanted` screen lines with `position` at the bottom.
+    fn render_at(&mut self, position: (usize, usize), lines_wanted: usize) -> Vec<String> {
         let fmt = self.fmt;
+        let skip = position.1;
 
         if self.history.len() > 0 {
             // Here we have a CONFUSING TANGLE OF ITERATORS.
@@ -273,10 +694,10 @@ impl WrappedView {
             // This does exactly what I want, but it's probably kind of hard to read.  In fact,
             // I've even kind of confused myself.  Sorry?
 
-            let v: Vec<String> = (0..self.position.0+1).rev().map(|i| {
+            let v: Vec<String> = (0..position.0+1).rev().flat_map(|i| {
                 // For every line in history, going backwards from the most recent...
                 self.wrap(i).expect("wrap(i) in render()").into_iter().rev()
-            }).flatten().map(|l| l.text).chain(std::iter::repeat(" ".repeat(fmt.w)))
+            }).skip(skip).map(|l| l.text).chain(std::iter::repeat(" ".repeat(fmt.w)))
               .take(lines_wanted).collect();
 
             // We needed to reverse the final iterator but take() isn't a DoubleEndedIterator.  So I
@@ -284,7 +705,188 @@ impl WrappedView {
             // doesn't hurt performance too much.
             v.into_iter().rev().collect()
         } else {
-            std::iter::repeat(" ".repeat(fmt.w)).take(self.h).collect()
+            std::iter::repeat_n(" ".repeat(fmt.w), lines_wanted).collect()
         }
     }
 }
+
+#[test]
+fn force_width_columns() {
+    assert_eq!(force_width("abc", 5), "abc  ");
+    assert_eq!(force_width("abcdef", 3), "abc");
+    // Styles don't take up room, and get switched off if the cut comes before they would be.
+    assert_eq!(force_width("\x1b[1mbold\x1b[0m text", 6), "\x1b[0;1mbold\x1b[0m t");
+    // Wide characters take two columns, and one that won't fit leaves a space instead.
+    assert_eq!(force_width("日本語", 5), "日本 ");
+    // An accent stays with its letter.
+    assert_eq!(force_width("cafe\u{301}!", 4), "cafe\u{301}");
+
+    // Wrapping doesn't split a letter from its accent, or an emoji sequence up.
+    let lines = format(&StyledLine::plain("abq\u{323}\u{1F469}\u{200D}\u{1F467}".to_string()), FmtOpts { w: 3, i: 0, hyphenate: false });
+    let lines: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+    assert_eq!(lines, vec!["abq\u{323}", "\u{1F469}\u{200D}\u{1F467} "]);
+}
+
+#[test]
+fn jump_to_mark() {
+    let mut view = WrappedView::new(20, 3);
+    assert!(!view.jump_to_last_mark());
+
+    for i in 0..5 {
+        view.push(Line::client(&format!("line {}", i)));
+    }
+    view.push_mark(Line::client("--- 22:14 ---"));
+    for i in 5..10 {
+        view.push(Line::client(&format!("line {}", i)));
+    }
+
+    // The mark goes to the top, and new lines don't move the view while we're looking back.
+    assert!(view.jump_to_last_mark());
+    view.push(Line::client("line 10"));
+    assert_eq!(view.render()[0].trim_end(), "--- 22:14 ---");
+
+    view.scroll_to_end();
+    assert_eq!(view.render()[2].trim_end(), "line 10");
+}
+
+#[test]
+fn scrollback() {
+    let mut view = WrappedView::new(20, 3);
+    for i in 0..10 {
+        view.push(Line::client(&format!("line {}", i)));
+    }
+
+    view.scroll_up(3);
+    assert_eq!(view.render()[2].trim_end(), "line 6");
+    view.push(Line::client("line 10"));
+    assert_eq!(view.unseen(), 1);
+    assert_eq!(view.render()[2].trim_end(), "line 6");
+
+    // Not past the top, and not past the bottom.
+    view.scroll_up(100);
+    assert_eq!(view.render()[0].trim_end(), "line 0");
+    view.scroll_down(100);
+    assert_eq!(view.render()[2].trim_end(), "line 10");
+    assert_eq!(view.unseen(), 0);
+
+    view.set_max_history(5);
+    for i in 11..20 {
+        view.push(Line::client(&format!("line {}", i)));
+    }
+    let kept = view.last_lines(100);
+    assert!(kept.len() <= 5 + 5 / 10);
+    assert_eq!(kept.last().unwrap().text(), "line 19");
+}
+
+#[test]
+fn split_tail() {
+    let mut view = WrappedView::new(20, 3);
+    for i in 0..10 {
+        view.push(Line::client(&format!("line {}", i)));
+    }
+
+    // Scrolling back leaves the tail where it is.
+    view.scroll_up(5);
+    view.push(Line::client("line 10"));
+    assert_eq!(view.render()[2].trim_end(), "line 4");
+    let tail = view.render_tail(2);
+    assert_eq!(tail.len(), 2);
+    assert_eq!(tail[1].trim_end(), "line 10");
+}
+
+#[test]
+fn freezing() {
+    let mut view = WrappedView::new(20, 2);
+    view.push(Line::client("line 0"));
+    view.push(Line::client("line 1"));
+
+    view.set_frozen(true);
+    view.push(Line::client("line 2"));
+    view.push_mark(Line::client("--- 22:14 ---"));
+    assert_eq!(view.held(), 2);
+    assert_eq!(view.render()[1].trim_end(), "line 1");
+
+    view.set_frozen(false);
+    assert_eq!(view.held(), 0);
+    assert_eq!(view.render()[1].trim_end(), "--- 22:14 ---");
+    assert_eq!(view.lines_since_mark().map(|lines| lines.len()), Some(1));
+}
+
+#[test]
+fn display_rewraps() {
+    let mut view = WrappedView::new(20, 3);
+    view.push(Line::client("one two three four"));
+    assert_eq!(view.render()[2].trim_end(), "one two three four");
+
+    // Lines already there are wrapped again, not just new ones.
+    view.set_display(Display { indent: 2, wrap: Some(10), ..Display::default() });
+    assert_eq!(view.render(), vec!["one two   ", "  three   ", "  four    "]);
+
+    view.set_display(Display { timestamps: Some(Style::default()), ..Display::default() });
+    let line = &view.render()[1];
+    assert!(line.starts_with('[') && line.contains("] one two"));
+}
+
+#[test]
+fn hyphenation() {
+    let wrap = |text: &str, w| -> Vec<String> {
+        format(&StyledLine::plain(text.to_string()), FmtOpts { w, i: 0, hyphenate: true })
+            .into_iter().map(|line| line.text.trim_end().to_string()).collect()
+    };
+    assert_eq!(wrap("antidisestablishment", 8), vec!["antidis-", "establi-", "shment"]);
+    // Map rows and numbers aren't words.
+    assert_eq!(wrap("#####|#####", 8), vec!["#####|##", "###"]);
+    // Breaks that are already there do instead.
+    assert_eq!(wrap("well-established", 10), vec!["well-", "establish-", "ed"]);
+    assert_eq!(wrap("see https://example.com/a/long/path?q=1", 12),
+               vec!["see", "https://", "example.com/", "a/long/path?", "q=1"]);
+    // Wide characters still fit, hyphen and all.
+    assert!(wrap("aaa日本語aaaa", 6).iter().all(|line| line.width() <= 6));
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn format_fits_width(text in "(\\PC| ){0,300}", indent in -8isize..8, extra in 2usize..80) {
+        let w = indent.unsigned_abs() + extra;
+        let lines = format(&StyledLine::plain(text.clone()), FmtOpts { w, i: indent, hyphenate: false });
+
+        // Every screen line is exactly as wide as the view...
+        for line in &lines {
+            prop_assert_eq!(line.text.width(), w, "{:?}", line.text);
+        }
+        // ...and nothing but whitespace goes missing on the way.
+        let kept: String = lines.iter().flat_map(|l| l.text.chars()).filter(|c| !c.is_whitespace()).collect();
+        let wanted: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        prop_assert_eq!(kept, wanted);
+    }
+
+    #[test]
+    fn format_tiny_views(text in "[a-z ]{0,100}", indent in -20isize..20, w in 0usize..6) {
+        // The indent shrinks to fit, so every line still comes out as wide as the view (or one
+        // column, for a view with no room at all.)
+        for line in format(&StyledLine::plain(text), FmtOpts { w, i: indent, hyphenate: false }) {
+            prop_assert_eq!(line.text.width(), w.max(1), "{:?}", line.text);
+        }
+    }
+
+    #[test]
+    fn format_hyphenated_fits(text in "([a-z#/-]{1,30}| ){0,20}", indent in -8isize..8, extra in 2usize..40) {
+        let w = indent.unsigned_abs() + extra;
+        let lines = format(&StyledLine::plain(text.clone()), FmtOpts { w, i: indent, hyphenate: true });
+        for line in &lines {
+            prop_assert_eq!(line.text.width(), w, "{:?}", line.text);
+        }
+        // Hyphens can be added, but nothing else.
+        let kept: String = lines.iter().flat_map(|l| l.text.chars()).filter(|c| !c.is_whitespace() && *c != '-').collect();
+        let wanted: String = text.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+        prop_assert_eq!(kept, wanted);
+    }
+
+    #[test]
+    fn force_width_fits(text in "(\\PC| ){0,100}", w in 0usize..60) {
+        let forced = force_width(&text, w);
+        prop_assert_eq!(forced.width(), w);
+        prop_assert!(text.starts_with(forced.trim_end()));
+    }
+}
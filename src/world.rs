@@ -0,0 +1,273 @@
+use crate::charset::Charset;
+use crate::config;
+use crate::log::LogFormat;
+use crate::net::reconnect::ReconnectPolicy;
+use crate::repeat;
+use crate::ui::BellPolicy;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How the worlds file (see parse_worlds) starts, when there isn't one yet.
+pub const WORLDS_EXAMPLE: &str = "\
+# Worlds to connect to with mint --world name.  Each one's settings go under its name:
+#
+# [tharsis]
+# address tls://tharsis.example.org:4443
+# charset latin1
+# log /home/me/logs/tharsis.html
+# idle 10m notify
+# key F1 /cast heal
+# send F2 kill orc
+";
+
+/// Something bound to a key for a particular world.
+#[derive(Clone, Debug)]
+pub enum KeyMacro {
+    /// Treat the text as if the user had typed it into the input line and pressed enter.
+    Command(String),
+    /// Send the text straight to the server, no questions asked.
+    Send(String),
+}
+
+/// When to say a server's gone quiet (see Event::ServerIdle.)  A connection that's still up but
+/// hasn't had anything come over it in a long while often means the game's crashed, or the
+/// network's stalled somewhere along the way.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdlePolicy {
+    /// How long the server has to say nothing for.  None means it never counts as quiet.
+    pub after: Option<Duration>,
+    /// Whether to raise a desktop notification, as well as marking the window and status bar.
+    pub notify: bool,
+}
+
+/// A MUD server we know how to connect to (a "world," in tinyfugue's terms), along with the
+/// user's preferences for how it should be treated.
+pub struct World {
+    pub name: String,
+    /// In whatever format the ConnectionInterface that'll be used for this world expects.
+    pub address: String,
+
+    pub bell: BellPolicy,
+
+    /// Key macros, keyed on the UI's name for the key (see Event::KeyPress.)  These live here and
+    /// not with the rest of the key handling because they're usually specific to one game.
+    pub keys: HashMap<String, KeyMacro>,
+
+    /// If this is set, outbound commands are held back until the server shows a prompt after
+    /// each one (see net::stack.)  `prompt` says what a prompt looks like.
+    pub stack_commands: bool,
+    pub prompt: Option<Regex>,
+
+    /// Where to log the session to, if anywhere, how, and whether the log should include what we
+    /// send as well as what the server sends.
+    pub log_file: Option<PathBuf>,
+    pub log_format: LogFormat,
+    pub log_sent: bool,
+
+    /// Whether lines starting with "/" typed in the world's window are client commands.  Some
+    /// talkers and MUSHes use "/" for their own commands; for those, turning this off sends
+    /// everything to the server just as it was typed.
+    pub client_commands: bool,
+
+    /// Raw send mode: lines typed for the world go out without any automation having a go at
+    /// them first (no alias expansion, and likewise for anything else that rewrites input.)
+    /// Client commands still work.  Meant for MUSHes, where softcode is full of characters that
+    /// would otherwise get mangled.
+    pub raw_send: bool,
+
+    /// Whether to use MCCP compression with the server when it offers (see net::mccp.)  It saves
+    /// bandwidth, but it's a lot harder to see what's going on with a packet sniffer.
+    pub compress: bool,
+
+    /// What the server's text is in, for ones that don't send UTF-8 and don't say what they send
+    /// instead.  If the server does say (telnet CHARSET) and offers this, it's what's picked.
+    /// None means UTF-8.
+    pub charset: Option<Charset>,
+
+    /// MSDP variables (see net::msdp) to ask the server to keep us up to date on, and to show in
+    /// the window's status, like HEALTH or MOVEMENT.
+    pub msdp: Vec<String>,
+
+    /// Whether to reconnect if the connection drops, and how soon.
+    pub reconnect: ReconnectPolicy,
+
+    /// When to say the server's gone quiet.
+    pub idle: IdlePolicy,
+
+    /// Whether to remember the windows and panes used with the world, and put them back on
+    /// connecting to it again (see layouts.)  On for worlds from the worlds file, and off for ones
+    /// made up on the spot from an address, which there'd be nothing to keep it with.
+    pub layout: bool,
+}
+
+impl World {
+    pub fn new(name: String, address: String) -> World {
+        World {
+            name,
+            address,
+            bell: BellPolicy::Ring,
+            keys: HashMap::new(),
+            stack_commands: false,
+            prompt: None,
+            log_file: None,
+            log_format: LogFormat::Ansi,
+            log_sent: false,
+            client_commands: true,
+            raw_send: false,
+            compress: true,
+            charset: None,
+            msdp: vec![],
+            reconnect: ReconnectPolicy::default(),
+            idle: IdlePolicy::default(),
+            layout: false,
+        }
+    }
+
+    /// Apply one of a world's settings from the worlds file (see parse_worlds.)
+    fn set(&mut self, setting: &str, value: &str) -> Result<(), String> {
+        let on_off = |value: &str| match value {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(format!("Expected on or off for {}, not {}", setting, value)),
+        };
+        let (key, rest) = match value.split_once(char::is_whitespace) {
+            Some((key, rest)) => (key.to_string(), rest.trim().to_string()),
+            None => (value.to_string(), String::new()),
+        };
+
+        match setting {
+            "address" => self.address = value.to_string(),
+            "bell" => self.bell = match value {
+                "ring" => BellPolicy::Ring,
+                "visual" => BellPolicy::Visual,
+                "notify" => BellPolicy::Notify,
+                "ignore" => BellPolicy::Ignore,
+                _ => return Err(format!("Expected ring, visual, notify or ignore for bell, not {}", value)),
+            },
+            "charset" => self.charset = Some(Charset::from_name(value)
+                .ok_or_else(|| format!("Don't know the charset {}", value))?),
+            "commands" => self.client_commands = on_off(value)?,
+            "compress" => self.compress = on_off(value)?,
+            "log" => {
+                self.log_file = Some(PathBuf::from(value));
+                self.log_format = LogFormat::for_path(Path::new(value));
+            },
+            "idle" if value == "off" => self.idle = IdlePolicy::default(),
+            "idle" => self.idle = IdlePolicy {
+                after: Some(repeat::parse_interval(&key).filter(|after| after.as_secs() > 0)
+                    .ok_or_else(|| format!("Expected a length of time (like 10m) or off for idle, not {}", key))?),
+                notify: match rest.as_str() {
+                    "" => false,
+                    "notify" => true,
+                    _ => return Err(format!("Expected notify or nothing after idle's time, not {}", rest)),
+                },
+            },
+            "layout" => self.layout = on_off(value)?,
+            "log-sent" => self.log_sent = on_off(value)?,
+            "msdp" => self.msdp = value.split_whitespace().map(str::to_string).collect(),
+            "prompt" => self.prompt = Some(Regex::new(value).map_err(|e| format!("Bad prompt pattern: {}", e))?),
+            "raw" => self.raw_send = on_off(value)?,
+            "stack" => self.stack_commands = on_off(value)?,
+            "key" | "send" if rest.is_empty() => return Err(format!("Expected a key and what it does after {}", setting)),
+            "key" => { self.keys.insert(key, KeyMacro::Command(rest)); },
+            "send" => { self.keys.insert(key, KeyMacro::Send(rest)); },
+            _ => return Err(format!("No such setting: {}", setting)),
+        }
+        Ok(())
+    }
+}
+
+/// Read the worlds file: each world starts with its name in brackets, and each line after that
+/// is one of its settings, then the setting's value, like "address example.com:4000" or "key F1
+/// /cast heal".  (See World::set() for what there is.)  Blank lines and ones starting with #
+/// don't count.  Worlds without an address are left out, and what went wrong comes back with
+/// the worlds that were all right.
+pub fn parse_worlds(text: &str) -> (Vec<World>, Vec<String>) {
+    let mut worlds: Vec<World> = vec![];
+    let mut errors = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            let mut world = World::new(name.trim().to_string(), String::new());
+            world.layout = true;
+            worlds.push(world);
+            continue;
+        }
+        let (setting, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let result = match worlds.last_mut() {
+            Some(world) => world.set(setting, value.trim()),
+            None => Err("Settings have to go under a world's [name]".to_string()),
+        };
+        if let Err(e) = result {
+            errors.push(format!("line {}: {}", n + 1, e));
+        }
+    }
+
+    worlds.retain(|world| {
+        if world.address.is_empty() {
+            errors.push(format!("{} has no address", world.name));
+        }
+        !world.address.is_empty()
+    });
+    (worlds, errors)
+}
+
+/// Where the worlds file goes: `worlds`, in the config directory.
+pub fn worlds_path() -> Option<PathBuf> {
+    config::ensure_config_dir().map(|dir| dir.join("worlds"))
+}
+
+/// The world called `name` in the worlds file at `path`, if there's a file and it's in it.
+/// Whatever's wrong with the rest of the file doesn't matter here.
+pub fn find_world(path: &Path, name: &str) -> Option<World> {
+    let text = fs::read_to_string(path).ok()?;
+    parse_worlds(&text).0.into_iter().find(|world| world.name == name)
+}
+
+#[test]
+fn worlds_file() {
+    let (worlds, errors) = parse_worlds("\
+# A comment.
+[tharsis]
+address tls://tharsis.example.org:4443
+charset latin1
+log /tmp/tharsis.html
+key F1 /cast heal
+send F2 kill orc
+idle 10m notify
+layout off
+
+[nowhere]
+compress sometimes
+");
+    assert_eq!(worlds.len(), 1);
+    let tharsis = &worlds[0];
+    assert_eq!(tharsis.name, "tharsis");
+    assert_eq!(tharsis.address, "tls://tharsis.example.org:4443");
+    assert_eq!(tharsis.charset, Some(Charset::Latin1));
+    assert_eq!(tharsis.log_format, LogFormat::Html);
+    assert!(matches!(tharsis.keys.get("F1"), Some(KeyMacro::Command(line)) if line == "/cast heal"));
+    assert!(matches!(tharsis.keys.get("F2"), Some(KeyMacro::Send(text)) if text == "kill orc"));
+    assert_eq!(tharsis.idle, IdlePolicy { after: Some(Duration::from_secs(600)), notify: true });
+    assert!(!tharsis.layout);
+    assert_eq!(errors, vec!["line 12: Expected on or off for compress, not sometimes".to_string(),
+                            "nowhere has no address".to_string()]);
+
+    let (worlds, _) = parse_worlds(WORLDS_EXAMPLE);
+    assert!(worlds.is_empty());
+
+    let path = std::env::temp_dir().join(format!("mint-worlds-test-{}", std::process::id()));
+    assert!(find_world(&path, "tharsis").is_none());
+    fs::write(&path, "[tharsis]\naddress tharsis.example.org:4000\ncharset latin1\n[broken]\n").unwrap();
+    let tharsis = find_world(&path, "tharsis").unwrap();
+    assert_eq!((tharsis.address.as_str(), tharsis.charset), ("tharsis.example.org:4000", Some(Charset::Latin1)));
+    assert!(find_world(&path, "tharsis.example.org:4000").is_none());
+    fs::remove_file(&path).unwrap();
+}